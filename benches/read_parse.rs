@@ -0,0 +1,81 @@
+//! Reads and parses the xml of a generated corpus of many small files, with
+//! and without reusing the read buffers, see
+//! `korp_mono_rs::pipeline::buffers`.
+//!
+//!     cargo bench --bench read_parse
+//!
+//! The number of files is 100 000, or `KORP_MONO_BENCH_FILES`. The files
+//! are generated in a temporary directory, which is removed afterwards.
+
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use gtcorpusutil::AnalysedFilePath;
+use korp_mono_rs::pipeline::buffers::READ_BUFFERS;
+use korp_mono_rs::pipeline::{Pipeline, stages};
+
+const COHORT: &str = "\"<boađán>\"\n\t\"boahtit\" V IV Ind Prs Sg1 <W:0.0> @FMV #2->0\n";
+
+/// An analysed document with `cohorts` cohorts.
+fn document(cohorts: usize) -> String {
+    format!(
+        concat!(
+            "<?xml version='1.0' encoding='utf8'?>\n",
+            "<document xml:lang=\"sme\" id=\"no_id\">\n",
+            "  <header><title>Ođđasat</title><genre code=\"news\"/><year>2018</year>",
+            "<conversion_status type=\"standard\"/><availability/></header>\n",
+            "  <body><dependency><![CDATA[\n{}\n]]></dependency></body></document>\n",
+        ),
+        COHORT.repeat(cohorts)
+    )
+}
+
+/// Read and parse all `files`, and how long it took.
+fn run(files: &[PathBuf]) -> Duration {
+    let pipeline = Pipeline::new()
+        .then(stages::ReadFile)
+        .then(stages::ParseXml::default());
+    let start = Instant::now();
+    let parsed = files
+        .iter()
+        .cloned()
+        .map(AnalysedFilePath::new_unchecked)
+        .filter_map(|path| pipeline.run(path))
+        .count();
+    assert_eq!(parsed, files.len());
+    start.elapsed()
+}
+
+fn main() {
+    let nfiles = std::env::var("KORP_MONO_BENCH_FILES")
+        .ok()
+        .and_then(|n| n.parse::<usize>().ok())
+        .unwrap_or(100_000);
+    let dir = std::env::temp_dir().join(format!("korp-mono-bench-{}", std::process::id()));
+    let analysed = dir.join("corpus-sme").join("analysed").join("news");
+    std::fs::create_dir_all(&analysed).unwrap();
+    let files = (0..nfiles)
+        .map(|i| {
+            let path = analysed.join(format!("{i}.xml"));
+            // files of different sizes, so that the buffers have to grow
+            std::fs::write(&path, document(1 + i % 200)).unwrap();
+            path
+        })
+        .collect::<Vec<_>>();
+
+    // once, so that the files are in the page cache for both runs
+    READ_BUFFERS.set_max(0);
+    run(&files);
+    let fresh = run(&files);
+    READ_BUFFERS.set_max(64);
+    let reused = run(&files);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let per_file = |elapsed: Duration| elapsed / nfiles.max(1) as u32;
+    println!("{nfiles} files");
+    println!("fresh buffers:  {fresh:?} ({:?} per file)", per_file(fresh));
+    println!(
+        "reused buffers: {reused:?} ({:?} per file)",
+        per_file(reused)
+    );
+}
@@ -0,0 +1,10 @@
+#![no_main]
+
+use korp_mono_rs::analysed::file::parse_dependency;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|dep: &str| {
+    let mut skipped = vec![];
+    let _ = parse_dependency(dep, false, &mut skipped);
+    assert!(skipped.is_empty());
+});
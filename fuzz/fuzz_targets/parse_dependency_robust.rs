@@ -0,0 +1,17 @@
+#![no_main]
+
+use korp_mono_rs::analysed::file::parse_dependency;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|dep: &str| {
+    let mut skipped = vec![];
+    let _ = parse_dependency(dep, true, &mut skipped);
+    // skipped regions are in order, don't overlap, and are inside the input
+    let mut last_end = 0;
+    for region in skipped {
+        assert!(last_end <= region.start);
+        assert!(region.start < region.end);
+        assert!(region.end <= dep.len());
+        last_end = region.end;
+    }
+});
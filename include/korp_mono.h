@@ -0,0 +1,46 @@
+/*
+ * The C interface of korp-mono-rs, built with
+ *
+ *     cargo build --release --features ffi
+ *
+ * The functions return 0 when the document was converted, 1 when it could
+ * not be converted, and 2 for invalid arguments or an internal error, like
+ * the exit statuses of korp-mono-rs.
+ */
+
+#ifndef KORP_MONO_H
+#define KORP_MONO_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define KORP_MONO_OK 0
+#define KORP_MONO_FILE_ERRORS 1
+#define KORP_MONO_FATAL 2
+
+/*
+ * Convert the analysed file at `path` (UTF-8), and write it to the
+ * korp_mono/ directory of its corpus.
+ */
+int korp_mono_convert_file(const char *path);
+
+/*
+ * Convert the analysed document in the `input_len` UTF-8 bytes at `input`.
+ * On success, `*output` is the korp_mono xml, nul-terminated, of
+ * `*output_len` bytes, to be freed with korp_mono_free_string().
+ */
+int korp_mono_convert_string(const uint8_t *input, size_t input_len,
+                             char **output, size_t *output_len);
+
+/* Free a string returned by korp_mono_convert_string(). */
+void korp_mono_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
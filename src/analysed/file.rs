@@ -27,137 +27,321 @@
 //! ...  will be parsed by giellacgparser             ...
 //! ]]></dependency></body></document>
 //! ```
+//!
+//! Some corpora have files in an older format, where the analyses are in
+//! `<analysis>` instead of `<dependency>`, either directly in the `<body>`,
+//! or split into one per paragraph, `<body><p><analysis>...</analysis></p>`.
+//! These are read into the same [`Body`], see [`BodyVariant`].
+//!
+//! The header is read by [`crate::giella_xml::header`], strictly or
+//! leniently, see [`parse_document`].
 
 #![allow(dead_code)]
 
-use serde::Deserialize;
+use std::borrow::Cow;
+use std::ops::Range;
+
+use serde::{Deserialize, Deserializer};
+
+use super::markers::{self, Sections};
+use super::repair::{self, Repairs};
+use super::sentence::Sentence;
+use crate::giella_xml::header::lenient_header;
+pub use crate::giella_xml::header::{
+    Author, Authors, Availability, ConversationStatus, Genre, Header, HeaderMode,
+    KNOWN_HEADER_ELEMENTS, License, ParallelText, Person, header_extras,
+};
 
 #[derive(Deserialize)]
 pub struct UnparsedAnalysedDocument {
-    #[serde(rename = "@xml:lang")]
+    // quick-xml gives the attributes to serde by their local name, `lang`
+    #[serde(rename = "@xml:lang", alias = "@lang")]
     pub lang: Option<String>,
+    /// The id of the document, `no_id` when it has none
+    #[serde(rename = "@id")]
+    pub id: Option<String>,
     pub header: Header,
     pub body: Body,
 }
 
+/// [`UnparsedAnalysedDocument`], with the header that is read separately,
+/// with [`HeaderMode::Lenient`].
 #[derive(Deserialize)]
-pub struct Header {
-    pub title: Option<String>,
-    pub genre: Option<Genre>,
-    #[serde(rename = "author")]
-    pub authors: Option<Vec<Person>>,
-    pub year: Option<String>,
-    pub conversion_status: ConversationStatus,
-    pub availability: Availability,
-    #[serde(rename = "origFileName")]
-    pub orig_file_name: Option<String>,
-    pub translated_from: Option<String>,
-    pub parallel_text: Option<Vec<ParallelText>>,
-}
-
-/// `<genre code="facta"/>`
-#[derive(Deserialize)]
-pub struct Genre {
-    #[serde(rename = "@code")]
-    pub code: String,
+struct LenientDocument {
+    #[serde(rename = "@xml:lang", alias = "@lang")]
+    lang: Option<String>,
+    #[serde(rename = "@id")]
+    id: Option<String>,
+    body: Body,
 }
 
-/// `<author><person ... /></author>`
-#[derive(Deserialize)]
-pub struct Author {
-    pub person: Person,
+/// Deserialize the analysed document `xml`, with its header read as `mode`
+/// says. The `extra` elements of the header are not filled in.
+pub fn parse_document(
+    xml: &str,
+    mode: HeaderMode,
+) -> Result<UnparsedAnalysedDocument, quick_xml::DeError> {
+    match mode {
+        HeaderMode::Strict => quick_xml::de::from_str(xml),
+        HeaderMode::Lenient => {
+            let document = quick_xml::de::from_str::<LenientDocument>(xml)?;
+            Ok(UnparsedAnalysedDocument {
+                lang: document.lang,
+                id: document.id,
+                header: lenient_header(xml),
+                body: document.body,
+            })
+        }
+    }
 }
 
-/// `<person>`. Has many optional attributes.
-#[derive(Deserialize)]
-pub struct Person {
-    #[serde(rename = "@firstname")]
-    pub firstname: Option<String>,
-    #[serde(rename = "@lastname")]
-    pub lastname: Option<String>,
-    #[serde(rename = "@sex")]
-    pub sex: Option<String>,
-    #[serde(rename = "@born")]
-    pub born: Option<String>,
-    #[serde(rename = "@nationality")]
-    pub nationality: Option<String>,
+/// The analyses of the document. Whichever format the file has, they end up
+/// in `dependency`, as one blob.
+pub struct Body {
+    pub dependency: String,
+    /// Where in the file the analyses were found
+    pub variant: BodyVariant,
 }
 
-/// `<conversion_status type="standard"/>`
-#[derive(Deserialize)]
-pub struct ConversationStatus {
-    #[serde(rename = "@type")]
-    pub r#type: Option<String>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyVariant {
+    /// `<body><dependency>`, the current format
+    Dependency,
+    /// `<body><analysis>`
+    Analysis,
+    /// `<body><p><analysis>`, one per paragraph
+    Paragraphs,
 }
 
-/// `<availability><license type="standard"/></availability>`
+/// The `<body>` element, as it is in the file.
 #[derive(Deserialize)]
-pub struct Availability {
-    pub license: Option<License>,
+struct RawBody {
+    dependency: Option<String>,
+    #[serde(default)]
+    analysis: Vec<String>,
+    #[serde(default)]
+    p: Vec<RawParagraph>,
 }
 
-/// `<availability><license type="standard"/></availability>`
+/// `<p>` of the older format, with the analyses of the paragraph.
 #[derive(Deserialize)]
-pub struct License {
-    #[serde(rename = "@type")]
-    r#type: Option<String>,
+struct RawParagraph {
+    dependency: Option<String>,
+    #[serde(default)]
+    analysis: Vec<String>,
 }
 
-/// <parallel_text xml:lang="nob" location="sami_statistihkka_2018.pdf"/>
-#[derive(Deserialize)]
-pub struct ParallelText {
-    #[serde(rename = "@xml:lang")]
-    pub lang: Option<String>,
-    #[serde(rename = "@location")]
-    pub location: Option<String>,
+/// Join the analysis blobs `blobs` into one, each on lines of its own.
+fn join_blobs<'a>(blobs: impl Iterator<Item = &'a str>) -> String {
+    let mut joined = String::new();
+    for blob in blobs.map(|blob| blob.trim_matches('\n')) {
+        if blob.trim().is_empty() {
+            continue;
+        }
+        joined.push_str(blob);
+        joined.push_str("\n\n");
+    }
+    joined
 }
 
-#[derive(Deserialize)]
-pub struct Body {
-    pub dependency: String,
+impl<'de> Deserialize<'de> for Body {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let raw = RawBody::deserialize(deserializer)?;
+        if let Some(dependency) = raw.dependency {
+            return Ok(Self {
+                dependency,
+                variant: BodyVariant::Dependency,
+            });
+        }
+        if !raw.analysis.is_empty() {
+            return Ok(Self {
+                dependency: join_blobs(raw.analysis.iter().map(String::as_str)),
+                variant: BodyVariant::Analysis,
+            });
+        }
+        let blobs = raw
+            .p
+            .iter()
+            .flat_map(|p| {
+                let dependency = p.dependency.as_deref();
+                dependency
+                    .into_iter()
+                    .chain(p.analysis.iter().map(String::as_str))
+            })
+            .collect::<Vec<_>>();
+        if !blobs.is_empty() {
+            return Ok(Self {
+                dependency: join_blobs(blobs.into_iter()),
+                variant: BodyVariant::Paragraphs,
+            });
+        }
+        Err(serde::de::Error::custom(
+            "no <dependency> or <analysis> in <body>",
+        ))
+    }
 }
 
 pub struct ParsedAnalysedDocument {
     pub lang: Option<String>,
+    pub id: Option<String>,
     pub header: Header,
-    pub body: ParsedBody,
+    /// The parsed sentences of the `<dependency>` blob, `None` if it could
+    /// not be parsed
+    pub sentences: Option<Vec<Sentence>>,
+    /// Byte ranges of the `<dependency>` blob that could not be parsed, and
+    /// were skipped. Only ever non-empty when parsing in robust mode.
+    pub skipped: Vec<Range<usize>>,
+    /// What was repaired in the `<dependency>` blob before it was parsed,
+    /// see [`repair::repair`]
+    pub repairs: Repairs,
+    /// The sections of the `<dependency>` blob in another language or
+    /// genre, see [`markers`]
+    pub sections: Sections,
 }
 
-#[ouroboros::self_referencing]
-pub struct ParsedBody {
-    pub dependency: String,
-    /// The parsed analyses in dependency
-    #[serde(skip)]
-    #[not_covariant]
-    #[borrows(dependency)]
-    pub sentences: Option<Vec<giellacgparser::Sentence<'this>>>,
-}
+/// Parse the `<dependency>` blob `dep` into sentences.
+///
+/// Normally, parsing stops at the first cohort that can't be parsed, and
+/// everything after it is lost. With `robust`, parsing is instead resumed
+/// at the next cohort (the next line starting with `"<`), and the byte
+/// ranges of `dep` that were skipped are added to `skipped`.
+pub fn parse_dependency<'a>(
+    dep: &'a str,
+    robust: bool,
+    skipped: &mut Vec<Range<usize>>,
+) -> Option<Vec<giellacgparser::Sentence<'a>>> {
+    if !robust {
+        // TODO should really check that _rem is empty, to be sure
+        // that the entire <dependency> has been parsed
+        let (_rem, sents) = giellacgparser::parse_sentences(dep).ok()?;
+        return Some(sents.sentences);
+    }
 
-// FIXME: ParsedBody contains Rc, which is not Send. But only 1 thread
-// should be looking at these things at a time, so it should be ok...maybe,
-// right?
-unsafe impl Send for ParsedBody {}
+    let offset_of = |s: &str| s.as_ptr() as usize - dep.as_ptr() as usize;
+    let mut sentences = vec![];
+    let mut input = dep;
+    while !input.trim().is_empty() {
+        let rem = match giellacgparser::parse_sentences(input) {
+            Ok((rem, sents)) => {
+                sentences.extend(sents.sentences);
+                rem
+            }
+            Err(_) => input,
+        };
+        if rem.trim().is_empty() {
+            break;
+        }
+        // `rem` starts at a cohort that can't be parsed, skip to the next one
+        let start = offset_of(rem);
+        input = match rem.find("\n\"<") {
+            Some(i) => &rem[i + 1..],
+            None => "",
+        };
+        let end = match input {
+            "" => dep.len(),
+            input => offset_of(input),
+        };
+        skipped.push(start..end);
+    }
+    Some(sentences)
+}
 
 impl TryFrom<UnparsedAnalysedDocument> for ParsedAnalysedDocument {
     type Error = anyhow::Error;
 
     fn try_from(value: UnparsedAnalysedDocument) -> Result<Self, Self::Error> {
-        let parsed_body = ParsedBodyBuilder {
-            dependency: value.body.dependency,
-            sentences_builder: |dep| {
-                let parse_result = giellacgparser::parse_sentences(&dep);
-                // TODO should really check that _rem is empty, to be sure
-                // that the entire <dependency> has been parsed
-                let (_rem, sents) = parse_result.ok()?;
-                Some(sents.sentences)
-            },
-        }
-        .build();
+        Self::parse(value, false)
+    }
+}
+
+impl ParsedAnalysedDocument {
+    /// Parse the analyses of `value`, after repairing them. See
+    /// [`parse_dependency`] for what `robust` does.
+    pub fn parse(value: UnparsedAnalysedDocument, robust: bool) -> anyhow::Result<Self> {
+        let (repaired, repairs) = match repair::repair(&value.body.dependency) {
+            (Cow::Owned(repaired), repairs) => (Some(repaired), repairs),
+            (Cow::Borrowed(_), repairs) => (None, repairs),
+        };
+        let dependency = repaired.unwrap_or(value.body.dependency);
+        let (stripped, sections) = match markers::extract(&dependency) {
+            (Cow::Owned(stripped), sections) => (Some(stripped), sections),
+            (Cow::Borrowed(_), sections) => (None, sections),
+        };
+        let dependency = stripped.unwrap_or(dependency);
+        repair::validate(&dependency)?;
+        let mut skipped = vec![];
+        let sentences = parse_dependency(&dependency, robust, &mut skipped)
+            .map(|sentences| Sentence::all(&sentences, &dependency));
 
         Ok(ParsedAnalysedDocument {
             lang: value.lang,
+            id: value.id,
             header: value.header,
-            body: parsed_body,
+            sentences,
+            skipped,
+            repairs,
+            sections,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Body, BodyVariant, HeaderMode, UnparsedAnalysedDocument, parse_document};
+
+    fn body(xml: &str) -> Body {
+        quick_xml::de::from_str(xml).unwrap()
+    }
+
+    #[test]
+    fn body_variants() {
+        let body1 = body("<body><dependency><![CDATA[\n\"<a>\"\n]]></dependency></body>");
+        assert_eq!(body1.variant, BodyVariant::Dependency);
+        assert_eq!(body1.dependency.trim(), "\"<a>\"");
+
+        let body2 = body("<body><analysis><![CDATA[\n\"<a>\"\n]]></analysis></body>");
+        assert_eq!(body2.variant, BodyVariant::Analysis);
+        assert_eq!(body2.dependency, "\"<a>\"\n\n");
+
+        let body3 = body(concat!(
+            "<body>",
+            "<p><analysis><![CDATA[\"<a>\"\n]]></analysis></p>",
+            "<p><analysis></analysis></p>",
+            "<p><analysis><![CDATA[\"<b>\"]]></analysis></p>",
+            "</body>",
+        ));
+        assert_eq!(body3.variant, BodyVariant::Paragraphs);
+        assert_eq!(body3.dependency, "\"<a>\"\n\n\"<b>\"\n\n");
+
+        assert!(quick_xml::de::from_str::<Body>("<body><p>text</p></body>").is_err());
+        assert!(quick_xml::de::from_str::<Body>("<body/>").is_err());
+    }
+
+    #[test]
+    fn lenient_document() {
+        let xml = concat!(
+            r#"<document xml:lang="sme" id="a"><header><title>A</title><genre/></header>"#,
+            r#"<body><dependency><![CDATA["<a>"]]></dependency></body></document>"#,
+        );
+        assert!(parse_document(xml, HeaderMode::Strict).is_err());
+        let document = parse_document(xml, HeaderMode::Lenient).unwrap();
+        assert_eq!(document.lang.as_deref(), Some("sme"));
+        assert_eq!(document.header.title.as_deref(), Some("A"));
+        assert!(document.header.genre.is_none());
+        assert_eq!(document.body.dependency, "\"<a>\"");
+    }
+
+    #[test]
+    fn document_lang() {
+        let xml = concat!(
+            r#"<document xml:lang="sme" id="a"><header><title>A</title>"#,
+            r#"<conversion_status type="standard"/><availability/></header>"#,
+            r#"<body><dependency><![CDATA["<a>"]]></dependency></body></document>"#,
+        );
+        let document: UnparsedAnalysedDocument = quick_xml::de::from_str(xml).unwrap();
+        assert_eq!(document.lang.as_deref(), Some("sme"));
+        assert_eq!(document.id.as_deref(), Some("a"));
+        let document = parse_document(xml, HeaderMode::Strict).unwrap();
+        assert_eq!(document.lang.as_deref(), Some("sme"));
+    }
+}
@@ -0,0 +1,179 @@
+//! Sections of the `<dependency>` blob that are in another language, or of
+//! another genre, than the document, such as quotations.
+//!
+//! A section is marked by lines of their own in the blob, between the
+//! cohorts:
+//!
+//! ```not_rust
+//! <section xml:lang="nob" genre="bible">
+//! "<Herren>"
+//!     "herre" N Msc Sg Def <W:0.0> @SUBJ> #1->2
+//! ...
+//! </section>
+//! ```
+//!
+//! Both attributes are optional, and a section that leaves one out keeps
+//! that of the section it is in. Sections that are not closed end with the
+//! blob, and a `</section>` without a section is left out.
+//!
+//! [`extract`] removes the marker lines, which the parser doesn't know, and
+//! returns the byte ranges of the sections in what is left. The sentences
+//! that start in a section get its language and genre, see
+//! [`Sections::at`].
+
+use std::borrow::Cow;
+use std::ops::Range;
+
+use quick_xml::events::Event;
+
+const OPEN: &str = "<section";
+const CLOSE: &str = "</section>";
+
+/// A section of the blob, and what it overrides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// The byte range of the section, in the blob without the markers
+    pub range: Range<usize>,
+    pub lang: Option<String>,
+    /// The `genre` code, as in `<genre code="...">` of the header
+    pub genre: Option<String>,
+}
+
+/// All sections of a blob, in the order they start.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Sections(pub Vec<Section>);
+
+impl Sections {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// The innermost section at the byte `offset` of the blob.
+    pub fn at(&self, offset: usize) -> Option<&Section> {
+        self.0
+            .iter()
+            .filter(|section| section.range.contains(&offset))
+            .max_by_key(|section| section.range.start)
+    }
+}
+
+/// The `xml:lang` and `genre` of the opening marker `line`.
+fn open_attrs(line: &str) -> Option<(Option<String>, Option<String>)> {
+    let mut reader = quick_xml::Reader::from_str(line);
+    let (Ok(Event::Start(e)) | Ok(Event::Empty(e))) = reader.read_event() else {
+        return None;
+    };
+    if e.name().as_ref() != b"section" {
+        return None;
+    }
+    let attr = |name: &[u8]| {
+        e.attributes()
+            .flatten()
+            .find(|attr| attr.key.as_ref() == name)
+            .and_then(|attr| attr.unescape_value().ok())
+            .map(Cow::into_owned)
+    };
+    Some((attr(b"xml:lang"), attr(b"genre")))
+}
+
+/// Remove the section markers from `dep`, and return the sections. The
+/// blob is only copied if it has markers.
+pub fn extract(dep: &str) -> (Cow<'_, str>, Sections) {
+    if !dep.contains(OPEN) && !dep.contains(CLOSE) {
+        return (Cow::Borrowed(dep), Sections::default());
+    }
+    let mut stripped = String::with_capacity(dep.len());
+    let mut sections: Vec<Section> = vec![];
+    // the indices in `sections` of the open sections
+    let mut open: Vec<usize> = vec![];
+    let mut markers = 0;
+    for line in dep.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if trimmed == CLOSE {
+            markers += 1;
+            if let Some(i) = open.pop() {
+                sections[i].range.end = stripped.len();
+            }
+            continue;
+        }
+        let attrs = match trimmed.starts_with(OPEN) {
+            true => open_attrs(trimmed),
+            false => None,
+        };
+        if let Some((lang, genre)) = attrs {
+            markers += 1;
+            let outer = open.last().map(|&i| &sections[i]);
+            let section = Section {
+                range: stripped.len()..dep.len(),
+                lang: lang.or_else(|| outer.and_then(|outer| outer.lang.clone())),
+                genre: genre.or_else(|| outer.and_then(|outer| outer.genre.clone())),
+            };
+            open.push(sections.len());
+            sections.push(section);
+            continue;
+        }
+        stripped.push_str(line);
+    }
+    if markers == 0 {
+        return (Cow::Borrowed(dep), Sections::default());
+    }
+    for i in open {
+        sections[i].range.end = stripped.len();
+    }
+    sections.retain(|section| !section.range.is_empty());
+    (Cow::Owned(stripped), Sections(sections))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::extract;
+
+    const DEP: &str = concat!(
+        "\"<Son>\"\n\t\"son\" Pron <W:0.0> @SUBJ> #1->2\n",
+        "\"<celkkii>\"\n\t\"cealkit\" V <W:0.0> @FMV #2->0\n",
+        "\n",
+        "<section xml:lang=\"nob\" genre=\"bible\">\n",
+        "\"<Herren>\"\n\t\"herre\" N <W:0.0> @SUBJ> #1->0\n",
+        "\n",
+        "<section genre=\"news\">\n",
+        "\"<er>\"\n\t\"være\" V <W:0.0> @FMV #1->0\n",
+        "</section>\n",
+        "\n",
+        "</section>\n",
+        "\"<Nu>\"\n\t\"nu\" Adv <W:0.0> @ADVL #1->0\n",
+    );
+
+    #[test]
+    fn sections() {
+        let (stripped, sections) = extract(DEP);
+        assert!(!stripped.contains("section"));
+        assert_eq!(sections.0.len(), 2);
+
+        let at = |word: &str| {
+            let section = sections.at(stripped.find(word).unwrap())?;
+            Some((section.lang.as_deref(), section.genre.as_deref()))
+        };
+        assert_eq!(at("\"<Son>\""), None);
+        assert_eq!(at("\"<Herren>\""), Some((Some("nob"), Some("bible"))));
+        assert_eq!(at("\"<er>\""), Some((Some("nob"), Some("news"))));
+        assert_eq!(at("\"<Nu>\""), None);
+    }
+
+    #[test]
+    fn unbalanced() {
+        let (stripped, sections) =
+            extract("</section>\n\"<a>\"\n<section xml:lang=\"sme\">\n\"<b>\"\n");
+        assert_eq!(stripped, "\"<a>\"\n\"<b>\"\n");
+        assert_eq!(sections.0.len(), 1);
+        assert_eq!(sections.0[0].range, 6..stripped.len());
+        assert_eq!(sections.0[0].lang.as_deref(), Some("sme"));
+    }
+
+    #[test]
+    fn untouched() {
+        let dep = "\"<section>\"\n\t\"section\" N <W:0.0>\n";
+        let (stripped, sections) = extract(dep);
+        assert_eq!(stripped, dep);
+        assert!(sections.is_empty());
+    }
+}
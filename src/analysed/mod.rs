@@ -1 +1,4 @@
 pub mod file;
+pub mod markers;
+pub mod repair;
+pub mod sentence;
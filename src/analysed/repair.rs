@@ -0,0 +1,204 @@
+//! Repairing the `<dependency>` blob before its analyses are parsed.
+//!
+//! The blob is in a CDATA section, where entities are not decoded, so when
+//! the text was escaped before it was wrapped in CDATA, `&amp;`, `&quot;`
+//! and the like end up in the word forms and lemmas. Some files have the
+//! CDATA wrapped twice, or the `]]>` of a token split over two CDATA
+//! sections (`]]]]><![CDATA[>`) escaped as well, so that the markers
+//! themselves are in the blob. And some have control characters, which
+//! can't be written to the korp_mono XML.
+//!
+//! [`repair`] undoes these, and [`validate`] checks that what is left looks
+//! like analyses, before it is given to `giellacgparser`.
+
+use std::borrow::Cow;
+
+const CDATA_START: &str = "<![CDATA[";
+const CDATA_END: &str = "]]>";
+/// A `]]>` split over two CDATA sections
+const SPLIT_CDATA_END: &str = "]]]]><![CDATA[>";
+
+/// What was repaired in a blob.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Repairs {
+    /// Entities that were decoded
+    pub entities: usize,
+    /// CDATA markers that were removed, or joined
+    pub cdata_markers: usize,
+    /// Control characters that were removed
+    pub control_chars: usize,
+}
+
+impl Repairs {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The character of the entity at the start of `s` (after the `&`), and the
+/// length of the entity, without the `&`.
+fn entity(s: &str) -> Option<(char, usize)> {
+    // the longest entity is `&#x10FFFF;`
+    let (end, _) = s.char_indices().take(10).find(|(_, c)| *c == ';')?;
+    let name = &s[..end];
+    let c = match name {
+        "amp" => '&',
+        "lt" => '<',
+        "gt" => '>',
+        "quot" => '"',
+        "apos" => '\'',
+        _ => {
+            let code = match name.strip_prefix("#x").or_else(|| name.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => name.strip_prefix('#')?.parse::<u32>().ok()?,
+            };
+            char::from_u32(code)?
+        }
+    };
+    Some((c, end + 1))
+}
+
+/// Decode the entities in `line`, once, so `&amp;lt;` becomes `&lt;`.
+/// Anything that is not a known entity is left as it is.
+fn decode_entities<'a>(line: &'a str, repairs: &mut Repairs) -> Cow<'a, str> {
+    if !line.contains('&') {
+        return Cow::Borrowed(line);
+    }
+    let mut decoded = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(i) = rest.find('&') {
+        decoded.push_str(&rest[..i]);
+        rest = &rest[i + 1..];
+        match entity(rest) {
+            Some((c, len)) => {
+                decoded.push(c);
+                rest = &rest[len..];
+                repairs.entities += 1;
+            }
+            None => decoded.push('&'),
+        }
+    }
+    decoded.push_str(rest);
+    Cow::Owned(decoded)
+}
+
+/// Repair the blob `dep`, see the [module docs](self). The blob is only
+/// copied if something was repaired.
+pub fn repair(dep: &str) -> (Cow<'_, str>, Repairs) {
+    let mut repairs = Repairs::default();
+    let needs_repair = dep.contains('&')
+        || dep.contains(CDATA_START)
+        || dep.contains(CDATA_END)
+        || dep
+            .chars()
+            .any(|c| c.is_control() && c != '\n' && c != '\t');
+    if !needs_repair {
+        return (Cow::Borrowed(dep), repairs);
+    }
+    let mut repaired = String::with_capacity(dep.len());
+    for line in dep.split_inclusive('\n') {
+        let (line, newline) = match line.strip_suffix('\n') {
+            Some(line) => (line, "\n"),
+            None => (line, ""),
+        };
+        let mut line = Cow::Borrowed(line);
+        if line.contains(SPLIT_CDATA_END) {
+            repairs.cdata_markers += line.matches(SPLIT_CDATA_END).count();
+            line = Cow::Owned(line.replace(SPLIT_CDATA_END, CDATA_END));
+        }
+        // the markers of a CDATA section in the CDATA section, which are on
+        // the lines of their own, or at the start and end of the blob
+        if let Some(rest) = line.trim_start().strip_prefix(CDATA_START) {
+            repairs.cdata_markers += 1;
+            line = Cow::Owned(rest.to_string());
+        }
+        if let Some(rest) = line.trim_end().strip_suffix(CDATA_END) {
+            repairs.cdata_markers += 1;
+            line = Cow::Owned(rest.to_string());
+        }
+        if line.chars().any(|c| c.is_control() && c != '\t') {
+            let before = line.chars().count();
+            let kept = line
+                .chars()
+                .filter(|c| !c.is_control() || *c == '\t')
+                .collect::<String>();
+            repairs.control_chars += before - kept.chars().count();
+            line = Cow::Owned(kept);
+        }
+        repaired.push_str(&decode_entities(&line, &mut repairs));
+        repaired.push_str(newline);
+    }
+    if repairs.is_empty() {
+        return (Cow::Borrowed(dep), repairs);
+    }
+    (Cow::Owned(repaired), repairs)
+}
+
+/// Check that the (repaired) blob `dep` looks like analyses: that it is
+/// empty, or starts with a cohort (`"<...>"`), and has no CDATA markers
+/// left.
+pub fn validate(dep: &str) -> anyhow::Result<()> {
+    let Some(first) = dep
+        .lines()
+        .map(str::trim_end)
+        .find(|line| !line.trim().is_empty())
+    else {
+        return Ok(());
+    };
+    if !first.starts_with("\"<") {
+        let first = first.chars().take(40).collect::<String>();
+        anyhow::bail!("the analyses don't start with a cohort, but with '{first}'");
+    }
+    if let Some(line) = dep.lines().position(|line| line.contains(CDATA_START)) {
+        anyhow::bail!(
+            "a CDATA section starts on line {} of the analyses",
+            line + 1
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Repairs, repair, validate};
+
+    #[test]
+    fn entities() {
+        let (repaired, repairs) =
+            repair("\"<&quot;>\"\n\t\"&amp;amp;\" CLB &#8230; &#x41; &nope; & x\n");
+        assert_eq!(repaired, "\"<\">\"\n\t\"&amp;\" CLB … A &nope; & x\n");
+        assert_eq!(repairs.entities, 4);
+    }
+
+    #[test]
+    fn cdata() {
+        let (repaired, repairs) = repair("<![CDATA[\n\"<]]]]><![CDATA[>>\"\n\t\"a\" N\n]]>\n");
+        assert_eq!(repaired, "\n\"<]]>>\"\n\t\"a\" N\n\n");
+        assert_eq!(repairs.cdata_markers, 3);
+        assert!(validate(&repaired).is_ok());
+    }
+
+    #[test]
+    fn control_chars() {
+        let (repaired, repairs) = repair("\"<a\u{1}>\"\r\n\t\"a\" N\n");
+        assert_eq!(repaired, "\"<a>\"\n\t\"a\" N\n");
+        assert_eq!(repairs.control_chars, 2);
+    }
+
+    #[test]
+    fn untouched() {
+        let dep = "\"<a>\"\n\t\"a\" N <W:0.0> #1->0\n";
+        let (repaired, repairs) = repair(dep);
+        assert_eq!(repaired, dep);
+        assert_eq!(repairs, Repairs::default());
+    }
+
+    #[test]
+    fn validation() {
+        assert!(validate("").is_ok());
+        assert!(validate("\n\n").is_ok());
+        assert!(validate("\n\"<a>\"\n\t\"a\" N\n").is_ok());
+        assert!(validate("Mun boađán.\n").is_err());
+        assert!(validate("\"<a>\"\n<![CDATA[\n").is_err());
+    }
+}
@@ -0,0 +1,189 @@
+//! An owned copy of the sentences parsed by `giellacgparser`, with what the
+//! conversion needs of them.
+//!
+//! The sentences of `giellacgparser` borrow from the `<dependency>` blob
+//! they were parsed from, and share their readings in `Rc`s, so they can't
+//! be kept next to the blob, or be moved to another thread. The copy is
+//! taken right after parsing, see [`Sentence::new`], and the blob can then
+//! be dropped. The documents can then be moved between threads and kept
+//! around, like any other value.
+//!
+//! The lemmas, tags and functional labels repeat a lot, so they are shared
+//! by all sentences of a document, with an [`Interner`].
+
+use std::sync::Arc;
+
+use giellacgparser::tag::Pos;
+
+use crate::intern::Interner;
+
+/// A tag of an analysis, and the classes of tags it is in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tag {
+    pub name: Arc<str>,
+    /// CG-internal tags, like `<mv>`
+    pub angle_bracketed: bool,
+    /// Semantic tags, like `Sem/Hum`
+    pub sem: bool,
+    /// Orthographic error tags, `Err/Orth...`
+    pub orth_error: bool,
+}
+
+impl Tag {
+    fn new(tag: &giellacgparser::tag::Tag, strings: &mut Interner) -> Self {
+        Self {
+            name: strings.intern(&tag.to_string()),
+            angle_bracketed: tag.is_angle_bracketed(),
+            sem: tag.is_sem(),
+            orth_error: tag.is_err_starts_with("Orth"),
+        }
+    }
+}
+
+/// The analysis of the first reading of a cohort that has one.
+#[derive(Clone)]
+pub struct Analysis {
+    pub lemma: Arc<str>,
+    /// The functional label, as it is in the analyses
+    pub func: Option<Arc<str>>,
+    /// The ids of the token and of its head
+    pub deprel: Option<(usize, usize)>,
+    pub pos: Pos,
+    pub tags: Vec<Tag>,
+}
+
+#[derive(Clone)]
+pub struct Cohort {
+    pub word_form: String,
+    pub analysis: Option<Analysis>,
+    /// The lemmas of all readings that have an analysis, in order
+    pub lemmas: Vec<Arc<str>>,
+}
+
+#[derive(Clone)]
+pub enum SentencePart {
+    Cohort(Cohort),
+    /// The whitespace between two cohorts
+    CohortSeparator(String),
+}
+
+#[derive(Clone)]
+pub struct Sentence {
+    /// The byte offset of the first cohort in the blob, if it has cohorts
+    pub start: Option<usize>,
+    pub parts: Vec<SentencePart>,
+}
+
+impl SentencePart {
+    fn new(part: &giellacgparser::SentencePart, strings: &mut Interner) -> Self {
+        let cohort = match part {
+            giellacgparser::SentencePart::Cohort(cohort) => cohort,
+            giellacgparser::SentencePart::CohortSeparator(sep) => {
+                return SentencePart::CohortSeparator(sep.0.to_string());
+            }
+        };
+        let analysis = cohort.first_reading_with_analysis().and_then(|reading| {
+            let lemma = strings.intern(&giellacgparser::reading_lemma(reading.clone()).to_string());
+            let reading = reading.borrow();
+            let analysis = reading.analysis.as_ref()?;
+            Some(Analysis {
+                lemma,
+                func: analysis.func.map(|func| strings.intern(func)),
+                deprel: analysis.deprel,
+                pos: analysis.pos,
+                tags: analysis
+                    .all_tags()
+                    .map(|tag| Tag::new(tag, strings))
+                    .collect(),
+            })
+        });
+        let lemmas = cohort
+            .readings
+            .iter()
+            .filter(|reading| reading.borrow().analysis.is_some())
+            .map(|reading| {
+                strings.intern(&giellacgparser::reading_lemma(reading.clone()).to_string())
+            })
+            .collect();
+        SentencePart::Cohort(Cohort {
+            word_form: cohort.word_form.to_string(),
+            analysis,
+            lemmas,
+        })
+    }
+}
+
+impl Sentence {
+    /// A copy of `sentence`, which was parsed from `blob`, with the strings
+    /// shared through `strings`.
+    pub fn new(sentence: &giellacgparser::Sentence, blob: &str, strings: &mut Interner) -> Self {
+        let start = sentence.parts.iter().find_map(|part| match part {
+            giellacgparser::SentencePart::Cohort(cohort) => {
+                (cohort.word_form.as_ptr() as usize).checked_sub(blob.as_ptr() as usize)
+            }
+            giellacgparser::SentencePart::CohortSeparator(_) => None,
+        });
+        Self {
+            start,
+            parts: sentence
+                .parts
+                .iter()
+                .map(|part| SentencePart::new(part, strings))
+                .collect(),
+        }
+    }
+
+    /// Copies of all `sentences`, which were parsed from `blob`. The strings
+    /// are shared by all of them, but not with the sentences of other blobs.
+    pub fn all(sentences: &[giellacgparser::Sentence], blob: &str) -> Vec<Self> {
+        let mut strings = Interner::default();
+        sentences
+            .iter()
+            .map(|sentence| Self::new(sentence, blob, &mut strings))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sentence, SentencePart};
+    use std::sync::Arc;
+
+    #[test]
+    fn copy() {
+        let blob = concat!(
+            "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom <W:0.0> @SUBJ> #1->2\n",
+            "\"<boađán>\"\n\t\"boahtit\" V IV Ind Prs Sg1 <W:0.0> @FMV #2->0\n",
+            "\t\"boađđit\" V TV Ind Prs Sg1 <W:0.0> @FMV #2->0\n",
+        );
+        let (_, parsed) = giellacgparser::parse_sentences(blob).unwrap();
+        let sentences = Sentence::all(&parsed.sentences, blob);
+        assert_eq!(sentences.len(), 1);
+        assert_eq!(sentences[0].start, Some(2));
+        let cohorts = sentences[0]
+            .parts
+            .iter()
+            .filter_map(|part| match part {
+                SentencePart::Cohort(cohort) => Some(cohort),
+                SentencePart::CohortSeparator(_) => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cohorts[0].word_form, "Mun");
+        let analysis = cohorts[0].analysis.as_ref().unwrap();
+        assert_eq!(&*analysis.lemma, "mun");
+        assert_eq!(analysis.deprel, Some((1, 2)));
+        assert!(analysis.tags.iter().any(|tag| &*tag.name == "Pers"));
+        assert!(analysis.tags.iter().any(|tag| tag.angle_bracketed));
+        assert_eq!(
+            cohorts[1].lemmas,
+            [Arc::from("boahtit"), Arc::from("boađđit")]
+        );
+        // the same tags share one string
+        let tag = |cohort: usize, name: &str| {
+            let analysis = cohorts[cohort].analysis.as_ref().unwrap();
+            let tag = analysis.tags.iter().find(|tag| &*tag.name == name);
+            Arc::clone(&tag.unwrap().name)
+        };
+        assert!(Arc::ptr_eq(&tag(0, "Sg1"), &tag(1, "Sg1")));
+    }
+}
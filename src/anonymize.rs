@@ -0,0 +1,99 @@
+//! Masking of personal names in the output.
+//!
+//! Some corpora (fiction, interviews) can't be published in Korp with the
+//! names of real people in them. A token is considered a personal name if
+//! either its word form or lemma is in a user-supplied list of names, or if
+//! it is a proper noun with a semantic tag for humans or person names. The
+//! word form and lemma of such tokens are replaced with [`PLACEHOLDER`],
+//! while pos, msd, and the dependency information is kept as is.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::analysed::sentence::Tag;
+
+/// What the word form and lemma of a masked token is replaced with.
+pub const PLACEHOLDER: &str = "NAME";
+
+/// The semantic tags, in addition to all the `Sem/Hum` ones, that mark a
+/// proper noun as the name of a person.
+const PERSON_NAME_SEM_TAGS: [&str; 3] = ["Sem/Sur", "Sem/Fem", "Sem/Mal"];
+
+#[derive(Debug, Default, Clone)]
+pub struct Anonymizer {
+    names: HashSet<String>,
+}
+
+impl Anonymizer {
+    /// Create an anonymizer that masks the names in `names`, in addition to
+    /// the proper nouns that are tagged as person names.
+    pub fn new<I, S>(names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            names: names.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Read the list of names from the file at `path`. The file has one name
+    /// per line. Empty lines, and lines starting with `#`, are ignored.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read name list '{}'", path.display()))?;
+        Ok(Self::from_name_list(&contents))
+    }
+
+    fn from_name_list(contents: &str) -> Self {
+        Self::new(
+            contents
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#')),
+        )
+    }
+
+    /// Is the word form or lemma in the list of names?
+    pub fn is_listed(&self, word_form: &str, lemma: &str) -> bool {
+        self.names.contains(word_form) || self.names.contains(lemma)
+    }
+
+    /// Should the token with word form `word_form`, lemma `lemma`, and
+    /// the tags `tags` be masked?
+    pub fn is_name(&self, word_form: &str, lemma: &str, tags: &[Tag]) -> bool {
+        if self.is_listed(word_form, lemma) {
+            return true;
+        }
+        let mut is_prop = false;
+        let mut is_person = false;
+        for tag in tags.iter().map(|tag| &*tag.name) {
+            if tag == "Prop" {
+                is_prop = true;
+            } else if tag.starts_with("Sem/Hum") || PERSON_NAME_SEM_TAGS.contains(&tag) {
+                is_person = true;
+            }
+        }
+        is_prop && is_person
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Anonymizer;
+
+    #[test]
+    fn name_list() {
+        let anonymizer = Anonymizer::from_name_list("# names\nÁilu\n\n  Máret \n");
+        assert!(anonymizer.is_listed("Áilu", "Áilu"));
+        assert!(anonymizer.is_listed("Máret", "Máret"));
+        assert!(anonymizer.is_listed("Áilu", "Áillu"));
+        assert!(anonymizer.is_listed("Ááilu", "Áilu"));
+        assert!(!anonymizer.is_listed("# names", "# names"));
+        assert!(!anonymizer.is_listed("", ""));
+        assert!(!anonymizer.is_listed("áilu", "áilu"));
+    }
+}
@@ -0,0 +1,377 @@
+//! Writing the korp_mono files into one archive, instead of one file each,
+//! and reading the analysed files from one.
+//!
+//! Writing a million small files is slow on network file systems. With
+//! `--archive-output corpus.tar.zst`, all converted documents are streamed
+//! into a single archive instead, at the paths they would have been written
+//! to, relative to the output directory, or to the directory of the corpus
+//! directories:
+//!
+//! ```not_rust
+//! corpus-sme/korp_mono/news/avvir/a.xml
+//! corpus-sme/korp_mono/news/avvir/b.xml
+//! ```
+//!
+//! The format is taken from the extension, see [`ArchiveFormat`]. The
+//! documents are written as they are converted, so the order of the
+//! entries differs between runs.
+//!
+//! Symmetrically, with `--input-archive analysed.tar.zst`, the analysed
+//! files are read from the entries of an archive, see [`read_entries`], and
+//! handed to the pipeline as they are read, without unpacking them. The
+//! paths of the entries are taken as the paths of the analysed files, so an
+//! archive of `corpus-sme/analysed/...` is converted to
+//! `corpus-sme/korp_mono/...`.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::encoding;
+use crate::korp_mono::path::corpus_dir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// `.tar`
+    Tar,
+    /// `.tar.gz` or `.tgz`
+    TarGz,
+    /// `.tar.zst` or `.tzst`
+    TarZst,
+    /// `.zip`, with each entry deflated
+    Zip,
+}
+
+impl ArchiveFormat {
+    /// The format of an archive named `path`, from its extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        let formats = [
+            (".tar", Self::Tar),
+            (".tar.gz", Self::TarGz),
+            (".tgz", Self::TarGz),
+            (".tar.zst", Self::TarZst),
+            (".tzst", Self::TarZst),
+            (".zip", Self::Zip),
+        ];
+        formats
+            .into_iter()
+            .find(|(extension, _)| name.ends_with(extension))
+            .map(|(_, format)| format)
+    }
+}
+
+/// The format of the archive `path`, or an error for the user.
+fn format_of(path: &Path) -> io::Result<ArchiveFormat> {
+    ArchiveFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "unknown archive format, expected .tar, .tar.gz, .tar.zst or .zip",
+        )
+    })
+}
+
+enum Writer {
+    Tar(tar::Builder<BufWriter<File>>),
+    TarGz(tar::Builder<flate2::write::GzEncoder<BufWriter<File>>>),
+    TarZst(tar::Builder<zstd::Encoder<'static, BufWriter<File>>>),
+    Zip(zip::ZipWriter<BufWriter<File>>),
+}
+
+/// An archive that is written to by all threads. The entries are written
+/// one at a time, under a lock.
+pub struct Archive {
+    pub path: PathBuf,
+    /// `None` once it is finished
+    writer: Mutex<Option<Writer>>,
+}
+
+fn append_tar<W: Write>(tar: &mut tar::Builder<W>, name: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mtime = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    tar.append_data(&mut header, name, bytes)
+}
+
+impl Archive {
+    /// Create the archive `path`, in the format of its extension.
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let format = format_of(path)?;
+        let file = BufWriter::new(File::create(path)?);
+        let writer = match format {
+            ArchiveFormat::Tar => Writer::Tar(tar::Builder::new(file)),
+            ArchiveFormat::TarGz => {
+                let gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+                Writer::TarGz(tar::Builder::new(gz))
+            }
+            ArchiveFormat::TarZst => {
+                let zst = zstd::Encoder::new(file, 0)?;
+                Writer::TarZst(tar::Builder::new(zst))
+            }
+            ArchiveFormat::Zip => Writer::Zip(zip::ZipWriter::new(file)),
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: Mutex::new(Some(writer)),
+        })
+    }
+
+    /// Add the file `name`, with the content `bytes`. `name` must be
+    /// relative, see [`entry_name`].
+    pub fn append(&self, name: &Path, bytes: &[u8]) -> io::Result<()> {
+        let mut writer = self
+            .writer
+            .lock()
+            .expect("no thread panics while holding the lock");
+        match writer.as_mut() {
+            Some(Writer::Tar(tar)) => append_tar(tar, name, bytes),
+            Some(Writer::TarGz(tar)) => append_tar(tar, name, bytes),
+            Some(Writer::TarZst(tar)) => append_tar(tar, name, bytes),
+            Some(Writer::Zip(zip)) => {
+                // zip entries are always separated by `/`
+                let name = name
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                let options = zip::write::SimpleFileOptions::default()
+                    .compression_method(zip::CompressionMethod::Deflated);
+                zip.start_file(name, options).map_err(io::Error::other)?;
+                zip.write_all(bytes)
+            }
+            None => Err(io::Error::other("the archive is already finished")),
+        }
+    }
+
+    /// Write the end of the archive, and flush it to disk. The entries that
+    /// are appended after this fail.
+    pub fn finish(&self) -> io::Result<()> {
+        let writer = self
+            .writer
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .take();
+        let file = match writer {
+            Some(Writer::Tar(tar)) => tar.into_inner()?,
+            Some(Writer::TarGz(tar)) => tar.into_inner()?.finish()?,
+            Some(Writer::TarZst(tar)) => tar.into_inner()?.finish()?,
+            Some(Writer::Zip(zip)) => zip.finish().map_err(io::Error::other)?,
+            None => return Ok(()),
+        };
+        let file = file.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()
+    }
+}
+
+/// The name in the archive of the korp_mono file `output`, converted from
+/// `analysed_file`: relative to `output_dir`, if there is one, or else to
+/// the directory of the corpus directory. Only the normal components of
+/// the path are kept, so that the entries can't be unpacked outside of the
+/// directory they are unpacked in.
+pub fn entry_name(output: &Path, analysed_file: &Path, output_dir: Option<&Path>) -> PathBuf {
+    let base = match output_dir {
+        Some(dir) => Some(dir),
+        None => corpus_dir(analysed_file).and_then(Path::parent),
+    };
+    let relative = base
+        .and_then(|base| output.strip_prefix(base).ok())
+        .unwrap_or(output);
+    normal(relative)
+}
+
+/// `path` without the components that are not normal, like `..` and `/`.
+fn normal(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| matches!(c, Component::Normal(_)))
+        .collect()
+}
+
+/// Read the `.xml` files in the archive `path` one by one, in the order they
+/// are in the archive, and hand the ones that are `wanted` to `found`,
+/// together with their content, or the error of reading it. Stops early if
+/// `found` returns `false`. Fails if the archive itself can't be read.
+pub fn read_entries(
+    path: &Path,
+    wanted: &mut dyn FnMut(&Path) -> bool,
+    found: &mut dyn FnMut(PathBuf, io::Result<String>) -> bool,
+) -> io::Result<()> {
+    let format = format_of(path)?;
+    let file = File::open(path)?;
+    let is_xml = |path: &Path| path.extension().is_some_and(|ext| ext == "xml");
+    let read = |entry: &mut dyn Read, size: u64, name: &Path| {
+        let mut content = Vec::with_capacity(size as usize);
+        entry
+            .read_to_end(&mut content)
+            .map(|_| encoding::decode_file(content, name))
+    };
+    let tar: Box<dyn Read> = match format {
+        ArchiveFormat::Tar => Box::new(BufReader::new(file)),
+        ArchiveFormat::TarGz => Box::new(flate2::read::GzDecoder::new(BufReader::new(file))),
+        ArchiveFormat::TarZst => Box::new(zstd::Decoder::new(file)?),
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipArchive::new(BufReader::new(file)).map_err(io::Error::other)?;
+            for i in 0..zip.len() {
+                let mut entry = zip.by_index(i).map_err(io::Error::other)?;
+                let Some(name) = entry.enclosed_name() else {
+                    continue;
+                };
+                if !entry.is_file() || !is_xml(&name) || !wanted(&name) {
+                    continue;
+                }
+                let size = entry.size();
+                if !found(name.clone(), read(&mut entry, size, &name)) {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+    };
+    let mut tar = tar::Archive::new(tar);
+    for entry in tar.entries()? {
+        let mut entry = entry?;
+        let name = normal(&entry.path()?);
+        if !entry.header().entry_type().is_file() || !is_xml(&name) || !wanted(&name) {
+            continue;
+        }
+        let size = entry.size();
+        if !found(name.clone(), read(&mut entry, size, &name)) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Archive, ArchiveFormat, entry_name, read_entries};
+    use std::io::Read;
+    use std::path::Path;
+
+    #[test]
+    fn formats() {
+        let format = |name: &str| ArchiveFormat::from_path(Path::new(name));
+        assert_eq!(format("corpus.tar"), Some(ArchiveFormat::Tar));
+        assert_eq!(format("corpus.TGZ"), Some(ArchiveFormat::TarGz));
+        assert_eq!(format("/x/corpus.tar.zst"), Some(ArchiveFormat::TarZst));
+        assert_eq!(format("corpus.zip"), Some(ArchiveFormat::Zip));
+        assert_eq!(format("corpus.zst"), None);
+    }
+
+    #[test]
+    fn entry_names() {
+        let analysed = Path::new("/giellalt/corpus-sme/analysed/news/a.xml");
+        let output = Path::new("/giellalt/corpus-sme/korp_mono/news/a.xml");
+        assert_eq!(
+            entry_name(output, analysed, None),
+            Path::new("corpus-sme/korp_mono/news/a.xml")
+        );
+        let output = Path::new("/out/news/a.xml");
+        assert_eq!(
+            entry_name(output, analysed, Some(Path::new("/out"))),
+            Path::new("news/a.xml")
+        );
+        let output = Path::new("/elsewhere/../a.xml");
+        assert_eq!(
+            entry_name(output, Path::new("a.xml"), None),
+            Path::new("elsewhere/a.xml")
+        );
+    }
+
+    #[test]
+    fn tar_zst_and_zip() {
+        let dir = std::env::temp_dir().join(format!("korp-mono-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("corpus.tar.zst");
+        let archive = Archive::create(&path).unwrap();
+        archive
+            .append(Path::new("corpus-sme/korp_mono/a.xml"), b"<text/>")
+            .unwrap();
+        archive.finish().unwrap();
+        assert!(archive.append(Path::new("b.xml"), b"").is_err());
+        let decoder = zstd::Decoder::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut tar = tar::Archive::new(decoder);
+        let mut entries = tar.entries().unwrap();
+        let mut entry = entries.next().unwrap().unwrap();
+        assert_eq!(
+            entry.path().unwrap(),
+            Path::new("corpus-sme/korp_mono/a.xml")
+        );
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "<text/>");
+
+        let path = dir.join("corpus.zip");
+        let archive = Archive::create(&path).unwrap();
+        archive
+            .append(Path::new("corpus-sme/korp_mono/a.xml"), b"<text/>")
+            .unwrap();
+        archive.finish().unwrap();
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut content = String::new();
+        zip.by_name("corpus-sme/korp_mono/a.xml")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "<text/>");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn read() {
+        let dir =
+            std::env::temp_dir().join(format!("korp-mono-read-archive-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        for name in ["analysed.tar.gz", "analysed.zip"] {
+            let path = dir.join(name);
+            let archive = Archive::create(&path).unwrap();
+            archive
+                .append(Path::new("corpus-sme/analysed/a.xml"), b"<a/>")
+                .unwrap();
+            archive
+                .append(Path::new("corpus-sme/analysed/a.txt"), b"a")
+                .unwrap();
+            archive
+                .append(Path::new("corpus-sme/analysed/b.xml"), b"<b/>")
+                .unwrap();
+            archive
+                .append(Path::new("corpus-sme/analysed/c.xml"), b"\xff")
+                .unwrap();
+            archive.finish().unwrap();
+
+            let mut read = vec![];
+            read_entries(
+                &path,
+                &mut |path| !path.ends_with("b.xml"),
+                &mut |path, content| {
+                    read.push((path, content.ok()));
+                    true
+                },
+            )
+            .unwrap();
+            let read = read
+                .iter()
+                .map(|(path, content)| (path.to_str().unwrap(), content.as_deref()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                read,
+                [
+                    ("corpus-sme/analysed/a.xml", Some("<a/>")),
+                    // not UTF-8, so read as Latin-1
+                    ("corpus-sme/analysed/c.xml", Some("ÿ")),
+                ]
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
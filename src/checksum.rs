@@ -0,0 +1,98 @@
+//! CRC-32 checksums of the written files.
+
+use std::io::Write;
+
+const fn make_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {
+                0xEDB8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+static TABLE: [u32; 256] = make_table();
+
+/// Continue the CRC-32 (IEEE) checksum `crc` with `bytes`. Start with 0.
+pub fn crc32_update(crc: u32, bytes: &[u8]) -> u32 {
+    let mut c = !crc;
+    for &b in bytes {
+        c = TABLE[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8);
+    }
+    !c
+}
+
+/// The CRC-32 (IEEE) checksum of `bytes`.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    crc32_update(0, bytes)
+}
+
+/// A writer that computes the checksum, and counts the bytes, of everything
+/// written through it.
+pub struct ChecksumWriter<W> {
+    inner: W,
+    crc: u32,
+    bytes: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            crc: 0,
+            bytes: 0,
+        }
+    }
+
+    /// The checksum and number of bytes written, and the inner writer.
+    pub fn finish(self) -> (u32, u64, W) {
+        (self.crc, self.bytes, self.inner)
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc = crc32_update(self.crc, &buf[..n]);
+        self.bytes += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChecksumWriter, crc32};
+    use std::io::Write;
+
+    #[test]
+    fn check_value() {
+        assert_eq!(crc32(b""), 0);
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn writer() {
+        let mut writer = ChecksumWriter::new(vec![]);
+        writer.write_all(b"12345").unwrap();
+        writer.write_all(b"6789").unwrap();
+        let (crc, bytes, inner) = writer.finish();
+        assert_eq!(crc, 0xCBF4_3926);
+        assert_eq!(bytes, 9);
+        assert_eq!(inner, b"123456789");
+    }
+}
@@ -0,0 +1,409 @@
+//! Compare the converted documents with the output of the old Python
+//! korp_mono converter, with `--compare-python old_korp_mono/`, to see that
+//! nothing changed that shouldn't have.
+//!
+//! Each converted document is compared with the file at the same path in
+//! the old `korp_mono/` directory: the attributes of `<text>`, the
+//! sentences, by id, and the token lines of each sentence, column by
+//! column. The differences are counted by attribute and by column, with
+//! the most frequent pairs of old and new values, so that systematic
+//! differences stand out in the report at the end of the run.
+//!
+//! Differences that are known and intended are left out with
+//! `--compare-rules rules.toml`:
+//!
+//! ```toml
+//! # the lemmas of dynamic compounds are generated now
+//! [[ignore]]
+//! column = "lemma"
+//! old = "[[[GEN:*]]]"
+//!
+//! # the old converter didn't write the source urls
+//! [[ignore]]
+//! attribute = "source_url"
+//! ```
+//!
+//! A rule is for a `column`, named as in `--columns`, or an `attribute` of
+//! `<text>`. Without `old` and `new`, it ignores all differences of the
+//! column or attribute, otherwise only those where the old and the new
+//! value match the glob patterns, see [`crate::path_filter`].
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+
+use anyhow::Context;
+use clap::ValueEnum;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use serde::Deserialize;
+
+use crate::path_filter::glob_match;
+use crate::process_sentence::Column;
+
+/// The number of most frequent differences that are shown for each column
+/// and attribute.
+const SHOWN_PAIRS: usize = 5;
+
+/// A known difference, see the module documentation.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    column: Option<String>,
+    attribute: Option<String>,
+    old: Option<String>,
+    new: Option<String>,
+}
+
+impl Rule {
+    fn matches(&self, place: &Place, old: &str, new: &str) -> bool {
+        let name = match place {
+            Place::Column(name) => self.column.as_ref().filter(|column| *column == name),
+            Place::Attribute(name) => self
+                .attribute
+                .as_ref()
+                .filter(|attribute| *attribute == name),
+        };
+        name.is_some()
+            && self
+                .old
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern, old))
+            && self
+                .new
+                .as_ref()
+                .is_none_or(|pattern| glob_match(pattern, new))
+    }
+}
+
+/// The rules of `--compare-rules`.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rules {
+    #[serde(default)]
+    ignore: Vec<Rule>,
+}
+
+impl Rules {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read compare rules '{}'", path.display()))?;
+        let rules: Self = toml::from_str(&contents)
+            .with_context(|| format!("invalid compare rules '{}'", path.display()))?;
+        for rule in rules.ignore.iter() {
+            if rule.column.is_some() == rule.attribute.is_some() {
+                anyhow::bail!(
+                    "invalid compare rules '{}': each rule needs either a column or an attribute",
+                    path.display()
+                );
+            }
+        }
+        Ok(rules)
+    }
+
+    fn ignores(&self, place: &Place, old: &str, new: &str) -> bool {
+        self.ignore.iter().any(|rule| rule.matches(place, old, new))
+    }
+}
+
+/// Where two documents differ.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Place {
+    Attribute(String),
+    Column(String),
+}
+
+impl std::fmt::Display for Place {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Place::Attribute(name) => write!(f, "attribute {name}"),
+            Place::Column(name) => write!(f, "column {name}"),
+        }
+    }
+}
+
+/// The `<text>` attributes and the sentences of a korp_mono file, with
+/// the token lines of each sentence. The attributes of the first `<text>`
+/// are kept, and the sentences of all of them.
+#[derive(Debug, Default)]
+struct Vrt {
+    attrs: BTreeMap<String, String>,
+    sentences: Vec<(String, Vec<String>)>,
+}
+
+impl Vrt {
+    fn parse(xml: &str) -> anyhow::Result<Self> {
+        let mut vrt = Self::default();
+        let mut texts = 0;
+        let mut sentence: Option<(String, String)> = None;
+        let mut reader = Reader::from_str(xml);
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) if e.name().as_ref() == b"text" => {
+                    texts += 1;
+                    if texts > 1 {
+                        continue;
+                    }
+                    for attr in e.attributes() {
+                        let attr = attr?;
+                        let name = String::from_utf8_lossy(attr.key.as_ref()).into_owned();
+                        vrt.attrs.insert(name, attr.unescape_value()?.into_owned());
+                    }
+                }
+                Event::Start(e) if e.name().as_ref() == b"sentence" => {
+                    let id = e
+                        .try_get_attribute("id")?
+                        .map(|id| id.unescape_value().map(|id| id.into_owned()))
+                        .transpose()?
+                        .unwrap_or_default();
+                    sentence = Some((id, String::new()));
+                }
+                Event::Text(t) => {
+                    if let Some((_, text)) = sentence.as_mut() {
+                        text.push_str(&t.unescape()?);
+                    }
+                }
+                Event::End(e) if e.name().as_ref() == b"sentence" => {
+                    if let Some((id, text)) = sentence.take() {
+                        let lines = text
+                            .lines()
+                            .filter(|line| !line.trim().is_empty())
+                            .map(str::to_string)
+                            .collect();
+                        vrt.sentences.push((id, lines));
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+        Ok(vrt)
+    }
+}
+
+#[derive(Debug, Default)]
+struct Differences {
+    count: u64,
+    pairs: HashMap<(String, String), u64>,
+}
+
+/// The differences of the converted documents from the old ones, counted
+/// over the whole run.
+#[derive(Debug, Default)]
+pub struct Comparison {
+    rules: Rules,
+    files: u64,
+    identical: u64,
+    /// Documents that are not in the old tree
+    missing: u64,
+    /// Sentences that are only in the old, or only in the new document
+    old_sentences: u64,
+    new_sentences: u64,
+    /// Sentences with another number of tokens, whose tokens are not
+    /// compared
+    token_counts: u64,
+    ignored: u64,
+    differences: BTreeMap<Place, Differences>,
+}
+
+impl Comparison {
+    pub fn new(rules: Rules) -> Self {
+        Self {
+            rules,
+            ..Default::default()
+        }
+    }
+
+    /// A converted document that has no counterpart in the old tree.
+    pub fn add_missing(&mut self) {
+        self.files += 1;
+        self.missing += 1;
+    }
+
+    /// Compare the korp_mono file `new` with `old`. `columns` are the
+    /// columns of the token lines of `new`, for the names of the columns in
+    /// the report.
+    pub fn compare(&mut self, old: &str, new: &str, columns: &[Column]) -> anyhow::Result<()> {
+        let old = Vrt::parse(old).context("can't parse the old document")?;
+        let new = Vrt::parse(new).context("can't parse the new document")?;
+        self.files += 1;
+        let mut differ = false;
+
+        let names = old
+            .attrs
+            .keys()
+            .chain(new.attrs.keys())
+            .collect::<BTreeSet<_>>();
+        for name in names {
+            let old_value = old.attrs.get(name).map(String::as_str).unwrap_or_default();
+            let new_value = new.attrs.get(name).map(String::as_str).unwrap_or_default();
+            differ |= self.add(Place::Attribute(name.clone()), old_value, new_value);
+        }
+
+        let mut old_sentences = old.sentences.into_iter().collect::<HashMap<_, _>>();
+        for (id, new_lines) in new.sentences.iter() {
+            let Some(old_lines) = old_sentences.remove(id) else {
+                self.new_sentences += 1;
+                differ = true;
+                continue;
+            };
+            if old_lines.len() != new_lines.len() {
+                self.token_counts += 1;
+                differ = true;
+                continue;
+            }
+            for (old_line, new_line) in old_lines.iter().zip(new_lines.iter()) {
+                let old_fields = old_line.split('\t').collect::<Vec<_>>();
+                let new_fields = new_line.split('\t').collect::<Vec<_>>();
+                for i in 0..old_fields.len().max(new_fields.len()) {
+                    let old_value = old_fields.get(i).copied().unwrap_or_default();
+                    let new_value = new_fields.get(i).copied().unwrap_or_default();
+                    differ |=
+                        self.add(Place::Column(column_name(columns, i)), old_value, new_value);
+                }
+            }
+        }
+        if !old_sentences.is_empty() {
+            self.old_sentences += old_sentences.len() as u64;
+            differ = true;
+        }
+
+        if !differ {
+            self.identical += 1;
+        }
+        Ok(())
+    }
+
+    /// Count the difference of `old` and `new` at `place`, if they differ
+    /// and the rules don't ignore it, and return whether it was counted.
+    fn add(&mut self, place: Place, old: &str, new: &str) -> bool {
+        if old == new {
+            return false;
+        }
+        if self.rules.ignores(&place, old, new) {
+            self.ignored += 1;
+            return false;
+        }
+        let differences = self.differences.entry(place).or_default();
+        differences.count += 1;
+        *differences
+            .pairs
+            .entry((old.to_string(), new.to_string()))
+            .or_default() += 1;
+        true
+    }
+
+    /// A summary for the end of the run: the counts, and the most frequent
+    /// differences of each column and attribute.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(
+            s,
+            "compared {} documents with the old converter: {} identical, {} not in the old tree",
+            self.files, self.identical, self.missing
+        )
+        .expect(w);
+        writeln!(
+            s,
+            "  sentences only in the old: {}, only in the new: {}, with another number of tokens: {}",
+            self.old_sentences, self.new_sentences, self.token_counts
+        )
+        .expect(w);
+        if self.ignored > 0 {
+            writeln!(s, "  {} differences ignored by the rules", self.ignored).expect(w);
+        }
+        for (place, differences) in self.differences.iter() {
+            writeln!(s, "  {place}: {} differences", differences.count).expect(w);
+            let mut pairs = differences.pairs.iter().collect::<Vec<_>>();
+            pairs.sort_by(|(a, n), (b, m)| m.cmp(n).then_with(|| a.cmp(b)));
+            for ((old, new), n) in pairs.into_iter().take(SHOWN_PAIRS) {
+                writeln!(s, "    {old:?} -> {new:?}: {n}").expect(w);
+            }
+        }
+        s
+    }
+}
+
+/// The name of column `i`, as in `--columns`, or its number if there are
+/// more columns than `columns`.
+fn column_name(columns: &[Column], i: usize) -> String {
+    columns
+        .get(i)
+        .and_then(|column| column.to_possible_value())
+        .map(|value| value.get_name().to_string())
+        .unwrap_or_else(|| (i + 1).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Comparison, Rules};
+    use crate::process_sentence::Column;
+
+    const OLD: &str = r#"<text title="Ođđasat" lang="sme" datefrom="20180101">
+<sentence id="1">
+Guolli	guolli	N
+vuodjá	vuodjit	V
+</sentence>
+<sentence id="2">
+Buorre	buorre	A
+</sentence>
+</text>"#;
+
+    const NEW: &str = r#"<text title="Ođđasat" lang="sme" datefrom="20180101" source_url="https://example.org">
+<sentence id="1">
+Guolli	guolli	N
+vuodjá	vuodjit	V
+</sentence>
+<sentence id="2">
+Buorre	[[[GEN:buorre]]]	Adv
+</sentence>
+<sentence id="3">
+Juo	juo	Adv
+</sentence>
+</text>"#;
+
+    const COLUMNS: [Column; 3] = [Column::Word, Column::Lemma, Column::Pos];
+
+    #[test]
+    fn differences() {
+        let mut comparison = Comparison::default();
+        comparison.compare(OLD, OLD, &COLUMNS).unwrap();
+        comparison.compare(OLD, NEW, &COLUMNS).unwrap();
+        comparison.add_missing();
+        assert_eq!(
+            comparison.report(),
+            "compared 3 documents with the old converter: 1 identical, 1 not in the old tree
+  sentences only in the old: 0, only in the new: 1, with another number of tokens: 0
+  attribute source_url: 1 differences
+    \"\" -> \"https://example.org\": 1
+  column lemma: 1 differences
+    \"buorre\" -> \"[[[GEN:buorre]]]\": 1
+  column pos: 1 differences
+    \"A\" -> \"Adv\": 1
+"
+        );
+    }
+
+    #[test]
+    fn rules() {
+        let rules: Rules = toml::from_str(
+            r#"
+            [[ignore]]
+            attribute = "source_url"
+
+            [[ignore]]
+            column = "lemma"
+            new = "[[[GEN:*]]]"
+            "#,
+        )
+        .unwrap();
+        let mut comparison = Comparison::new(rules);
+        comparison.compare(OLD, NEW, &COLUMNS).unwrap();
+        let report = comparison.report();
+        assert!(report.contains("2 differences ignored"), "{report}");
+        assert!(!report.contains("lemma"), "{report}");
+        assert!(report.contains("column pos: 1 differences"), "{report}");
+        assert!(toml::from_str::<Rules>("[[ignore]]\ncolumn = \"pos\"\nold = 1").is_err());
+    }
+}
@@ -0,0 +1,281 @@
+//! Finding the corpus directories of a language, also those that
+//! `gtcorpusutil` doesn't recognise.
+//!
+//! `gtcorpusutil` only takes `corpus-xxx` directories, with exactly three
+//! lowercase letters, as corpora. Newer corpus repositories are also named
+//! `corpus-xxx-x-closed`, `corpus-xxx-orig`, or have language codes of
+//! other lengths, like `corpus-zh`. Some corpora have no `corpus-xxx`
+//! directory at all, and keep the analysed files in `analysed/xxx/`
+//! directly under the root. The analysed files of those are found here.
+//!
+//! The analysed directories are walked as described by [`Walk`]: by default
+//! all the way down, without following symlinks, and across file systems.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use crate::korp_mono::path::ANALYSED_DIR;
+
+/// The suffix of the closed corpora.
+const CLOSED_SUFFIX: &str = "x-closed";
+
+/// The name of a corpus directory: `corpus-<lang>[-<suffix>]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorpusName {
+    pub lang: String,
+    /// What comes after the language, like `x-closed` or `orig`
+    pub suffix: Option<String>,
+}
+
+impl CorpusName {
+    /// The corpus name of the directory named `name`, or `None` if it isn't
+    /// named like a corpus. The language has at least two lowercase
+    /// letters, the suffix lowercase letters and digits, in parts separated
+    /// by `-`.
+    pub fn parse(name: &str) -> Option<Self> {
+        let rest = name.strip_prefix("corpus-")?;
+        let (lang, suffix) = match rest.split_once('-') {
+            Some((lang, suffix)) => (lang, Some(suffix)),
+            None => (rest, None),
+        };
+        if lang.len() < 2 || !lang.bytes().all(|b| b.is_ascii_lowercase()) {
+            return None;
+        }
+        if let Some(suffix) = suffix {
+            let valid = suffix.split('-').all(|part| {
+                !part.is_empty()
+                    && part
+                        .bytes()
+                        .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit())
+            });
+            if !valid {
+                return None;
+            }
+        }
+        Some(Self {
+            lang: lang.to_string(),
+            suffix: suffix.map(str::to_string),
+        })
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.suffix
+            .as_deref()
+            .is_some_and(|suffix| format!("-{suffix}-").contains(&format!("-{CLOSED_SUFFIX}-")))
+    }
+}
+
+/// Is `dir` named like a corpus directory?
+pub fn is_corpus_dir(dir: &Path) -> bool {
+    dir.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| CorpusName::parse(name).is_some())
+}
+
+/// An `analysed/` directory of a language, found in a root directory.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AnalysedDir {
+    pub path: PathBuf,
+    pub closed: bool,
+}
+
+/// The analysed directories of the language `lang` in `root`: the
+/// `analysed/` directory of each corpus directory of the language, and
+/// `analysed/<lang>/`, if there is one. The alternate layout is taken to be
+/// open.
+pub fn analysed_dirs(root: &Path, lang: &str) -> std::io::Result<BTreeSet<AnalysedDir>> {
+    let mut dirs = BTreeSet::new();
+    for entry in std::fs::read_dir(root)? {
+        let entry = entry?;
+        let Some(corpus) = entry.file_name().to_str().and_then(CorpusName::parse) else {
+            continue;
+        };
+        let path = entry.path().join(ANALYSED_DIR);
+        if corpus.lang == lang && path.is_dir() {
+            let closed = corpus.is_closed();
+            dirs.insert(AnalysedDir { path, closed });
+        }
+    }
+    let path = root.join(ANALYSED_DIR).join(lang);
+    if path.is_dir() {
+        dirs.insert(AnalysedDir {
+            path,
+            closed: false,
+        });
+    }
+    Ok(dirs)
+}
+
+/// How the analysed directories are walked, with `--max-depth`,
+/// `--follow-symlinks` and `--one-file-system`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Walk {
+    /// Only take the files at most this deep in the analysed directory. The
+    /// files directly in it are at depth 1.
+    pub max_depth: Option<usize>,
+    /// Follow symlinks to files and directories. Symlinks that lead back to
+    /// a directory that is being walked are skipped, with a warning.
+    pub follow_symlinks: bool,
+    /// Don't walk into directories on other file systems than the analysed
+    /// directory.
+    pub one_file_system: bool,
+}
+
+impl Walk {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The analysed files in `dir`, sorted, walked as `walk` says.
+pub fn analysed_files(dir: &Path, walk: &Walk) -> Vec<PathBuf> {
+    let mut walker = walkdir::WalkDir::new(dir)
+        .follow_links(walk.follow_symlinks)
+        .same_file_system(walk.one_file_system);
+    if let Some(depth) = walk.max_depth {
+        walker = walker.max_depth(depth);
+    }
+    let mut files = walker
+        .into_iter()
+        .filter_map(|entry| match entry {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                let path = e.path().map(Path::to_path_buf);
+                match e.loop_ancestor() {
+                    Some(ancestor) => {
+                        tracing::warn!(path = ?path, ancestor = ?ancestor, "skipping symlink loop")
+                    }
+                    None => tracing::warn!(path = ?path, error = %e, "can't walk directory"),
+                }
+                None
+            }
+        })
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "xml"))
+        .collect::<Vec<_>>();
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AnalysedDir, CorpusName, Walk, analysed_dirs, analysed_files, is_corpus_dir};
+    use std::path::Path;
+
+    fn name(lang: &str, suffix: Option<&str>) -> Option<CorpusName> {
+        Some(CorpusName {
+            lang: lang.to_string(),
+            suffix: suffix.map(str::to_string),
+        })
+    }
+
+    #[test]
+    fn corpus_names() {
+        assert_eq!(CorpusName::parse("corpus-sme"), name("sme", None));
+        assert_eq!(
+            CorpusName::parse("corpus-sme-x-closed"),
+            name("sme", Some("x-closed"))
+        );
+        assert_eq!(
+            CorpusName::parse("corpus-sma-orig"),
+            name("sma", Some("orig"))
+        );
+        assert_eq!(CorpusName::parse("corpus-zh"), name("zh", None));
+        assert_eq!(CorpusName::parse("corpus-smjx"), name("smjx", None));
+        assert_eq!(
+            CorpusName::parse("corpus-fkv-orig-x-closed"),
+            name("fkv", Some("orig-x-closed"))
+        );
+    }
+
+    #[test]
+    fn not_corpus_names() {
+        [
+            "corpus-",
+            "corpus-s",
+            "corpus-SME",
+            "corpus-sme-",
+            "corpus-sme--closed",
+            "corpus-sme-x_closed",
+            "corpus-sme.bak",
+            "corpussme",
+            "sme",
+        ]
+        .iter()
+        .for_each(|name| assert_eq!(CorpusName::parse(name), None, "{name}"));
+    }
+
+    #[test]
+    fn closed() {
+        let closed = |name| CorpusName::parse(name).unwrap().is_closed();
+        assert!(closed("corpus-sme-x-closed"));
+        assert!(closed("corpus-sme-orig-x-closed"));
+        assert!(!closed("corpus-sme"));
+        assert!(!closed("corpus-sme-orig"));
+        assert!(!closed("corpus-sme-x"));
+        assert!(is_corpus_dir(Path::new("/giellalt/corpus-sme-x-closed")));
+        assert!(!is_corpus_dir(Path::new("/giellalt/analysed")));
+    }
+
+    #[test]
+    fn dirs_of_language() {
+        let root =
+            std::env::temp_dir().join(format!("korp-mono-corpus-dirs-{}", std::process::id()));
+        for dir in [
+            "corpus-sme/analysed",
+            "corpus-sme-x-closed/analysed",
+            "corpus-sme-orig",
+            "corpus-sma/analysed",
+            "analysed/sme",
+        ] {
+            std::fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        let dirs = analysed_dirs(&root, "sme").unwrap();
+        std::fs::remove_dir_all(&root).unwrap();
+        let dir = |path: &str, closed| AnalysedDir {
+            path: root.join(path),
+            closed,
+        };
+        assert_eq!(
+            dirs.into_iter().collect::<Vec<_>>(),
+            [
+                dir("analysed/sme", false),
+                dir("corpus-sme/analysed", false),
+                dir("corpus-sme-x-closed/analysed", true),
+            ]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn walk() {
+        let root = std::env::temp_dir().join(format!("korp-mono-walk-{}", std::process::id()));
+        let analysed = root.join("analysed");
+        std::fs::create_dir_all(analysed.join("news/2018")).unwrap();
+        for file in ["a.xml", "news/b.xml", "news/2018/c.xml"] {
+            std::fs::write(analysed.join(file), "").unwrap();
+        }
+        std::os::unix::fs::symlink(&analysed, analysed.join("news/loop")).unwrap();
+        let relative = |walk: Walk| {
+            analysed_files(&analysed, &walk)
+                .iter()
+                .map(|path| path.strip_prefix(&analysed).unwrap().display().to_string())
+                .collect::<Vec<_>>()
+        };
+        let default = relative(Walk::default());
+        let shallow = relative(Walk {
+            max_depth: Some(2),
+            ..Default::default()
+        });
+        let followed = relative(Walk {
+            follow_symlinks: true,
+            ..Default::default()
+        });
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(default, ["a.xml", "news/2018/c.xml", "news/b.xml"]);
+        assert_eq!(shallow, ["a.xml", "news/b.xml"]);
+        // the loop is skipped, and nothing is found twice
+        assert_eq!(followed, default);
+    }
+}
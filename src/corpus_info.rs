@@ -0,0 +1,175 @@
+//! Corpus statistics in the format of the CWB/Korp `.info` file.
+//!
+//! Korp reads the number of sentences, and the dates of the first and last
+//! text, from an `.info` file next to the encoded corpus. This module
+//! collects those numbers from the converted documents, and writes a
+//! `korp_mono.info` file to each corpus directory, that can be copied next
+//! to the data of the encoded corpus.
+//!
+//! ```not_rust
+//! Sentences: 12345
+//! Tokens: 234567
+//! Texts: 89
+//! Updated: 2026-10-16
+//! FirstDate: 1998-01-01 00:00:00
+//! LastDate: 2018-01-01 23:59:59
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::date;
+use crate::korp_mono::KorpMonoFile;
+use crate::korp_mono::path::corpus_dir;
+
+/// Name of the info file, written to the corpus directory.
+pub const INFO_FILE_NAME: &str = "korp_mono.info";
+
+/// The statistics of one corpus.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Info {
+    pub texts: usize,
+    pub sentences: usize,
+    pub tokens: usize,
+    /// Earliest `datefrom` of all texts, as `YYYYMMDD`.
+    pub first_date: Option<String>,
+    /// Latest `dateto` of all texts, as `YYYYMMDD`.
+    pub last_date: Option<String>,
+}
+
+/// `YYYYMMDD` to `YYYY-MM-DD`
+fn dashed(date: &str) -> String {
+    format!("{}-{}-{}", &date[0..4], &date[4..6], &date[6..8])
+}
+
+/// Is `date` a known date in the `YYYYMMDD` form?
+fn is_known_date(date: &str) -> bool {
+    date.len() == 8 && date.bytes().all(|b| b.is_ascii_digit()) && !date.starts_with("0000")
+}
+
+/// Today's date, as `YYYY-MM-DD`, according to the system clock.
+fn today() -> String {
+    let days = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86400)
+        .unwrap_or(0) as i64;
+    let (year, month, day) = date::civil_from_days(days);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+impl Info {
+    /// Add the numbers of `korp_mono_file` to the statistics.
+    pub fn add(&mut self, korp_mono_file: &KorpMonoFile) {
+        self.texts += 1;
+        self.sentences += korp_mono_file.sentence.len();
+        self.tokens += korp_mono_file
+            .sentence
+            .iter()
+            .map(|sentence| sentence.rows.len())
+            .sum::<usize>();
+
+        if let Some(datefrom) = korp_mono_file
+            .datefrom
+            .as_deref()
+            .filter(|d| is_known_date(d))
+            .filter(|d| self.first_date.as_deref().is_none_or(|first| *d < first))
+        {
+            self.first_date = Some(datefrom.to_string());
+        }
+        if let Some(dateto) = korp_mono_file
+            .dateto
+            .as_deref()
+            .filter(|d| is_known_date(d))
+            .filter(|d| self.last_date.as_deref().is_none_or(|last| *d > last))
+        {
+            self.last_date = Some(dateto.to_string());
+        }
+    }
+
+    /// The contents of the `.info` file, with `updated` as the update date.
+    fn to_info_file(&self, updated: &str) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(s, "Sentences: {}", self.sentences).expect(w);
+        writeln!(s, "Tokens: {}", self.tokens).expect(w);
+        writeln!(s, "Texts: {}", self.texts).expect(w);
+        writeln!(s, "Updated: {updated}").expect(w);
+        if let Some(first) = &self.first_date {
+            writeln!(s, "FirstDate: {} 00:00:00", dashed(first)).expect(w);
+        }
+        if let Some(last) = &self.last_date {
+            writeln!(s, "LastDate: {} 23:59:59", dashed(last)).expect(w);
+        }
+        s
+    }
+}
+
+/// Statistics of all corpora, by corpus directory.
+#[derive(Debug, Default)]
+pub struct CorpusInfos {
+    corpora: BTreeMap<PathBuf, Info>,
+}
+
+impl CorpusInfos {
+    /// Add the converted `korp_mono_file`, from the analysed file
+    /// `analysed_file`, to the statistics of its corpus.
+    pub fn add(&mut self, analysed_file: &Path, korp_mono_file: &KorpMonoFile) {
+        let corpus = corpus_dir(analysed_file)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        self.corpora.entry(corpus).or_default().add(korp_mono_file);
+    }
+
+    /// Write `{corpus}/korp_mono.info` for each corpus.
+    pub fn write_info_files(&self) -> std::io::Result<()> {
+        let updated = today();
+        for (corpus, info) in self.corpora.iter() {
+            std::fs::write(corpus.join(INFO_FILE_NAME), info.to_info_file(&updated))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Info;
+    use crate::korp_mono::KorpMonoFile;
+
+    fn file(datefrom: &str, dateto: &str, sentences: &[&str]) -> KorpMonoFile {
+        KorpMonoFile {
+            datefrom: Some(datefrom.to_string()),
+            dateto: Some(dateto.to_string()),
+            ..KorpMonoFile::with_sentences(sentences)
+        }
+    }
+
+    #[test]
+    fn info_file() {
+        let mut info = Info::default();
+        info.add(&file("20180101", "20180101", &["a\n", "b\nc\n"]));
+        info.add(&file("19980101", "20020101", &["d\n"]));
+        info.add(&file("00000000", "00000000", &["e\nf\n"]));
+        assert_eq!(
+            info.to_info_file("2026-10-16"),
+            concat!(
+                "Sentences: 4\n",
+                "Tokens: 6\n",
+                "Texts: 3\n",
+                "Updated: 2026-10-16\n",
+                "FirstDate: 1998-01-01 00:00:00\n",
+                "LastDate: 2018-01-01 23:59:59\n",
+            )
+        );
+    }
+
+    #[test]
+    fn no_dates() {
+        let mut info = Info::default();
+        info.add(&file("00000000", "00000000", &[]));
+        assert_eq!(
+            info.to_info_file("2026-10-16"),
+            "Sentences: 0\nTokens: 0\nTexts: 1\nUpdated: 2026-10-16\n"
+        );
+    }
+}
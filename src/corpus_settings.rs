@@ -0,0 +1,172 @@
+//! Settings of a single corpus, from a `korp-mono.toml` in the corpus
+//! directory, so that a run over many corpora can treat each of them
+//! differently.
+//!
+//! ```toml
+//! # corpus-sme/korp-mono.toml
+//! domain-map = "domains.tsv"
+//! text-attrs = ["id", "title", "lang", "gt_domain", "datefrom", "dateto"]
+//! columns = ["word", "lemma", "pos", "msd", "ref", "deprel", "dephead"]
+//! license-filter = ["free", "standard"]
+//! ```
+//!
+//! The keys are named like the options they stand for, and each replaces
+//! that option for the documents of the corpus: `domain-map` is a file like
+//! the one of `--domain-map`, relative to the corpus directory, and turns on
+//! `--infer-domain`; `text-attrs` is like `--text-attrs`; `columns` like
+//! `--columns`; and `license-filter` like `--license-filter`. The options
+//! that are not in the file are taken from the command line.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use clap::ValueEnum;
+use serde::Deserialize;
+
+use crate::domain::DomainMap;
+use crate::korp_mono::path::corpus_dir;
+use crate::license::LicenseFilter;
+use crate::process_sentence::Column;
+use crate::text_attrs::TextAttrs;
+
+/// The name of the settings file in the corpus directory.
+pub const SETTINGS_FILE: &str = "korp-mono.toml";
+
+/// The settings file, as it is written.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct SettingsFile {
+    domain_map: Option<PathBuf>,
+    text_attrs: Option<Vec<String>>,
+    columns: Option<Vec<String>>,
+    license_filter: Option<Vec<String>>,
+}
+
+/// The settings of one corpus. `None` is the option given on the command
+/// line.
+#[derive(Debug, Default, Clone)]
+pub struct CorpusSettings {
+    pub domain_map: Option<DomainMap>,
+    pub text_attrs: Option<TextAttrs>,
+    pub columns: Option<Vec<Column>>,
+    pub license_filter: Option<LicenseFilter>,
+}
+
+impl CorpusSettings {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read corpus settings '{}'", path.display()))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Self::parse(&contents, dir)
+            .with_context(|| format!("invalid corpus settings '{}'", path.display()))
+    }
+
+    /// Parse the settings file `contents`, of the corpus directory `dir`.
+    fn parse(contents: &str, dir: &Path) -> anyhow::Result<Self> {
+        let file = toml::from_str::<SettingsFile>(contents)?;
+        let domain_map = match file.domain_map {
+            Some(path) => Some(DomainMap::from_file(dir.join(path))?),
+            None => None,
+        };
+        let columns = match file.columns {
+            Some(columns) => Some(
+                columns
+                    .iter()
+                    .map(|name| Column::from_str(name, false).map_err(anyhow::Error::msg))
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+            ),
+            None => None,
+        };
+        Ok(Self {
+            domain_map,
+            text_attrs: file.text_attrs.map(|names| TextAttrs::new(&names)),
+            columns,
+            license_filter: file
+                .license_filter
+                .map(|allowed| LicenseFilter::new(&allowed)),
+        })
+    }
+}
+
+/// The settings of the corpora, read when the first file of each corpus is
+/// seen. Shared between the threads.
+#[derive(Debug, Default)]
+pub struct Settings {
+    /// The settings by corpus directory, `None` for corpora without a
+    /// settings file
+    corpora: Mutex<BTreeMap<PathBuf, Option<Arc<CorpusSettings>>>>,
+}
+
+impl Settings {
+    /// Read the settings of the corpus of `analysed_file`, unless they have
+    /// been read already. A settings file that can't be read is tried again
+    /// for the next file of the corpus.
+    pub fn load(&self, analysed_file: &Path) -> anyhow::Result<Option<Arc<CorpusSettings>>> {
+        let Some(corpus) = corpus_dir(analysed_file) else {
+            return Ok(None);
+        };
+        if let Some(settings) = self
+            .corpora
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .get(corpus)
+        {
+            return Ok(settings.clone());
+        }
+        let path = corpus.join(SETTINGS_FILE);
+        let settings = match path.is_file() {
+            true => Some(Arc::new(CorpusSettings::from_file(&path)?)),
+            false => None,
+        };
+        self.corpora
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .insert(corpus.to_path_buf(), settings.clone());
+        Ok(settings)
+    }
+
+    /// The settings of the corpus of `analysed_file`, if it has any, and
+    /// they could be read.
+    pub fn of(&self, analysed_file: &Path) -> Option<Arc<CorpusSettings>> {
+        self.load(analysed_file).ok().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CorpusSettings;
+    use crate::process_sentence::Column;
+    use crate::text_attrs::TextAttrs;
+    use std::path::Path;
+
+    #[test]
+    fn parse() {
+        let settings = CorpusSettings::parse(
+            concat!(
+                "text-attrs = [\"id\", \"title\"]\n",
+                "columns = [\"word\", \"lemma\"]\n",
+                "license-filter = [\"free\", \"standard\"]\n",
+            ),
+            Path::new("/corpus-sme"),
+        )
+        .unwrap();
+        assert!(settings.domain_map.is_none());
+        assert_eq!(settings.text_attrs, Some(TextAttrs::new(&["id", "title"])));
+        assert_eq!(settings.columns, Some(vec![Column::Word, Column::Lemma]));
+        let filter = settings.license_filter.unwrap();
+        assert!(filter.allows(Some("standard")));
+        assert!(!filter.allows(None));
+    }
+
+    #[test]
+    fn invalid() {
+        let parse = |contents| CorpusSettings::parse(contents, Path::new("/corpus-sme"));
+        assert!(parse("").is_ok());
+        assert!(parse("columns = [\"word\", \"nope\"]\n").is_err());
+        assert!(parse("genre-map = \"domains.tsv\"\n").is_err());
+        assert!(parse("domain-map = \"does-not-exist.tsv\"\n").is_err());
+    }
+}
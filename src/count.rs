@@ -0,0 +1,169 @@
+//! Count the sentences and tokens of analysed documents, without converting
+//! them, for the `count` subcommand.
+//!
+//! The analyses are parsed, so that the counts are the same as those of the
+//! converted documents, but nothing is converted or written, which makes
+//! this much faster than a conversion when only the size of the corpus is
+//! needed. The counts are summed per language and domain.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::analysed::file::{UnparsedAnalysedDocument, parse_dependency};
+use crate::analysed::repair;
+use crate::korp_mono::file::genre_map;
+
+/// The counts of one document, or a sum of them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Totals {
+    pub documents: u64,
+    pub sentences: u64,
+    pub tokens: u64,
+}
+
+impl Totals {
+    fn add(&mut self, other: Totals) {
+        self.documents += other.documents;
+        self.sentences += other.sentences;
+        self.tokens += other.tokens;
+    }
+}
+
+/// The counts of the analysed document `xml`, with its language and domain.
+/// See [`parse_dependency`] for `robust`.
+pub fn count_document(xml: &str, robust: bool) -> anyhow::Result<(String, String, Totals)> {
+    let doc = quick_xml::de::from_str::<UnparsedAnalysedDocument>(xml)?;
+    let lang = doc.lang.unwrap_or_default();
+    let domain = match &doc.header.genre {
+        Some(genre) => genre_map(&genre.code).to_string(),
+        None => String::new(),
+    };
+    let (dependency, _repairs) = repair::repair(&doc.body.dependency);
+    repair::validate(&dependency)?;
+    let mut skipped = vec![];
+    let sentences = parse_dependency(&dependency, robust, &mut skipped)
+        .ok_or_else(|| anyhow::anyhow!("can't parse analyses"))?;
+    let tokens = sentences
+        .iter()
+        .flat_map(|sentence| sentence.parts.iter())
+        .filter(|part| match part {
+            // "¶" is the end of paragraph sentinel, which is not written
+            giellacgparser::SentencePart::Cohort(cohort) => cohort.word_form != "¶",
+            giellacgparser::SentencePart::CohortSeparator(_) => false,
+        })
+        .count();
+    let totals = Totals {
+        documents: 1,
+        sentences: sentences.len() as u64,
+        tokens: tokens as u64,
+    };
+    Ok((lang, domain, totals))
+}
+
+/// The counts of many documents, by language and domain.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Counts {
+    totals: BTreeMap<(String, String), Totals>,
+    /// The number of documents that could not be counted
+    pub failed: u64,
+}
+
+impl Counts {
+    pub fn add(&mut self, lang: String, domain: String, totals: Totals) {
+        self.totals.entry((lang, domain)).or_default().add(totals);
+    }
+
+    pub fn merge(mut self, other: Counts) -> Counts {
+        for ((lang, domain), totals) in other.totals {
+            self.add(lang, domain, totals);
+        }
+        self.failed += other.failed;
+        self
+    }
+
+    /// A table of the counts, one line per language and domain, and one
+    /// with the totals.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(s, "lang\tdomain\tdocuments\tsentences\ttokens").expect(w);
+        let mut all = Totals::default();
+        for ((lang, domain), totals) in self.totals.iter() {
+            let domain = if domain.is_empty() { "-" } else { domain };
+            writeln!(
+                s,
+                "{lang}\t{domain}\t{}\t{}\t{}",
+                totals.documents, totals.sentences, totals.tokens
+            )
+            .expect(w);
+            all.add(*totals);
+        }
+        writeln!(
+            s,
+            "total\t\t{}\t{}\t{}",
+            all.documents, all.sentences, all.tokens
+        )
+        .expect(w);
+        if self.failed > 0 {
+            writeln!(s, "{} documents could not be counted", self.failed).expect(w);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Counts, Totals, count_document};
+
+    const DOCUMENT: &str = r#"<?xml version='1.0' encoding='utf8'?>
+<document xml:lang="sme" id="no_id">
+  <header>
+    <title>Ođđasat</title>
+    <genre code="news"/>
+    <conversion_status type="standard"/>
+    <availability><license type="standard"/></availability>
+  </header>
+  <body><dependency><![CDATA[
+"<Mun>"
+	"mun" Pron Pers Sg1 Nom <W:0.0> @SUBJ> #1->2
+"<boađán>"
+	"boahtit" V IV Ind Prs Sg1 <W:0.0> @FMV #2->0
+"<.>"
+	"." CLB <W:0.0> #3->2
+
+]]></dependency></body></document>"#;
+
+    #[test]
+    fn document() {
+        let (lang, domain, totals) = count_document(DOCUMENT, false).unwrap();
+        assert_eq!((lang.as_str(), domain.as_str()), ("sme", "news"));
+        assert_eq!(totals.documents, 1);
+        assert_eq!(totals.tokens, 3);
+        assert!(count_document("<document>", false).is_err());
+    }
+
+    #[test]
+    fn report() {
+        let totals = |sentences, tokens| Totals {
+            documents: 1,
+            sentences,
+            tokens,
+        };
+        let mut counts = Counts::default();
+        counts.add("sme".to_string(), "news".to_string(), totals(2, 10));
+        let mut other = Counts::default();
+        other.add("sme".to_string(), "news".to_string(), totals(1, 4));
+        other.add("sme".to_string(), String::new(), totals(3, 30));
+        other.failed = 1;
+        assert_eq!(
+            counts.merge(other).report(),
+            concat!(
+                "lang\tdomain\tdocuments\tsentences\ttokens\n",
+                "sme\t-\t1\t3\t30\n",
+                "sme\tnews\t2\t3\t14\n",
+                "total\t\t3\t6\t44\n",
+                "1 documents could not be counted\n",
+            )
+        );
+    }
+}
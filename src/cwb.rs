@@ -0,0 +1,374 @@
+//! Encode the converted documents into CWB corpora, with `--cwb-encode`,
+//! by piping them into a `cwb-encode` process per corpus, next to writing
+//! the korp_mono files.
+//!
+//! Each corpus directory becomes a CWB corpus, named after the directory in
+//! lowercase, with `-` replaced by `_` (`corpus-sme-x-closed` becomes
+//! `corpus_sme_x_closed`). Its data is encoded into `{data}/{name}/`, and
+//! its registry file is `{registry}/{name}`. The positional attributes are
+//! the written columns, named as in `--columns`, with the first column as
+//! the `word` attribute. The structural attributes are `<text>`, with all
+//! the attributes it can have, and `<sentence>` with its `id`, `text`,
+//! `lang` and `gt_domain`.
+//!
+//! The documents are piped in the order they are converted, which is not
+//! the order of the files. With `--sort-by`, they are instead kept until
+//! all files are converted, and then piped in order, see
+//! [`crate::text_order`]. This keeps all documents in memory. What
+//! `cwb-encode` writes to stderr is kept, and shown in the report at the
+//! end of the run, with its exit status.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use clap::ValueEnum;
+
+use crate::process_sentence::Column;
+use crate::status_message::ErrorCategory;
+use crate::text_order::{SortBy, TextKey};
+
+/// The attributes of `<text>` that are declared, see
+/// [`crate::korp_mono::KorpMonoFile::attrs`].
+const TEXT_ATTRS: [&str; 18] = [
+    "id",
+    "title",
+    "lang",
+    "orig_lang",
+    "text_type",
+    "first_name",
+    "last_name",
+    "nationality",
+    "authors",
+    "gt_domain",
+    "date",
+    "datefrom",
+    "dateto",
+    "timefrom",
+    "timeto",
+    "source_url",
+    "part",
+    "empty",
+];
+
+/// The name of the CWB corpus of the corpus directory `corpus`.
+pub fn corpus_name(corpus: &Path) -> String {
+    corpus
+        .file_name()
+        .map(|name| name.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// The arguments of `cwb-encode`, for the corpus `name`.
+pub fn encode_args(registry: &Path, data: &Path, name: &str, columns: &[Column]) -> Vec<String> {
+    let mut args = vec![
+        "-d".to_string(),
+        data.join(name).display().to_string(),
+        "-R".to_string(),
+        registry.join(name).display().to_string(),
+        "-c".to_string(),
+        "utf8".to_string(),
+        // xml aware, skip blank lines, strip whitespace from tokens
+        "-xsB".to_string(),
+    ];
+    // the first column is the default `word` attribute
+    for column in columns.iter().skip(1) {
+        let value = column.to_possible_value().expect("no column is skipped");
+        args.push("-P".to_string());
+        args.push(value.get_name().to_string());
+    }
+    args.push("-S".to_string());
+    args.push(format!("text:0+{}", TEXT_ATTRS.join("+")));
+    args.push("-S".to_string());
+    args.push("sentence:0+id+text+lang+gt_domain".to_string());
+    args
+}
+
+/// A running `cwb-encode` process.
+struct Encoder {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    /// Reads stderr, so that a full pipe doesn't block the process
+    stderr: JoinHandle<String>,
+}
+
+impl std::fmt::Debug for Encoder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encoder")
+            .field("pid", &self.child.id())
+            .finish()
+    }
+}
+
+/// How encoding a corpus went.
+#[derive(Debug)]
+pub struct Encoded {
+    pub corpus: PathBuf,
+    pub name: String,
+    pub documents: u64,
+    /// `None` if the process could not be waited for
+    pub status: Option<ExitStatus>,
+    pub stderr: String,
+}
+
+impl Encoded {
+    pub fn success(&self) -> bool {
+        self.status.is_some_and(|status| status.success())
+    }
+}
+
+/// The `cwb-encode` processes of all corpora. Shared between the threads.
+#[derive(Debug)]
+pub struct CwbEncode {
+    pub registry: PathBuf,
+    pub data: PathBuf,
+    /// The `cwb-encode` program
+    pub program: PathBuf,
+    pub columns: Vec<Column>,
+    /// Pipe the documents in this order, when all are converted
+    pub sort_by: Option<SortBy>,
+    /// The encoder, and the number of documents piped into it, by corpus
+    /// directory. Each encoder has its own lock, so that corpora are
+    /// encoded in parallel, and the documents of one corpus one at a time.
+    encoders: Mutex<BTreeMap<PathBuf, Arc<Mutex<Counted>>>>,
+    /// The documents that are piped when all are converted, with
+    /// `sort_by`, by corpus directory
+    pending: Mutex<BTreeMap<PathBuf, Pending>>,
+}
+
+/// An encoder, and the number of documents piped into it.
+type Counted = (Encoder, u64);
+
+/// The columns of a corpus, if it has its own, and its documents, with
+/// their sort keys.
+type Pending = (Option<Vec<Column>>, Vec<(TextKey, Vec<u8>)>);
+
+impl CwbEncode {
+    pub fn new(registry: PathBuf, data: PathBuf, columns: Vec<Column>) -> Self {
+        Self {
+            registry,
+            data,
+            program: PathBuf::from("cwb-encode"),
+            columns,
+            sort_by: None,
+            encoders: Mutex::default(),
+            pending: Mutex::default(),
+        }
+    }
+
+    pub fn with_sort_by(mut self, sort_by: Option<SortBy>) -> Self {
+        self.sort_by = sort_by;
+        self
+    }
+
+    fn spawn(&self, name: &str, columns: &[Column]) -> std::io::Result<Encoder> {
+        std::fs::create_dir_all(self.data.join(name))?;
+        std::fs::create_dir_all(&self.registry)?;
+        let mut child = Command::new(&self.program)
+            .args(encode_args(&self.registry, &self.data, name, columns))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdin = child.stdin.take();
+        let mut pipe = child.stderr.take().expect("stderr is piped");
+        let stderr = std::thread::Builder::new()
+            .name(format!("cwb-encode-{name}"))
+            .spawn(move || {
+                let mut stderr = String::new();
+                let _ = pipe.read_to_string(&mut stderr);
+                stderr
+            })
+            .expect("can spawn thread");
+        Ok(Encoder {
+            child,
+            stdin,
+            stderr,
+        })
+    }
+
+    /// Pipe `vrt`, the korp_mono XML of a document, into the `cwb-encode` of
+    /// the corpus directory `corpus`, or keep it until all documents are
+    /// converted, with `sort_by`. `key` is the sort key of the document, if
+    /// sorted. The process is started for the first document of each
+    /// corpus, with the `columns` of the corpus, if it has its own, see
+    /// [`crate::corpus_settings`].
+    pub fn encode(
+        &self,
+        corpus: &Path,
+        columns: Option<&[Column]>,
+        key: Option<TextKey>,
+        vrt: Vec<u8>,
+    ) -> std::io::Result<()> {
+        let Some(key) = key else {
+            return self.pipe(corpus, columns, &vrt);
+        };
+        let mut pending = self
+            .pending
+            .lock()
+            .expect("no thread panics while holding the lock");
+        let (_, documents) = pending
+            .entry(corpus.to_path_buf())
+            .or_insert_with(|| (columns.map(<[Column]>::to_vec), vec![]));
+        documents.push((key, vrt));
+        Ok(())
+    }
+
+    fn pipe(&self, corpus: &Path, columns: Option<&[Column]>, vrt: &[u8]) -> std::io::Result<()> {
+        let encoder = {
+            let mut encoders = self
+                .encoders
+                .lock()
+                .expect("no thread panics while holding the lock");
+            match encoders.get(corpus) {
+                Some(encoder) => Arc::clone(encoder),
+                None => {
+                    let columns = columns.unwrap_or(&self.columns);
+                    let encoder = self.spawn(&corpus_name(corpus), columns)?;
+                    let encoder = Arc::new(Mutex::new((encoder, 0)));
+                    encoders.insert(corpus.to_path_buf(), Arc::clone(&encoder));
+                    encoder
+                }
+            }
+        };
+        let mut encoder = encoder
+            .lock()
+            .expect("no thread panics while holding the lock");
+        let (encoder, documents) = &mut *encoder;
+        let stdin = encoder
+            .stdin
+            .as_mut()
+            .ok_or_else(|| std::io::Error::other("cwb-encode was closed"))?;
+        stdin.write_all(vrt)?;
+        stdin.write_all(b"\n")?;
+        *documents += 1;
+        Ok(())
+    }
+
+    /// Pipe the sorted documents, close the input of all `cwb-encode`
+    /// processes, and wait for them to finish.
+    pub fn finish(&self) -> Vec<Encoded> {
+        let pending = std::mem::take(
+            &mut *self
+                .pending
+                .lock()
+                .expect("no thread panics while holding the lock"),
+        );
+        for (corpus, (columns, mut documents)) in pending {
+            documents.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (key, vrt) in documents {
+                if let Err(e) = self.pipe(&corpus, columns.as_deref(), &vrt) {
+                    tracing::error!(
+                        error = ?e,
+                        corpus = ?corpus,
+                        document = ?key,
+                        category = ErrorCategory::Write.as_str(),
+                        "can't pipe document to cwb-encode"
+                    );
+                    break;
+                }
+            }
+        }
+        let encoders = std::mem::take(
+            &mut *self
+                .encoders
+                .lock()
+                .expect("no thread panics while holding the lock"),
+        );
+        encoders
+            .into_iter()
+            .map(|(corpus, encoder)| {
+                let (mut encoder, documents) = Arc::into_inner(encoder)
+                    .expect("the pipeline is done")
+                    .into_inner()
+                    .expect("no thread panics while holding the lock");
+                drop(encoder.stdin.take());
+                let status = encoder.child.wait().ok();
+                let stderr = encoder.stderr.join().unwrap_or_default();
+                Encoded {
+                    name: corpus_name(&corpus),
+                    corpus,
+                    documents,
+                    status,
+                    stderr,
+                }
+            })
+            .collect()
+    }
+}
+
+/// A summary of the encoded corpora, for the end of the run, with what
+/// `cwb-encode` wrote to stderr.
+pub fn report(encoded: &[Encoded]) -> String {
+    let mut s = String::new();
+    let w = "can always write to String";
+    for corpus in encoded {
+        let status = match corpus.status {
+            Some(status) => status.to_string(),
+            None => "unknown status".to_string(),
+        };
+        writeln!(
+            s,
+            "cwb-encode {} ({}): {} documents, {status}",
+            corpus.name,
+            corpus.corpus.display(),
+            corpus.documents,
+        )
+        .expect(w);
+        for line in corpus.stderr.lines().filter(|line| !line.trim().is_empty()) {
+            writeln!(s, "  {line}").expect(w);
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{corpus_name, encode_args};
+    use crate::process_sentence::Column;
+    use std::path::Path;
+
+    #[test]
+    fn names() {
+        assert_eq!(corpus_name(Path::new("/c/corpus-sme")), "corpus_sme");
+        assert_eq!(
+            corpus_name(Path::new("/c/corpus-SME-x-closed")),
+            "corpus_sme_x_closed"
+        );
+    }
+
+    #[test]
+    fn args() {
+        let args = encode_args(
+            Path::new("/r"),
+            Path::new("/d"),
+            "corpus_sme",
+            &[Column::Word, Column::Lemma, Column::Dephead],
+        );
+        assert_eq!(
+            args[..7],
+            [
+                "-d",
+                "/d/corpus_sme",
+                "-R",
+                "/r/corpus_sme",
+                "-c",
+                "utf8",
+                "-xsB"
+            ]
+        );
+        assert_eq!(args[7..11], ["-P", "lemma", "-P", "dephead"]);
+        assert!(args[12].starts_with("text:0+id+title+lang+"));
+        assert_eq!(args[14], "sentence:0+id+text+lang+gt_domain");
+    }
+}
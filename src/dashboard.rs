@@ -0,0 +1,379 @@
+//! A dashboard for long runs, with `--tui`, instead of the progress bar.
+//!
+//! The dashboard shows the progress and the expected time left, how many
+//! files per second each stage handles, the most recent errors, and the
+//! files that took the longest to convert. [`Dashboard`] keeps track of all
+//! of this while the pipeline runs. It is drawn with `ratatui`, on the
+//! alternate screen of the terminal, when the crate is built with the `tui`
+//! feature. When stdout is not a terminal, the usual progress bar is used
+//! instead.
+
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::metrics::Metrics;
+
+/// How many of the most recent errors are shown.
+const RECENT_ERRORS: usize = 8;
+/// How many of the slowest files are shown.
+const SLOWEST_FILES: usize = 8;
+
+#[derive(Debug, Default)]
+struct State {
+    converted: u64,
+    failed: u64,
+    recent_errors: VecDeque<String>,
+    /// Slowest first
+    slowest: Vec<(Duration, PathBuf)>,
+}
+
+/// What the dashboard shows. Shared between the threads. Only the errors and
+/// the files are recorded without the `tui` feature.
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+pub struct Dashboard {
+    files: usize,
+    start: Instant,
+    /// The stages are measured here, see [`crate::pipeline::Measured`]
+    metrics: Arc<Metrics>,
+    state: Mutex<State>,
+}
+
+impl Dashboard {
+    pub fn new(files: usize, metrics: Arc<Metrics>) -> Self {
+        Self {
+            files,
+            start: Instant::now(),
+            metrics,
+            state: Mutex::default(),
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .expect("no thread panics while holding the lock")
+    }
+
+    /// The file `path` went through the pipeline in `duration`, and was
+    /// converted, or not.
+    pub fn file_done(&self, path: PathBuf, duration: Duration, converted: bool) {
+        let mut state = self.state();
+        match converted {
+            true => state.converted += 1,
+            false => state.failed += 1,
+        }
+        let slowest = &mut state.slowest;
+        if slowest.len() < SLOWEST_FILES || slowest.last().is_some_and(|(d, _)| *d < duration) {
+            let i = slowest.partition_point(|(d, _)| *d >= duration);
+            slowest.insert(i, (duration, path));
+            slowest.truncate(SLOWEST_FILES);
+        }
+    }
+
+    /// An error was logged.
+    pub fn error(&self, message: String) {
+        let mut state = self.state();
+        if state.recent_errors.len() == RECENT_ERRORS {
+            state.recent_errors.pop_front();
+        }
+        state.recent_errors.push_back(message);
+    }
+
+    /// The number of files that are done, converted or not.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    fn done(&self) -> u64 {
+        let state = self.state();
+        state.converted + state.failed
+    }
+
+    /// The expected time left, from the average time per file so far.
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
+    fn eta(&self, elapsed: Duration) -> Option<Duration> {
+        let done = self.done();
+        if done == 0 {
+            return None;
+        }
+        let left = (self.files as u64).saturating_sub(done);
+        Some(elapsed.mul_f64(left as f64 / done as f64))
+    }
+}
+
+/// Shows the error events on the [`Dashboard`].
+pub struct DashboardLayer {
+    pub dashboard: Arc<Dashboard>,
+}
+
+impl DashboardLayer {
+    pub fn new(dashboard: Arc<Dashboard>) -> Self {
+        Self { dashboard }
+    }
+}
+
+#[derive(Default)]
+struct ErrorVisitor {
+    message: String,
+    file: Option<String>,
+}
+
+impl Visit for ErrorVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        match field.name() {
+            "message" => self.message = format!("{value:?}"),
+            // paths are logged with their Debug impl, which quotes them
+            "file" => self.file = Some(format!("{value:?}").trim_matches('"').to_string()),
+            _ => {}
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for DashboardLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        let mut visitor = ErrorVisitor::default();
+        event.record(&mut visitor);
+        let message = match visitor.file {
+            Some(file) => format!("{file}: {}", visitor.message),
+            None => visitor.message,
+        };
+        self.dashboard.error(message);
+    }
+}
+
+/// `1h02m03s`, `2m03s` or `3s`
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match (secs / 3600, secs / 60 % 60, secs % 60) {
+        (0, 0, s) => format!("{s}s"),
+        (0, m, s) => format!("{m}m{s:02}s"),
+        (h, m, s) => format!("{h}h{m:02}m{s:02}s"),
+    }
+}
+
+#[cfg(feature = "tui")]
+mod render {
+    use std::io::Stdout;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+
+    use ratatui::Frame;
+    use ratatui::Terminal;
+    use ratatui::backend::CrosstermBackend;
+    use ratatui::crossterm::{cursor, execute, terminal};
+    use ratatui::layout::{Constraint, Layout};
+    use ratatui::widgets::{Block, Gauge, List, Row, Table};
+
+    use super::{Dashboard, format_duration};
+
+    /// How often the dashboard is redrawn.
+    const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+    impl Dashboard {
+        fn draw(&self, frame: &mut Frame) {
+            let elapsed = self.start.elapsed();
+            let [progress, stages, errors, slowest] = Layout::vertical([
+                Constraint::Length(3),
+                Constraint::Min(6),
+                Constraint::Length(super::RECENT_ERRORS as u16 + 2),
+                Constraint::Length(super::SLOWEST_FILES as u16 + 3),
+            ])
+            .areas(frame.area());
+
+            let done = self.done();
+            let eta = match self.eta(elapsed) {
+                Some(eta) => format_duration(eta),
+                None => "-".to_string(),
+            };
+            let (converted, failed) = {
+                let state = self.state();
+                (state.converted, state.failed)
+            };
+            let ratio = match self.files {
+                0 => 1.0,
+                files => (done as f64 / files as f64).min(1.0),
+            };
+            let label = format!(
+                "{done}/{} files, {converted} converted, {failed} not converted, {} elapsed, {eta} left",
+                self.files,
+                format_duration(elapsed),
+            );
+            frame.render_widget(
+                Gauge::default()
+                    .block(Block::bordered().title("Progress"))
+                    .ratio(ratio)
+                    .label(label),
+                progress,
+            );
+
+            let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+            let rows = self
+                .metrics
+                .stages()
+                .into_iter()
+                .map(|(name, runs, failures, seconds)| {
+                    let average = match runs {
+                        0 => 0.0,
+                        runs => seconds * 1000.0 / runs as f64,
+                    };
+                    Row::new(vec![
+                        name.to_string(),
+                        runs.to_string(),
+                        failures.to_string(),
+                        format!("{:.1}", runs as f64 / secs),
+                        format!("{average:.1}"),
+                    ])
+                });
+            let widths = [
+                Constraint::Min(20),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(10),
+            ];
+            frame.render_widget(
+                Table::new(rows, widths)
+                    .header(Row::new(vec![
+                        "stage", "files", "failures", "files/s", "ms/file",
+                    ]))
+                    .block(Block::bordered().title("Stages")),
+                stages,
+            );
+
+            let state = self.state();
+            frame.render_widget(
+                List::new(state.recent_errors.iter().map(String::as_str))
+                    .block(Block::bordered().title("Recent errors")),
+                errors,
+            );
+            let rows = state.slowest.iter().map(|(duration, path)| {
+                Row::new(vec![
+                    format!("{:.1}s", duration.as_secs_f64()),
+                    path.display().to_string(),
+                ])
+            });
+            frame.render_widget(
+                Table::new(rows, [Constraint::Length(10), Constraint::Min(20)])
+                    .header(Row::new(vec!["time", "file"]))
+                    .block(Block::bordered().title("Slowest files")),
+                slowest,
+            );
+        }
+
+        /// Draw the dashboard on the alternate screen, until the returned
+        /// handle is finished. The terminal is not put in raw mode, so that
+        /// Ctrl-C still stops the run.
+        pub fn show(self: Arc<Self>) -> std::io::Result<DashboardHandle> {
+            let mut stdout = std::io::stdout();
+            execute!(stdout, terminal::EnterAlternateScreen, cursor::Hide)?;
+            let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+            terminal.clear()?;
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread = {
+                let stop = Arc::clone(&stop);
+                std::thread::Builder::new()
+                    .name("dashboard".to_string())
+                    .spawn(move || {
+                        while !stop.load(Ordering::Relaxed) {
+                            terminal.draw(|frame| self.draw(frame))?;
+                            std::thread::sleep(REDRAW_INTERVAL);
+                        }
+                        Ok(terminal)
+                    })
+                    .expect("can spawn thread")
+            };
+            Ok(DashboardHandle { stop, thread })
+        }
+    }
+
+    /// The thread drawing the dashboard.
+    pub struct DashboardHandle {
+        stop: Arc<AtomicBool>,
+        thread: JoinHandle<std::io::Result<Terminal<CrosstermBackend<Stdout>>>>,
+    }
+
+    impl DashboardHandle {
+        /// Stop drawing, and go back to the normal screen.
+        pub fn finish(self) -> std::io::Result<()> {
+            self.stop.store(true, Ordering::Relaxed);
+            let terminal = match self.thread.join() {
+                Ok(terminal) => terminal,
+                Err(e) => std::panic::resume_unwind(e),
+            };
+            let mut stdout = std::io::stdout();
+            execute!(stdout, terminal::LeaveAlternateScreen, cursor::Show)?;
+            terminal.map(|_| ())
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+pub use render::DashboardHandle;
+
+#[cfg(test)]
+mod tests {
+    use super::{Dashboard, format_duration};
+    use crate::metrics::Metrics;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn slowest_and_eta() {
+        let dashboard = Dashboard::new(20, Arc::new(Metrics::default()));
+        assert_eq!(dashboard.eta(Duration::from_secs(1)), None);
+        for i in 0..10 {
+            let path = PathBuf::from(format!("{i}.xml"));
+            dashboard.file_done(path, Duration::from_millis(i * 10), i != 3);
+        }
+        let state = dashboard.state();
+        assert_eq!(state.converted, 9);
+        assert_eq!(state.failed, 1);
+        let slowest = state
+            .slowest
+            .iter()
+            .map(|(_, path)| path.display().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            slowest,
+            [
+                "9.xml", "8.xml", "7.xml", "6.xml", "5.xml", "4.xml", "3.xml", "2.xml"
+            ]
+        );
+        drop(state);
+        assert_eq!(
+            dashboard.eta(Duration::from_secs(10)),
+            Some(Duration::from_secs(10))
+        );
+    }
+
+    #[test]
+    fn recent_errors() {
+        let dashboard = Dashboard::new(0, Arc::new(Metrics::default()));
+        for i in 0..10 {
+            dashboard.error(format!("error {i}"));
+        }
+        let state = dashboard.state();
+        assert_eq!(state.recent_errors.len(), 8);
+        assert_eq!(
+            state.recent_errors.front().map(String::as_str),
+            Some("error 2")
+        );
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(Duration::from_secs(3)), "3s");
+        assert_eq!(format_duration(Duration::from_secs(123)), "2m03s");
+        assert_eq!(format_duration(Duration::from_secs(3723)), "1h02m03s");
+    }
+}
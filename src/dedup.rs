@@ -0,0 +1,136 @@
+//! Find documents that have the same content under different names.
+//!
+//! The corpus trees contain some documents more than once, which inflates
+//! the counts in Korp. Documents are identified by a hash of their
+//! `<dependency>` blob. The first file seen with some content is the
+//! original, all later ones are duplicates of it. Since files are processed
+//! in parallel, which one of them is seen first can differ between runs.
+//!
+//! The duplicates are reported per corpus, in a `korp_mono.duplicates` file
+//! in the corpus directory, one line per duplicate, with the path of the
+//! duplicate and the original, relative to the corpus directory.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::hash::{DefaultHasher, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::korp_mono::path::corpus_dir;
+
+/// Name of the report file, written to the corpus directory.
+pub const REPORT_FILE_NAME: &str = "korp_mono.duplicates";
+
+/// What to do with documents that are duplicates of another document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum DuplicatePolicy {
+    /// Convert them, and list them in the report
+    #[default]
+    Report,
+    /// Don't convert them, only list them in the report
+    Skip,
+}
+
+/// Hash of the content of a document, from its `<dependency>` blob. Empty
+/// documents have no content hash, they are not duplicates of each other.
+pub fn content_hash(dependency: &str) -> Option<u64> {
+    let dependency = dependency.trim();
+    if dependency.is_empty() {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    hasher.write(dependency.as_bytes());
+    Some(hasher.finish())
+}
+
+#[derive(Debug, Default)]
+pub struct Duplicates {
+    /// The first file seen with each content hash
+    seen: HashMap<u64, PathBuf>,
+    /// Duplicate file, and its original, by corpus directory
+    corpora: BTreeMap<PathBuf, BTreeMap<PathBuf, PathBuf>>,
+}
+
+impl Duplicates {
+    /// Record that `analysed_file` has the content hash `hash`. Returns the
+    /// file that was first seen with the same content, if any.
+    pub fn check(&mut self, analysed_file: PathBuf, hash: u64) -> Option<PathBuf> {
+        let original = match self.seen.get(&hash) {
+            Some(original) => original.clone(),
+            None => {
+                self.seen.insert(hash, analysed_file);
+                return None;
+            }
+        };
+        let corpus = corpus_dir(&analysed_file)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        self.corpora
+            .entry(corpus)
+            .or_default()
+            .insert(analysed_file, original.clone());
+        Some(original)
+    }
+
+    /// The report of a single corpus.
+    fn report(corpus: &Path, files: &BTreeMap<PathBuf, PathBuf>) -> String {
+        let relative = |path: &Path| {
+            path.strip_prefix(corpus)
+                .unwrap_or(path)
+                .display()
+                .to_string()
+        };
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(s, "# korp_mono duplicates for {}", corpus.display()).expect(w);
+        writeln!(s, "# duplicate files: {}", files.len()).expect(w);
+        for (duplicate, original) in files.iter() {
+            writeln!(s, "{}\t{}", relative(duplicate), relative(original)).expect(w);
+        }
+        s
+    }
+
+    /// Write the report of each corpus to `{corpus}/korp_mono.duplicates`.
+    pub fn write_reports(&self) -> std::io::Result<()> {
+        for (corpus, files) in self.corpora.iter() {
+            std::fs::write(corpus.join(REPORT_FILE_NAME), Self::report(corpus, files))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Duplicates, content_hash};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn hashes() {
+        assert_eq!(content_hash(""), None);
+        assert_eq!(content_hash(" \n"), None);
+        assert_eq!(content_hash("\n\"<a>\"\n"), content_hash("\"<a>\""));
+        assert_ne!(content_hash("\"<a>\""), content_hash("\"<b>\""));
+    }
+
+    #[test]
+    fn first_seen_is_original() {
+        let corpus = Path::new("/c/corpus-sme");
+        let a = corpus.join("analysed/news/a.xml");
+        let b = corpus.join("analysed/news/b.xml");
+        let c = corpus.join("analysed/admin/c.xml");
+
+        let mut duplicates = Duplicates::default();
+        assert_eq!(duplicates.check(a.clone(), 1), None);
+        assert_eq!(duplicates.check(b.clone(), 2), None);
+        assert_eq!(duplicates.check(c.clone(), 1), Some(a.clone()));
+
+        let files = &duplicates.corpora[&PathBuf::from(corpus)];
+        assert_eq!(
+            Duplicates::report(corpus, files),
+            concat!(
+                "# korp_mono duplicates for /c/corpus-sme\n",
+                "# duplicate files: 1\n",
+                "analysed/admin/c.xml\tanalysed/news/a.xml\n",
+            )
+        );
+    }
+}
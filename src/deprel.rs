@@ -0,0 +1,183 @@
+//! Normalize the functional labels of the tokens to a small, closed set,
+//! with `--normalize-deprel`, so that they can be searched for in Korp
+//! without knowing all the variants of the analysers (`-F←OBJ`, `←OBJ`,
+//! `OBJ→`, `@OBJ`, ...).
+//!
+//! The labels are:
+//!
+//! | label   | for                                              |
+//! |---------|--------------------------------------------------|
+//! | `subj`  | subjects                                         |
+//! | `obj`   | objects                                          |
+//! | `pred`  | subject and object predicatives                  |
+//! | `advl`  | adverbials                                       |
+//! | `mod`   | modifiers of nouns, adjectives and adverbs       |
+//! | `comp`  | complements of adpositions and adjectives        |
+//! | `verb`  | main verbs, finite or not                        |
+//! | `aux`   | auxiliary verbs                                  |
+//! | `conj`  | conjunctions and conjoined phrases               |
+//! | `head`  | heads of free-standing noun phrases              |
+//! | `voc`   | vocatives and interjections                      |
+//! | `punct` | punctuation                                      |
+//! | `other` | everything else                                  |
+//!
+//! A label is mapped by itself first, and otherwise without the direction
+//! arrows, and the `-F` (of non-finite clauses) and `@` prefixes. So
+//! `-F←OBJ` is `obj`, unless `-F←OBJ` is mapped to something else. The
+//! built-in mapping covers the labels of the Giella analysers. It can be
+//! extended or changed with `--deprel-map`, a file of `label<TAB>normalized`
+//! lines, where `normalized` must be one of the labels above. Everything
+//! after a `#` is a comment.
+//!
+//! Tokens without an analysis keep their label `X`. The label before
+//! normalization can be kept in a column of its own, with
+//! `--raw-deprel-column`.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Context;
+
+/// The label of the functional labels that are not mapped to anything else.
+pub const OTHER: &str = "other";
+
+/// The labels that functional labels are normalized to.
+pub const LABELS: [&str; 13] = [
+    "subj", "obj", "pred", "advl", "mod", "comp", "verb", "aux", "conj", "head", "voc", "punct",
+    OTHER,
+];
+
+/// The built-in mapping, of the labels without arrows and prefixes.
+const GIELLA_LABELS: &[(&str, &str)] = &[
+    ("SUBJ", "subj"),
+    ("OBJ", "obj"),
+    ("SPRED", "pred"),
+    ("OPRED", "pred"),
+    ("PPRED", "pred"),
+    ("ADVL", "advl"),
+    ("N", "mod"),
+    ("A", "mod"),
+    ("Adv", "mod"),
+    ("Num", "mod"),
+    ("Pron", "mod"),
+    ("P", "comp"),
+    ("Pr", "comp"),
+    ("Po", "comp"),
+    ("FMV", "verb"),
+    ("IMV", "verb"),
+    ("FMVdic", "verb"),
+    ("FAUX", "aux"),
+    ("IAUX", "aux"),
+    ("CNP", "conj"),
+    ("CVP", "conj"),
+    ("CC", "conj"),
+    ("CS", "conj"),
+    ("HNOUN", "head"),
+    ("HAB", "head"),
+    ("APP", "head"),
+    ("VOC", "voc"),
+    ("INTERJ", "voc"),
+    ("CLB", "punct"),
+    ("PUNCT", "punct"),
+];
+
+/// The label `func` without the direction arrows, and the `-F` and `@`
+/// prefixes.
+fn bare_label(func: &str) -> &str {
+    let func = func.trim_start_matches('@');
+    let func = func.strip_prefix("-F").unwrap_or(func);
+    func.trim_matches(['←', '→', '<', '>'])
+}
+
+#[derive(Debug, Clone)]
+pub struct DeprelMap {
+    labels: HashMap<String, &'static str>,
+}
+
+impl Default for DeprelMap {
+    fn default() -> Self {
+        let labels = GIELLA_LABELS
+            .iter()
+            .map(|(func, label)| (func.to_string(), *label))
+            .collect();
+        Self { labels }
+    }
+}
+
+impl DeprelMap {
+    /// The built-in mapping, extended with the mapping file at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read deprel map '{}'", path.display()))?;
+        let mut map = Self::default();
+        map.extend_from_list(&contents)
+            .with_context(|| format!("invalid deprel map '{}'", path.display()))?;
+        Ok(map)
+    }
+
+    fn extend_from_list(&mut self, contents: &str) -> anyhow::Result<()> {
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(entry, _)| entry).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((func, label)) = line.split_once('\t') else {
+                anyhow::bail!(
+                    "line {}: expected 'label<TAB>normalized', got '{line}'",
+                    i + 1
+                );
+            };
+            let label = label.trim();
+            let Some(label) = LABELS.iter().find(|known| **known == label) else {
+                anyhow::bail!(
+                    "line {}: '{label}' is not one of the labels {}",
+                    i + 1,
+                    LABELS.join(", ")
+                );
+            };
+            self.labels.insert(func.trim().to_string(), *label);
+        }
+        Ok(())
+    }
+
+    /// The normalized label of the functional label `func`.
+    pub fn normalize(&self, func: &str) -> &'static str {
+        self.labels
+            .get(func)
+            .or_else(|| self.labels.get(bare_label(func)))
+            .copied()
+            .unwrap_or(OTHER)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeprelMap;
+
+    #[test]
+    fn normalize() {
+        let map = DeprelMap::default();
+        assert_eq!(map.normalize("-F←OBJ"), "obj");
+        assert_eq!(map.normalize("←SUBJ"), "subj");
+        assert_eq!(map.normalize("SPRED→"), "pred");
+        assert_eq!(map.normalize("@ADVL"), "advl");
+        assert_eq!(map.normalize("N→"), "mod");
+        assert_eq!(map.normalize("FMV"), "verb");
+        assert_eq!(map.normalize("@X"), "other");
+        assert_eq!(map.normalize(""), "other");
+    }
+
+    #[test]
+    fn mapping_file() {
+        let mut map = DeprelMap::default();
+        map.extend_from_list("# sma\n-F←OBJ\tverb\nCOMP-CS←\tconj  # comparison\n")
+            .unwrap();
+        assert_eq!(map.normalize("-F←OBJ"), "verb");
+        assert_eq!(map.normalize("←OBJ"), "obj");
+        assert_eq!(map.normalize("COMP-CS←"), "conj");
+
+        assert!(map.extend_from_list("OBJ\tobject\n").is_err());
+        assert!(map.extend_from_list("OBJ obj\n").is_err());
+    }
+}
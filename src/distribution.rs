@@ -0,0 +1,135 @@
+//! How the tokens of the converted documents are spread over the domains and
+//! the years, with `--distribution`.
+//!
+//! The tables are printed at the end of the run, so that it is easy to see
+//! if the corpus is made up as expected, e.g. that a domain isn't missing
+//! because of a broken header, or that most texts aren't of an unknown
+//! year.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::korp_mono::KorpMonoFile;
+
+/// The key of documents without a domain, or a known year.
+pub const UNKNOWN: &str = "unknown";
+
+/// The documents and tokens of one domain, or one year.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct Share {
+    documents: u64,
+    tokens: u64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Distribution {
+    by_domain: BTreeMap<String, Share>,
+    by_year: BTreeMap<String, Share>,
+}
+
+/// The year of the `datefrom` attribute `datefrom` (`YYYYMMDD`), or `None`
+/// if the date is unknown (`00000000`).
+fn year(datefrom: &str) -> Option<&str> {
+    datefrom
+        .get(..4)
+        .filter(|year| *year != "0000" && year.bytes().all(|b| b.is_ascii_digit()))
+}
+
+impl Distribution {
+    /// Count the tokens of the converted `document`.
+    pub fn add_document(&mut self, document: &KorpMonoFile) {
+        let tokens = document
+            .sentence
+            .iter()
+            .map(|sentence| sentence.rows.len() as u64)
+            .sum::<u64>();
+        let domain = document
+            .gt_domain
+            .as_deref()
+            .filter(|domain| !domain.is_empty())
+            .unwrap_or(UNKNOWN);
+        let year = document
+            .datefrom
+            .as_deref()
+            .and_then(year)
+            .unwrap_or(UNKNOWN);
+        for share in [
+            self.by_domain.entry(domain.to_string()).or_default(),
+            self.by_year.entry(year.to_string()).or_default(),
+        ] {
+            share.documents += 1;
+            share.tokens += tokens;
+        }
+    }
+
+    fn table(s: &mut String, key: &str, shares: &BTreeMap<String, Share>) {
+        let w = "can always write to String";
+        let total = shares.values().map(|share| share.tokens).sum::<u64>();
+        writeln!(s, "{key}\tdocuments\ttokens\t%").expect(w);
+        for (name, share) in shares.iter() {
+            let percent = match total {
+                0 => 0.0,
+                total => share.tokens as f64 * 100.0 / total as f64,
+            };
+            writeln!(
+                s,
+                "{name}\t{}\t{}\t{percent:.1}",
+                share.documents, share.tokens
+            )
+            .expect(w);
+        }
+    }
+
+    /// The tokens per domain, and per year, for the end of the run. The
+    /// unknown years are listed last.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        Self::table(&mut s, "domain", &self.by_domain);
+        s.push('\n');
+        // the years sort before "unknown", as they are digits
+        Self::table(&mut s, "year", &self.by_year);
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Distribution, year};
+    use crate::korp_mono::KorpMonoFile;
+
+    fn document(domain: Option<&str>, datefrom: &str, tokens: usize) -> KorpMonoFile {
+        KorpMonoFile {
+            gt_domain: domain.map(str::to_string),
+            datefrom: Some(datefrom.to_string()),
+            ..KorpMonoFile::with_sentences(&[&"a\tb\n".repeat(tokens)])
+        }
+    }
+
+    #[test]
+    fn years() {
+        assert_eq!(year("20180101"), Some("2018"));
+        assert_eq!(year("00000000"), None);
+        assert_eq!(year("20"), None);
+    }
+
+    #[test]
+    fn report() {
+        let mut distribution = Distribution::default();
+        distribution.add_document(&document(Some("news"), "20180101", 3));
+        distribution.add_document(&document(Some("news"), "20190101", 1));
+        distribution.add_document(&document(None, "00000000", 4));
+        assert_eq!(
+            distribution.report(),
+            concat!(
+                "domain\tdocuments\ttokens\t%\n",
+                "news\t2\t4\t50.0\n",
+                "unknown\t1\t4\t50.0\n",
+                "\n",
+                "year\tdocuments\ttokens\t%\n",
+                "2018\t1\t3\t37.5\n",
+                "2019\t1\t1\t12.5\n",
+                "unknown\t1\t4\t50.0\n",
+            )
+        );
+    }
+}
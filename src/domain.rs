@@ -0,0 +1,115 @@
+//! Infer the `gt_domain` of documents without a `<genre>`, from the
+//! directory they are in, with `--infer-domain`.
+//!
+//! The corpora are mostly organised by genre, so the first directory inside
+//! `analysed/` (`admin/`, `news/`, `science/`, ...) is taken as the genre.
+//! Other layouts can be given with `--domain-map`, a file with one
+//! `path<TAB>domain` line per directory, where `path` is relative to
+//! `analysed/`, e.g. `facta/skuvla<TAB>science`. The longest matching path
+//! wins, and the first directory is only used when no path in the map
+//! matches. Everything after a `#` is a comment.
+
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::korp_mono::file::genre_map;
+use crate::path_filter::analysed_relative;
+
+#[derive(Debug, Default, Clone)]
+pub struct DomainMap {
+    /// `(path, domain)`, longest path first
+    paths: Vec<(String, String)>,
+}
+
+impl DomainMap {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read domain map '{}'", path.display()))?;
+        Self::from_list(&contents)
+            .with_context(|| format!("invalid domain map '{}'", path.display()))
+    }
+
+    fn from_list(contents: &str) -> anyhow::Result<Self> {
+        let mut paths = vec![];
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.split_once('#').map_or(line, |(entry, _)| entry).trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((path, domain)) = line.split_once('\t') else {
+                anyhow::bail!("line {}: expected 'path<TAB>domain', got '{line}'", i + 1);
+            };
+            let path = path.trim().trim_start_matches("./").trim_end_matches('/');
+            paths.push((path.to_string(), domain.trim().to_string()));
+        }
+        paths.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+        Ok(Self { paths })
+    }
+
+    /// The domain of the analysed file `path`, if it can be told from the
+    /// directory it is in.
+    pub fn domain_of(&self, path: &Path) -> Option<String> {
+        let relative = analysed_relative(path);
+        let mapped = self.paths.iter().find(|(prefix, _)| {
+            relative
+                .strip_prefix(prefix.as_str())
+                .is_some_and(|rest| rest.starts_with('/'))
+        });
+        if let Some((_, domain)) = mapped {
+            return Some(domain.clone());
+        }
+        let (first_dir, _) = relative.split_once('/')?;
+        Some(genre_map(first_dir))
+            .filter(|domain| !domain.is_empty())
+            .map(str::to_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DomainMap;
+    use std::path::Path;
+
+    #[test]
+    fn from_directory() {
+        let map = DomainMap::default();
+        let domain = |path: &str| map.domain_of(Path::new(path));
+        assert_eq!(
+            domain("corpus-sme/analysed/admin/sd/a.xml").as_deref(),
+            Some("administration")
+        );
+        assert_eq!(
+            domain("corpus-sme/analysed/news/avvir/a.xml").as_deref(),
+            Some("news")
+        );
+        assert_eq!(domain("corpus-sme/analysed/misc/a.xml"), None);
+        assert_eq!(domain("corpus-sme/analysed/a.xml"), None);
+    }
+
+    #[test]
+    fn from_map() {
+        let map = DomainMap::from_list(concat!(
+            "# domains of the other directories\n",
+            "facta\tfacts\n",
+            "facta/skuvla/\tscience  # school books\n",
+        ))
+        .unwrap();
+        let domain = |path: &str| map.domain_of(Path::new(path));
+        assert_eq!(
+            domain("corpus-sme/analysed/facta/skuvla/a.xml").as_deref(),
+            Some("science")
+        );
+        assert_eq!(
+            domain("corpus-sme/analysed/facta/a.xml").as_deref(),
+            Some("facts")
+        );
+        assert_eq!(domain("corpus-sme/analysed/factabc/a.xml"), None);
+        assert_eq!(
+            domain("corpus-sme/analysed/news/a.xml").as_deref(),
+            Some("news")
+        );
+        assert!(DomainMap::from_list("no tab").is_err());
+    }
+}
@@ -0,0 +1,195 @@
+//! What to do with documents and sentences without any tokens.
+//!
+//! A `<dependency>` blob can parse to sentences without any tokens, or to no
+//! sentences at all, and some Korp importers break on a `<text>` without
+//! sentences. By default the empty sentences are left out, and documents
+//! without sentences are not written. With `mark`, both are kept, and the
+//! empty documents get an `empty="yes"` attribute, so that they can be found
+//! later. Either way, they are counted, and reported at the end of the run.
+//!
+//! Documents with only a token or two, often OCR garbage, can be skipped
+//! too, with `--min-tokens`. Their tokens are counted right after the
+//! analyses are parsed, see [`count_tokens`], and the documents that are
+//! skipped are counted in [`ShortDocuments`].
+
+use std::fmt::Write;
+
+use crate::analysed::sentence::{Sentence, SentencePart};
+use crate::korp_mono::KorpMonoFile;
+
+/// The value of the `empty` attribute of empty documents, with
+/// [`EmptyPolicy::Mark`].
+pub const EMPTY_MARKER: &str = "yes";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum EmptyPolicy {
+    /// Leave out empty sentences, and don't write empty documents
+    #[default]
+    Skip,
+    /// Keep empty sentences, and write empty documents with an
+    /// `empty="yes"` attribute
+    Mark,
+}
+
+/// The number of empty documents and sentences that were seen.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EmptyCounts {
+    pub documents: u64,
+    pub sentences: u64,
+}
+
+impl EmptyCounts {
+    /// Apply `policy` to the empty sentences of `document`, and count them.
+    /// Returns `false` if the document is empty, and should not be written.
+    pub fn apply(&mut self, document: &mut KorpMonoFile, policy: EmptyPolicy) -> bool {
+        let empty = document
+            .sentence
+            .iter()
+            .filter(|sentence| sentence.rows.is_empty())
+            .count();
+        self.sentences += empty as u64;
+        if empty < document.sentence.len() {
+            if policy == EmptyPolicy::Skip {
+                document
+                    .sentence
+                    .retain(|sentence| !sentence.rows.is_empty());
+            }
+            return true;
+        }
+        self.documents += 1;
+        match policy {
+            EmptyPolicy::Skip => false,
+            EmptyPolicy::Mark => {
+                document.empty = Some(EMPTY_MARKER.to_string());
+                true
+            }
+        }
+    }
+
+    /// A summary for the end of the run.
+    pub fn report(&self, policy: EmptyPolicy) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        let (documents, sentences) = match policy {
+            EmptyPolicy::Skip => ("skipped", "left out"),
+            EmptyPolicy::Mark => ("marked", "kept"),
+        };
+        writeln!(s, "{} empty documents {documents}", self.documents).expect(w);
+        writeln!(s, "{} empty sentences {sentences}", self.sentences).expect(w);
+        s
+    }
+}
+
+/// The number of tokens in the parsed `sentences`, not counting the `¶`
+/// markers, which are left out of the output.
+pub fn count_tokens(sentences: &[Sentence]) -> usize {
+    sentences
+        .iter()
+        .flat_map(|sentence| sentence.parts.iter())
+        .filter(|part| match part {
+            SentencePart::Cohort(cohort) => cohort.word_form != "¶",
+            SentencePart::CohortSeparator(_) => false,
+        })
+        .count()
+}
+
+/// The documents that were skipped for having fewer tokens than the
+/// minimum.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ShortDocuments {
+    pub documents: u64,
+    pub tokens: u64,
+}
+
+impl ShortDocuments {
+    pub fn add(&mut self, tokens: usize) {
+        self.documents += 1;
+        self.tokens += tokens as u64;
+    }
+
+    /// A summary for the end of the run.
+    pub fn report(&self, min_tokens: usize) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(
+            s,
+            "{} documents with fewer than {min_tokens} tokens skipped ({} tokens)",
+            self.documents, self.tokens
+        )
+        .expect(w);
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EmptyCounts, EmptyPolicy, ShortDocuments, count_tokens};
+    use crate::analysed::sentence;
+    use crate::korp_mono::KorpMonoFile;
+
+    const TOKEN: &str = "Mun\tmun\tPron\tPron.Pers.Sg1.Nom\t1\tSUBJ→\t2\n";
+
+    #[test]
+    fn skip() {
+        let mut counts = EmptyCounts::default();
+        let mut doc = KorpMonoFile::with_sentences(&[TOKEN, "", TOKEN]);
+        assert!(counts.apply(&mut doc, EmptyPolicy::Skip));
+        let ids = doc
+            .sentence
+            .iter()
+            .map(|s| s.id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["1", "3"]);
+        assert!(!counts.apply(
+            &mut KorpMonoFile::with_sentences(&["\n"]),
+            EmptyPolicy::Skip
+        ));
+        assert!(!counts.apply(&mut KorpMonoFile::with_sentences(&[]), EmptyPolicy::Skip));
+        assert_eq!(
+            counts,
+            EmptyCounts {
+                documents: 2,
+                sentences: 2
+            }
+        );
+        assert_eq!(
+            counts.report(EmptyPolicy::Skip),
+            "2 empty documents skipped\n2 empty sentences left out\n"
+        );
+    }
+
+    #[test]
+    fn mark() {
+        let mut counts = EmptyCounts::default();
+        let mut doc = KorpMonoFile::with_sentences(&[TOKEN, ""]);
+        assert!(counts.apply(&mut doc, EmptyPolicy::Mark));
+        assert_eq!(doc.sentence.len(), 2);
+        assert_eq!(doc.empty, None);
+        let mut doc = KorpMonoFile::with_sentences(&[""]);
+        assert!(counts.apply(&mut doc, EmptyPolicy::Mark));
+        assert_eq!(doc.empty.as_deref(), Some("yes"));
+        assert_eq!(counts.documents, 1);
+        assert_eq!(counts.sentences, 2);
+    }
+
+    #[test]
+    fn short_documents() {
+        let blob = concat!(
+            "\"<Mun>\"\n\t\"mun\" Pron Pers Sg1 Nom <W:0.0> @SUBJ> #1->2\n",
+            "\"<boađán>\"\n\t\"boahtit\" V IV Ind Prs Sg1 <W:0.0> @FMV #2->0\n",
+            "\"<¶>\"\n\t\"¶\" CLB <W:0.0> #3->2\n",
+        );
+        let (_, parsed) = giellacgparser::parse_sentences(blob).unwrap();
+        let sentences = sentence::Sentence::all(&parsed.sentences, blob);
+        assert_eq!(count_tokens(&sentences), 2);
+        assert_eq!(count_tokens(&[]), 0);
+
+        let mut short = ShortDocuments::default();
+        short.add(2);
+        short.add(0);
+        assert_eq!(
+            short.report(3),
+            "2 documents with fewer than 3 tokens skipped (2 tokens)\n"
+        );
+    }
+}
@@ -0,0 +1,243 @@
+//! Reading analysed files that are not the plain UTF-8 they say they are.
+//!
+//! Some analysed files declare `encoding='utf8'`, but start with a UTF-8
+//! byte order mark, or are really Latin-1, and fail to parse. The bytes of
+//! each file are sniffed before they are parsed: a byte order mark is
+//! removed (and a UTF-16 file is transcoded), and a file that is not valid
+//! UTF-8 is read as Latin-1, in which every byte is a character. Each file
+//! that needed any of this gets a warning that says what was found, and how
+//! it differs from its XML declaration, see [`Sniffed::note`].
+
+use std::path::Path;
+
+/// An encoding that the analysed files can be read in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin-1",
+        }
+    }
+
+    /// The encoding of the `encoding` of an XML declaration, like `utf8`,
+    /// `UTF-8` or `ISO-8859-1`, if it is one of those that can be read.
+    pub fn from_label(label: &str) -> Option<Self> {
+        let label = label.to_ascii_lowercase().replace(['-', '_', ' '], "");
+        match label.as_str() {
+            "utf8" => Some(Encoding::Utf8),
+            "utf16le" => Some(Encoding::Utf16Le),
+            "utf16be" => Some(Encoding::Utf16Be),
+            "latin1" | "l1" | "iso88591" | "isolatin1" | "cp819" => Some(Encoding::Latin1),
+            _ => None,
+        }
+    }
+}
+
+/// What was found out about the encoding of a file by [`decode`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sniffed {
+    /// The file started with a byte order mark, which was removed
+    pub bom: bool,
+    /// The `encoding` of the XML declaration, if there is one
+    pub declared: Option<String>,
+    /// The encoding the file was read in
+    pub actual: Encoding,
+    /// The file was transcoded to UTF-8, which an ASCII file never needs
+    pub transcoded: bool,
+}
+
+impl Sniffed {
+    /// Is the file read in another encoding than it declares? Only known
+    /// declarations can be told apart from the actual encoding.
+    pub fn mismatch(&self) -> bool {
+        let declared = self.declared.as_deref().map(Encoding::from_label);
+        match declared {
+            Some(Some(declared)) => declared != self.actual,
+            // without a declaration, XML is UTF-8, or UTF-16 with a byte
+            // order mark
+            None => self.actual == Encoding::Latin1,
+            Some(None) => false,
+        }
+    }
+
+    /// What is worth a note about the encoding of the file, if anything.
+    pub fn note(&self) -> Option<String> {
+        let mut notes = vec![];
+        if self.bom {
+            notes.push("removed the byte order mark".to_string());
+        }
+        if self.transcoded {
+            notes.push(format!("transcoded from {}", self.actual.as_str()));
+        }
+        if self.mismatch() {
+            match &self.declared {
+                Some(declared) => notes.push(format!("the XML declaration says {declared}")),
+                None => notes.push("there is no XML declaration, which means utf-8".to_string()),
+            }
+        }
+        match notes.is_empty() {
+            true => None,
+            false => Some(notes.join(", ")),
+        }
+    }
+}
+
+/// The `encoding` of the XML declaration at the start of `bytes`, if there
+/// is one.
+fn declared_encoding(bytes: &[u8]) -> Option<String> {
+    let start = bytes.iter().position(|b| !b.is_ascii_whitespace())?;
+    let bytes = &bytes[start..];
+    if !bytes.starts_with(b"<?xml") {
+        return None;
+    }
+    let end = bytes.windows(2).take(256).position(|w| w == b"?>")?;
+    let declaration = std::str::from_utf8(&bytes[..end]).ok()?;
+    let (_, rest) = declaration.split_once("encoding")?;
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"')?;
+    let (value, _) = rest[1..].split_once(quote)?;
+    Some(value.to_string())
+}
+
+/// The UTF-16 `bytes`, in `encoding`, as a string. Broken surrogate pairs,
+/// and an odd last byte, become U+FFFD.
+fn decode_utf16(bytes: &[u8], encoding: Encoding) -> String {
+    let units = bytes.chunks(2).map(|pair| match (pair, encoding) {
+        ([a, b], Encoding::Utf16Be) => u16::from_be_bytes([*a, *b]),
+        ([a, b], _) => u16::from_le_bytes([*a, *b]),
+        _ => 0xfffd,
+    });
+    char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+/// The contents of an analysed file, `bytes`, as a string, without a byte
+/// order mark, and transcoded to UTF-8 if it is not already. The allocation
+/// of `bytes` is kept when the file is UTF-8.
+pub fn decode(mut bytes: Vec<u8>) -> (String, Sniffed) {
+    let utf16 = match bytes.get(..2) {
+        Some([0xff, 0xfe]) => Some(Encoding::Utf16Le),
+        Some([0xfe, 0xff]) => Some(Encoding::Utf16Be),
+        _ => None,
+    };
+    if let Some(encoding) = utf16 {
+        let text = decode_utf16(&bytes[2..], encoding);
+        let sniffed = Sniffed {
+            bom: true,
+            declared: declared_encoding(text.as_bytes()),
+            actual: encoding,
+            transcoded: true,
+        };
+        return (text, sniffed);
+    }
+
+    let bom = bytes.starts_with(b"\xef\xbb\xbf");
+    if bom {
+        bytes.drain(..3);
+    }
+    let declared = declared_encoding(&bytes);
+    let (text, actual, transcoded) = match String::from_utf8(bytes) {
+        // ASCII is Latin-1 as much as it is UTF-8
+        Ok(text) if text.is_ascii() => {
+            let actual = match declared.as_deref().and_then(Encoding::from_label) {
+                Some(Encoding::Latin1) => Encoding::Latin1,
+                _ => Encoding::Utf8,
+            };
+            (text, actual, false)
+        }
+        Ok(text) => (text, Encoding::Utf8, false),
+        Err(e) => {
+            let text = e.into_bytes().into_iter().map(char::from).collect();
+            (text, Encoding::Latin1, true)
+        }
+    };
+    let sniffed = Sniffed {
+        bom,
+        declared,
+        actual,
+        transcoded,
+    };
+    (text, sniffed)
+}
+
+/// [`decode`] the contents of the analysed `file`, and warn about what was
+/// found, if anything.
+pub fn decode_file(bytes: Vec<u8>, file: &Path) -> String {
+    let (text, sniffed) = decode(bytes);
+    if let Some(note) = sniffed.note() {
+        tracing::warn!(
+            file = ?file,
+            encoding = sniffed.actual.as_str(),
+            declared = sniffed.declared.as_deref(),
+            "{note}"
+        );
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Encoding, decode};
+
+    const DECLARATION: &str = "<?xml version='1.0' encoding='utf8'?>\n";
+
+    #[test]
+    fn sniff() {
+        let (text, sniffed) = decode(format!("{DECLARATION}<document>ášŋ</document>").into_bytes());
+        assert_eq!(text, format!("{DECLARATION}<document>ášŋ</document>"));
+        assert_eq!(sniffed.declared.as_deref(), Some("utf8"));
+        assert_eq!(sniffed.note(), None);
+
+        let (text, sniffed) = decode(format!("\u{feff}{DECLARATION}<document/>").into_bytes());
+        assert_eq!(text, format!("{DECLARATION}<document/>"));
+        assert_eq!(
+            sniffed.note().as_deref(),
+            Some("removed the byte order mark")
+        );
+
+        let mut latin1 = DECLARATION.as_bytes().to_vec();
+        latin1.extend(b"<document>\xe1v\xe1</document>");
+        let (text, sniffed) = decode(latin1);
+        assert_eq!(text, format!("{DECLARATION}<document>ává</document>"));
+        assert_eq!(sniffed.actual, Encoding::Latin1);
+        assert_eq!(
+            sniffed.note().as_deref(),
+            Some("transcoded from latin-1, the XML declaration says utf8")
+        );
+
+        let (_, sniffed) = decode(b"<a>\xe1</a>".to_vec());
+        assert_eq!(
+            sniffed.note().as_deref(),
+            Some("transcoded from latin-1, there is no XML declaration, which means utf-8")
+        );
+
+        // declared, and is, Latin-1
+        let (text, sniffed) =
+            decode(b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?><a>\xe1</a>".to_vec());
+        assert!(text.ends_with("<a>á</a>"));
+        assert!(!sniffed.mismatch());
+        assert_eq!(sniffed.note().as_deref(), Some("transcoded from latin-1"));
+        let (_, sniffed) = decode(b"<?xml version='1.0' encoding='latin1'?><a/>".to_vec());
+        assert_eq!(sniffed.note(), None);
+
+        let mut utf16 = vec![0xff, 0xfe];
+        utf16.extend("<a>ŋ</a>".encode_utf16().flat_map(u16::to_le_bytes));
+        let (text, sniffed) = decode(utf16);
+        assert_eq!(text, "<a>ŋ</a>");
+        assert_eq!(
+            sniffed.note().as_deref(),
+            Some("removed the byte order mark, transcoded from utf-16le")
+        );
+    }
+}
@@ -0,0 +1,108 @@
+//! Plain sentence text from korp_mono files, for the `extract-text`
+//! subcommand, e.g. as training data for language models, from corpora that
+//! have already been converted.
+//!
+//! Each sentence is written on a line of its own, the word forms separated
+//! by spaces ([`TextStyle::Tokenized`]), or put together as in running text
+//! ([`TextStyle::Detokenized`], see [`process_sentence::detokenize`]). The
+//! documents are separated by an empty line.
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use crate::process_sentence;
+
+/// How the word forms of a sentence are put together.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TextStyle {
+    /// Separated by spaces, as they were tokenized
+    Tokenized,
+    /// As in running text, without spaces before closing punctuation and
+    /// after opening punctuation
+    #[default]
+    Detokenized,
+}
+
+/// The sentence with the token lines `text`, where the word form is in
+/// column `word_column`, as one line, without the newline.
+pub fn sentence_line(text: &str, word_column: usize, style: TextStyle) -> String {
+    let words = text
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.split('\t').nth(word_column).unwrap_or_default());
+    match style {
+        TextStyle::Tokenized => words.collect::<Vec<_>>().join(" "),
+        TextStyle::Detokenized => process_sentence::detokenize(words),
+    }
+}
+
+/// The sentences of the korp_mono document `xml`, one line each. Empty
+/// sentences are left out.
+pub fn extract_document(
+    xml: &str,
+    word_column: usize,
+    style: TextStyle,
+) -> anyhow::Result<Vec<String>> {
+    let mut lines = vec![];
+    let mut sentence: Option<String> = None;
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event()? {
+            Event::Start(e) if e.name().as_ref() == b"sentence" => sentence = Some(String::new()),
+            Event::Text(t) => {
+                if let Some(text) = sentence.as_mut() {
+                    text.push_str(&t.unescape()?);
+                }
+            }
+            Event::End(e) if e.name().as_ref() == b"sentence" => {
+                let line = sentence
+                    .take()
+                    .map(|text| sentence_line(&text, word_column, style))
+                    .unwrap_or_default();
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    Ok(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TextStyle, extract_document};
+
+    const DOCUMENT: &str = "<text title=\"Ođđasat\" lang=\"sme\">
+<sentence id=\"1\">
+Mun\tmun\tPron
+boađán\tboahtit\tV
+.\t.\tCLB
+</sentence>
+<sentence id=\"2\">
+</sentence>
+<sentence id=\"3\">
+«\t«\tPUNCT
+Bures\tbures\tAdv
+»\t»\tPUNCT
+</sentence>
+</text>";
+
+    #[test]
+    fn styles() {
+        assert_eq!(
+            extract_document(DOCUMENT, 0, TextStyle::Detokenized).unwrap(),
+            ["Mun boađán.", "«Bures»"]
+        );
+        assert_eq!(
+            extract_document(DOCUMENT, 0, TextStyle::Tokenized).unwrap(),
+            ["Mun boađán .", "« Bures »"]
+        );
+        assert_eq!(
+            extract_document(DOCUMENT, 1, TextStyle::Tokenized).unwrap(),
+            ["mun boahtit .", "« bures »"]
+        );
+        assert!(extract_document("<text><sentence></text>", 0, TextStyle::Tokenized).is_err());
+    }
+}
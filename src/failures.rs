@@ -0,0 +1,230 @@
+//! Count the files that failed to convert, by [`ErrorCategory`].
+//!
+//! The stages report their errors by logging them, with a `category` field
+//! set to [`ErrorCategory::as_str`], and a `file` field with the path of the
+//! file. [`FailureLayer`] is a tracing layer that counts the distinct files
+//! of those events, so that the totals can be reported, and turned into the
+//! exit status, at the end of the run. A file that fails in more than one
+//! way is counted once, in the category of its first error. Errors that are
+//! logged without a category are counted as [`ErrorCategory::Other`].
+//!
+//! Errors without a `file` field, like a `cwb-encode` that failed for a
+//! whole corpus, or a pool that lost all its threads, are not about a
+//! single file, and are counted separately.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+use crate::status_message::ErrorCategory;
+
+/// Exit status of a run where all files were converted.
+pub const EXIT_OK: u8 = 0;
+/// Exit status of a run that completed, but where some files failed, or
+/// something else that is not fatal went wrong.
+pub const EXIT_FILE_ERRORS: u8 = 1;
+/// Exit status of a run that could not start, or was stopped, by an error
+/// that is not about a single file, e.g. bad arguments, or a report that
+/// could not be written.
+pub const EXIT_FATAL: u8 = 2;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Failures {
+    /// The failed files, with the category of their first error
+    files: BTreeMap<String, ErrorCategory>,
+    /// The errors that are not about a single file, by category
+    errors: BTreeMap<ErrorCategory, u64>,
+}
+
+impl Failures {
+    /// Count `file` as failed in `category`, unless it already failed.
+    pub fn add_file(&mut self, file: impl Into<String>, category: ErrorCategory) {
+        self.files.entry(file.into()).or_insert(category);
+    }
+
+    /// Count an error that is not about a single file.
+    pub fn add_error(&mut self, category: ErrorCategory) {
+        *self.errors.entry(category).or_default() += 1;
+    }
+
+    /// The number of files that failed in `category`.
+    pub fn count(&self, category: ErrorCategory) -> u64 {
+        self.files.values().filter(|c| **c == category).count() as u64
+    }
+
+    /// The number of files that failed.
+    pub fn total(&self) -> u64 {
+        self.files.len() as u64
+    }
+
+    /// The categories that had failed files, with their counts.
+    pub fn iter(&self) -> impl Iterator<Item = (ErrorCategory, u64)> + '_ {
+        let mut counts = BTreeMap::<ErrorCategory, u64>::new();
+        for category in self.files.values() {
+            *counts.entry(*category).or_default() += 1;
+        }
+        counts.into_iter()
+    }
+
+    /// The number of errors that were not about a single file.
+    pub fn errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    /// The categories that had errors that were not about a single file,
+    /// with their counts.
+    pub fn iter_errors(&self) -> impl Iterator<Item = (ErrorCategory, u64)> + '_ {
+        self.errors.iter().map(|(category, n)| (*category, *n))
+    }
+
+    pub fn exit_code(&self) -> u8 {
+        match self.total() + self.errors() {
+            0 => EXIT_OK,
+            _ => EXIT_FILE_ERRORS,
+        }
+    }
+
+    /// A summary for the end of the run, one line per category.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        match self.total() {
+            0 => writeln!(s, "no files failed").expect(w),
+            n => writeln!(s, "{n} files failed:").expect(w),
+        }
+        for (category, n) in self.iter() {
+            writeln!(s, "  {category}: {n}").expect(w);
+        }
+        if self.errors() > 0 {
+            writeln!(s, "{} other errors:", self.errors()).expect(w);
+            for (category, n) in self.iter_errors() {
+                writeln!(s, "  {category}: {n}").expect(w);
+            }
+        }
+        s
+    }
+}
+
+/// Counts the error events into `failures`.
+pub struct FailureLayer {
+    pub failures: Arc<Mutex<Failures>>,
+}
+
+impl FailureLayer {
+    pub fn new(failures: Arc<Mutex<Failures>>) -> Self {
+        Self { failures }
+    }
+}
+
+#[derive(Default)]
+struct FailureVisitor {
+    category: Option<ErrorCategory>,
+    file: Option<String>,
+}
+
+impl Visit for FailureVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "category" => self.category = value.parse().ok(),
+            "file" => self.file = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "file" {
+            self.file = Some(format!("{value:?}"));
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for FailureLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        let mut visitor = FailureVisitor::default();
+        event.record(&mut visitor);
+        let category = visitor.category.unwrap_or(ErrorCategory::Other);
+        let mut failures = self
+            .failures
+            .lock()
+            .expect("no thread panics while holding the lock");
+        match visitor.file {
+            Some(file) => failures.add_file(file, category),
+            None => failures.add_error(category),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EXIT_FILE_ERRORS, EXIT_OK, FailureLayer, Failures};
+    use crate::status_message::ErrorCategory;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn counts_failed_files() {
+        let failures = Arc::new(Mutex::new(Failures::default()));
+        let subscriber =
+            tracing_subscriber::registry().with(FailureLayer::new(Arc::clone(&failures)));
+        tracing::subscriber::with_default(subscriber, || {
+            let (a, b, c) = (Path::new("a.xml"), Path::new("b.xml"), Path::new("c.xml"));
+            let timeout = ErrorCategory::Timeout.as_str();
+            tracing::error!(file = ?a, category = timeout, "timed out");
+            tracing::error!(file = ?b, category = timeout, "timed out");
+            tracing::error!(file = ?b, category = "write", "and can't be written");
+            tracing::error!(file = ?c, category = "xml", "bad xml");
+            tracing::error!(file = "d.xml", "no category");
+            tracing::warn!(file = "e.xml", category = "xml", "only a warning");
+        });
+        let failures = failures.lock().unwrap();
+        assert_eq!(failures.count(ErrorCategory::Timeout), 2);
+        assert_eq!(failures.count(ErrorCategory::Write), 0);
+        assert_eq!(failures.count(ErrorCategory::Xml), 1);
+        assert_eq!(failures.count(ErrorCategory::Other), 1);
+        assert_eq!(failures.total(), 4);
+        assert_eq!(failures.errors(), 0);
+        assert_eq!(failures.exit_code(), EXIT_FILE_ERRORS);
+    }
+
+    #[test]
+    fn counts_other_errors() {
+        let failures = Arc::new(Mutex::new(Failures::default()));
+        let subscriber =
+            tracing_subscriber::registry().with(FailureLayer::new(Arc::clone(&failures)));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::error!(corpus = "sme", category = "write", "cwb-encode failed");
+            tracing::error!("all threads of the pool are gone");
+        });
+        let failures = failures.lock().unwrap();
+        assert_eq!(failures.total(), 0);
+        assert_eq!(failures.errors(), 2);
+        assert_eq!(failures.exit_code(), EXIT_FILE_ERRORS);
+    }
+
+    #[test]
+    fn report() {
+        assert_eq!(Failures::default().exit_code(), EXIT_OK);
+        assert_eq!(Failures::default().report(), "no files failed\n");
+        let mut failures = Failures::default();
+        failures.add_file("a.xml", ErrorCategory::Write);
+        failures.add_file("b.xml", ErrorCategory::Read);
+        failures.add_file("b.xml", ErrorCategory::Write);
+        assert_eq!(
+            failures.report(),
+            "2 files failed:\n  read: 1\n  write: 1\n"
+        );
+        failures.add_error(ErrorCategory::Write);
+        assert_eq!(
+            failures.report(),
+            "2 files failed:\n  read: 1\n  write: 1\n1 other errors:\n  write: 1\n"
+        );
+    }
+}
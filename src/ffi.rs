@@ -0,0 +1,184 @@
+//! A C interface to the conversion, for calling it in-process from other
+//! languages, instead of running `korp-mono-rs` as a subprocess.
+//!
+//! Only with `--features ffi`. The shared library is built with
+//!
+//! ```sh
+//! cargo build --release --features ffi
+//! ```
+//!
+//! and the functions are declared in `include/korp_mono.h`. The documents
+//! are converted with the default options, like `korp-mono-rs sme` and
+//! `korp-mono-rs convert-one` without any other options. The functions
+//! return the exit statuses of the binary, see [`crate::failures`]:
+//! [`EXIT_OK`], [`EXIT_FILE_ERRORS`] when the document could not be
+//! converted, and [`EXIT_FATAL`] for invalid arguments or a panic. The
+//! errors are logged with `tracing`, if the caller has set up a subscriber.
+
+use std::ffi::{CStr, c_char, c_int};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::PathBuf;
+
+use gtcorpusutil::AnalysedFilePath;
+
+use crate::failures::{EXIT_FATAL, EXIT_FILE_ERRORS, EXIT_OK};
+use crate::pipeline::{Pipeline, convert_document, stages};
+
+/// `status` as returned to C.
+fn status(status: u8) -> c_int {
+    status as c_int
+}
+
+/// Run `f`, and return its status, or [`EXIT_FATAL`] if it panicked, since
+/// panics can't unwind into C.
+fn guarded(f: impl FnOnce() -> u8) -> c_int {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(code) => status(code),
+        Err(_) => status(EXIT_FATAL),
+    }
+}
+
+/// Convert the analysed file at the nul-terminated UTF-8 `path`, and write
+/// it to the `korp_mono/` directory of its corpus.
+///
+/// # Safety
+///
+/// `path` must be a valid pointer to a nul-terminated string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn korp_mono_convert_file(path: *const c_char) -> c_int {
+    if path.is_null() {
+        return status(EXIT_FATAL);
+    }
+    // SAFETY: a nul-terminated string, as required of the caller
+    let Ok(path) = unsafe { CStr::from_ptr(path) }.to_str() else {
+        return status(EXIT_FATAL);
+    };
+    let path = AnalysedFilePath::new_unchecked(PathBuf::from(path));
+    guarded(|| {
+        let pipeline = Pipeline::new()
+            .then(stages::ReadFile)
+            .then(stages::ParseXml::default())
+            .then(stages::ParseAnalyses::default())
+            .then(stages::Convert::default())
+            .then(stages::WriteKorpMono::default());
+        match pipeline.run(path) {
+            Some(_) => EXIT_OK,
+            None => EXIT_FILE_ERRORS,
+        }
+    })
+}
+
+/// Convert the analysed document in the `input_len` UTF-8 bytes at `input`.
+/// On success, `*output` is set to the korp_mono xml, nul-terminated, and
+/// `*output_len` to its length without the nul. The output must be freed
+/// with [`korp_mono_free_string`].
+///
+/// # Safety
+///
+/// `input` must point to `input_len` readable bytes, and `output` and
+/// `output_len` must be valid pointers to write to.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn korp_mono_convert_string(
+    input: *const u8,
+    input_len: usize,
+    output: *mut *mut c_char,
+    output_len: *mut usize,
+) -> c_int {
+    if input.is_null() || output.is_null() || output_len.is_null() {
+        return status(EXIT_FATAL);
+    }
+    // SAFETY: `input_len` readable bytes, as required of the caller
+    let input = unsafe { std::slice::from_raw_parts(input, input_len) };
+    let Ok(input) = std::str::from_utf8(input) else {
+        return status(EXIT_FILE_ERRORS);
+    };
+    let input = input.to_string();
+    guarded(|| {
+        let Some(converted) = convert_document(input, Default::default(), Default::default())
+        else {
+            return EXIT_FILE_ERRORS;
+        };
+        // the xml has no nul bytes in it, quick_xml refuses to write them
+        let Ok(converted) = std::ffi::CString::new(converted) else {
+            return EXIT_FILE_ERRORS;
+        };
+        // SAFETY: valid pointers to write to, as required of the caller
+        unsafe {
+            *output_len = converted.as_bytes().len();
+            *output = converted.into_raw();
+        }
+        EXIT_OK
+    })
+}
+
+/// Free a string returned by [`korp_mono_convert_string`]. Does nothing
+/// with a null pointer.
+///
+/// # Safety
+///
+/// `s` must be null, or a string returned by [`korp_mono_convert_string`]
+/// that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn korp_mono_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: allocated by `CString::into_raw` in korp_mono_convert_string
+        drop(unsafe { std::ffi::CString::from_raw(s) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{korp_mono_convert_string, korp_mono_free_string};
+    use crate::failures::{EXIT_FATAL, EXIT_FILE_ERRORS, EXIT_OK};
+    use std::ffi::CStr;
+
+    const DOCUMENT: &str = concat!(
+        "<?xml version='1.0' encoding='utf8'?>\n",
+        "<document xml:lang=\"sme\" id=\"no_id\">\n",
+        "  <header><title>Ođđasat</title><year>2018</year>",
+        "<conversion_status type=\"standard\"/>",
+        "<availability><license type=\"standard\"/></availability></header>\n",
+        "  <body><dependency><![CDATA[\n",
+        "\"<boađán>\"\n\t\"boahtit\" V IV Ind Prs Sg1 <W:0.0> @FMV #1->0\n",
+        "\n]]></dependency></body></document>\n",
+    );
+
+    fn convert(input: &str) -> (i32, Option<String>) {
+        let mut output = std::ptr::null_mut();
+        let mut output_len = 0;
+        let status = unsafe {
+            korp_mono_convert_string(input.as_ptr(), input.len(), &mut output, &mut output_len)
+        };
+        if output.is_null() {
+            return (status, None);
+        }
+        let converted = unsafe { CStr::from_ptr(output) }
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert_eq!(converted.len(), output_len);
+        unsafe { korp_mono_free_string(output) };
+        (status, Some(converted))
+    }
+
+    #[test]
+    fn convert_string() {
+        let (status, converted) = convert(DOCUMENT);
+        assert_eq!(status, EXIT_OK as i32);
+        let converted = converted.unwrap();
+        assert!(converted.starts_with("<text"));
+        assert!(converted.contains("boađán\tboahtit"));
+
+        assert_eq!(convert("<document>").0, EXIT_FILE_ERRORS as i32);
+    }
+
+    #[test]
+    fn null_pointers() {
+        let mut output_len = 0;
+        let status = unsafe {
+            korp_mono_convert_string(std::ptr::null(), 0, std::ptr::null_mut(), &mut output_len)
+        };
+        assert_eq!(status, EXIT_FATAL as i32);
+        unsafe { korp_mono_free_string(std::ptr::null_mut()) };
+    }
+}
@@ -0,0 +1,98 @@
+//! Lists of the analysed files to convert, given with `--files-from` or
+//! `--stdin-list-null`, instead of finding them in the corpus directories.
+//!
+//! A list is either one path per line, or separated by NUL bytes, like the
+//! output of `find -print0`. Only the NUL-separated lists can hold file
+//! names with newlines in them. The paths are taken as the bytes they are,
+//! so names that are not valid UTF-8 are kept as well (on unix).
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+/// How the paths of a list are separated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separator {
+    Newline,
+    Nul,
+}
+
+impl Separator {
+    fn byte(&self) -> u8 {
+        match self {
+            Separator::Newline => b'\n',
+            Separator::Nul => b'\0',
+        }
+    }
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+/// The paths in the list `contents`. Empty entries are skipped, and so are
+/// the `\r` of lists with `\r\n` line endings.
+pub fn parse_file_list(contents: &[u8], separator: Separator) -> Vec<PathBuf> {
+    contents
+        .split(|&b| b == separator.byte())
+        .map(|entry| match separator {
+            Separator::Newline => entry.strip_suffix(b"\r").unwrap_or(entry),
+            Separator::Nul => entry,
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect()
+}
+
+/// Read the list of files at `path`, or from stdin if `path` is `-`.
+pub fn read_file_list(path: &Path, separator: Separator) -> anyhow::Result<Vec<PathBuf>> {
+    let mut contents = vec![];
+    match path == Path::new("-") {
+        true => std::io::stdin()
+            .lock()
+            .read_to_end(&mut contents)
+            .context("can't read the list of files from stdin")?,
+        false => std::fs::File::open(path)
+            .and_then(|mut file| file.read_to_end(&mut contents))
+            .with_context(|| format!("can't read the list of files '{}'", path.display()))?,
+    };
+    Ok(parse_file_list(&contents, separator))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Separator, parse_file_list};
+    use std::path::PathBuf;
+
+    #[test]
+    fn newline_separated() {
+        let contents = b"corpus-sme/analysed/a.xml\r\n\ncorpus-sme/analysed/b.xml";
+        assert_eq!(
+            parse_file_list(contents, Separator::Newline),
+            [
+                PathBuf::from("corpus-sme/analysed/a.xml"),
+                PathBuf::from("corpus-sme/analysed/b.xml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn nul_separated() {
+        let contents = b"corpus-sme/analysed/a\nb.xml\0\0corpus-sme/analysed/c.xml\0";
+        assert_eq!(
+            parse_file_list(contents, Separator::Nul),
+            [
+                PathBuf::from("corpus-sme/analysed/a\nb.xml"),
+                PathBuf::from("corpus-sme/analysed/c.xml"),
+            ]
+        );
+    }
+}
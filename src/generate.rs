@@ -0,0 +1,329 @@
+//! Replace the `[[[GEN:#...]]]` lemma directives of dynamic compounds with
+//! the lemma generated by a generator transducer, with `hfst-lookup`.
+//!
+//! The lemma of a dynamic compound is not known from the analysis alone, so
+//! the conversion writes a directive with the analysis of the compound,
+//! e.g. `[[[GEN:#ášši+N+Cmp/SgNom+Cmp#fáddá+N+Sg+Nom]]]`. The analysis
+//! (without the leading `#`) is looked up in the generator, and the
+//! directive is replaced by the first form it generates. Directives that
+//! can't be generated are left as they are.
+//!
+//! The results are cached by [`CachedGenerator`], so that each analysis is
+//! only looked up once per run, and, with a cache file, once across runs.
+//!
+//! Only built with the `generate` feature.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+const DIRECTIVE_START: &str = "[[[GEN:";
+const DIRECTIVE_END: &str = "]]]";
+
+/// Looks up analyses in a generator transducer, with `hfst-lookup`.
+#[derive(Debug, Clone)]
+pub struct Generator {
+    /// The generator transducer, e.g. `generator-gt-norm.hfstol`
+    pub transducer: PathBuf,
+    /// The `hfst-lookup` program
+    pub lookup: PathBuf,
+}
+
+impl Generator {
+    pub fn new(transducer: PathBuf) -> Self {
+        Self {
+            transducer,
+            lookup: PathBuf::from("hfst-lookup"),
+        }
+    }
+
+    /// Generate the forms of `analyses`, in one run of `hfst-lookup`. The
+    /// analyses that could not be generated are not in the result.
+    pub fn generate(&self, analyses: &[&str]) -> std::io::Result<HashMap<String, String>> {
+        if analyses.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut child = Command::new(&self.lookup)
+            .arg("--quiet")
+            .arg(&self.transducer)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let mut input = analyses.join("\n");
+        input.push('\n');
+        // write on another thread, so that a full stdout pipe can't block
+        // the writing
+        let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output()?;
+        writer.join().expect("writer thread doesn't panic")?;
+        if !output.status.success() {
+            return Err(std::io::Error::other(format!(
+                "{} exited with {}",
+                self.lookup.display(),
+                output.status
+            )));
+        }
+        Ok(parse_lookup_output(&String::from_utf8_lossy(
+            &output.stdout,
+        )))
+    }
+}
+
+/// Identifies the version of the transducer in a cache file: its size and
+/// modification time, so that the cache is not used with another version.
+fn transducer_stamp(transducer: &Path) -> String {
+    let metadata = std::fs::metadata(transducer).ok();
+    let size = metadata.as_ref().map(|m| m.len()).unwrap_or_default();
+    let modified = metadata
+        .and_then(|m| m.modified().ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    format!("{size}:{modified}")
+}
+
+/// A [`Generator`] that remembers what it has generated, including the
+/// analyses that could not be generated.
+///
+/// The cache can be saved to a file, one `analysis\tform` line per analysis
+/// (with an empty form when it could not be generated), after a first line
+/// that identifies the transducer. A cache file made with another version of
+/// the transducer is ignored.
+#[derive(Debug)]
+pub struct CachedGenerator {
+    pub generator: Generator,
+    forms: Mutex<HashMap<String, Option<String>>>,
+}
+
+impl CachedGenerator {
+    pub fn new(generator: Generator) -> Self {
+        Self {
+            generator,
+            forms: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn forms(&self) -> std::sync::MutexGuard<'_, HashMap<String, Option<String>>> {
+        self.forms
+            .lock()
+            .expect("no thread panics while holding the lock")
+    }
+
+    /// Start with the cache in the file at `path`, if it exists, and was
+    /// made with the same transducer.
+    pub fn load(generator: Generator, path: &Path) -> std::io::Result<Self> {
+        let cached = Self::new(generator);
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(cached),
+            Err(e) => return Err(e),
+        };
+        let stamp = transducer_stamp(&cached.generator.transducer);
+        let header = format!("#\t{stamp}");
+        let mut lines = contents.lines();
+        if lines.next() != Some(header.as_str()) {
+            tracing::info!(cache = ?path, "the generator cache is for another transducer, not using it");
+            return Ok(cached);
+        }
+        cached.forms().extend(lines.filter_map(|line| {
+            let (analysis, form) = line.split_once('\t')?;
+            let form = (!form.is_empty()).then(|| form.to_string());
+            Some((analysis.to_string(), form))
+        }));
+        Ok(cached)
+    }
+
+    /// Write the cache to the file at `path`, sorted by analysis.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let w = "can always write to String";
+        let mut s = format!("#\t{}\n", transducer_stamp(&self.generator.transducer));
+        let forms = self.forms();
+        let sorted = forms.iter().collect::<BTreeMap<_, _>>();
+        for (analysis, form) in sorted {
+            writeln!(s, "{analysis}\t{}", form.as_deref().unwrap_or_default()).expect(w);
+        }
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, s)?;
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Like [`Generator::generate`], but only runs the generator for the
+    /// analyses that are not in the cache.
+    pub fn generate(&self, analyses: &[&str]) -> std::io::Result<HashMap<String, String>> {
+        let missing = {
+            let forms = self.forms();
+            analyses
+                .iter()
+                .copied()
+                .filter(|analysis| !forms.contains_key(*analysis))
+                .collect::<Vec<_>>()
+        };
+        // the generator runs without the lock, so that other threads can use
+        // the cache meanwhile
+        let mut generated = self.generator.generate(&missing)?;
+        let mut forms = self.forms();
+        for analysis in missing {
+            forms.insert(analysis.to_string(), generated.get(analysis).cloned());
+        }
+        for analysis in analyses {
+            if let Some(Some(form)) = forms.get(*analysis) {
+                generated.insert(analysis.to_string(), form.clone());
+            }
+        }
+        Ok(generated)
+    }
+}
+
+/// Parse the output of `hfst-lookup`: lines of `input\toutput\tweight`, with
+/// a blank line after the outputs of each input. Failed lookups have an
+/// output ending in `+?`. Only the first output of each input is kept.
+fn parse_lookup_output(output: &str) -> HashMap<String, String> {
+    let mut generated = HashMap::new();
+    for line in output.lines() {
+        let mut fields = line.split('\t');
+        let (Some(input), Some(form)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        if input.is_empty() || form.is_empty() || form.ends_with("+?") {
+            continue;
+        }
+        generated
+            .entry(input.to_string())
+            .or_insert_with(|| form.to_string());
+    }
+    generated
+}
+
+/// The analysis in a directive: the part between `[[[GEN:` and `]]]`,
+/// without the leading `#`.
+fn analysis_of(directive: &str) -> &str {
+    directive
+        .trim_start_matches(DIRECTIVE_START)
+        .trim_end_matches(DIRECTIVE_END)
+        .trim_start_matches('#')
+}
+
+/// The directives in `text`, with their byte ranges.
+fn directives(text: &str) -> impl Iterator<Item = (usize, usize)> + '_ {
+    let mut from = 0;
+    std::iter::from_fn(move || {
+        let start = from + text[from..].find(DIRECTIVE_START)?;
+        let end = start + text[start..].find(DIRECTIVE_END)? + DIRECTIVE_END.len();
+        from = end;
+        Some((start, end))
+    })
+}
+
+/// The distinct analyses of the directives in `texts`.
+pub fn analyses<'a>(texts: impl IntoIterator<Item = &'a str>) -> BTreeSet<&'a str> {
+    texts
+        .into_iter()
+        .flat_map(|text| directives(text).map(|(start, end)| analysis_of(&text[start..end])))
+        .collect()
+}
+
+/// Replace the directives in `text` that have a form in `generated`.
+/// Returns the number of directives that were left as they are.
+pub fn replace_directives(text: &mut String, generated: &HashMap<String, String>) -> usize {
+    let ranges = directives(text).collect::<Vec<_>>();
+    let mut failed = 0;
+    // from the end, so that the earlier ranges stay valid
+    for (start, end) in ranges.into_iter().rev() {
+        match generated.get(analysis_of(&text[start..end])) {
+            Some(form) => text.replace_range(start..end, form),
+            None => failed += 1,
+        }
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachedGenerator, Generator, analyses, parse_lookup_output, replace_directives};
+    use std::path::PathBuf;
+
+    #[test]
+    fn lookup_output() {
+        let output = concat!(
+            "ášši+N+Cmp/SgNom+Cmp#fáddá+N+Sg+Nom\táššefáddá\t0,000000\n",
+            "ášši+N+Cmp/SgNom+Cmp#fáddá+N+Sg+Nom\táššifáddá\t1,000000\n",
+            "\n",
+            "x+N+Sg+Nom\tx+N+Sg+Nom+?\tinf\n",
+            "\n",
+        );
+        let generated = parse_lookup_output(output);
+        assert_eq!(generated.len(), 1);
+        assert_eq!(
+            generated["ášši+N+Cmp/SgNom+Cmp#fáddá+N+Sg+Nom"],
+            "áššefáddá"
+        );
+    }
+
+    #[test]
+    fn replace() {
+        let mut text = concat!(
+            "áššefáttát\t[[[GEN:#ášši+N+Cmp/SgNom+Cmp#fáddá+N+Sg+Nom]]]\tN\n",
+            "xy\t[[[GEN:#x+N+Cmp/SgNom+Cmp#y+N+Sg+Nom]]]\tN\n",
+            "ja\tja\tCC\n",
+        )
+        .to_string();
+        let found = analyses([text.as_str()]);
+        assert_eq!(
+            found.into_iter().collect::<Vec<_>>(),
+            [
+                "x+N+Cmp/SgNom+Cmp#y+N+Sg+Nom",
+                "ášši+N+Cmp/SgNom+Cmp#fáddá+N+Sg+Nom"
+            ]
+        );
+
+        let generated = parse_lookup_output("ášši+N+Cmp/SgNom+Cmp#fáddá+N+Sg+Nom\táššefáddá\t0\n");
+        assert_eq!(replace_directives(&mut text, &generated), 1);
+        assert_eq!(
+            text,
+            concat!(
+                "áššefáttát\táššefáddá\tN\n",
+                "xy\t[[[GEN:#x+N+Cmp/SgNom+Cmp#y+N+Sg+Nom]]]\tN\n",
+                "ja\tja\tCC\n",
+            )
+        );
+    }
+
+    #[test]
+    fn cache_file() {
+        let dir = std::env::temp_dir().join(format!("korp-mono-gen-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let transducer = dir.join("generator.hfstol");
+        std::fs::write(&transducer, "not really a transducer").unwrap();
+        let cache = dir.join("cache.tsv");
+        let generator = Generator {
+            transducer: transducer.clone(),
+            // never run: everything is in the cache
+            lookup: PathBuf::from("/nonexistent/hfst-lookup"),
+        };
+
+        let cached = CachedGenerator::new(generator.clone());
+        cached
+            .forms()
+            .insert("a+N".to_string(), Some("a".to_string()));
+        cached.forms().insert("b+N".to_string(), None);
+        cached.save(&cache).unwrap();
+
+        let loaded = CachedGenerator::load(generator.clone(), &cache).unwrap();
+        let generated = loaded.generate(&["a+N", "b+N"]).unwrap();
+        assert_eq!(generated.len(), 1);
+        assert_eq!(generated["a+N"], "a");
+        // not in the cache, so the generator must run, and fails
+        assert!(loaded.generate(&["c+N"]).is_err());
+
+        std::fs::write(&transducer, "another transducer").unwrap();
+        let stale = CachedGenerator::load(generator, &cache).unwrap();
+        assert!(stale.forms().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
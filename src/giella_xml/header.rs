@@ -0,0 +1,480 @@
+//! The `<header>` of the documents of the Giella corpora.
+//!
+//! ```not_rust
+//! <header>
+//!   <title>Sámi statistihkka 2018</title>
+//!   <genre code="facta"/>
+//!   <author>
+//!     <person firstname="Anders" lastname="Sønstebø" sex="m" born="" nationality=""/>
+//!   </author>
+//!   <year>2018</year>
+//!   <conversion_status type="standard"/>
+//!   <availability>
+//!     <license type="standard"/>
+//!   </availability>
+//!   <origFileName>https://www.ssb.no/...</origFileName>
+//!   <parallel_text xml:lang="nob" location="sami_statistihkka_2018.pdf"/>
+//!   <wordcount>803</wordcount>
+//! </header>
+//! ```
+//!
+//! A header is read in one of two [`HeaderMode`]s. [`HeaderMode::Strict`]
+//! deserializes it with serde, and fails on a header whose elements don't
+//! have the expected shape: a missing `<conversion_status>` or
+//! `<availability>`, a `<genre>` without a `code`, an element that holds
+//! text where elements are expected, and so on. [`HeaderMode::Lenient`]
+//! reads whatever it can make sense of, and leaves out the rest, so it
+//! never fails. Where strict mode succeeds, both give the same [`Header`].
+//!
+//! The elements that are not modelled by [`Header`] are found by
+//! [`header_extras`], in both modes.
+
+use std::borrow::Cow;
+
+use quick_xml::events::{BytesStart, Event};
+use serde::{Deserialize, Deserializer};
+
+/// How to read the headers, with `--header-mode`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum HeaderMode {
+    /// Fail the documents whose header has an unexpected shape
+    Strict,
+    /// Read what can be read of the header, and leave out the rest
+    #[default]
+    Lenient,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Header {
+    pub title: Option<String>,
+    pub genre: Option<Genre>,
+    #[serde(rename = "author", default)]
+    pub authors: Authors,
+    pub year: Option<String>,
+    pub conversion_status: ConversationStatus,
+    pub availability: Availability,
+    #[serde(rename = "origFileName")]
+    pub orig_file_name: Option<String>,
+    pub translated_from: Option<String>,
+    pub parallel_text: Option<Vec<ParallelText>>,
+    /// The elements of the header that are not modelled above, as
+    /// `(path, text)` pairs. Filled in by [`header_extras`], after the
+    /// document has been deserialized.
+    #[serde(skip)]
+    pub extra: Vec<(String, String)>,
+}
+
+/// The elements of `<header>` that have their own field in [`Header`].
+pub const KNOWN_HEADER_ELEMENTS: [&str; 9] = [
+    "title",
+    "genre",
+    "author",
+    "year",
+    "conversion_status",
+    "availability",
+    "origFileName",
+    "translated_from",
+    "parallel_text",
+];
+
+/// Find the elements of the `<header>` of the analysed document `xml` that
+/// are not in [`KNOWN_HEADER_ELEMENTS`]. Each element without child elements
+/// becomes a `(path, text)` pair, where the path is the element names from
+/// the header down, joined by `/`. So `<metadata><uncomplete/></metadata>`
+/// becomes `("metadata/uncomplete", "")`.
+///
+/// Only the header is read, the body is never looked at.
+pub fn header_extras(xml: &str) -> Vec<(String, String)> {
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut extras = vec![];
+    let mut in_header = false;
+    // names of the open elements inside <header>, and if they have children
+    let mut open: Vec<(String, bool)> = vec![];
+    let mut text = String::new();
+
+    let is_extra = |open: &[(String, bool)], name: &str| match open.first() {
+        Some((top, _)) => !KNOWN_HEADER_ELEMENTS.contains(&top.as_str()),
+        None => !KNOWN_HEADER_ELEMENTS.contains(&name),
+    };
+    let path = |open: &[(String, bool)], name: &str| {
+        open.iter()
+            .map(|(name, _)| name.as_str())
+            .chain([name])
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                if !in_header {
+                    in_header = name == "header";
+                    continue;
+                }
+                if let Some((_, has_children)) = open.last_mut() {
+                    *has_children = true;
+                }
+                open.push((name, false));
+                text.clear();
+            }
+            Ok(Event::Empty(e)) if in_header => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                if let Some((_, has_children)) = open.last_mut() {
+                    *has_children = true;
+                }
+                if is_extra(&open, &name) {
+                    extras.push((path(&open, &name), String::new()));
+                }
+            }
+            Ok(Event::Text(t)) if in_header => {
+                text.push_str(&t.unescape().unwrap_or_default());
+            }
+            Ok(Event::CData(t)) if in_header => {
+                text.push_str(&String::from_utf8_lossy(&t));
+            }
+            Ok(Event::End(_)) if in_header => {
+                let Some((name, has_children)) = open.pop() else {
+                    // </header>
+                    break;
+                };
+                if !has_children && is_extra(&open, &name) {
+                    extras.push((path(&open, &name), text.trim().to_string()));
+                }
+                text.clear();
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    extras
+}
+
+/// `<genre code="facta"/>`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Genre {
+    #[serde(rename = "@code")]
+    pub code: String,
+}
+
+/// `<author><person ... /></author>`. An `<author>` can have any number of
+/// `<person>`s, also none.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Author {
+    #[serde(default)]
+    pub person: Vec<Person>,
+}
+
+/// The `<person>`s of all the `<author>` elements of the header, in order.
+/// Real files have both one `<author>` per person, and several `<person>`s
+/// in one `<author>`, so the persons are flattened into one list.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Authors(pub Vec<Person>);
+
+impl<'de> Deserialize<'de> for Authors {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let authors = Vec::<Author>::deserialize(deserializer)?;
+        Ok(Self(
+            authors
+                .into_iter()
+                .flat_map(|author| author.person)
+                .collect(),
+        ))
+    }
+}
+
+/// `<person>`. Has many optional attributes.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Person {
+    #[serde(rename = "@firstname")]
+    pub firstname: Option<String>,
+    #[serde(rename = "@lastname")]
+    pub lastname: Option<String>,
+    #[serde(rename = "@sex")]
+    pub sex: Option<String>,
+    #[serde(rename = "@born")]
+    pub born: Option<String>,
+    #[serde(rename = "@nationality")]
+    pub nationality: Option<String>,
+}
+
+/// `<conversion_status type="standard"/>`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ConversationStatus {
+    #[serde(rename = "@type")]
+    pub r#type: Option<String>,
+}
+
+/// `<availability><license type="standard"/></availability>`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Availability {
+    pub license: Option<License>,
+}
+
+/// `<availability><license type="standard"/></availability>`
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct License {
+    #[serde(rename = "@type")]
+    pub r#type: Option<String>,
+}
+
+/// <parallel_text xml:lang="nob" location="sami_statistihkka_2018.pdf"/>
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ParallelText {
+    // quick-xml gives the attributes to serde by their local name, `lang`
+    #[serde(rename = "@xml:lang", alias = "@lang")]
+    pub lang: Option<String>,
+    #[serde(rename = "@location")]
+    pub location: Option<String>,
+}
+
+/// The value of the attribute `name` of `element`, if it has one.
+fn attribute(element: &BytesStart, name: &[u8]) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|attribute| attribute.key.as_ref() == name)
+        .and_then(|attribute| attribute.unescape_value().ok().map(Cow::into_owned))
+}
+
+/// Read the attributes of the header element `e` into `header`. `path` is
+/// the names of the elements from the header down to `e`, joined by `/`.
+fn read_element(header: &mut Header, path: &str, e: &BytesStart) {
+    match path {
+        "genre" => header.genre = attribute(e, b"code").map(|code| Genre { code }),
+        "author/person" => header.authors.0.push(Person {
+            firstname: attribute(e, b"firstname"),
+            lastname: attribute(e, b"lastname"),
+            sex: attribute(e, b"sex"),
+            born: attribute(e, b"born"),
+            nationality: attribute(e, b"nationality"),
+        }),
+        "conversion_status" => header.conversion_status.r#type = attribute(e, b"type"),
+        "availability/license" => {
+            header.availability.license = Some(License {
+                r#type: attribute(e, b"type"),
+            })
+        }
+        "parallel_text" => header
+            .parallel_text
+            .get_or_insert_with(Vec::new)
+            .push(ParallelText {
+                lang: attribute(e, b"xml:lang"),
+                location: attribute(e, b"location"),
+            }),
+        _ => {}
+    }
+}
+
+/// Read the `text` of the element `name`, directly in the header, into
+/// `header`.
+fn read_text(header: &mut Header, name: &str, text: String) {
+    match name {
+        "title" => header.title = Some(text),
+        "year" => header.year = Some(text),
+        "origFileName" => header.orig_file_name = Some(text),
+        "translated_from" => header.translated_from = Some(text),
+        _ => {}
+    }
+}
+
+/// The `<header>` of the document `xml`, with whatever could be read of
+/// it, see [`HeaderMode::Lenient`]. The `extra` elements are not filled
+/// in.
+pub fn lenient_header(xml: &str) -> Header {
+    let mut header = Header {
+        title: None,
+        genre: None,
+        authors: Authors::default(),
+        year: None,
+        conversion_status: ConversationStatus { r#type: None },
+        availability: Availability { license: None },
+        orig_file_name: None,
+        translated_from: None,
+        parallel_text: None,
+        extra: vec![],
+    };
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut in_header = false;
+    // the names of the open elements inside <header>
+    let mut open: Vec<String> = vec![];
+    let mut text = String::new();
+
+    let path = |open: &[String], name: &str| {
+        open.iter()
+            .map(String::as_str)
+            .chain([name])
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e)) => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                if !in_header {
+                    in_header = name == "header";
+                    continue;
+                }
+                read_element(&mut header, &path(&open, &name), &e);
+                open.push(name);
+                text.clear();
+            }
+            Ok(Event::Empty(e)) if in_header => {
+                let name = String::from_utf8_lossy(e.name().as_ref()).into_owned();
+                read_element(&mut header, &path(&open, &name), &e);
+                if open.is_empty() {
+                    read_text(&mut header, &name, String::new());
+                }
+            }
+            Ok(Event::Text(t)) if in_header => {
+                text.push_str(&t.unescape().unwrap_or_default());
+            }
+            Ok(Event::CData(t)) if in_header => {
+                text.push_str(&String::from_utf8_lossy(&t));
+            }
+            Ok(Event::End(_)) if in_header => {
+                let Some(name) = open.pop() else {
+                    // </header>
+                    break;
+                };
+                if open.is_empty() {
+                    read_text(&mut header, &name, text.trim().to_string());
+                }
+                text.clear();
+            }
+            Ok(Event::Eof) | Err(_) => break,
+            _ => {}
+        }
+    }
+    header
+}
+
+/// The `<header>` of the document `xml`, read as `mode` says, with the
+/// `extra` elements filled in.
+pub fn parse_header(xml: &str, mode: HeaderMode) -> Result<Header, quick_xml::DeError> {
+    /// Only the header of the document
+    #[derive(Deserialize)]
+    struct Document {
+        header: Header,
+    }
+
+    let mut header = match mode {
+        HeaderMode::Strict => match quick_xml::de::from_str::<Document>(xml) {
+            Ok(document) => document.header,
+            // `xml` is the header on its own
+            Err(_) => quick_xml::de::from_str::<Header>(xml)?,
+        },
+        HeaderMode::Lenient => lenient_header(xml),
+    };
+    header.extra = header_extras(xml);
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Authors, HeaderMode, header_extras, parse_header};
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Header {
+        #[serde(rename = "author", default)]
+        authors: Authors,
+    }
+
+    fn names(header: &str) -> Vec<String> {
+        let header: Header = quick_xml::de::from_str(header).unwrap();
+        header
+            .authors
+            .0
+            .iter()
+            .map(|person| {
+                let first = person.firstname.as_deref().unwrap_or_default();
+                let last = person.lastname.as_deref().unwrap_or_default();
+                format!("{first} {last}")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn authors() {
+        assert_eq!(names("<header><title>A</title></header>"), [""; 0]);
+        assert_eq!(names("<header><author/></header>"), [""; 0]);
+        assert_eq!(
+            names(r#"<header><author><person firstname="A" lastname="B"/></author></header>"#),
+            ["A B"]
+        );
+        assert_eq!(
+            names(concat!(
+                "<header>",
+                r#"<author><person firstname="A" lastname="B"/></author>"#,
+                r#"<author><person firstname="C" lastname="D"/><person lastname="E"/></author>"#,
+                "</header>",
+            )),
+            ["A B", "C D", " E"]
+        );
+    }
+
+    const HEADER: &str = r#"<header>
+    <title>Ođđasat &amp; diehtu</title>
+    <genre code="news"/>
+    <author><person firstname="Áile" lastname="Gaup" sex="f"/></author>
+    <year>2018</year>
+    <conversion_status type="standard"/>
+    <availability><license type="free"/></availability>
+    <parallel_text xml:lang="nob" location="a.pdf"/>
+    <wordcount>10</wordcount>
+  </header>"#;
+
+    #[test]
+    fn modes() {
+        let strict = parse_header(HEADER, HeaderMode::Strict).unwrap();
+        let lenient = parse_header(HEADER, HeaderMode::Lenient).unwrap();
+        assert_eq!(strict, lenient);
+        assert_eq!(lenient.title.as_deref(), Some("Ođđasat & diehtu"));
+        assert_eq!(lenient.genre.unwrap().code, "news");
+        assert_eq!(lenient.authors.0[0].firstname.as_deref(), Some("Áile"));
+        assert_eq!(
+            lenient.availability.license.unwrap().r#type.as_deref(),
+            Some("free")
+        );
+        assert_eq!(lenient.extra, [("wordcount".to_string(), "10".to_string())]);
+
+        let broken = r#"<header><title>A</title><genre/><year>2018</year></header>"#;
+        assert!(parse_header(broken, HeaderMode::Strict).is_err());
+        let lenient = parse_header(broken, HeaderMode::Lenient).unwrap();
+        assert_eq!(lenient.title.as_deref(), Some("A"));
+        assert!(lenient.genre.is_none());
+        assert!(lenient.conversion_status.r#type.is_none());
+    }
+
+    #[test]
+    fn extras() {
+        let xml = r#"<?xml version='1.0' encoding='utf8'?>
+<document xml:lang="sme" id="no_id">
+  <header>
+    <title>Sámi statistihkka 2018</title>
+    <genre code="facta"/>
+    <year>2018</year>
+    <wordcount>803</wordcount>
+    <metadata>
+      <uncomplete/>
+      <collection>SSB &amp; co</collection>
+    </metadata>
+    <version>XSLtemplate $Revision: 161400 $</version>
+  </header>
+  <body><dependency><![CDATA[
+"<Sámi>"
+]]></dependency></body></document>"#;
+        assert_eq!(
+            header_extras(xml),
+            [
+                ("wordcount", "803"),
+                ("metadata/uncomplete", ""),
+                ("metadata/collection", "SSB & co"),
+                ("version", "XSLtemplate $Revision: 161400 $"),
+            ]
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+        );
+    }
+}
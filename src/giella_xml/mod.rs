@@ -0,0 +1,4 @@
+//! The xml of the documents of the Giella corpora, apart from this tool's
+//! conversion of them, so that it can be used on its own.
+
+pub mod header;
@@ -0,0 +1,291 @@
+//! A full-text index of the converted sentences, with `--index idx/`, to
+//! search a corpus before it is in Korp, with `korp-mono-rs search idx/
+//! QUERY`.
+//!
+//! Each sentence is a document of a [tantivy](https://docs.rs/tantivy)
+//! index, with the word forms and the lemmas of its tokens, and the
+//! metadata of its text: the file it was converted from, its `title`,
+//! `lang`, `gt_domain` and `year` (of `datefrom`). The index is built anew
+//! on every run.
+//!
+//! The words and lemmas are searched by default, and both have to match:
+//! `guolli mearra` finds the sentences with both words, in any of their
+//! forms if they are lemmas. The other fields are searched by name, like
+//! `lang:sme`, `gt_domain:news`, `title:ođđasat` or `year:[2000 TO 2010]`.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::Context;
+use tantivy::collector::TopDocs;
+use tantivy::directory::MmapDirectory;
+use tantivy::query::QueryParser;
+use tantivy::schema::{Field, INDEXED, STORED, STRING, Schema, TEXT, Value};
+use tantivy::{Index, IndexWriter, TantivyDocument, doc};
+
+use crate::korp_mono::KorpMonoFile;
+use crate::process_sentence::TokenRow;
+
+/// The memory of the index writer, shared by its threads.
+const WRITER_MEMORY: usize = 100 << 20;
+
+/// The fields of the index, see the module documentation.
+#[derive(Debug, Clone, Copy)]
+struct Fields {
+    file: Field,
+    sentence: Field,
+    words: Field,
+    lemmas: Field,
+    title: Field,
+    lang: Field,
+    gt_domain: Field,
+    year: Field,
+}
+
+impl Fields {
+    fn schema() -> Schema {
+        let mut schema = Schema::builder();
+        schema.add_text_field("file", STRING | STORED);
+        schema.add_text_field("sentence", STORED);
+        schema.add_text_field("words", TEXT | STORED);
+        schema.add_text_field("lemmas", TEXT | STORED);
+        schema.add_text_field("title", TEXT | STORED);
+        schema.add_text_field("lang", STRING | STORED);
+        schema.add_text_field("gt_domain", STRING | STORED);
+        schema.add_u64_field("year", INDEXED | STORED);
+        schema.build()
+    }
+
+    fn of(schema: &Schema) -> anyhow::Result<Self> {
+        Ok(Self {
+            file: schema.get_field("file")?,
+            sentence: schema.get_field("sentence")?,
+            words: schema.get_field("words")?,
+            lemmas: schema.get_field("lemmas")?,
+            title: schema.get_field("title")?,
+            lang: schema.get_field("lang")?,
+            gt_domain: schema.get_field("gt_domain")?,
+            year: schema.get_field("year")?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Indexed {
+    documents: u64,
+    sentences: u64,
+}
+
+/// The index that the converted documents are added to, with `--index`.
+pub struct SentenceIndex {
+    dir: PathBuf,
+    fields: Fields,
+    writer: Mutex<IndexWriter>,
+    indexed: Mutex<Indexed>,
+}
+
+impl SentenceIndex {
+    /// An empty index in `dir`, which is created if it doesn't exist. An
+    /// index that is already there is emptied.
+    pub fn create(dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        let index = Index::open_or_create(MmapDirectory::open(dir)?, Fields::schema())
+            .context("not an index of korp-mono-rs, remove it first")?;
+        let fields = Fields::of(&index.schema())?;
+        let writer: IndexWriter = index.writer(WRITER_MEMORY)?;
+        writer.delete_all_documents()?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            fields,
+            writer: Mutex::new(writer),
+            indexed: Mutex::new(Indexed::default()),
+        })
+    }
+
+    /// Add the sentences of `korp_mono_file`, converted from `file`.
+    pub fn add(&self, file: &Path, korp_mono_file: &KorpMonoFile) -> anyhow::Result<()> {
+        let fields = &self.fields;
+        let file = file.display().to_string();
+        let title = korp_mono_file.title.as_deref().unwrap_or_default();
+        let year = korp_mono_file
+            .datefrom
+            .as_deref()
+            .and_then(|date| date.get(..4))
+            .and_then(|year| year.parse::<u64>().ok());
+        let writer = self
+            .writer
+            .lock()
+            .expect("no thread panics while holding the lock");
+        for sentence in korp_mono_file.sentence.iter() {
+            let lang = sentence.lang.as_ref().or(korp_mono_file.lang.as_ref());
+            let gt_domain = sentence
+                .gt_domain
+                .as_ref()
+                .or(korp_mono_file.gt_domain.as_ref());
+            let mut document = doc!(
+                fields.file => file.as_str(),
+                fields.sentence => sentence.id.as_str(),
+                fields.words => joined(&sentence.rows, |row| &*row.word_form),
+                fields.lemmas => joined(&sentence.rows, |row| &*row.lemma),
+                fields.title => title,
+            );
+            if let Some(lang) = lang {
+                document.add_text(fields.lang, lang);
+            }
+            if let Some(gt_domain) = gt_domain {
+                document.add_text(fields.gt_domain, gt_domain);
+            }
+            if let Some(year) = year {
+                document.add_u64(fields.year, year);
+            }
+            writer.add_document(document)?;
+        }
+        drop(writer);
+        let mut indexed = self
+            .indexed
+            .lock()
+            .expect("no thread panics while holding the lock");
+        indexed.documents += 1;
+        indexed.sentences += korp_mono_file.sentence.len() as u64;
+        Ok(())
+    }
+
+    /// Write the added sentences to the index, at the end of the run.
+    pub fn commit(&self) -> anyhow::Result<()> {
+        self.writer
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .commit()?;
+        Ok(())
+    }
+
+    /// A summary for the end of the run.
+    pub fn report(&self) -> String {
+        let indexed = self
+            .indexed
+            .lock()
+            .expect("no thread panics while holding the lock");
+        let mut s = String::new();
+        writeln!(
+            s,
+            "indexed {} sentences of {} documents in '{}'",
+            indexed.sentences,
+            indexed.documents,
+            self.dir.display()
+        )
+        .expect("can always write to String");
+        s
+    }
+}
+
+/// The `field` of all `rows` that have one, separated by spaces.
+fn joined<'r>(
+    rows: &'r [TokenRow<'static>],
+    field: impl Fn(&'r TokenRow<'static>) -> &'r str,
+) -> String {
+    rows.iter()
+        .map(field)
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A sentence that was found by [`search`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub file: String,
+    pub sentence: String,
+    pub words: String,
+}
+
+/// The at most `limit` sentences of the index in `dir` that match `query`,
+/// the best first. See the module documentation for the syntax.
+pub fn search(dir: &Path, query: &str, limit: usize) -> anyhow::Result<Vec<Hit>> {
+    let index =
+        Index::open_in_dir(dir).with_context(|| format!("can't open index '{}'", dir.display()))?;
+    let fields = Fields::of(&index.schema()).context("not an index of korp-mono-rs")?;
+    let searcher = index.reader()?.searcher();
+    let mut parser = QueryParser::for_index(&index, vec![fields.words, fields.lemmas]);
+    parser.set_conjunction_by_default();
+    let query = parser
+        .parse_query(query)
+        .with_context(|| format!("invalid query '{query}'"))?;
+    let stored = |doc: &TantivyDocument, field| {
+        doc.get_first(field)
+            .and_then(|value| value.as_str())
+            .unwrap_or_default()
+            .to_string()
+    };
+    searcher
+        .search(&query, &TopDocs::with_limit(limit))?
+        .into_iter()
+        .map(|(_score, address)| {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            Ok(Hit {
+                file: stored(&doc, fields.file),
+                sentence: stored(&doc, fields.sentence),
+                words: stored(&doc, fields.words),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SentenceIndex, search};
+    use crate::korp_mono::KorpMonoFile;
+    use crate::korp_mono::file::Sentence;
+    use crate::process_sentence::rows_from_lines;
+    use std::path::Path;
+
+    fn sentence(id: &str, text: &str) -> Sentence {
+        Sentence::new(id.to_string(), rows_from_lines(text))
+    }
+
+    #[test]
+    fn index_and_search() {
+        let dir = std::env::temp_dir().join(format!("korp-mono-index-{}", std::process::id()));
+        let doc = KorpMonoFile {
+            title: Some("Ođđasat".to_string()),
+            lang: Some("sme".to_string()),
+            datefrom: Some("20180101".to_string()),
+            sentence: vec![
+                sentence("1", "Guolli\tguolli\nvuodjá\tvuodjit\n"),
+                sentence("2", "Mearas\tmearra\nleat\tleat\nguolit\tguolli\n"),
+            ],
+            ..Default::default()
+        };
+        let index = SentenceIndex::create(&dir).unwrap();
+        index.add(Path::new("a.xml"), &doc).unwrap();
+        index.commit().unwrap();
+        assert_eq!(
+            index.report(),
+            format!(
+                "indexed 2 sentences of 1 documents in '{}'\n",
+                dir.display()
+            )
+        );
+
+        let hits = search(&dir, "guolli", 10).unwrap();
+        assert_eq!(hits.len(), 2);
+        let hits = search(&dir, "guolli mearra", 10).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].file, "a.xml");
+        assert_eq!(hits[0].sentence, "2");
+        assert_eq!(hits[0].words, "Mearas leat guolit");
+        assert_eq!(
+            search(&dir, "vuodjá AND year:[2000 TO 2020]", 10)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(search(&dir, "lang:sma", 10).unwrap().is_empty());
+
+        // a new index replaces the old one
+        drop(index);
+        SentenceIndex::create(&dir).unwrap().commit().unwrap();
+        assert!(search(&dir, "guolli", 10).unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+//! Sharing the strings that repeat across the tokens of a document.
+//!
+//! The same lemmas, tags and functional labels occur over and over in a
+//! document. With an [`Interner`], each distinct string is allocated once
+//! per document, and shared by all tokens that have it.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// The distinct strings of one document.
+#[derive(Debug, Default)]
+pub struct Interner {
+    strings: HashSet<Arc<str>>,
+}
+
+impl Interner {
+    /// The shared copy of `s`, which is allocated the first time it is
+    /// asked for.
+    pub fn intern(&mut self, s: &str) -> Arc<str> {
+        if let Some(interned) = self.strings.get(s) {
+            return Arc::clone(interned);
+        }
+        let interned = Arc::<str>::from(s);
+        self.strings.insert(Arc::clone(&interned));
+        interned
+    }
+
+    /// The number of distinct strings.
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Interner;
+    use std::sync::Arc;
+
+    #[test]
+    fn shared() {
+        let mut interner = Interner::default();
+        let a = interner.intern("leat");
+        let b = interner.intern("leat");
+        let c = interner.intern("Sg3");
+        assert!(Arc::ptr_eq(&a, &b));
+        assert_eq!(&*c, "Sg3");
+        assert_eq!(interner.len(), 2);
+    }
+}
@@ -8,7 +8,7 @@
 //! Example:
 //!
 //! ```not_rust
-//! <text title="Sääʹmǩiõll da kulttuur jeälltummuš Sääʹm mošttbaŋkk -haʹŋǩǩõõzzâst" lang="sms" orig_lang="" first_name="Marko" last_name="Jouste" nationality="FI" gt_domain="science" date="2018-01-01" datefrom="20180101" dateto="20180101" timefrom="000000" timeto="235959">
+//! <text title="Sääʹmǩiõll da kulttuur jeälltummuš Sääʹm mošttbaŋkk -haʹŋǩǩõõzzâst" lang="sms" orig_lang="" text_type="unknown" first_name="Marko" last_name="Jouste" nationality="FI" gt_domain="science" date="2018-01-01" datefrom="20180101" dateto="20180101" timefrom="000000" timeto="235959">
 //! <sentence id="1">
 //! 24	24	Num	Num.Arab.Sg.Acc	1	HNOUN	0
 //! </sentence>
@@ -19,64 +19,190 @@
 //! jeälltummuš	jeälltummuš	N	N.Sg.Nom	4	HNOUN	0
 //! ```
 
+use std::collections::BTreeMap;
+
 use serde::Serialize;
 
-use crate::analysed::file::ParsedAnalysedDocument;
+use crate::analysed::file::{ParallelText, ParsedAnalysedDocument, Person};
 use crate::parse_year::parse_year;
-use crate::process_sentence;
+use crate::process_sentence::{
+    self, Column, TokenRow, escape_rows, renumber_ids, sentence_rows, write_rows,
+};
+use crate::text_attrs::TextAttrs;
 
 /// The root element of the korp mono xml file. Deliberately using lower case
 /// "t" in "text", so that the element in the final file will be "<text>", and
 /// not "<Text>". Don't know if it matters, but you know.
 #[allow(non_camel_case_types)]
-#[derive(Serialize, Default)]
+#[derive(Serialize, Default, Clone)]
 pub struct text {
-    #[serde(rename = "@title")]
+    /// A stable id of the text, see [`crate::text_id`].
+    #[serde(rename = "@id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    #[serde(rename = "@title", skip_serializing_if = "Option::is_none")]
     pub title: Option<String>,
-    #[serde(rename = "@lang")]
+    #[serde(rename = "@lang", skip_serializing_if = "Option::is_none")]
     pub lang: Option<String>,
-    #[serde(rename = "@orig_lang")]
+    #[serde(rename = "@orig_lang", skip_serializing_if = "Option::is_none")]
     pub orig_lang: Option<String>,
-    #[serde(rename = "@first_name")]
+    /// `original`, `translation` or `unknown`, see [`text_type`].
+    #[serde(rename = "@text_type", skip_serializing_if = "Option::is_none")]
+    pub text_type: Option<String>,
+    #[serde(rename = "@first_name", skip_serializing_if = "Option::is_none")]
     pub first_name: Option<String>,
-    #[serde(rename = "@last_name")]
+    #[serde(rename = "@last_name", skip_serializing_if = "Option::is_none")]
     pub last_name: Option<String>,
-    #[serde(rename = "@nationality")]
+    #[serde(rename = "@nationality", skip_serializing_if = "Option::is_none")]
     pub nationality: Option<String>,
-    #[serde(rename = "@gt_domain")]
+    /// All authors, as a Korp set-valued attribute (`|A B|C D|`), when the
+    /// document has more than one. `first_name`, `last_name` and
+    /// `nationality` are those of the first author.
+    #[serde(rename = "@authors", skip_serializing_if = "Option::is_none")]
+    pub authors: Option<String>,
+    #[serde(rename = "@gt_domain", skip_serializing_if = "Option::is_none")]
     pub gt_domain: Option<String>,
-    #[serde(rename = "@date")]
+    #[serde(rename = "@date", skip_serializing_if = "Option::is_none")]
     pub date: Option<String>,
-    #[serde(rename = "@datefrom")]
+    #[serde(rename = "@datefrom", skip_serializing_if = "Option::is_none")]
     pub datefrom: Option<String>,
-    #[serde(rename = "@dateto")]
+    #[serde(rename = "@dateto", skip_serializing_if = "Option::is_none")]
     pub dateto: Option<String>,
-    #[serde(rename = "@timefrom")]
+    #[serde(rename = "@timefrom", skip_serializing_if = "Option::is_none")]
     pub timefrom: Option<String>,
-    #[serde(rename = "@timeto")]
+    #[serde(rename = "@timeto", skip_serializing_if = "Option::is_none")]
     pub timeto: Option<String>,
+    /// Where the document came from, see [`crate::source_url`].
+    #[serde(rename = "@source_url", skip_serializing_if = "Option::is_none")]
+    pub source_url: Option<String>,
+    /// Which part of the document this is, when a large document has been
+    /// split into several `<text>` elements. See [`text::split_parts`].
+    #[serde(rename = "@part", skip_serializing_if = "Option::is_none")]
+    pub part: Option<String>,
+    /// Set on documents without any tokens, see [`crate::empty`].
+    #[serde(rename = "@empty", skip_serializing_if = "Option::is_none")]
+    pub empty: Option<String>,
+    /// Attributes copied from the header of the analysed document, with
+    /// [`text::copy_header_extras`].
+    #[serde(flatten)]
+    pub extra_attrs: ExtraAttrs,
+    /// The header elements of the analysed document that have no attribute
+    /// of their own. Not written, see [`text::copy_header_extras`].
+    #[serde(skip)]
+    pub header_extra: Vec<(String, String)>,
+    /// The id of the analysed document. Not written, see
+    /// [`crate::text_id`].
+    #[serde(skip)]
+    pub document_id: Option<String>,
+    /// The `<origFileName>` of the analysed document. Not written, see
+    /// [`crate::source_url`].
+    #[serde(skip)]
+    pub orig_file_name: Option<String>,
+    /// The number of tokens that had a tab or a newline in a column, which
+    /// was replaced. Not written, see
+    /// [`process_sentence::TokenRow::escape_columns`].
+    #[serde(skip)]
+    pub escaped_tokens: usize,
+    /// The number of sentences whose token ids were renumbered. Not
+    /// written, see [`process_sentence::renumber_ids`].
+    #[serde(skip)]
+    pub renumbered_sentences: usize,
 
-    //#[serde(flatten)]
+    /// Written with their token lines, see [`text::written`].
+    #[serde(skip)]
     pub sentence: Vec<Sentence>,
 }
 
-#[derive(Serialize)]
+/// Extra attributes of `<text>`, by attribute name.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ExtraAttrs(pub BTreeMap<String, String>);
+
+impl Serialize for ExtraAttrs {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (name, value) in self.0.iter() {
+            map.serialize_entry(&format!("@{name}"), value)?;
+        }
+        map.end()
+    }
+}
+
+#[derive(Serialize, Clone)]
 pub struct Sentence {
     #[serde(rename = "@id")]
     pub id: String,
-    #[serde(rename = "$text")]
-    pub text: String,
+    /// The sentence as plain text, see
+    /// [`process_sentence::surface_text`].
+    #[serde(rename = "@text", skip_serializing_if = "Option::is_none")]
+    pub surface: Option<String>,
+    /// The language of the sentence, when it is in a section of another
+    /// language than the document, see [`crate::analysed::markers`].
+    #[serde(rename = "@lang", skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// The domain of the sentence, when it is in a section of another genre
+    /// than the document.
+    #[serde(rename = "@gt_domain", skip_serializing_if = "Option::is_none")]
+    pub gt_domain: Option<String>,
+    /// The tokens of the sentence. They are only turned into token lines,
+    /// with the columns of the corpus, when the document is written, see
+    /// [`text::written`].
+    #[serde(skip)]
+    pub rows: Vec<TokenRow<'static>>,
 }
 
 impl Sentence {
-    fn new(id: String, text: String) -> Self {
-        Self { id, text }
+    pub(crate) fn new(id: String, rows: Vec<TokenRow<'static>>) -> Self {
+        Self {
+            id,
+            surface: None,
+            lang: None,
+            gt_domain: None,
+            rows,
+        }
+    }
+}
+
+/// A document as it is written, with the token rows of its sentences as
+/// token lines. See [`text::written`].
+#[derive(Serialize)]
+pub struct Written<'a> {
+    #[serde(flatten)]
+    text: &'a text,
+    sentence: Vec<WrittenSentence<'a>>,
+}
+
+#[derive(Serialize)]
+struct WrittenSentence<'a> {
+    #[serde(flatten)]
+    sentence: &'a Sentence,
+    #[serde(rename = "$text")]
+    lines: Lines<'a>,
+}
+
+/// The token lines of `rows`, with `columns`.
+struct Lines<'a> {
+    rows: &'a [TokenRow<'static>],
+    columns: &'a [Column],
+}
+
+/// The lines are put between newlines, so that `<sentence>` and
+/// `</sentence>` are on lines of their own.
+impl Serialize for Lines<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.rows.is_empty() {
+            return serializer.serialize_str("");
+        }
+        let mut lines = String::from("\n");
+        write_rows(self.rows, self.columns, &mut lines);
+        lines.push('\n');
+        serializer.serialize_str(&lines)
     }
 }
 
 /// In the <header><genre> of a corpus document, there are many strings that
 /// we must consolidate to our categories.
-fn genre_map(s: &str) -> &str {
+pub(crate) fn genre_map(s: &str) -> &str {
     match s {
         "admin" | "administration" => "administration",
         "bible" => "bible",
@@ -93,27 +219,289 @@ fn genre_map(s: &str) -> &str {
     }
 }
 
+/// Whether the document is an `original` or a `translation`, for filtering
+/// on in Korp. A document that says what it is `translated_from` is a
+/// translation. One that doesn't, but has `parallel_texts`, is the original
+/// that they were translated from. Of the rest, it is `unknown`.
+pub fn text_type(
+    translated_from: Option<&str>,
+    parallel_texts: Option<&[ParallelText]>,
+) -> &'static str {
+    let translated = translated_from.is_some_and(|lang| !lang.trim().is_empty());
+    let parallel = parallel_texts.is_some_and(|texts| {
+        texts.iter().any(|text| {
+            text.location
+                .as_deref()
+                .is_some_and(|l| !l.trim().is_empty())
+        })
+    });
+    match (translated, parallel) {
+        (true, _) => "translation",
+        (false, true) => "original",
+        (false, false) => "unknown",
+    }
+}
+
+/// The names of `persons` as a Korp set-valued attribute, `|A B|C D|`.
+/// Persons without a name are left out.
+fn authors_attr(persons: &[Person]) -> String {
+    let mut attr = String::from("|");
+    for person in persons {
+        let name = [&person.firstname, &person.lastname]
+            .into_iter()
+            .flatten()
+            .map(|name| name.trim())
+            .filter(|name| !name.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !name.is_empty() {
+            attr.push_str(&name.replace('|', " "));
+            attr.push('|');
+        }
+    }
+    attr
+}
+
 /// How a ParsedAnalysedDocument is turned into a KorpMonoFile
 impl From<ParsedAnalysedDocument> for text {
     fn from(doc: ParsedAnalysedDocument) -> Self {
+        Self::from_document(doc, &process_sentence::Options::default())
+    }
+}
+
+/// Make `value` fit on one line: control characters (newlines, tabs, ...)
+/// become spaces, runs of whitespace are collapsed to one space, and
+/// whitespace at the ends is removed. With `max_chars`, the value is cut
+/// after that many characters.
+pub fn normalize_attr_value(value: &str, max_chars: Option<usize>) -> String {
+    let mut normalized = value
+        .split(|c: char| c.is_whitespace() || c.is_control())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    if let Some((cut, _)) = max_chars.and_then(|max| normalized.char_indices().nth(max)) {
+        normalized.truncate(cut);
+        normalized.truncate(normalized.trim_end().len());
+    }
+    normalized
+}
+
+impl text {
+    /// A document with a sentence of the token lines of each of `texts`,
+    /// numbered from 1, for the tests of the modules that look at converted
+    /// documents. See [`process_sentence::rows_from_lines`].
+    #[cfg(test)]
+    pub(crate) fn with_sentences(texts: &[&str]) -> Self {
+        Self {
+            sentence: texts
+                .iter()
+                .enumerate()
+                .map(|(i, text)| {
+                    let rows = process_sentence::rows_from_lines(text);
+                    Sentence::new((i + 1).to_string(), rows)
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The document as it is written, with the token lines of `columns`.
+    pub fn written<'a>(&'a self, columns: &'a [Column]) -> Written<'a> {
+        let sentence = self
+            .sentence
+            .iter()
+            .map(|sentence| WrittenSentence {
+                sentence,
+                lines: Lines {
+                    rows: &sentence.rows,
+                    columns,
+                },
+            })
+            .collect();
+        Written {
+            text: self,
+            sentence,
+        }
+    }
+
+    /// All attributes of the `<text>` element that have a value, by name,
+    /// in the order they are written.
+    pub fn attrs(&self) -> Vec<(&str, &str)> {
+        let attrs = [
+            ("id", &self.id),
+            ("title", &self.title),
+            ("lang", &self.lang),
+            ("orig_lang", &self.orig_lang),
+            ("text_type", &self.text_type),
+            ("first_name", &self.first_name),
+            ("last_name", &self.last_name),
+            ("nationality", &self.nationality),
+            ("authors", &self.authors),
+            ("gt_domain", &self.gt_domain),
+            ("date", &self.date),
+            ("datefrom", &self.datefrom),
+            ("dateto", &self.dateto),
+            ("timefrom", &self.timefrom),
+            ("timeto", &self.timeto),
+            ("source_url", &self.source_url),
+            ("part", &self.part),
+            ("empty", &self.empty),
+        ];
+        attrs
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+            .chain(
+                self.extra_attrs
+                    .0
+                    .iter()
+                    .map(|(name, value)| (name.as_str(), value.as_str())),
+            )
+            .collect()
+    }
+
+    /// The attributes that come from the header of the document.
+    fn header_attrs_mut(&mut self) -> [&mut Option<String>; 15] {
+        [
+            &mut self.title,
+            &mut self.lang,
+            &mut self.orig_lang,
+            &mut self.text_type,
+            &mut self.first_name,
+            &mut self.last_name,
+            &mut self.nationality,
+            &mut self.authors,
+            &mut self.gt_domain,
+            &mut self.date,
+            &mut self.datefrom,
+            &mut self.dateto,
+            &mut self.timefrom,
+            &mut self.timeto,
+            &mut self.source_url,
+        ]
+    }
+
+    /// All attributes that have their own field, by name.
+    fn named_attrs_mut(&mut self) -> [(&'static str, &mut Option<String>); 18] {
+        [
+            ("id", &mut self.id),
+            ("title", &mut self.title),
+            ("lang", &mut self.lang),
+            ("orig_lang", &mut self.orig_lang),
+            ("text_type", &mut self.text_type),
+            ("first_name", &mut self.first_name),
+            ("last_name", &mut self.last_name),
+            ("nationality", &mut self.nationality),
+            ("authors", &mut self.authors),
+            ("gt_domain", &mut self.gt_domain),
+            ("date", &mut self.date),
+            ("datefrom", &mut self.datefrom),
+            ("dateto", &mut self.dateto),
+            ("timefrom", &mut self.timefrom),
+            ("timeto", &mut self.timeto),
+            ("source_url", &mut self.source_url),
+            ("part", &mut self.part),
+            ("empty", &mut self.empty),
+        ]
+    }
+
+    /// Leave out all attributes that are not in `selected`. See
+    /// [`crate::text_attrs`].
+    pub fn select_attrs(&mut self, selected: &TextAttrs) {
+        for (name, value) in self.named_attrs_mut() {
+            if !selected.keeps(name) {
+                *value = None;
+            }
+        }
+        self.extra_attrs.0.retain(|name, _| selected.keeps(name));
+    }
+
+    /// Turn the attributes that are the empty string into `None`, so that
+    /// they are left out of the serialized `<text>` element.
+    pub fn omit_empty_attrs(&mut self) {
+        for attr in self.header_attrs_mut() {
+            if attr.as_deref() == Some("") {
+                *attr = None;
+            }
+        }
+        self.extra_attrs.0.retain(|_, value| !value.is_empty());
+    }
+
+    /// Normalize the values of all attributes with [`normalize_attr_value`],
+    /// so that the `<text>` element, and metadata exported from it, stays on
+    /// one line.
+    pub fn normalize_attrs(&mut self, max_chars: Option<usize>) {
+        for value in self.header_attrs_mut().into_iter().flatten() {
+            *value = normalize_attr_value(value, max_chars);
+        }
+        for value in self.extra_attrs.0.values_mut() {
+            *value = normalize_attr_value(value, max_chars);
+        }
+    }
+
+    /// Copy the header elements in `selected` to attributes of `<text>`.
+    /// `selected` is pairs of the path of the header element, as found by
+    /// [`crate::analysed::file::header_extras`], and the name of the
+    /// attribute to copy it to. Elements that are not in the header of this
+    /// document get an empty attribute, so that all texts have the same
+    /// attributes.
+    pub fn copy_header_extras(&mut self, selected: &[(String, String)]) {
+        for (path, attr) in selected {
+            let value = self
+                .header_extra
+                .iter()
+                .find(|(p, _)| p == path)
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default();
+            self.extra_attrs.0.insert(attr.clone(), value);
+        }
+    }
+
+    /// Split the document into parts of at most `max_sentences` sentences
+    /// each, numbered from 1 in the `part` attribute. The part number is
+    /// appended to the `id` (`id.N`), all other attributes are copied to
+    /// every part, and the sentences keep their ids. A
+    /// document that is small enough is returned as it is, without a `part`.
+    pub fn split_parts(mut self, max_sentences: usize) -> Vec<Self> {
+        let max_sentences = max_sentences.max(1);
+        if self.sentence.len() <= max_sentences {
+            return vec![self];
+        }
+        let nparts = self.sentence.len().div_ceil(max_sentences);
+        let mut sentences = std::mem::take(&mut self.sentence).into_iter();
+        (1..=nparts)
+            .map(|n| Self {
+                // the parts are separate texts, that need ids of their own
+                id: self.id.as_ref().map(|id| format!("{id}.{n}")),
+                part: Some(n.to_string()),
+                sentence: sentences.by_ref().take(max_sentences).collect(),
+                ..self.clone()
+            })
+            .collect()
+    }
+
+    /// Turn a ParsedAnalysedDocument into a KorpMonoFile, processing the
+    /// sentences with `options`.
+    pub fn from_document(doc: ParsedAnalysedDocument, options: &process_sentence::Options) -> Self {
         let gt_domain = match doc.header.genre {
             Some(genre) => Some(genre_map(genre.code.as_str()).to_string()),
             None => Some("".to_string()),
         };
 
         let (date, datefrom, dateto) = parse_year(doc.header.year.as_deref());
+        let text_type = text_type(
+            doc.header.translated_from.as_deref(),
+            doc.header.parallel_text.as_deref(),
+        );
 
-        let (first_name, last_name, nationality) = match doc.header.authors {
+        let persons = &doc.header.authors.0;
+        let authors = (persons.len() > 1).then(|| authors_attr(persons));
+        let (first_name, last_name, nationality) = match persons.first() {
             None => (
                 Some("".to_string()),
                 Some("".to_string()),
                 Some("".to_string()),
             ),
-            Some(authors) => {
-                // anders: for now, we just take the first?
-                // what should we do here? there's only one "field"
-                // in the outgoing xml structure...
-                let person = authors.first().expect("if no authors in original file, would have serialized to None instead of Some(vec), but we have a vec, so it should not be empty.");
+            Some(person) => {
                 let firstname = match &person.firstname {
                     Some(firstname) => Some(firstname.clone()),
                     None => Some("".to_string()),
@@ -131,47 +519,250 @@ impl From<ParsedAnalysedDocument> for text {
             }
         };
 
-        // HERE is how Vec<giellacgparser::Sentence> gets turned into
-        // the string
-        // sentences: &Option<Vec<giellacgparser::Sentence>>
-        let body = doc.body;
-        let sentence = body.with_sentences(|sentences| {
-            match sentences {
-                None => vec![],
-                Some(vec) => {
-                    let mut out = vec![];
-                    let mut sentence_id = 1;
-                    for sent in vec.iter() {
-                        let processed = process_sentence(sent);
-                        let sentence_id_str = format!("{sentence_id}");
-                        let s = Sentence::new(sentence_id_str, processed);
-                        out.push(s);
-                        sentence_id += 1;
+        // HERE is how Vec<Sentence> gets turned into the token rows
+        // sentences: &Option<Vec<Sentence>>
+        let sections = doc.sections;
+        let doc_lang = doc.lang.as_deref().unwrap_or_default();
+        let mut escaped_tokens = 0;
+        let mut renumbered_sentences = 0;
+        let sentence = match &doc.sentences {
+            None => vec![],
+            Some(vec) => {
+                let mut out = Vec::with_capacity(vec.len());
+                let mut offset = 0;
+                for (sentence_id, sent) in (1..).zip(vec.iter()) {
+                    let section = sent.start.and_then(|start| sections.at(start));
+                    let lang = section
+                        .and_then(|section| section.lang.as_deref())
+                        .filter(|lang| *lang != doc_lang);
+                    let domain = section
+                        .and_then(|section| section.genre.as_deref())
+                        .map(genre_map)
+                        .filter(|domain| !domain.is_empty())
+                        .filter(|domain| Some(*domain) != gt_domain.as_deref());
+                    let sentence_lang = lang.unwrap_or(doc_lang);
+                    let mut rows = sentence_rows(sent, options, sentence_lang, &mut offset);
+                    escaped_tokens += escape_rows(&mut rows);
+                    if options.renumber_ids && renumber_ids(&mut rows) {
+                        renumbered_sentences += 1;
                     }
-                    out
-                    //vec
-                    //    .iter()
-                    //    .map(|sentence| process_sentence(sentence))
-                    //    .enumerate()
-                    //    .map(|(i, string)| Sentence::new(format!("{i}"), string.to_string()))
-                    //    .collect()
+                    let surface = options
+                        .sentence_text
+                        .then(|| process_sentence::surface_text(&rows));
+                    // the rows outlive the analysed document
+                    let rows = rows.into_iter().map(TokenRow::into_owned).collect();
+                    let mut s = Sentence::new(sentence_id.to_string(), rows);
+                    s.surface = surface;
+                    s.lang = lang.map(str::to_string);
+                    s.gt_domain = domain.map(str::to_string);
+                    out.push(s);
                 }
+                out
+                //vec
+                //    .iter()
+                //    .map(|sentence| process_sentence(sentence))
+                //    .enumerate()
+                //    .map(|(i, string)| Sentence::new(format!("{i}"), string.to_string()))
+                //    .collect()
             }
-        });
+        };
         Self {
+            id: None,
             title: doc.header.title,
             lang: doc.lang,
             orig_lang: doc.header.translated_from,
+            text_type: Some(text_type.to_string()),
             first_name,
             last_name,
             nationality,
+            authors,
             gt_domain,
             date: Some(date),
             datefrom: Some(datefrom),
             dateto: Some(dateto),
             timefrom: Some("000000".to_string()),
             timeto: Some("235959".to_string()),
+            source_url: None,
+            part: None,
+            empty: None,
+            extra_attrs: ExtraAttrs::default(),
+            header_extra: doc.header.extra,
+            document_id: doc.id,
+            orig_file_name: doc.header.orig_file_name,
+            escaped_tokens,
+            renumbered_sentences,
             sentence,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Sentence, authors_attr, normalize_attr_value, text, text_type};
+    use crate::analysed::file::{
+        HeaderMode, ParallelText, ParsedAnalysedDocument, Person, parse_document,
+    };
+    use crate::text_attrs::TextAttrs;
+
+    fn document(nsentences: usize) -> text {
+        text {
+            title: Some("Title".to_string()),
+            sentence: (1..=nsentences)
+                .map(|i| Sentence::new(i.to_string(), vec![]))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn split_parts() {
+        let mut doc = document(5);
+        doc.id = Some("corpus-sme/a".to_string());
+        let parts = doc.split_parts(2);
+        assert_eq!(parts.len(), 3);
+        for (i, part) in parts.iter().enumerate() {
+            assert_eq!(part.title.as_deref(), Some("Title"));
+            assert_eq!(part.part, Some((i + 1).to_string()));
+            assert_eq!(part.id, Some(format!("corpus-sme/a.{}", i + 1)));
+        }
+        let ids = parts
+            .iter()
+            .map(|part| {
+                part.sentence
+                    .iter()
+                    .map(|s| s.id.as_str())
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [vec!["1", "2"], vec!["3", "4"], vec!["5"]]);
+    }
+
+    #[test]
+    fn copy_header_extras() {
+        let mut doc = document(1);
+        doc.header_extra = vec![
+            ("wordcount".to_string(), "803".to_string()),
+            ("metadata/uncomplete".to_string(), "".to_string()),
+        ];
+        doc.copy_header_extras(&[
+            ("wordcount".to_string(), "wordcount".to_string()),
+            ("version".to_string(), "xsl_version".to_string()),
+        ]);
+        let attrs = doc.extra_attrs.0.iter();
+        let attrs = attrs
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(attrs, [("wordcount", "803"), ("xsl_version", "")]);
+    }
+
+    #[test]
+    fn select_attrs() {
+        let mut doc = document(1);
+        doc.lang = Some("sme".to_string());
+        doc.extra_attrs
+            .0
+            .insert("wordcount".to_string(), "803".to_string());
+        doc.extra_attrs
+            .0
+            .insert("version".to_string(), "1".to_string());
+        doc.select_attrs(&TextAttrs::new(&["lang", "wordcount", "datefrom"]));
+        assert_eq!(doc.attrs(), [("lang", "sme"), ("wordcount", "803")]);
+    }
+
+    #[test]
+    fn small_document_is_not_split() {
+        let parts = document(2).split_parts(2);
+        assert_eq!(parts.len(), 1);
+        assert_eq!(parts[0].part, None);
+        assert_eq!(parts[0].sentence.len(), 2);
+    }
+
+    #[test]
+    fn normalize_values() {
+        assert_eq!(
+            normalize_attr_value("  Ođđa\n\tgirji \u{7}  ", None),
+            "Ođđa girji"
+        );
+        assert_eq!(normalize_attr_value("Ođđa girji", Some(5)), "Ođđa");
+        assert_eq!(normalize_attr_value("Ođđa", Some(4)), "Ođđa");
+        assert_eq!(normalize_attr_value("\n", None), "");
+    }
+
+    #[test]
+    fn normalize_all_attrs() {
+        let mut doc = document(1);
+        doc.title = Some("A\r\ntitle".to_string());
+        doc.first_name = Some(" Ánde ".to_string());
+        doc.extra_attrs
+            .0
+            .insert("version".to_string(), "1\t2".to_string());
+        doc.normalize_attrs(None);
+        assert_eq!(doc.title.as_deref(), Some("A title"));
+        assert_eq!(doc.first_name.as_deref(), Some("Ánde"));
+        assert_eq!(doc.extra_attrs.0["version"], "1 2");
+    }
+
+    #[test]
+    fn authors() {
+        let person = |firstname: Option<&str>, lastname: Option<&str>| Person {
+            firstname: firstname.map(str::to_string),
+            lastname: lastname.map(str::to_string),
+            sex: None,
+            born: None,
+            nationality: None,
+        };
+        let persons = [
+            person(Some("Anders"), Some("Sønstebø")),
+            person(None, Some("Hætta")),
+            person(Some(""), None),
+        ];
+        assert_eq!(authors_attr(&persons), "|Anders Sønstebø|Hætta|");
+    }
+
+    #[test]
+    fn text_types() {
+        let parallel = [ParallelText {
+            lang: Some("nob".to_string()),
+            location: Some("a.pdf".to_string()),
+        }];
+        assert_eq!(text_type(Some("nob"), Some(&parallel)), "translation");
+        assert_eq!(text_type(Some("nob"), None), "translation");
+        assert_eq!(text_type(None, Some(&parallel)), "original");
+        assert_eq!(text_type(Some(" "), Some(&parallel)), "original");
+        assert_eq!(text_type(None, Some(&[])), "unknown");
+        assert_eq!(text_type(None, None), "unknown");
+    }
+
+    #[test]
+    fn sentence_sections() {
+        let xml = concat!(
+            "<document xml:lang=\"sme\" id=\"a\">",
+            "<header><title>A</title><genre code=\"news\"/>",
+            "<conversion_status type=\"standard\"/><availability/></header>",
+            "<body><dependency><![CDATA[\n",
+            "\"<Son>\"\n\t\"son\" Pron <W:0.0> @SUBJ> #1->0\n",
+            "\"<.>\"\n\t\".\" CLB <W:0.0> #2->1\n\n",
+            "<section xml:lang=\"nob\" genre=\"bible\">\n",
+            "\"<Herren>\"\n\t\"herre\" N <W:0.0> @SUBJ> #1->0\n",
+            "\"<.>\"\n\t\".\" CLB <W:0.0> #2->1\n\n",
+            "</section>\n",
+            "<section genre=\"news\">\n",
+            "\"<Nu>\"\n\t\"nu\" Adv <W:0.0> @ADVL #1->0\n",
+            "\"<.>\"\n\t\".\" CLB <W:0.0> #2->1\n\n",
+            "</section>\n",
+            "]]></dependency></body></document>",
+        );
+        let document = parse_document(xml, HeaderMode::Strict).unwrap();
+        let document = ParsedAnalysedDocument::parse(document, false).unwrap();
+        let text = text::from_document(document, &Default::default());
+        let overrides = text
+            .sentence
+            .iter()
+            .map(|s| (s.lang.as_deref(), s.gt_domain.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            overrides,
+            [(None, None), (Some("nob"), Some("bible")), (None, None)]
+        );
+    }
+}
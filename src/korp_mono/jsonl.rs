@@ -0,0 +1,177 @@
+//! JSON Lines output, for `--format jsonl`: one JSON object per sentence,
+//! so that the corpus can be loaded without an XML parser.
+//!
+//! ```not_rust
+//! {"text":{"title":"Ođđasat","lang":"sme",...},"sentence":"1","tokens":[{"word":"Mun","lemma":"mun","pos":"Pron",...,"ref":1,"dephead":2},...]}
+//! ```
+//!
+//! `text` has the attributes of the `<text>` element, and `text` of the
+//! sentence (with `--sentence-text`) is written as `surface`, next to its
+//! `lang` and `gt_domain` when it has its own. The tokens have one field
+//! per written column, named as in `--columns`. The numeric columns are
+//! numbers, the others strings.
+
+use std::io::Write;
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use super::KorpMonoFile;
+use crate::process_sentence::{Column, ColumnValue};
+
+/// The format of the written files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// korp_mono XML, for cwb-encode
+    #[default]
+    Xml,
+    /// One JSON object per sentence, see [`crate::korp_mono::jsonl`]
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// The file extension of files in this format.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Xml => "xml",
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+/// The fields of a JSON object, in this order.
+struct Fields<'a, V>(Vec<(&'a str, V)>);
+
+impl<V: Serialize> Serialize for Fields<'_, V> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
+
+/// The value of a column of a token.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum Value<'a> {
+    Number(usize),
+    Text(&'a str),
+}
+
+impl<'a> From<ColumnValue<'a>> for Value<'a> {
+    fn from(value: ColumnValue<'a>) -> Self {
+        match value {
+            ColumnValue::Number(n) => Value::Number(n),
+            ColumnValue::Text(text) => Value::Text(text),
+        }
+    }
+}
+
+/// One line of the output.
+#[derive(Serialize)]
+struct SentenceLine<'a> {
+    text: &'a Fields<'a, &'a str>,
+    sentence: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    surface: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lang: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gt_domain: Option<&'a str>,
+    tokens: Vec<Fields<'a, Value<'a>>>,
+}
+
+/// The JSON lines of the sentences of `text`, with the `columns` of the
+/// tokens.
+pub fn jsonl_lines(text: &KorpMonoFile, columns: &[Column]) -> String {
+    let names = columns
+        .iter()
+        .map(|column| {
+            let value = column.to_possible_value().expect("no column is skipped");
+            value.get_name().to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let attrs = Fields(text.attrs());
+    let mut out = String::new();
+    for sentence in text.sentence.iter() {
+        let tokens = sentence
+            .rows
+            .iter()
+            .map(|row| {
+                let fields = names.iter().zip(columns);
+                Fields(
+                    fields
+                        .map(|(name, column)| (name.as_str(), row.value(*column).into()))
+                        .collect(),
+                )
+            })
+            .collect();
+        let line = SentenceLine {
+            text: &attrs,
+            sentence: &sentence.id,
+            surface: sentence.surface.as_deref(),
+            lang: sentence.lang.as_deref(),
+            gt_domain: sentence.gt_domain.as_deref(),
+            tokens,
+        };
+        out.push_str(&serde_json::to_string(&line).expect("a sentence can always be serialized"));
+        out.push('\n');
+    }
+    out
+}
+
+/// Write the sentences of all `parts` to `writer` as JSON lines.
+pub fn write_jsonl<W: Write>(
+    mut writer: W,
+    parts: &[KorpMonoFile],
+    columns: &[Column],
+) -> std::io::Result<()> {
+    for part in parts {
+        writer.write_all(jsonl_lines(part, columns).as_bytes())?;
+    }
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::jsonl_lines;
+    use crate::korp_mono::KorpMonoFile;
+    use crate::korp_mono::file::Sentence;
+    use crate::process_sentence::{Column, rows_from_lines};
+
+    #[test]
+    fn sentences() {
+        let text = KorpMonoFile {
+            title: Some("\"Ođđa\" girji".to_string()),
+            lang: Some("sme".to_string()),
+            sentence: vec![Sentence {
+                id: "1".to_string(),
+                surface: Some("Mun boađán.".to_string()),
+                lang: None,
+                gt_domain: None,
+                rows: rows_from_lines(
+                    "Mun\tmun\tPron\tPron.Pers.Sg1.Nom\t1\tSUBJ→\t2\n.\t.\tCLB\tCLB\t3\tX\t2",
+                ),
+            }],
+            ..Default::default()
+        };
+        // the test rows have no pos
+        let columns = [
+            Column::Word,
+            Column::Lemma,
+            Column::Msd,
+            Column::Ref,
+            Column::Deprel,
+            Column::Dephead,
+        ];
+        assert_eq!(
+            jsonl_lines(&text, &columns),
+            concat!(
+                r#"{"text":{"title":"\"Ođđa\" girji","lang":"sme"},"sentence":"1","surface":"Mun boađán.","tokens":["#,
+                r#"{"word":"Mun","lemma":"mun","msd":"Pron.Pers.Sg1.Nom","ref":1,"deprel":"SUBJ→","dephead":2},"#,
+                r#"{"word":".","lemma":".","msd":"CLB","ref":3,"deprel":"X","dephead":2}"#,
+                "]}\n",
+            )
+        );
+        assert_eq!(jsonl_lines(&KorpMonoFile::default(), &[Column::Word]), "");
+    }
+}
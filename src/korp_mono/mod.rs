@@ -1,3 +1,7 @@
-mod file;
+pub mod file;
+pub mod jsonl;
+pub mod newline;
+pub mod path;
+pub mod template;
 
 pub use file::text as KorpMonoFile;
@@ -0,0 +1,94 @@
+//! The line endings of the written files, with `--newline` and
+//! `--final-newline`.
+//!
+//! The files are written with `\n` by default, on every platform. Before
+//! the policy is applied, all line endings are made `\n`, so that a `\r`
+//! can never end up at the end of a token line, whatever the input had.
+//! Without `--final-newline`, the files end as each format ends them: the
+//! XML after `</text>`, the JSON lines with a newline.
+
+use std::borrow::Cow;
+
+/// The line ending to write.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Newline {
+    /// `\n`, as on Unix
+    #[default]
+    Lf,
+    /// `\r\n`, as on Windows
+    Crlf,
+}
+
+impl Newline {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Newline::Lf => "\n",
+            Newline::Crlf => "\r\n",
+        }
+    }
+}
+
+/// How the line endings of a written file are written.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct NewlinePolicy {
+    pub newline: Newline,
+    /// End the file with a newline, or without one. `None` leaves it as it
+    /// is.
+    pub final_newline: Option<bool>,
+}
+
+/// `text` with all line endings (`\r\n` and `\r`) made `\n`.
+pub fn normalize(text: &str) -> Cow<'_, str> {
+    match text.contains('\r') {
+        true => Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n")),
+        false => Cow::Borrowed(text),
+    }
+}
+
+impl NewlinePolicy {
+    /// `text`, with the line endings of the policy.
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = match self.newline {
+            Newline::Lf => normalize(text),
+            Newline::Crlf => Cow::Owned(normalize(text).replace('\n', "\r\n")),
+        };
+        let newline = self.newline.as_str();
+        match self.final_newline {
+            Some(true) if !text.ends_with(newline) => text.to_mut().push_str(newline),
+            Some(false) => {
+                let len = text.trim_end_matches(['\r', '\n']).len();
+                if len < text.len() {
+                    text.to_mut().truncate(len);
+                }
+            }
+            _ => {}
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Newline, NewlinePolicy};
+
+    #[test]
+    fn policies() {
+        let text = "<text>\r\na\tb\rc\n</text>";
+        assert_eq!(
+            NewlinePolicy::default().apply(text),
+            "<text>\na\tb\nc\n</text>"
+        );
+        let crlf = NewlinePolicy {
+            newline: Newline::Crlf,
+            final_newline: Some(true),
+        };
+        assert_eq!(crlf.apply(text), "<text>\r\na\tb\r\nc\r\n</text>\r\n");
+        assert_eq!(crlf.apply("a\r\n"), "a\r\n");
+        let without = NewlinePolicy {
+            final_newline: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(without.apply("{}\n{}\n\n"), "{}\n{}");
+        assert_eq!(without.apply("{}"), "{}");
+    }
+}
@@ -0,0 +1,242 @@
+//! Manipulation of the paths of korp_mono files.
+//!
+//! Paths are only ever handled by their components, never as strings, so
+//! file names that are not valid UTF-8, and Windows path prefixes (`C:`,
+//! `\\?\C:`, `\\server\share`), pass through unchanged.
+
+use std::path::{Component, Path, PathBuf};
+
+/// Name of the directory that holds the korp_mono files of a corpus.
+pub const KORP_MONO_DIR: &str = "korp_mono";
+
+/// Name of the directory that holds the analysed files of a corpus.
+pub const ANALYSED_DIR: &str = "analysed";
+
+/// The path of a korp_mono file, derived from the path of the analysed file
+/// it is converted from, by replacing the `analysed/` directory with
+/// `korp_mono/`. Can only be created from paths that are inside an
+/// `analysed/` directory, so that the output can never end up overwriting
+/// the input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KorpMonoPath {
+    pub inner: PathBuf,
+}
+
+/// The error of trying to make a [`KorpMonoPath`] out of a path that is not
+/// inside an `analysed/` directory.
+#[derive(Debug)]
+pub struct NotAnalysedPath(pub PathBuf);
+
+impl std::fmt::Display for NotAnalysedPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "'{}' is not inside an '{ANALYSED_DIR}' directory",
+            self.0.display()
+        )
+    }
+}
+
+impl std::error::Error for NotAnalysedPath {}
+
+impl TryFrom<&Path> for KorpMonoPath {
+    type Error = NotAnalysedPath;
+
+    fn try_from(analysed_file: &Path) -> Result<Self, Self::Error> {
+        let components = analysed_file.components().collect::<Vec<_>>();
+        let Some(i) = components
+            .iter()
+            .rposition(|c| *c == Component::Normal(ANALYSED_DIR.as_ref()))
+        else {
+            return Err(NotAnalysedPath(analysed_file.to_path_buf()));
+        };
+        // there must be a file inside the analysed directory
+        if i + 1 >= components.len() {
+            return Err(NotAnalysedPath(analysed_file.to_path_buf()));
+        }
+
+        let mut inner = PathBuf::new();
+        inner.extend(&components[..i]);
+        inner.push(KORP_MONO_DIR);
+        inner.extend(&components[i + 1..]);
+        Ok(Self { inner })
+    }
+}
+
+impl KorpMonoPath {
+    /// The `korp_mono/` directory that the path is inside of.
+    pub fn dir(&self) -> &Path {
+        self.inner
+            .ancestors()
+            .find(|dir| dir.file_name().is_some_and(|name| name == KORP_MONO_DIR))
+            .unwrap_or(Path::new(""))
+    }
+
+    /// The part of the path that is inside the `korp_mono/` directory.
+    pub fn relative(&self) -> &Path {
+        self.inner.strip_prefix(self.dir()).unwrap_or(&self.inner)
+    }
+}
+
+/// Domain directory used for documents without a `gt_domain`.
+pub const UNKNOWN_DOMAIN: &str = "unknown";
+
+/// `value` as one path component: `unknown` if empty, and with `/` and `\`
+/// replaced with `_`.
+pub(crate) fn component(value: &str) -> String {
+    match value.trim() {
+        "" | "." | ".." => UNKNOWN_DOMAIN.to_string(),
+        value => value.replace(['/', '\\'], "_"),
+    }
+}
+
+/// The corpus directory (`.../corpus-xxx`) of a file in the `analysed/`
+/// directory of that corpus.
+pub fn corpus_dir(analysed_file: &Path) -> Option<&Path> {
+    analysed_file
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| name == ANALYSED_DIR))
+        .and_then(Path::parent)
+}
+
+/// Move `path` into a subdirectory named after `domain`, directly under the
+/// `korp_mono/` directory, so that `korp_mono/news/a.xml` becomes
+/// `korp_mono/<domain>/news/a.xml`. If `path` is not inside a `korp_mono/`
+/// directory, the domain directory is put right before the file name. The
+/// domain comes from the metadata, or from the `--domain-map`, so it is made
+/// into a single path component first, see [`component`].
+pub fn partition_by_domain(path: &Path, domain: &str) -> PathBuf {
+    let domain = component(domain);
+    let components = path.components().collect::<Vec<_>>();
+    let position = components
+        .iter()
+        .rposition(|c| *c == Component::Normal(KORP_MONO_DIR.as_ref()))
+        .map(|i| i + 1)
+        .unwrap_or(components.len().saturating_sub(1));
+
+    let mut out = PathBuf::new();
+    out.extend(&components[..position]);
+    out.push(&domain);
+    out.extend(&components[position..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KorpMonoPath, corpus_dir, partition_by_domain};
+    use std::path::Path;
+
+    #[test]
+    fn from_analysed() {
+        let path = Path::new("/giellalt/corpus-sme/analysed/news/avvir/a.xml");
+        let korp_mono_path = KorpMonoPath::try_from(path).unwrap();
+        assert_eq!(
+            korp_mono_path.inner,
+            Path::new("/giellalt/corpus-sme/korp_mono/news/avvir/a.xml")
+        );
+        assert_eq!(korp_mono_path.relative(), Path::new("news/avvir/a.xml"));
+        assert_eq!(
+            korp_mono_path.dir(),
+            Path::new("/giellalt/corpus-sme/korp_mono")
+        );
+    }
+
+    #[test]
+    fn from_not_analysed() {
+        [
+            "/giellalt/corpus-sme/orig/news/a.xml",
+            "/tmp/a.xml",
+            "a.xml",
+            "/giellalt/corpus-sme/analysed",
+        ]
+        .iter()
+        .for_each(|path| assert!(KorpMonoPath::try_from(Path::new(path)).is_err(), "{path}"));
+    }
+
+    #[test]
+    fn corpus_of_file() {
+        let path = Path::new("/giellalt/corpus-sme/analysed/news/a.xml");
+        assert_eq!(corpus_dir(path), Some(Path::new("/giellalt/corpus-sme")));
+        assert_eq!(corpus_dir(Path::new("/tmp/a.xml")), None);
+    }
+
+    #[test]
+    fn under_korp_mono() {
+        let path = Path::new("/giellalt/corpus-sme/korp_mono/news/avvir/a.xml");
+        assert_eq!(
+            partition_by_domain(path, "news"),
+            Path::new("/giellalt/corpus-sme/korp_mono/news/news/avvir/a.xml")
+        );
+    }
+
+    #[test]
+    fn empty_domain() {
+        let path = Path::new("corpus-sme/korp_mono/a.xml");
+        assert_eq!(
+            partition_by_domain(path, ""),
+            Path::new("corpus-sme/korp_mono/unknown/a.xml")
+        );
+    }
+
+    #[test]
+    fn domain_is_one_component() {
+        let path = Path::new("corpus-sme/korp_mono/a.xml");
+        [
+            ("..", "corpus-sme/korp_mono/unknown/a.xml"),
+            ("../x", "corpus-sme/korp_mono/.._x/a.xml"),
+            ("a/b", "corpus-sme/korp_mono/a_b/a.xml"),
+            ("../../etc", "corpus-sme/korp_mono/.._.._etc/a.xml"),
+            ("/tmp", "corpus-sme/korp_mono/_tmp/a.xml"),
+            ("news\\law", "corpus-sme/korp_mono/news_law/a.xml"),
+        ]
+        .iter()
+        .for_each(|(domain, expected)| {
+            assert_eq!(partition_by_domain(path, domain), Path::new(expected))
+        });
+    }
+
+    #[test]
+    fn not_under_korp_mono() {
+        let path = Path::new("/tmp/out/a.xml");
+        assert_eq!(
+            partition_by_domain(path, "law"),
+            Path::new("/tmp/out/law/a.xml")
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_file_name() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"\xe1hkku.xml");
+        let path = Path::new("/giellalt/corpus-sme/analysed/news").join(name);
+        let korp_mono_path = KorpMonoPath::try_from(path.as_path()).unwrap();
+        assert_eq!(
+            korp_mono_path.inner,
+            Path::new("/giellalt/corpus-sme/korp_mono/news").join(name)
+        );
+        assert_eq!(corpus_dir(&path), Some(Path::new("/giellalt/corpus-sme")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn windows_prefixes() {
+        [
+            r"C:\giellalt\corpus-sme",
+            r"\\?\C:\giellalt\corpus-sme",
+            r"\\server\share\corpus-sme",
+        ]
+        .iter()
+        .for_each(|corpus| {
+            let path = Path::new(corpus).join(r"analysed\news\a.xml");
+            let korp_mono_path = KorpMonoPath::try_from(path.as_path()).unwrap();
+            assert_eq!(
+                korp_mono_path.inner,
+                Path::new(corpus).join(r"korp_mono\news\a.xml")
+            );
+            assert_eq!(korp_mono_path.relative(), Path::new(r"news\a.xml"));
+        });
+    }
+}
@@ -0,0 +1,228 @@
+//! Naming the korp_mono files after the metadata of their documents, with
+//! `--output-template`, so that the korp_mono tree can be organized in
+//! another way than the `analysed/` tree, like by language and year, while
+//! it is converted.
+//!
+//! A template is a relative path with variables in braces, like
+//! `{lang}/{domain}/{year}/{stem}.xml`, and is taken from the `korp_mono/`
+//! directory of the corpus, or from `--output-dir`. The variables are listed
+//! in [`Variable`]. Values that are missing are `unknown`, and a `/` in a
+//! value is replaced with `_`, so that no value can add directories, or
+//! leave the output directory. Only `{dir}` can hold several directories.
+
+use std::path::{Component, Path, PathBuf};
+
+use crate::korp_mono::KorpMonoFile;
+use crate::korp_mono::path::component;
+
+/// A variable of an [`OutputTemplate`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variable {
+    /// The `lang` of the text
+    Lang,
+    /// The `gt_domain` of the text
+    Domain,
+    /// The year of `datefrom`
+    Year,
+    /// The name of the corpus directory, like `corpus-sme`
+    Corpus,
+    /// The directories of the file inside `analysed/`, like `news/avvir`
+    Dir,
+    /// The file name of the analysed file, without the extension
+    Stem,
+}
+
+impl Variable {
+    pub const ALL: [Variable; 6] = [
+        Variable::Lang,
+        Variable::Domain,
+        Variable::Year,
+        Variable::Corpus,
+        Variable::Dir,
+        Variable::Stem,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Variable::Lang => "lang",
+            Variable::Domain => "domain",
+            Variable::Year => "year",
+            Variable::Corpus => "corpus",
+            Variable::Dir => "dir",
+            Variable::Stem => "stem",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Literal(String),
+    Variable(Variable),
+}
+
+/// A template of the paths of the korp_mono files, like
+/// `{lang}/{year}/{stem}.xml`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputTemplate {
+    parts: Vec<Part>,
+}
+
+impl std::str::FromStr for OutputTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = vec![];
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let (literal, after) = rest.split_at(start);
+            let Some((name, after)) = after[1..].split_once('}') else {
+                return Err(format!("unclosed '{{' in template '{s}'"));
+            };
+            let Some(variable) = Variable::ALL.into_iter().find(|v| v.as_str() == name) else {
+                let known = Variable::ALL
+                    .map(|v| format!("{{{}}}", v.as_str()))
+                    .join(", ");
+                return Err(format!(
+                    "unknown variable '{{{name}}}' in template '{s}', known are {known}"
+                ));
+            };
+            if !literal.is_empty() {
+                parts.push(Part::Literal(literal.to_string()));
+            }
+            parts.push(Part::Variable(variable));
+            rest = after;
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        if parts
+            .iter()
+            .any(|part| matches!(part, Part::Literal(literal) if literal.contains('}')))
+        {
+            return Err(format!("unopened '}}' in template '{s}'"));
+        }
+
+        // the literal parts must keep the output inside the output directory
+        let literals = parts
+            .iter()
+            .map(|part| match part {
+                Part::Literal(literal) => literal.as_str(),
+                Part::Variable(_) => "x",
+            })
+            .collect::<String>();
+        let path = Path::new(&literals);
+        if s.ends_with(['/', '\\'])
+            || path.file_name().is_none()
+            || !path.components().all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(format!(
+                "template '{s}' must be a relative path to a file, without '..'"
+            ));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl OutputTemplate {
+    /// The path of the korp_mono file of `text`, relative to the output
+    /// directory. `relative` is the path of the analysed file inside
+    /// `analysed/`, and `corpus` the name of the corpus directory.
+    pub fn render(&self, relative: &Path, corpus: Option<&str>, text: &KorpMonoFile) -> PathBuf {
+        let year = text
+            .datefrom
+            .as_deref()
+            .and_then(|date| date.get(0..4))
+            .filter(|year| *year != "0000");
+        let dir = relative
+            .parent()
+            .map(|dir| {
+                dir.components()
+                    .map(|c| component(&c.as_os_str().to_string_lossy()))
+                    .collect::<Vec<_>>()
+                    .join("/")
+            })
+            .unwrap_or_default();
+        let stem = relative.file_stem().map(|stem| stem.to_string_lossy());
+        let mut path = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Literal(literal) => path.push_str(literal),
+                Part::Variable(Variable::Dir) => path.push_str(&dir),
+                Part::Variable(variable) => {
+                    let value = match variable {
+                        Variable::Lang => text.lang.as_deref(),
+                        Variable::Domain => text.gt_domain.as_deref(),
+                        Variable::Year => year,
+                        Variable::Corpus => corpus,
+                        Variable::Stem => stem.as_deref(),
+                        Variable::Dir => unreachable!("handled above"),
+                    };
+                    path.push_str(&component(value.unwrap_or_default()));
+                }
+            }
+        }
+        // an empty `{dir}` leaves an empty component, which is dropped here
+        Path::new(&path)
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::OutputTemplate;
+    use crate::korp_mono::KorpMonoFile;
+    use std::path::Path;
+
+    #[test]
+    fn render() {
+        let text = KorpMonoFile {
+            lang: Some("sme".to_string()),
+            gt_domain: Some("news".to_string()),
+            datefrom: Some("20120101".to_string()),
+            ..Default::default()
+        };
+        let relative = Path::new("news/avvir/a.xml");
+        let template: OutputTemplate = "{lang}/{domain}/{year}/{stem}.xml".parse().unwrap();
+        assert_eq!(
+            template.render(relative, Some("corpus-sme"), &text),
+            Path::new("sme/news/2012/a.xml")
+        );
+        let template: OutputTemplate = "{corpus}/{dir}/{year}-{stem}.xml".parse().unwrap();
+        assert_eq!(
+            template.render(relative, Some("corpus-sme"), &text),
+            Path::new("corpus-sme/news/avvir/2012-a.xml")
+        );
+        assert_eq!(
+            template.render(Path::new("a.xml"), None, &KorpMonoFile::default()),
+            Path::new("unknown/unknown-a.xml")
+        );
+        let text = KorpMonoFile {
+            lang: Some("../sme".to_string()),
+            datefrom: Some("00000000".to_string()),
+            ..Default::default()
+        };
+        let template: OutputTemplate = "{lang}/{year}/{stem}.xml".parse().unwrap();
+        assert_eq!(
+            template.render(relative, None, &text),
+            Path::new(".._sme/unknown/a.xml")
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        [
+            "",
+            "{lang}/",
+            "/{lang}/{stem}.xml",
+            "../{stem}.xml",
+            "{lang}/../{stem}.xml",
+            "{language}/{stem}.xml",
+            "{lang/{stem}.xml",
+            "{lang}}/{stem}.xml",
+        ]
+        .iter()
+        .for_each(|template| assert!(template.parse::<OutputTemplate>().is_err(), "{template}"));
+    }
+}
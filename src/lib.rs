@@ -0,0 +1,66 @@
+//! Turn analysed xml files into korp_mono (vrt xml) files.
+//!
+//! The `korp-mono-rs` binary is a thin layer on top of the [`pipeline`]
+//! module, which can also be used directly, for example to insert custom
+//! stages into the conversion.
+
+pub mod analysed;
+pub mod anonymize;
+pub mod archive;
+pub mod checksum;
+pub mod compare;
+pub mod corpus_dirs;
+pub mod corpus_info;
+pub mod corpus_settings;
+pub mod count;
+pub mod cwb;
+pub mod dashboard;
+pub mod date;
+pub mod dedup;
+pub mod deprel;
+pub mod distribution;
+pub mod domain;
+pub mod empty;
+pub mod encoding;
+pub mod extract_text;
+pub mod failures;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod file_list;
+#[cfg(feature = "generate")]
+pub mod generate;
+pub mod giella_xml;
+#[cfg(feature = "index")]
+pub mod index;
+pub mod intern;
+pub mod korp_mono;
+pub mod license;
+pub mod log_context;
+pub mod manifest;
+pub mod metrics;
+pub mod mojibake;
+pub mod msd_order;
+pub mod parallel;
+pub mod parse_year;
+pub mod path_filter;
+pub mod pipeline;
+pub mod pos_coverage;
+pub mod pos_ud;
+pub mod process_sentence;
+pub mod profile;
+pub mod progress;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reporting;
+pub mod sanity;
+pub mod sentence_dedup;
+pub mod sentence_filter;
+pub mod shard;
+pub mod sidecar;
+pub mod since;
+pub mod skiplist;
+pub mod source_url;
+pub mod status_message;
+pub mod text_attrs;
+pub mod text_id;
+pub mod text_order;
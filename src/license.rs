@@ -0,0 +1,109 @@
+//! Only convert the documents that may be redistributed, with
+//! `--license-filter`, so that a public Korp does not get restricted texts
+//! by accident.
+//!
+//! The license of a document is the `type` of
+//! `<availability><license type="..."/>` in its header, usually `free` or
+//! `standard`. Documents without a license type have the license
+//! [`UNKNOWN_LICENSE`], which can be allowed like any other.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// The license of documents whose header has no license type.
+pub const UNKNOWN_LICENSE: &str = "unknown";
+
+/// The license types that are allowed, when no types are given.
+pub const DEFAULT_ALLOWED_LICENSES: &[&str] = &["free"];
+
+#[derive(Debug, Clone)]
+pub struct LicenseFilter {
+    allowed: BTreeSet<String>,
+}
+
+impl LicenseFilter {
+    /// A filter that allows the license types `allowed`, or the
+    /// [`DEFAULT_ALLOWED_LICENSES`] if it is empty.
+    pub fn new<S: AsRef<str>>(allowed: &[S]) -> Self {
+        let allowed = match allowed.is_empty() {
+            true => DEFAULT_ALLOWED_LICENSES
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            false => allowed
+                .iter()
+                .map(|s| s.as_ref().trim().to_string())
+                .collect(),
+        };
+        Self { allowed }
+    }
+
+    /// The license of a document with the license type `license`.
+    pub fn license_of(license: Option<&str>) -> &str {
+        match license.map(str::trim) {
+            None | Some("") => UNKNOWN_LICENSE,
+            Some(license) => license,
+        }
+    }
+
+    pub fn allows(&self, license: Option<&str>) -> bool {
+        self.allowed.contains(Self::license_of(license))
+    }
+}
+
+/// The number of documents that were excluded, by license.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Excluded {
+    counts: BTreeMap<String, u64>,
+}
+
+impl Excluded {
+    pub fn add(&mut self, license: &str) {
+        *self.counts.entry(license.to_string()).or_default() += 1;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    /// A summary for the end of the run, one line per license.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(s, "{} documents excluded by license:", self.total()).expect(w);
+        for (license, n) in self.counts.iter() {
+            writeln!(s, "  {license}: {n}").expect(w);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Excluded, LicenseFilter};
+
+    #[test]
+    fn filter() {
+        let filter = LicenseFilter::new::<&str>(&[]);
+        assert!(filter.allows(Some("free")));
+        assert!(!filter.allows(Some("standard")));
+        assert!(!filter.allows(None));
+
+        let filter = LicenseFilter::new(&["free", "unknown"]);
+        assert!(filter.allows(None));
+        assert!(filter.allows(Some(" ")));
+        assert!(!filter.allows(Some("standard")));
+    }
+
+    #[test]
+    fn report() {
+        let mut excluded = Excluded::default();
+        excluded.add("standard");
+        excluded.add("unknown");
+        excluded.add("standard");
+        assert_eq!(
+            excluded.report(),
+            "3 documents excluded by license:\n  standard: 2\n  unknown: 1\n"
+        );
+    }
+}
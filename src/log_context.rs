@@ -0,0 +1,206 @@
+//! The context of the log messages of a document, with `--log-context`, so
+//! that the logs of many runs, like those of the shards of a corpus (see
+//! [`crate::shard`]), can be put together and searched by corpus, language
+//! and document, and not only by path.
+//!
+//! The stages of a document run in a `document` span, with the chosen
+//! fields: the corpus directory (`corpus-sme-x-closed`), the language of the
+//! corpus (`sme`), and the id of the analysed document, which is recorded
+//! when the xml has been parsed. Every log message of the document then
+//! carries them, and so do the `failed` events of `--json-progress`, see
+//! [`crate::progress::ProgressLayer`].
+
+use std::path::Path;
+
+use gtcorpusutil::AnalysedFilePath;
+use serde::Serialize;
+use tracing::Span;
+use tracing::field::{Empty, Field, Visit};
+
+use crate::corpus_dirs::CorpusName;
+use crate::korp_mono::path::corpus_dir;
+use crate::pipeline::Stage;
+
+/// The name of the span that the stages of a document run in.
+pub const DOCUMENT_SPAN: &str = "document";
+
+/// A field of the context of the log messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum LogField {
+    /// The corpus directory, like `corpus-sme`
+    Corpus,
+    /// The language of the corpus
+    Lang,
+    /// The id of the analysed document
+    DocId,
+}
+
+impl LogField {
+    pub const ALL: [LogField; 3] = [LogField::Corpus, LogField::Lang, LogField::DocId];
+
+    /// The name of the field in the logs.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LogField::Corpus => "corpus",
+            LogField::Lang => "lang",
+            LogField::DocId => "doc_id",
+        }
+    }
+}
+
+/// The fields that the spans of the documents are created with.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LogContext {
+    fields: Vec<LogField>,
+}
+
+impl LogContext {
+    pub fn new(fields: Vec<LogField>) -> Self {
+        Self { fields }
+    }
+
+    fn has(&self, field: LogField) -> bool {
+        self.fields.contains(&field)
+    }
+
+    /// The span of the analysed file `path`, with the fields that can be
+    /// told from the path. The document id is recorded later, with
+    /// [`record_doc_id`]. No span at all without any fields.
+    pub fn span(&self, path: &Path) -> Span {
+        let span = match (self.fields.is_empty(), self.has(LogField::DocId)) {
+            (true, _) => return Span::none(),
+            (false, true) => {
+                tracing::info_span!(DOCUMENT_SPAN, corpus = Empty, lang = Empty, doc_id = Empty)
+            }
+            (false, false) => tracing::info_span!(DOCUMENT_SPAN, corpus = Empty, lang = Empty),
+        };
+        let corpus = corpus_dir(path)
+            .and_then(Path::file_name)
+            .and_then(|name| name.to_str());
+        if let Some(corpus) = corpus.filter(|_| self.has(LogField::Corpus)) {
+            span.record(LogField::Corpus.as_str(), corpus);
+        }
+        let lang = corpus.and_then(CorpusName::parse).map(|name| name.lang);
+        if let Some(lang) = lang.filter(|_| self.has(LogField::Lang)) {
+            span.record(LogField::Lang.as_str(), lang.as_str());
+        }
+        span
+    }
+}
+
+/// Record the id of the analysed document in the span of the document, if
+/// it is the current span, and has the field.
+pub fn record_doc_id(id: &str) {
+    let span = Span::current();
+    if span
+        .metadata()
+        .is_some_and(|metadata| metadata.name() == DOCUMENT_SPAN)
+    {
+        span.record(LogField::DocId.as_str(), id);
+    }
+}
+
+/// Runs a stage that takes an analysed file, like reading it, in the span of
+/// the document.
+pub struct InDocumentSpan<S> {
+    stage: S,
+    context: LogContext,
+}
+
+impl<S> InDocumentSpan<S> {
+    pub fn new(stage: S, context: LogContext) -> Self {
+        Self { stage, context }
+    }
+}
+
+impl<S: Stage<Input = AnalysedFilePath>> Stage for InDocumentSpan<S> {
+    type Input = AnalysedFilePath;
+    type Output = S::Output;
+
+    fn name(&self) -> &'static str {
+        self.stage.name()
+    }
+
+    fn run(&self, input: Self::Input) -> Option<Self::Output> {
+        let span = self.context.span(&input.to_path_buf());
+        let _entered = span.enter();
+        self.stage.run(input)
+    }
+}
+
+/// The context fields of a span, as they are recorded.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ContextFields(pub Vec<(&'static str, String)>);
+
+impl ContextFields {
+    fn set(&mut self, name: &'static str, value: String) {
+        match self.0.iter_mut().find(|(field, _)| *field == name) {
+            Some((_, old)) => *old = value,
+            None => self.0.push((name, value)),
+        }
+    }
+}
+
+impl Serialize for ContextFields {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
+
+impl Visit for ContextFields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if let Some(name) = LogField::ALL
+            .iter()
+            .map(|f| f.as_str())
+            .find(|n| *n == field.name())
+        {
+            self.set(name, value.to_string());
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.record_str(field, format!("{value:?}").trim_matches('"'));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContextFields, LogContext, LogField, record_doc_id};
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+    use tracing::Subscriber;
+    use tracing::span::{Attributes, Id, Record};
+    use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+    /// Collects the fields of all spans.
+    #[derive(Clone, Default)]
+    struct Collect(Arc<Mutex<ContextFields>>);
+
+    impl<S: Subscriber> Layer<S> for Collect {
+        fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+            attrs.record(&mut *self.0.lock().unwrap());
+        }
+
+        fn on_record(&self, _id: &Id, values: &Record<'_>, _ctx: Context<'_, S>) {
+            values.record(&mut *self.0.lock().unwrap());
+        }
+    }
+
+    #[test]
+    fn fields() {
+        let collect = Collect::default();
+        let subscriber = tracing_subscriber::registry().with(collect.clone());
+        let path = Path::new("/c/corpus-sme-x-closed/analysed/news/a.xml");
+        tracing::subscriber::with_default(subscriber, || {
+            let context = LogContext::new(vec![LogField::Lang, LogField::DocId]);
+            let span = context.span(path);
+            let _entered = span.enter();
+            record_doc_id("a-1");
+            assert!(LogContext::default().span(path).is_none());
+        });
+        assert_eq!(
+            collect.0.lock().unwrap().0,
+            [("lang", "sme".to_string()), ("doc_id", "a-1".to_string())]
+        );
+    }
+}
@@ -1,34 +1,79 @@
-mod analysed;
-mod korp_mono;
-mod parse_year;
-mod process_sentence;
-mod status_message;
-
-use std::collections::HashMap;
-use std::io::BufWriter;
-use std::path::PathBuf;
+use std::collections::{BTreeSet, HashSet};
+#[cfg(feature = "tui")]
+use std::io::IsTerminal;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::{Arc, Mutex, mpsc};
+use std::time::{Duration, Instant, SystemTime};
 
 use anyhow::Context;
 use clap::{Parser, ValueEnum};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rayon::iter::Either;
 use rayon::prelude::*;
-use std::time::Instant;
+use regex::Regex;
 
 use gtcorpusutil::Root;
 
-use crate::analysed::file::{ParsedAnalysedDocument, UnparsedAnalysedDocument};
-use crate::korp_mono::KorpMonoFile;
-use crate::process_sentence::process_sentence;
-use crate::status_message::{StatusMessage, StatusMessageKind};
+use korp_mono_rs::anonymize::Anonymizer;
+use korp_mono_rs::archive::{self, Archive};
+use korp_mono_rs::compare::{Comparison, Rules};
+use korp_mono_rs::corpus_dirs::{CorpusName, Walk, analysed_dirs, analysed_files};
+use korp_mono_rs::corpus_info::CorpusInfos;
+use korp_mono_rs::corpus_settings::Settings;
+use korp_mono_rs::count::{Counts, count_document};
+use korp_mono_rs::cwb::{self, CwbEncode};
+use korp_mono_rs::dashboard::{Dashboard, DashboardLayer};
+use korp_mono_rs::dedup::{DuplicatePolicy, Duplicates};
+use korp_mono_rs::deprel::DeprelMap;
+use korp_mono_rs::distribution::Distribution;
+use korp_mono_rs::domain::DomainMap;
+use korp_mono_rs::empty::{EmptyCounts, EmptyPolicy, ShortDocuments};
+use korp_mono_rs::extract_text::{TextStyle, extract_document};
+use korp_mono_rs::failures::{EXIT_FATAL, EXIT_FILE_ERRORS, EXIT_OK, FailureLayer, Failures};
+use korp_mono_rs::file_list::{Separator, read_file_list};
+#[cfg(feature = "generate")]
+use korp_mono_rs::generate::{CachedGenerator, Generator};
+use korp_mono_rs::giella_xml::header::HeaderMode;
+#[cfg(feature = "index")]
+use korp_mono_rs::index::SentenceIndex;
+use korp_mono_rs::korp_mono::jsonl::OutputFormat;
+use korp_mono_rs::korp_mono::newline::{Newline, NewlinePolicy};
+use korp_mono_rs::korp_mono::path::corpus_dir;
+use korp_mono_rs::korp_mono::template::OutputTemplate;
+use korp_mono_rs::license::{Excluded, LicenseFilter};
+use korp_mono_rs::log_context::{InDocumentSpan, LogContext, LogField};
+use korp_mono_rs::manifest::{self, Manifest};
+use korp_mono_rs::metrics::Metrics;
+use korp_mono_rs::parallel::ParallelLinks;
+use korp_mono_rs::parse_year::{ImplausibleDatePolicy, PlausibleYears, YearRange};
+use korp_mono_rs::path_filter::PathFilter;
+use korp_mono_rs::pipeline::budget::{Admitted, MemoryBudget, parse_size};
+use korp_mono_rs::pipeline::chaos::{Chaos, Chaotic, parse_probability};
+use korp_mono_rs::pipeline::pool::{SourcePool, StagePool};
+use korp_mono_rs::pipeline::{Measured, Pipeline, Stage, stages, timed};
+use korp_mono_rs::pos_coverage::PosCoverage;
+use korp_mono_rs::pos_ud::Tagset;
+use korp_mono_rs::process_sentence::{self, Column, MsdProfile, PunctAttachment};
+use korp_mono_rs::profile::Profile;
+use korp_mono_rs::progress::{JsonProgress, ProgressLayer};
+use korp_mono_rs::sanity::{Demoted, Warnings};
+use korp_mono_rs::sentence_dedup::{DedupScope, SentenceDedup};
+use korp_mono_rs::sentence_filter::{DroppedSentences, SentenceFilter};
+use korp_mono_rs::shard::{RunReport, Shard};
+use korp_mono_rs::sidecar;
+use korp_mono_rs::since::{modified_since, parse_since};
+use korp_mono_rs::skiplist::Skiplist;
+use korp_mono_rs::status_message::{ErrorCategory, StatusMessage};
+use korp_mono_rs::text_attrs::TextAttrs;
+use korp_mono_rs::text_id::TextIdScheme;
+use korp_mono_rs::text_order::SortBy;
 
-
-use tracing::Span;
-
-    use tracing_indicatif::IndicatifLayer;
-    use tracing_subscriber::layer::SubscriberExt;
-    use tracing_subscriber::util::SubscriberInitExt;
-    use tracing_indicatif::span_ext::IndicatifSpanExt;
+use tracing_indicatif::IndicatifLayer;
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+use tracing_subscriber::layer::{Layer, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
 enum Section {
@@ -46,11 +91,15 @@ enum Section {
 /// `{gut_root}/giellalt` if `gut` is installed on the system. Otherwise, it
 /// can be specified with the `corpus-root` argument.
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(version, about, long_about = None, subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Language you want to process, in 3-letter ISO-639-3 code, e.g.
     /// `nob` or `sme`.
-    language: String,
+    #[arg(required_unless_present_any = ["files_from", "stdin_list_null", "input_archive"])]
+    language: Option<String>,
 
     /// Directory where the corpus directories are stored.
     ///
@@ -65,284 +114,1245 @@ struct Args {
     #[arg(long = "skip", long = "skip-section", value_enum)]
     skip_section: Vec<Section>,
 
-    /// Don't output anything, but still write the .log files
+    /// Convert the analysed files listed in this file, one per line, instead
+    /// of finding the files of the language in the corpus directories. With
+    /// `-`, the list is read from stdin.
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["stdin_list_null", "stream_files"])]
+    files_from: Option<PathBuf>,
+
+    /// Like `--files-from -`, but the paths on stdin are separated by NUL
+    /// bytes, as written by `find -print0`, so that they can have newlines
+    /// in them.
+    #[arg(long, conflicts_with = "stream_files")]
+    stdin_list_null: bool,
+
+    /// Read the analysed files from this archive (`.tar`, `.tar.gz`,
+    /// `.tar.zst` or `.zip`), as they are in it, instead of from the corpus
+    /// directories. The paths of the entries are the paths of the files, so
+    /// `corpus-sme/analysed/a.xml` is written to `corpus-sme/korp_mono/a.xml`.
+    #[arg(
+        long,
+        value_name = "PATH",
+        conflicts_with_all = ["files_from", "stdin_list_null", "stream_files", "prune", "since"]
+    )]
+    input_archive: Option<PathBuf>,
+
+    /// For testing the error accounting: fail each stage of each file with
+    /// this probability, from 0 to 1.
+    #[arg(long, value_name = "P", value_parser = parse_probability, hide = true)]
+    chaos: Option<f64>,
+
+    /// The seed of the random choices of the run, like those of `--chaos`,
+    /// so that runs over the same input make the same choices. Without it,
+    /// the seed is taken from the clock, and logged.
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+
+    /// Only convert the files at most this deep in the `analysed/`
+    /// directories. The files directly in `analysed/` are at depth 1.
+    #[arg(long, value_name = "DEPTH")]
+    max_depth: Option<usize>,
+
+    /// Follow symlinks in the `analysed/` directories. Symlinks that lead
+    /// back to a directory that is being walked are skipped.
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Don't walk into directories on other file systems than the
+    /// `analysed/` directory they are in.
+    #[arg(long)]
+    one_file_system: bool,
+
+    /// Don't show the progress bar, or log anything but errors. Only the
+    /// summary at the end of the run is printed.
     #[arg(short, long)]
     quiet: bool,
-}
 
-macro_rules! q_send_or_panic {
-    ($queue:ident, $msg:expr) => {
-        if let Err(_) = $queue.send($msg) {
-            panic!("can't send message to printer thread");
-        }
-    };
-}
+    /// Also log the files that went fine, e.g. each file that was read. By
+    /// default, those are only counted.
+    #[arg(short, long, conflicts_with = "quiet")]
+    verbose: bool,
+
+    /// Instead of the progress bar, write progress events to stdout, one
+    /// JSON object per line: `start`, `written` and `failed` for each file,
+    /// and `done`. The messages and the summary go to stderr.
+    #[arg(long)]
+    json_progress: bool,
+
+    /// Add these fields to every message about a document, and to the
+    /// `failed` events of `--json-progress`, so that the logs of several
+    /// runs can be put together: the corpus directory, the language of the
+    /// corpus, and the id of the document. `--log-context` alone adds all
+    /// of them.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        num_args = 0..,
+        default_missing_values = ["corpus", "lang", "doc-id"]
+    )]
+    log_context: Vec<LogField>,
+
+    /// Instead of the progress bar, show a dashboard of the run: the
+    /// progress and time left, the files per second of each stage, the most
+    /// recent errors, and the slowest files. Only when stdout is a terminal,
+    /// otherwise the progress bar is shown.
+    #[cfg(feature = "tui")]
+    #[arg(long, conflicts_with_all = ["quiet", "json_progress", "stream_files"])]
+    tui: bool,
+
+    /// Detect and repair double-encoded UTF-8 (mojibake) in the word forms,
+    /// before writing the korp_mono files.
+    #[arg(long)]
+    encoding_check: bool,
+
+    /// Mask personal names: replace the word form and lemma of proper nouns
+    /// that are tagged as humans or person names, with a placeholder.
+    #[arg(long)]
+    anonymize: bool,
+
+    /// File with additional names to mask when anonymizing, one per line.
+    #[arg(long, requires = "anonymize")]
+    name_list: Option<PathBuf>,
+
+    /// Check the converted sentences for structural anomalies (empty
+    /// sentences, missing or duplicate self ids, no parents, broken
+    /// dependency trees), and write a `korp_mono.warnings` report to each
+    /// corpus directory.
+    #[arg(long)]
+    warnings: bool,
+
+    /// Give all tokens of the sentences whose dependency trees are broken
+    /// (not exactly one root, parents outside of the sentence, or cycles)
+    /// `X` as deprel and `0` as parent, instead of writing the broken
+    /// trees.
+    #[arg(long)]
+    demote_broken_trees: bool,
+
+    /// Which classes of tags to keep in the msd column.
+    #[arg(long, value_enum, default_value_t)]
+    msd_profile: MsdProfile,
+
+    /// Sort the tags of the msd column in a canonical order (part of speech,
+    /// subclass, ..., number, case, ...), instead of the order of the
+    /// analyser, so that builds with different analysers can be diffed.
+    #[arg(long)]
+    canonical_msd: bool,
+
+    /// Which tags to write in the pos column: the giella parts of speech, or
+    /// the Universal Dependencies UPOS tags. With `--columns`, use the
+    /// `upos` column instead.
+    #[arg(long, value_enum, default_value_t)]
+    tagset: Tagset,
+
+    /// The earliest year that is plausible for a text in the corpus. Texts
+    /// with earlier dates are warned about.
+    #[arg(long, default_value_t = 1600)]
+    min_year: u16,
+
+    /// The latest year that is plausible for a text in the corpus. Texts
+    /// with later dates are warned about. Defaults to the current year.
+    #[arg(long)]
+    max_year: Option<u16>,
+
+    /// What to do with dates outside of the plausible range.
+    #[arg(long, value_enum, default_value_t)]
+    implausible_dates: ImplausibleDatePolicy,
+
+    /// What to do with sentences without tokens, and documents without
+    /// sentences: leave them out, or keep them, and give the empty
+    /// documents an `empty="yes"` attribute. They are counted at the end.
+    #[arg(long, value_enum, default_value_t)]
+    empty_documents: EmptyPolicy,
+
+    /// Override the header of documents with a `<file>.meta.toml` next to
+    /// them, with the values in it, like `year = 1998`. The overridden
+    /// fields are counted at the end. See `src/sidecar.rs`.
+    #[arg(long)]
+    meta_sidecars: bool,
+
+    /// Skip documents with fewer than this many tokens, which are often
+    /// OCR garbage. They are counted at the end.
+    #[arg(long, value_name = "N")]
+    min_tokens: Option<usize>,
+
+    /// Don't write attributes of `<text>` that have an empty value.
+    #[arg(long)]
+    omit_empty_attrs: bool,
+
+    /// Only write these attributes of `<text>`, separated by commas, e.g.
+    /// `id,title,lang,datefrom,dateto`. Attributes copied with
+    /// `--header-extra` are given by their attribute name.
+    #[arg(long, value_name = "ATTRS", value_delimiter = ',')]
+    text_attrs: Option<Vec<String>>,
+
+    /// Like `--text-attrs`, but read the attributes from this file, one per
+    /// line, or separated by commas. Lines can have `#` comments. Can be
+    /// combined with `--text-attrs`.
+    #[arg(long, value_name = "PATH")]
+    text_attrs_file: Option<PathBuf>,
+
+    /// Cut the values of the attributes of `<text>` to at most this many
+    /// characters. The values are always put on one line, with runs of
+    /// whitespace and control characters collapsed to one space.
+    #[arg(long, value_name = "N")]
+    max_attr_length: Option<usize>,
+
+    /// Give each `<text>` an `id` attribute: the id of the analysed
+    /// document (or the path, when the document has none), or the corpus
+    /// name and the path of the file in the corpus, like
+    /// `corpus-sme/news/a.html`.
+    #[arg(long, value_enum)]
+    text_id: Option<TextIdScheme>,
+
+    /// Use this instead of the name of the corpus directory in the text ids
+    /// made from paths.
+    #[arg(long, value_name = "NAME", requires = "text_id")]
+    corpus_id: Option<String>,
+
+    /// Add a `source_url` attribute to `<text>`, with the `<origFileName>`
+    /// of the document, when it looks like an URL or a path.
+    #[arg(long)]
+    source_url: bool,
+
+    /// Write each document to `korp_mono/<gt_domain>/...`, instead of
+    /// directly to `korp_mono/...`.
+    #[arg(long)]
+    partition_by_domain: bool,
+
+    /// Give documents without a `<genre>` the domain of the first directory
+    /// inside `analysed/` (`admin/`, `news/`, ...).
+    #[arg(long)]
+    infer_domain: bool,
+
+    /// With `--infer-domain`, take the domains of directories from this
+    /// file, with one `path<TAB>domain` line per directory, where the path
+    /// is relative to `analysed/`.
+    #[arg(long, value_name = "PATH", requires = "infer_domain")]
+    domain_map: Option<PathBuf>,
+
+    /// Write the korp_mono files to this directory, instead of to the
+    /// `korp_mono/` directory of each corpus.
+    #[arg(long)]
+    output_dir: Option<PathBuf>,
+
+    /// Also convert files that are not inside an `analysed/` directory. They
+    /// are written directly to the `--output-dir`.
+    #[arg(long, requires = "output_dir")]
+    allow_any_path: bool,
+
+    /// Name the korp_mono files after the metadata of their documents, with
+    /// a path relative to the `korp_mono/` directory (or `--output-dir`),
+    /// like `{lang}/{domain}/{year}/{stem}.xml`. The variables are `{lang}`,
+    /// `{domain}`, `{year}`, `{corpus}`, `{dir}` (the directories inside
+    /// `analysed/`) and `{stem}` (the file name without `.xml`).
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "partition_by_domain")]
+    output_template: Option<OutputTemplate>,
+
+    /// When a cohort in the analysis can't be parsed, skip to the next
+    /// cohort and keep going, instead of dropping the rest of the document.
+    #[arg(long)]
+    robust_parse: bool,
+
+    /// How to read the `<header>` of the documents: `lenient` reads what it
+    /// can of the header and leaves out the rest, `strict` fails the
+    /// documents whose header has an unexpected shape (like a `<genre>`
+    /// without a `code`, or no `<conversion_status>`).
+    #[arg(long, value_enum, default_value = "lenient")]
+    header_mode: HeaderMode,
+
+    /// Add two columns at the end of each token line, with the start and
+    /// end character offsets of the token in the text of the document.
+    #[arg(long)]
+    with_offsets: bool,
+
+    /// Write a `korp_mono.info` file to each corpus directory, with the
+    /// number of sentences, and the first and last date, in the format of
+    /// the CWB/Korp `.info` file.
+    #[arg(long)]
+    info: bool,
+
+    /// Print the documents and tokens per domain and per year at the end of
+    /// the run, to see if the corpus is made up as expected.
+    #[arg(long)]
+    distribution: bool,
+
+    /// Print the analyses without a known part of speech, and the word forms
+    /// without an analysis, the most frequent first, at the end of the run,
+    /// to find the tags that are missing from the tagsets.
+    #[arg(long)]
+    pos_coverage: bool,
+
+    /// Write all the analyses and word forms of `--pos-coverage` to this
+    /// file, as TSV.
+    #[arg(long, value_name = "PATH", requires = "pos_coverage")]
+    pos_coverage_file: Option<PathBuf>,
+
+    /// Put the distinct lemmas of all readings in the lemma column, as a
+    /// Korp set-valued attribute (`|lemma1|lemma2|`).
+    #[arg(long)]
+    multi_lemma: bool,
+
+    /// Write the korp_mono files on this many dedicated threads, instead of
+    /// on the threads that do the conversion. Helps when the file system is
+    /// slow.
+    #[arg(long, default_value_t = 0)]
+    write_threads: usize,
+
+    /// Keep the estimated memory of the files that are converted at the
+    /// same time within this budget, like `8G` or `512M`. Further files wait
+    /// until enough of them are done.
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    memory_budget: Option<u64>,
+
+    /// For `--memory-budget`, the memory of a file is estimated as its size
+    /// times this.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 8,
+        requires = "memory_budget"
+    )]
+    memory_factor: u64,
+
+    /// Flush each korp_mono file to disk before renaming it into place.
+    /// Slower, but the file is guaranteed to be complete after a power loss.
+    #[arg(long)]
+    fsync: bool,
+
+    /// Write a `korp_mono.manifest` file to each corpus directory, with the
+    /// CRC-32 checksum and size of each written korp_mono file.
+    #[arg(long)]
+    manifest: bool,
+
+    /// Write korp_mono XML files, or JSON lines files (`.jsonl`), with one
+    /// JSON object per sentence, with the attributes of the document and all
+    /// fields of the tokens.
+    #[arg(long, value_enum, default_value_t)]
+    format: OutputFormat,
+
+    /// The line endings of the written files, `\n` (lf) or `\r\n` (crlf),
+    /// whatever the platform. Stray `\r`s in the sentences are made line
+    /// endings too, so that no token line ends with one.
+    #[arg(long, value_enum, default_value_t)]
+    newline: Newline,
+
+    /// End the written files with a newline (`--final-newline` or
+    /// `--final-newline true`), or without one (`--final-newline false`).
+    /// By default, the XML files end after `</text>`, and the JSON lines
+    /// files with a newline. The output of `convert-one` always ends with
+    /// one, unless this is false.
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    final_newline: Option<bool>,
+
+    /// Write all korp_mono files into this archive, at the paths they would
+    /// have been written to, instead of one file each. The format is taken
+    /// from the extension: `.tar`, `.tar.gz`, `.tar.zst` or `.zip`.
+    #[arg(long, value_name = "PATH")]
+    archive_output: Option<PathBuf>,
+
+    /// Before converting, remove the korp_mono files whose analysed file no
+    /// longer exists, according to the manifest of each corpus, and drop
+    /// them from the manifest.
+    #[arg(long, requires = "manifest")]
+    prune: bool,
+
+    /// Split documents with more than this many sentences into several
+    /// `<text part="N">` elements, each with all the attributes of the
+    /// document.
+    #[arg(long)]
+    max_text_sentences: Option<usize>,
+
+    /// Only convert the files that match this glob pattern, relative to the
+    /// `analysed/` directory, e.g. `admin/sd/*2023*.xml`. Can be given more
+    /// than once.
+    #[arg(long, value_name = "GLOB")]
+    include: Vec<String>,
+
+    /// Don't convert the files that match this glob pattern, relative to the
+    /// `analysed/` directory. Can be given more than once.
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+
+    /// Only convert the files of this shard, e.g. `2/8`, to convert a corpus
+    /// in several independent jobs. The files are split by a stable hash of
+    /// their path inside the corpora, see `src/shard.rs`.
+    #[arg(long, value_name = "I/N")]
+    shard: Option<Shard>,
+
+    /// Write a summary of the run to this file, as JSON: the shard, the
+    /// number of files, and the failures by category. The reports of the
+    /// shards can be put together with the `merge-reports` subcommand.
+    #[arg(long, value_name = "PATH")]
+    json_report: Option<PathBuf>,
+
+    /// Skip the documents listed in this file, one per line, either by their
+    /// path inside the `analysed/` directory, or by their id (the file name
+    /// without `.xml`). For documents that are known to be broken: they are
+    /// reported as skipped, not as failed.
+    #[arg(long, value_name = "PATH")]
+    skiplist: Option<PathBuf>,
+
+    /// Only convert the files that were modified at or after this time,
+    /// given as `YYYY-MM-DD`, optionally followed by `HH:MM[:SS]`, in UTC.
+    /// For incremental builds.
+    #[arg(long, value_name = "DATE", value_parser = parse_since)]
+    since: Option<SystemTime>,
+
+    /// Start converting as soon as the first files are found, while the
+    /// rest of the corpus directories are still being walked, instead of
+    /// finding all files first. The number of files is only known at the
+    /// end, so the progress bar grows as files are found.
+    #[arg(long, conflicts_with_all = ["prune", "json_progress"])]
+    stream_files: bool,
+
+    /// Only convert the documents whose `<year>` is this year or later.
+    /// Documents without a known year are skipped when this or
+    /// `--year-to` is given.
+    #[arg(long, value_name = "YEAR")]
+    year_from: Option<u16>,
+
+    /// Only convert the documents whose `<year>` is this year or earlier.
+    #[arg(long, value_name = "YEAR")]
+    year_to: Option<u16>,
+
+    /// Only convert the documents whose license (the type of
+    /// `<availability><license>`) is one of these, e.g. `free,standard`,
+    /// or only `free` documents if no types are given. Documents without a
+    /// license type have the license `unknown`. The excluded documents are
+    /// counted by license at the end.
+    #[arg(long, value_name = "TYPES", value_delimiter = ',', num_args = 0..)]
+    license_filter: Option<Vec<String>>,
 
-#[inline(always)]
-fn timed<F, R>(f: F) -> (std::time::Duration, R)
-where
-    F: FnOnce() -> R,
-{
-    let t0 = Instant::now();
-    let result = f();
-    (t0.elapsed(), result)
+    /// Find documents with the same content as another document, and list
+    /// them in a `korp_mono.duplicates` file in each corpus directory. With
+    /// `skip`, only the first one of them is converted.
+    #[arg(long, value_enum)]
+    duplicates: Option<DuplicatePolicy>,
+
+    /// Remove the sentences that occur earlier in the same document, or in
+    /// any document of the same corpus. Sentences are compared by their
+    /// lowercased word forms. The number of removed sentences is reported
+    /// per corpus at the end.
+    #[arg(long, value_enum)]
+    dedup: Option<DedupScope>,
+
+    /// Leave out the sentences that match this regular expression, like
+    /// boilerplate and page numbers (`^Side \d+ av \d+$`). Matched against
+    /// the word forms of the sentence as running text. Can be given more
+    /// than once. The number of sentences left out by each is reported at
+    /// the end.
+    #[arg(long, value_name = "REGEX")]
+    drop_sentence_regex: Vec<Regex>,
+
+    /// Write a `parallel_links.tsv` file to each corpus directory, with the
+    /// translations of each document, from the `<parallel_text>` elements
+    /// of the header: the path of the document, the language and the
+    /// location of the translation.
+    #[arg(long)]
+    parallel_links: bool,
+
+    /// Copy the header element at PATH (e.g. `version`, or
+    /// `metadata/collection`) to an attribute of `<text>`. The attribute is
+    /// named ATTR, or after the path, with `/` replaced by `_`. Can be given
+    /// more than once.
+    #[arg(long, value_name = "PATH[=ATTR]")]
+    header_extra: Vec<String>,
+
+    /// Read the analysed files on this many dedicated threads, which can
+    /// run ahead of the conversion by a bounded number of files. By default,
+    /// the files are read on the threads that do the conversion.
+    #[arg(long, default_value_t = 0)]
+    threads_io: usize,
+
+    /// Add a column at the end of each token line, with the language of the
+    /// token: the language of its `Lang/xxx` tag, or the language of the
+    /// document.
+    #[arg(long)]
+    lang_column: bool,
+
+    /// Add a column at the end of each token line, with the lemmas of the
+    /// parts of dynamic compounds joined with `+` (e.g.
+    /// `várri+ovddasteaddji`), and empty for other tokens.
+    #[arg(long)]
+    compound_column: bool,
+
+    /// Normalize the functional labels (the deprel column) to a small set
+    /// of labels (`subj`, `obj`, `advl`, ..., `other`), so that they can be
+    /// searched for in Korp without knowing all their variants.
+    #[arg(long)]
+    normalize_deprel: bool,
+
+    /// A file of `label<TAB>normalized` lines, that adds to or changes the
+    /// built-in mapping of `--normalize-deprel`.
+    #[arg(long, value_name = "PATH", requires = "normalize_deprel")]
+    deprel_map: Option<PathBuf>,
+
+    /// Add a column at the end of each token line, with the functional
+    /// label before it was normalized by `--normalize-deprel`.
+    #[arg(long, requires = "normalize_deprel")]
+    raw_deprel_column: bool,
+
+    /// Give punctuation tokens the functional label `CLB` or `PUNCT` (or
+    /// `punct`, with `--normalize-deprel`), and attach them to the root of
+    /// the sentence, or to the token before them, so that they are part of
+    /// the dependency tree.
+    #[arg(long, value_enum, value_name = "TO")]
+    attach_punct: Option<PunctAttachment>,
+
+    /// The columns of the token lines, in order, separated by commas. The
+    /// default is `word,lemma,pos,msd,ref,deprel,dephead`.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        conflicts_with_all = ["with_offsets", "lang_column", "compound_column", "raw_deprel_column"],
+    )]
+    columns: Option<Vec<Column>>,
+
+    /// Add a `text` attribute to each `<sentence>`, with the sentence as
+    /// plain text, for the KWIC view of Korp. The word forms are joined
+    /// with spaces, except around punctuation.
+    #[arg(long)]
+    sentence_text: bool,
+
+    /// Fail the documents where a token has a tab or a newline in one of
+    /// its columns (e.g. in the lemma), instead of replacing them with
+    /// spaces.
+    #[arg(long)]
+    strict_columns: bool,
+
+    /// Renumber the ref and dephead columns of each sentence to start at 1,
+    /// for analyses that number the tokens through the whole document.
+    /// Heads outside of the sentence become 0.
+    #[arg(long)]
+    renumber_ids: bool,
+
+    /// Replace the `[[[GEN:...]]]` lemmas of dynamic compounds with the
+    /// lemma generated by this generator transducer (e.g.
+    /// `generator-gt-norm.hfstol`), with `hfst-lookup`. The lemmas that
+    /// can't be generated are left as they are.
+    #[cfg(feature = "generate")]
+    #[arg(long, value_name = "TRANSDUCER")]
+    generate_lemmas: Option<PathBuf>,
+
+    /// Keep the lemmas generated with `--generate-lemmas` in this file, and
+    /// use them in later runs, instead of running the generator again. The
+    /// file is not used if the transducer has changed.
+    #[cfg(feature = "generate")]
+    #[arg(long, value_name = "PATH", requires = "generate_lemmas")]
+    generator_cache: Option<PathBuf>,
+
+    /// Give up on files whose analyses take longer than this to parse, e.g.
+    /// `120s`, `2m`, or `500ms`. A plain number is seconds. A parse that
+    /// timed out keeps running in the background, and while too many of them
+    /// are, the next files are skipped.
+    #[arg(long, value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
+    /// Write summary metrics of the run (files, documents, sentences and
+    /// tokens converted, and the runs, failures and time of each stage) to
+    /// this file, in the Prometheus text format, e.g. for the textfile
+    /// collector of the node exporter.
+    #[arg(long, value_name = "PATH")]
+    metrics: Option<PathBuf>,
+
+    /// Write the time of each stage on each file to this file, as a Chrome
+    /// trace (JSON), which can be opened in speedscope or Perfetto, to see
+    /// which corpora and files take the most time.
+    #[arg(long, value_name = "PATH")]
+    profile: Option<PathBuf>,
+
+    /// Also encode the converted documents into CWB corpora, one per corpus
+    /// directory, by piping them into `cwb-encode`, which must be in the
+    /// PATH. The corpora are named after the corpus directories, e.g.
+    /// `corpus_sme_x_closed`.
+    #[arg(long, requires_all = ["registry", "data"])]
+    cwb_encode: bool,
+
+    /// The CWB registry directory, for `--cwb-encode`.
+    #[arg(long, value_name = "DIR", requires = "cwb_encode")]
+    registry: Option<PathBuf>,
+
+    /// The directory of the CWB data, for `--cwb-encode`. The data of each
+    /// corpus is put in a directory named after the corpus in it.
+    #[arg(long, value_name = "DIR", requires = "cwb_encode")]
+    data: Option<PathBuf>,
+
+    /// Encode the documents of each corpus in this order with
+    /// `--cwb-encode`, so that builds of the same corpus are the same. The
+    /// documents are then kept in memory until all files are converted.
+    #[arg(long, value_enum, requires = "cwb_encode")]
+    sort_by: Option<SortBy>,
+
+    /// Also add the converted sentences to a full-text index in this
+    /// directory, with their word forms, lemmas and text metadata, to be
+    /// searched with the `search` subcommand. An index that is already
+    /// there is replaced.
+    #[cfg(feature = "index")]
+    #[arg(long, value_name = "DIR")]
+    index: Option<PathBuf>,
+
+    /// Compare the converted documents with the files at the same paths in
+    /// this `korp_mono` directory, written by the old Python converter, and
+    /// report the differences by text attribute and by column at the end of
+    /// the run. The documents are written as usual.
+    #[arg(long, value_name = "DIR")]
+    compare_python: Option<PathBuf>,
+
+    /// Leave out the known differences in this file from the comparison
+    /// of `--compare-python`, see `src/compare.rs`.
+    #[arg(long, value_name = "PATH", requires = "compare_python")]
+    compare_rules: Option<PathBuf>,
+
+    /// Don't read the `korp-mono.toml` of the corpus directories, which can
+    /// set the domain map, the text attributes, the columns, and the
+    /// license filter of each corpus.
+    #[arg(long)]
+    no_corpus_settings: bool,
 }
 
-fn read_to_string(
-    //pb: ProgressBar,
-    //q: mpsc::Sender<StatusMessage>,
-    analysed_file: gtcorpusutil::AnalysedFilePath,
-) -> Option<(gtcorpusutil::AnalysedFilePath, String)> {
-    let file = analysed_file.to_path_buf();
-    let span = tracing::info_span!("reading file", file = ?file);
-    let _guard = span.enter();
-
-    let (dur, res) = timed(|| analysed_file.read_to_string());
-    match res {
-        Ok(string) => {
-            tracing::info!("file read ok");
-            Span::current().pb_inc(1);
-            //pb.inc(1);
-            Some((analysed_file, string))
-        }
-        Err(e) => {
-            tracing::error!(error = ?e, "error reading file");
-            None
-        }
+/// Parse a duration like `120s`, `2m`, `1h`, `500ms`, or `120`.
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number = number
+        .parse::<u64>()
+        .map_err(|_| format!("'{s}' does not start with a number"))?;
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "" | "s" => Ok(Duration::from_secs(number)),
+        "m" => Ok(Duration::from_secs(number * 60)),
+        "h" => Ok(Duration::from_secs(number * 60 * 60)),
+        unit => Err(format!("unknown unit '{unit}', expected ms, s, m, or h")),
     }
-    //q_send_or_panic!(q, StatusMessage::read(analysed_file.to_path_buf(), dur, &res));
-    //res.ok().map(|s| (analysed_file, s))
 }
 
-/// Use `quick_xml` to parse the contents of string `s` (coming from file with
-/// path `path`) into an XML document, and send the results as a
-/// `StatusMessage` over the queue `status_queue`.
-fn parse_xml(
-    //pb: ProgressBar,
-    //next_pb: ProgressBar,
-    //q: mpsc::Sender<StatusMessage>,
-    analysed_file: gtcorpusutil::AnalysedFilePath,
-    s: &str,
-) -> Option<(
-    gtcorpusutil::AnalysedFilePath,
-    Arc<Mutex<UnparsedAnalysedDocument>>,
-)> {
-    //pb.inc(1);
-    let (_dur, res) = timed(|| quick_xml::de::from_str(&s));
-    match res {
-        Ok(xml) => Some((analysed_file, Arc::new(Mutex::new(xml)))),
-        Err(_e) => {
-            // TODO handle error
-            //next_pb.set_length(pb.length().unwrap() - 1);
-            None
-        }
-    }
-    //q_send_or_panic!(
-    //    q,
-    //    StatusMessage::parse_xml(analysed_file.to_path_buf(), dur, &res)
-    //);
-    //res.ok()
-    //    .map(|doc| (analysed_file, Arc::new(Mutex::new(doc))))
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Convert a single analysed document read from stdin, and write the
+    /// korp_mono document to stdout. Errors go to stderr. The options given
+    /// before the subcommand apply, except the ones about files and
+    /// directories.
+    ConvertOne,
+    /// Count the documents, sentences and tokens of the analysed files in a
+    /// directory, per language and domain, without converting them. Much
+    /// faster than a conversion, for reporting the size of a corpus.
+    Count {
+        /// The directory to count the analysed files (`*.xml`) of, e.g.
+        /// `corpus-sme/analysed`
+        dir: PathBuf,
+    },
+    /// Write the sentences of the korp_mono files in a directory as plain
+    /// text, one sentence per line, with an empty line between the
+    /// documents, e.g. as training data for language models. The word form
+    /// is taken from the column of `--columns`.
+    ExtractText {
+        /// The directory of the korp_mono files (`*.xml`), e.g.
+        /// `corpus-sme/korp_mono`
+        dir: PathBuf,
+        /// Write the word forms as tokenized, separated by spaces, or as
+        /// running text
+        #[arg(long, value_enum, default_value_t)]
+        style: TextStyle,
+        /// Write the text to this file, instead of stdout
+        #[arg(long, short, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Add up the reports of `--json-report` of the shards of a run, and
+    /// print the merged report, as JSON. The shards that have no report are
+    /// listed on stderr.
+    MergeReports {
+        /// The reports of the shards
+        #[arg(required = true)]
+        reports: Vec<PathBuf>,
+        /// Write the merged report to this file, instead of stdout
+        #[arg(long, short, value_name = "PATH")]
+        output: Option<PathBuf>,
+    },
+    /// Search the index written with `--index`, and print the file, the
+    /// sentence id and the words of each sentence that matches, the best
+    /// first. The words and lemmas are searched by default; the other
+    /// fields by name, e.g. `guolli lang:sme year:[2000 TO 2010]`.
+    #[cfg(feature = "index")]
+    Search {
+        /// The directory of the index, as given to `--index`
+        dir: PathBuf,
+        /// The query
+        query: String,
+        /// Print at most this many sentences
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
 }
 
-fn parse_analyses(
-    //pb: ProgressBar,
-    //q: mpsc::Sender<StatusMessage>,
-    analysed_file_path: gtcorpusutil::AnalysedFilePath,
-    document: Arc<Mutex<UnparsedAnalysedDocument>>,
-) -> Option<(
-    gtcorpusutil::AnalysedFilePath,
-    Arc<Mutex<ParsedAnalysedDocument>>,
-)> {
-    let document = Arc::into_inner(document).expect("only 1 thread accesses this Arc");
-    let document = Mutex::into_inner(document).expect("only 1 thread accesses this mutex");
-    let (dur, res) = timed(|| std::panic::catch_unwind(|| ParsedAnalysedDocument::try_from(document)));
-    match res {
-        Ok(Ok(doc)) => {
-            //pb.inc(1);
-            Some((analysed_file_path, Arc::new(Mutex::new(doc))))
-        }
-        Ok(Err(e)) => {
-            None
-            //Err(e)
+/// At most this many found files wait for the pipeline, with
+/// `--stream-files`.
+const FILE_QUEUE_LEN: usize = 1024;
+
+/// Find the analysed files of the language `lang` in the corpora of `root`,
+/// and hand each of them to `found`. The files of the corpora that
+/// gtcorpusutil doesn't recognise are found with [`analysed_dirs`], in
+/// `root_dir`, or next to the corpora that it does. `skip` is whether to
+/// skip the open and the closed corpora. When `walk` is not the default,
+/// gtcorpusutil only finds the corpora, and all files are found with
+/// [`analysed_files`], which walks the directories as `walk` says. So are
+/// the files of the corpora that gtcorpusutil can't list, because one of
+/// the file names is not UTF-8.
+fn find_files(
+    root: Root,
+    root_dir: Option<&Path>,
+    lang: &str,
+    (skip_open, skip_closed): (bool, bool),
+    walk: Walk,
+    found: &mut dyn FnMut(gtcorpusutil::AnalysedFilePath),
+) -> anyhow::Result<()> {
+    let mut known = HashSet::new();
+    let mut search_roots = BTreeSet::new();
+    if let Some(dir) = root_dir {
+        search_roots.insert(dir.to_path_buf());
+    }
+    let corpora = root
+        .corpora()
+        .filter(|corpus| corpus.corpus_name.lang == lang)
+        .filter(|corpus| !skip_open || !corpus.corpus_name.is_open())
+        .filter(|corpus| !skip_closed || !corpus.corpus_name.is_closed());
+    let mut unlisted = 0;
+    for corpus in corpora {
+        // XXX collect() here, see the impl Analysed block comment
+        let analysed = corpus.into_analysed();
+        // gtcorpusutil panics on file names that are not UTF-8; the files
+        // of the corpus are then found in the search roots, which handle
+        // those names
+        let Ok(files) = catch_unwind(AssertUnwindSafe(|| analysed.files().collect::<Vec<_>>()))
+        else {
+            tracing::warn!(
+                lang,
+                "a file name in a corpus is not UTF-8, finding its files by walking the directories"
+            );
+            unlisted += 1;
+            continue;
+        };
+        for file in files {
+            let path = file.to_path_buf();
+            if root_dir.is_none() {
+                search_roots.extend(
+                    corpus_dir(&path)
+                        .and_then(Path::parent)
+                        .map(Path::to_path_buf),
+                );
+            }
+            if walk.is_default() {
+                known.insert(path);
+                found(file);
+            }
         }
-        Err(e) => {
-            let m = if let Some(p) = e.downcast_ref::<&str>() {
-                p.to_string()
-            } else if let Some(s) = e.downcast_ref::<String>() {
-                s.clone()
-            } else {
-                "(not &str nor String)".to_string()
-            };
-            //Err(anyhow::anyhow!("parsing analyses using giellacgparser paniced, {m}"))
-            None
+    }
+    if unlisted > 0 && search_roots.is_empty() {
+        anyhow::bail!(
+            "can't find the files of {unlisted} corpora with file names that are not UTF-8, \
+            give the directory of the corpora with --root"
+        );
+    }
+    for search_root in search_roots {
+        let dirs = analysed_dirs(&search_root, lang).with_context(|| {
+            format!("failed to read root directory '{}'", search_root.display())
+        })?;
+        for dir in dirs {
+            if (dir.closed && skip_closed) || (!dir.closed && skip_open) {
+                continue;
+            }
+            analysed_files(&dir.path, &walk)
+                .into_iter()
+                .filter(|path| !known.contains(path))
+                .for_each(|path| found(gtcorpusutil::AnalysedFilePath::new_unchecked(path)));
         }
     }
-    //q_send_or_panic!(
-    //    q,
-    //    StatusMessage::parse_analyses(analysed_file_path.to_path_buf(), dur, &res)
-    //);
-    //res.ok()
-    //    .map(|doc| (analysed_file_path, Arc::new(Mutex::new(doc))))
-}
-
-fn convert_document(
-    //pb: ProgressBar,
-    //_status_queue: mpsc::Sender<StatusMessage>,
-    analysed_file_path: gtcorpusutil::AnalysedFilePath,
-    document: Arc<Mutex<ParsedAnalysedDocument>>,
-) -> Option<(gtcorpusutil::AnalysedFilePath, KorpMonoFile)> {
-    let t0 = Instant::now();
-    let parsed_analysed_document =
-        Mutex::into_inner(Arc::into_inner(document).expect("only 1 thread accesses this arc"))
-            .expect("only 1 thread accesses this mutex");
-    let korp_mono_xml_file = KorpMonoFile::from(parsed_analysed_document);
-    let _dur = t0.elapsed();
-    //pb.inc(1);
-    //let s = quick_xml::se::to_string(&korp_mono_xml_file).unwrap();
-    //println!("{s}");
-    Some((analysed_file_path, korp_mono_xml_file))
+    Ok(())
 }
 
-fn write_korpmono_file(
-    //pb: ProgressBar,
-    path: gtcorpusutil::KorpMonoFilePath,
-    korp_mono_file: KorpMonoFile,
-) -> Option<gtcorpusutil::KorpMonoFilePath> {
-    let p = path.to_path_buf();
-    /* rust: temporary value dropped while borrowed */
-    let parent = p.parent().expect("path to file has a parent directory");
-    println!("{}", parent.display());
-    if let Err(e) = std::fs::create_dir_all(parent) {
-        println!("can't create directory");
-        //q_send_or_panic!(q, StatusMessage::cant_create_dir(&path.file, e));
-        //pb.set_length(pb.length().unwrap() - 1);
-        return None;
-    }
-
-    let open_result = std::fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .truncate(true)
-        .open(&path.to_path_buf());
-    let file = match open_result {
-        Ok(fp) => fp,
-        Err(e) => {
-            //q_send_or_panic!(q, StatusMessage::cant_open_file(path.to_path_buf(), e));
-            //pb.set_length(pb.length().unwrap() - 1);
-            return None;
+/// Read the analysed files of the language `lang` (or of all languages, if
+/// it is empty) from the archive `path` on a thread of its own, and send
+/// them on with their content as they are read. `skip` is whether to skip
+/// the open and the closed corpora. The files that `path_filter` doesn't
+/// accept are left out, and the files in `skiplist` are returned by the
+/// thread. `found` counts the files that are sent on, which are admitted to
+/// the memory `budget` first, if there is one.
+fn read_archive(
+    path: PathBuf,
+    lang: String,
+    ((skip_open, skip_closed), path_filter, skiplist): ((bool, bool), PathFilter, Skiplist),
+    sender: mpsc::SyncSender<(gtcorpusutil::AnalysedFilePath, String)>,
+    (found, budget): (
+        Arc<std::sync::atomic::AtomicUsize>,
+        Option<Arc<MemoryBudget>>,
+    ),
+) -> std::thread::JoinHandle<anyhow::Result<Vec<gtcorpusutil::AnalysedFilePath>>> {
+    let in_lang = move |file: &Path| {
+        if lang.is_empty() {
+            return true;
         }
+        let corpus = corpus_dir(file)
+            .and_then(Path::file_name)
+            .and_then(|name| name.to_str())
+            .and_then(CorpusName::parse);
+        corpus.is_some_and(|corpus| {
+            let skipped = match corpus.is_closed() {
+                true => skip_closed,
+                false => skip_open,
+            };
+            corpus.lang == lang && !skipped
+        })
     };
-
-    let writer = BufWriter::new(file);
-    if let Err(e) = quick_xml::se::to_utf8_io_writer(writer, &korp_mono_file) {
-        //pb.set_length(pb.length().unwrap() - 1);
-        //q_send_or_panic!(q, StatusMessage::serialize_error(path.to_path_buf(), e));
-    }
-    //pb.inc(1);
-    Some(path)
+    std::thread::Builder::new()
+        .name("read_archive".to_string())
+        .spawn(move || -> anyhow::Result<Vec<_>> {
+            let mut skipped = vec![];
+            let mut wanted = |file: &Path| {
+                if !in_lang(file) || !path_filter.accepts(file) {
+                    return false;
+                }
+                if skiplist.contains(file) {
+                    skipped.push(gtcorpusutil::AnalysedFilePath::new_unchecked(
+                        file.to_path_buf(),
+                    ));
+                    return false;
+                }
+                true
+            };
+            let mut send = |file: PathBuf, content: std::io::Result<String>| {
+                found.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                match content {
+                    Ok(content) => {
+                        if let Some(budget) = &budget {
+                            budget.admit(&file, content.len() as u64);
+                        }
+                        // the pipeline is gone if it stopped early
+                        let file = gtcorpusutil::AnalysedFilePath::new_unchecked(file);
+                        sender.send((file, content)).is_ok()
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            error = ?e,
+                            file = ?file,
+                            category = ErrorCategory::Read.as_str(),
+                            "error reading file from the archive"
+                        );
+                        true
+                    }
+                }
+            };
+            archive::read_entries(&path, &mut wanted, &mut send)
+                .with_context(|| format!("failed to read archive '{}'", path.display()))?;
+            Ok(skipped)
+        })
+        .expect("can spawn thread")
 }
 
-fn gen_missing_baseforms(q: mpsc::Sender<StatusMessage>, path: gtcorpusutil::KorpMonoFilePath) -> Option<()> {
-    let path = path.to_path_buf();
-    let (dur, res) = timed(|| std::fs::read_to_string(&path));
-    q_send_or_panic!(q, StatusMessage::read(&path, dur, &res));
-    let string = res.ok()?;
-    None
+/// Merge the run reports `reports`, and write the merged one to `output`,
+/// or stdout.
+fn merge_reports(reports: &[PathBuf], output: Option<&Path>) -> anyhow::Result<ExitCode> {
+    let reports = reports
+        .iter()
+        .map(|path| RunReport::from_file(path))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let merged = RunReport::merge(&reports)?;
+    match output {
+        Some(path) => merged
+            .write_file(path)
+            .with_context(|| format!("failed to write '{}'", path.display()))?,
+        None => println!("{}", serde_json::to_string_pretty(&merged)?),
+    }
+    let missing = merged.missing_shards();
+    if !missing.is_empty() {
+        let missing = missing.iter().map(Shard::to_string).collect::<Vec<_>>();
+        eprintln!("no report of the shards {}", missing.join(", "));
+    }
+    let errors = merged.errors.values().sum::<u64>();
+    Ok(ExitCode::from(
+        match (missing.is_empty(), merged.failed + errors) {
+            (true, 0) => EXIT_OK,
+            _ => EXIT_FILE_ERRORS,
+        },
+    ))
 }
 
-#[derive(Default)]
-struct Stats {
-    tot: usize,
-    read_ok: usize,
-    read_err: usize,
-    parsexml_ok: usize,
-    parsexml_err: usize,
-    parseanl_ok: usize,
-    parseanl_err: usize,
+/// Search the index in `dir` for `query`, and print the hits to stdout.
+#[cfg(feature = "index")]
+fn search(dir: &std::path::Path, query: &str, limit: usize) -> anyhow::Result<ExitCode> {
+    for hit in korp_mono_rs::index::search(dir, query, limit)? {
+        println!("{}\t{}\t{}", hit.file, hit.sentence, hit.words);
+    }
+    Ok(ExitCode::from(EXIT_OK))
 }
 
-impl Stats {
-    fn new(tot: usize) -> Self {
-        Self {
-            tot,
-            ..Default::default()
-        }
+/// Count the analysed files in `dir`, and print the counts to stdout.
+fn count(dir: &std::path::Path, robust: bool) -> anyhow::Result<ExitCode> {
+    if !dir.is_dir() {
+        anyhow::bail!("'{}' is not a directory", dir.display());
     }
-
-    fn update(&mut self, kind: &StatusMessageKind) {
-        match kind {
-            StatusMessageKind::Read { result } => {
-                if result.is_ok() {
-                    self.read_ok += 1;
-                } else {
-                    self.read_err += 1;
+    let files: Vec<PathBuf> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "xml"))
+        .collect();
+    let counts = files
+        .par_iter()
+        .map(|path| {
+            let mut counts = Counts::default();
+            let counted = std::fs::read_to_string(path)
+                .map_err(anyhow::Error::from)
+                .and_then(|xml| count_document(&xml, robust));
+            match counted {
+                Ok((lang, domain, totals)) => counts.add(lang, domain, totals),
+                Err(e) => {
+                    eprintln!("can't count {}: {e}", path.display());
+                    counts.failed += 1;
                 }
             }
-            StatusMessageKind::ParseXml { result } => {
-                if result.is_ok() {
-                    self.parsexml_ok += 1;
-                } else {
-                    self.parsexml_err += 1;
+            counts
+        })
+        .reduce(Counts::default, Counts::merge);
+    print!("{}", counts.report());
+    Ok(ExitCode::from(match counts.failed {
+        0 => EXIT_OK,
+        _ => EXIT_FILE_ERRORS,
+    }))
+}
+
+/// Write the sentences of the korp_mono files in `dir` as plain text, in
+/// the order of their paths, to `output`, or stdout. `columns` are the
+/// columns of the token lines.
+fn extract_text(
+    dir: &Path,
+    style: TextStyle,
+    output: Option<&Path>,
+    columns: &[Column],
+) -> anyhow::Result<ExitCode> {
+    use std::io::Write;
+
+    if !dir.is_dir() {
+        anyhow::bail!("'{}' is not a directory", dir.display());
+    }
+    let word_column = columns
+        .iter()
+        .position(|column| *column == Column::Word)
+        .context("the columns have no word form")?;
+    let mut files: Vec<PathBuf> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "xml"))
+        .collect();
+    files.sort();
+    let mut writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(std::io::BufWriter::new(
+            std::fs::File::create(path)
+                .with_context(|| format!("failed to create '{}'", path.display()))?,
+        )),
+        None => Box::new(std::io::BufWriter::new(std::io::stdout().lock())),
+    };
+    let mut failed = 0;
+    let mut documents = 0;
+    // in chunks, so that the text of a large corpus is never all in memory,
+    // but still in the order of the files
+    for chunk in files.chunks(256) {
+        let extracted = chunk
+            .par_iter()
+            .map(|path| {
+                std::fs::read_to_string(path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|xml| extract_document(&xml, word_column, style))
+            })
+            .collect::<Vec<_>>();
+        for (path, lines) in chunk.iter().zip(extracted) {
+            match lines {
+                Ok(lines) if lines.is_empty() => {}
+                Ok(lines) => {
+                    if documents > 0 {
+                        writeln!(writer)?;
+                    }
+                    documents += 1;
+                    for line in lines {
+                        writeln!(writer, "{line}")?;
+                    }
                 }
-            }
-            StatusMessageKind::ParseAnalyses { result } => {
-                if result.is_ok() {
-                    self.parseanl_ok += 1;
-                } else {
-                    self.parseanl_err += 1;
+                Err(e) => {
+                    eprintln!("can't extract the text of {}: {e}", path.display());
+                    failed += 1;
                 }
             }
-            _ => {}
         }
     }
+    writer.flush()?;
+    Ok(ExitCode::from(match failed {
+        0 => EXIT_OK,
+        _ => EXIT_FILE_ERRORS,
+    }))
+}
 
-    fn display(&self, field: &str) -> StatsDisplay {
-        match field {
-            "read" => {
-                StatsDisplay {
-                    title: "Read",
-                    ok: self.read_ok,
-                    err: self.read_err,
-                    tot: self.tot,
-                }
-            }
-            "parse_xml" => {
-                StatsDisplay {
-                    title: "Parse XML",
-                    ok: self.parsexml_ok,
-                    err: self.parsexml_err,
-                    tot: self.tot,
-                }
-            }
-            "parse_analyses" => {
-                StatsDisplay {
-                    title: "Parse analyses",
-                    ok: self.parseanl_ok,
-                    err: self.parseanl_err,
-                    tot: self.tot,
-                }
-            }
-            x => unimplemented!("SomeType missing impl for {x}"),
+/// The options of the stages that convert a document, from parsing its xml
+/// to comparing it with the output of the Python version, see
+/// [`conversion_pipeline`].
+struct Conversion<'a> {
+    header_mode: HeaderMode,
+    meta_sidecars: bool,
+    settings: Option<Arc<Settings>>,
+    years: YearRange,
+    license_filter: Option<LicenseFilter>,
+    duplicates: Option<DuplicatePolicy>,
+    parallel_links: bool,
+    robust_parse: bool,
+    timeout: Option<Duration>,
+    min_tokens: Option<usize>,
+    pos_coverage: bool,
+    options: process_sentence::Options,
+    #[cfg(feature = "generate")]
+    generator: Option<Arc<CachedGenerator>>,
+    domain_map: Option<DomainMap>,
+    plausible_years: PlausibleYears,
+    implausible_dates: ImplausibleDatePolicy,
+    encoding_check: bool,
+    header_extra: &'a [String],
+    text_id: Option<TextIdScheme>,
+    corpus_id: Option<String>,
+    source_url: bool,
+    max_attr_length: Option<usize>,
+    omit_empty_attrs: bool,
+    text_attrs: Option<TextAttrs>,
+    sentence_filter: &'a SentenceFilter,
+    dedup: Option<DedupScope>,
+    empty_documents: EmptyPolicy,
+    warnings: bool,
+    demote_broken_trees: bool,
+    info: bool,
+    distribution: bool,
+    compare_python: Option<PathBuf>,
+    columns: &'a [Column],
+}
+
+/// What the stages of the conversion collect, for the reports at the end
+/// of the run.
+#[derive(Default)]
+struct Collected {
+    sidecar_overrides: Arc<Mutex<sidecar::Applied>>,
+    duplicate_files: Arc<Mutex<Duplicates>>,
+    links: Arc<Mutex<ParallelLinks>>,
+    short_documents: Arc<Mutex<ShortDocuments>>,
+    unknown_pos: Arc<Mutex<PosCoverage>>,
+    dropped_sentences: Arc<Mutex<DroppedSentences>>,
+    sentence_dedup: Arc<Mutex<SentenceDedup>>,
+    empty_counts: Arc<Mutex<EmptyCounts>>,
+    excluded: Arc<Mutex<Excluded>>,
+    sanity_warnings: Arc<Mutex<Warnings>>,
+    demoted: Arc<Mutex<Demoted>>,
+    corpus_infos: Arc<Mutex<CorpusInfos>>,
+    token_distribution: Arc<Mutex<Distribution>>,
+    comparison: Arc<Mutex<Comparison>>,
+}
+
+/// Add the stages that convert a document to `pipeline`, as `conversion`
+/// says, collecting into `collected`. Both the run over the corpora and
+/// `convert-one` convert with this, so that the options apply the same to
+/// both.
+fn conversion_pipeline<I: 'static>(
+    pipeline: Pipeline<I, (gtcorpusutil::AnalysedFilePath, String)>,
+    conversion: Conversion,
+    collected: &Collected,
+) -> Pipeline<I, stages::Converted> {
+    let Conversion {
+        header_mode,
+        meta_sidecars,
+        settings,
+        years,
+        license_filter,
+        duplicates,
+        parallel_links,
+        robust_parse,
+        timeout,
+        min_tokens,
+        pos_coverage,
+        options,
+        #[cfg(feature = "generate")]
+        generator,
+        domain_map,
+        plausible_years,
+        implausible_dates,
+        encoding_check,
+        header_extra,
+        text_id,
+        corpus_id,
+        source_url,
+        max_attr_length,
+        omit_empty_attrs,
+        text_attrs,
+        sentence_filter,
+        dedup,
+        empty_documents,
+        warnings,
+        demote_broken_trees,
+        info,
+        distribution,
+        compare_python,
+        columns,
+    } = conversion;
+    let pipeline =
+        pipeline
+            .then(stages::ParseXml { header_mode })
+            .then_if(
+                meta_sidecars,
+                stages::ApplySidecars::new(Arc::clone(&collected.sidecar_overrides)),
+            )
+            .then_if_some(settings.clone().map(stages::LoadCorpusSettings::new))
+            .then_if(!years.is_unbounded(), stages::FilterYears::new(years))
+            .then_if(
+                license_filter.is_some() || settings.is_some(),
+                stages::FilterLicenses::new(license_filter, Arc::clone(&collected.excluded))
+                    .with_settings(settings.clone()),
+            )
+            .then_if(
+                duplicates.is_some(),
+                stages::FindDuplicates::new(
+                    Arc::clone(&collected.duplicate_files),
+                    duplicates.unwrap_or_default(),
+                ),
+            )
+            .then_if(
+                parallel_links,
+                stages::CollectParallelLinks::new(Arc::clone(&collected.links)),
+            )
+            .then(stages::ParseAnalyses::new(robust_parse, timeout))
+            .then_if_some(min_tokens.map(|min| {
+                stages::FilterMinTokens::new(min, Arc::clone(&collected.short_documents))
+            }))
+            .then_if(
+                pos_coverage,
+                stages::CollectPosCoverage::new(Arc::clone(&collected.unknown_pos)),
+            )
+            .then(stages::Convert::new(options).with_settings(settings.clone()));
+    #[cfg(feature = "generate")]
+    let pipeline = pipeline.then_if_some(generator.map(stages::GenerateLemmas::new));
+    pipeline
+        .then_if(
+            domain_map.is_some() || settings.is_some(),
+            stages::InferDomain::new(domain_map).with_settings(settings.clone()),
+        )
+        .then(stages::ValidateDates::new(
+            plausible_years,
+            implausible_dates,
+        ))
+        .then_if(encoding_check, stages::RepairEncoding)
+        .then_if(
+            !header_extra.is_empty(),
+            stages::CopyHeaderExtras::from_specs(header_extra),
+        )
+        .then_if_some(text_id.map(|scheme| stages::AssignTextId::new(scheme, corpus_id)))
+        .then_if(source_url, stages::AddSourceUrl)
+        .then(stages::NormalizeAttrs::new(max_attr_length))
+        .then_if(omit_empty_attrs, stages::OmitEmptyAttrs)
+        .then_if(
+            text_attrs.is_some() || settings.is_some(),
+            stages::SelectTextAttrs::new(text_attrs).with_settings(settings.clone()),
+        )
+        .then_if(
+            !sentence_filter.is_empty(),
+            stages::DropSentences::new(
+                sentence_filter.clone(),
+                Arc::clone(&collected.dropped_sentences),
+            ),
+        )
+        .then_if_some(
+            dedup.map(|scope| {
+                stages::DedupSentences::new(Arc::clone(&collected.sentence_dedup), scope)
+            }),
+        )
+        .then(stages::HandleEmpty::new(
+            Arc::clone(&collected.empty_counts),
+            empty_documents,
+        ))
+        .then_if(
+            warnings,
+            stages::SanityCheck::new(Arc::clone(&collected.sanity_warnings)),
+        )
+        .then_if(
+            demote_broken_trees,
+            stages::DemoteBrokenTrees::new(Arc::clone(&collected.demoted)),
+        )
+        .then_if(
+            info,
+            stages::CollectInfo::new(Arc::clone(&collected.corpus_infos)),
+        )
+        .then_if(
+            distribution,
+            stages::CollectDistribution::new(Arc::clone(&collected.token_distribution)),
+        )
+        .then_if_some(compare_python.map(|old_dir| {
+            stages::ComparePython::new(old_dir, Arc::clone(&collected.comparison), columns)
+                .with_settings(settings)
+        }))
+}
+
+/// Run `pipeline` on the analysed document on stdin, and write the result to
+/// stdout.
+fn convert_one(
+    pipeline: Pipeline<(gtcorpusutil::AnalysedFilePath, String), stages::Converted>,
+    max_text_sentences: Option<usize>,
+    format: OutputFormat,
+    columns: &[Column],
+    newlines: NewlinePolicy,
+) -> anyhow::Result<ExitCode> {
+    let input = std::io::read_to_string(std::io::stdin()).context("failed to read stdin")?;
+    let path = gtcorpusutil::AnalysedFilePath::new_unchecked(PathBuf::from("<stdin>"));
+    let Some((_, korp_mono_file)) = pipeline.run((path, input)) else {
+        eprintln!("the document was skipped, or could not be converted");
+        return Ok(ExitCode::from(EXIT_FILE_ERRORS));
+    };
+    let parts = match max_text_sentences {
+        Some(max) => korp_mono_file.split_parts(max),
+        None => vec![korp_mono_file],
+    };
+    // a terminal expects the output to end with a newline
+    let newlines = NewlinePolicy {
+        final_newline: newlines.final_newline.or(Some(true)),
+        ..newlines
+    };
+    stages::write_format(std::io::stdout().lock(), &parts, format, columns, newlines)
+        .context("failed to write stdout")?;
+    Ok(ExitCode::from(EXIT_OK))
+}
+
+/// Print to stdout, or to stderr with `--json-progress`, where stdout is
+/// only for the progress events.
+macro_rules! say {
+    ($json_progress:expr, $($arg:tt)*) => {
+        match $json_progress {
+            true => eprint!($($arg)*),
+            false => print!($($arg)*),
         }
-    }
+    };
 }
 
-struct StatsDisplay {
-    title: &'static str,
-    ok: usize,
-    err: usize,
-    tot: usize,
+macro_rules! q_send_or_panic {
+    ($queue:ident, $msg:expr) => {
+        if let Err(_) = $queue.send($msg) {
+            panic!("can't send message to printer thread");
+        }
+    };
 }
 
-impl std::fmt::Display for StatsDisplay {
-    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let ok = self.ok;
-        let err = self.err;
-        let tot = self.tot;
-        let pct = (ok + err) as f64 / tot as f64 * 100.0;
-        write!(formatter, "{}: {ok} OK, {err} FAILED (of {tot}, {pct}%)", self.title)
-    }
+fn gen_missing_baseforms(
+    q: mpsc::Sender<StatusMessage>,
+    path: gtcorpusutil::KorpMonoFilePath,
+) -> Option<()> {
+    let path = path.to_path_buf();
+    let (dur, res) = timed(|| std::fs::read_to_string(&path));
+    q_send_or_panic!(q, StatusMessage::read(&path, dur, &res));
+    let string = res.ok()?;
+    None
 }
 
 macro_rules! clear_line {
@@ -351,14 +1361,127 @@ macro_rules! clear_line {
     }
 }
 
-fn main() -> anyhow::Result<()> {
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            ExitCode::from(EXIT_FATAL)
+        }
+    }
+}
+
+fn run() -> anyhow::Result<ExitCode> {
     let Args {
-        language: lang,
+        command,
+        language,
         skip_section: skip_sections,
+        files_from,
+        stdin_list_null,
+        input_archive,
+        chaos,
+        seed,
+        max_depth,
+        follow_symlinks,
+        one_file_system,
         root,
         quiet,
+        verbose,
+        json_progress,
+        log_context,
+        #[cfg(feature = "tui")]
+        tui,
+        encoding_check,
+        anonymize,
+        name_list,
+        warnings,
+        demote_broken_trees,
+        msd_profile,
+        canonical_msd,
+        tagset,
+        min_year,
+        max_year,
+        implausible_dates,
+        empty_documents,
+        meta_sidecars,
+        min_tokens,
+        omit_empty_attrs,
+        text_attrs,
+        text_attrs_file,
+        max_attr_length,
+        text_id,
+        corpus_id,
+        source_url,
+        partition_by_domain,
+        infer_domain,
+        domain_map,
+        output_dir,
+        allow_any_path,
+        output_template,
+        robust_parse,
+        header_mode,
+        with_offsets,
+        info,
+        distribution,
+        pos_coverage,
+        pos_coverage_file,
+        multi_lemma,
+        write_threads,
+        memory_budget,
+        memory_factor,
+        fsync,
+        manifest,
+        format,
+        newline,
+        final_newline,
+        archive_output,
+        prune,
+        max_text_sentences,
+        include,
+        exclude,
+        shard,
+        json_report,
+        skiplist,
+        since,
+        stream_files,
+        year_from,
+        year_to,
+        license_filter,
+        duplicates,
+        dedup,
+        drop_sentence_regex,
+        parallel_links,
+        header_extra,
+        threads_io,
+        lang_column,
+        compound_column,
+        normalize_deprel,
+        deprel_map,
+        raw_deprel_column,
+        attach_punct,
+        columns,
+        sentence_text,
+        strict_columns,
+        renumber_ids,
+        #[cfg(feature = "generate")]
+        generate_lemmas,
+        #[cfg(feature = "generate")]
+        generator_cache,
+        timeout,
+        metrics: metrics_file,
+        profile: profile_file,
+        cwb_encode,
+        registry,
+        data,
+        sort_by,
+        #[cfg(feature = "index")]
+        index,
+        compare_python,
+        compare_rules,
+        no_corpus_settings,
         ..
     } = Args::parse();
+    let start = std::time::Instant::now();
 
     let skip_open = skip_sections.contains(&Section::Open);
     let skip_closed = skip_sections.contains(&Section::Closed);
@@ -368,32 +1491,365 @@ fn main() -> anyhow::Result<()> {
         );
     }
 
-    let root: Root = match root {
-        Some(dir) => Root::new(dir),
-        None => Root::from_gut_config()
-            .with_context(|| format!("failed to get gut root directory:\nhint: you can specify where corpus root directory resides explicitly with the --corpus-root argument"))?,
+    let anonymizer = match (anonymize, name_list) {
+        (false, _) => None,
+        (true, None) => Some(Anonymizer::default()),
+        (true, Some(path)) => Some(Anonymizer::from_file(path)?),
+    };
+    let deprel_map = match (normalize_deprel, deprel_map) {
+        (false, _) => None,
+        (true, None) => Some(DeprelMap::default()),
+        (true, Some(path)) => Some(DeprelMap::from_file(path)?),
+    };
+    let options = process_sentence::Options {
+        anonymizer,
+        msd_profile,
+        canonical_msd,
+        with_offsets,
+        multi_lemma,
+        lang_column,
+        compound_column,
+        columns,
+        tagset,
+        sentence_text,
+        strict_columns,
+        renumber_ids,
+        deprel_map,
+        raw_deprel_column,
+        attach_punct,
+    };
+    let columns = options.columns().into_owned();
+    let newlines = NewlinePolicy {
+        newline,
+        final_newline,
+    };
+    let sentence_filter = SentenceFilter::new(drop_sentence_regex);
+    let domain_map = match (infer_domain, domain_map) {
+        (false, _) => None,
+        (true, None) => Some(DomainMap::default()),
+        (true, Some(path)) => Some(DomainMap::from_file(path)?),
     };
 
-    let files: Vec<gtcorpusutil::AnalysedFilePath> = root
-        .corpora()
-        .filter(|corpus| corpus.corpus_name.lang == lang)
-        .filter(|corpus| !skip_open || !corpus.corpus_name.is_open())
-        .filter(|corpus| !skip_closed || !corpus.corpus_name.is_closed())
-        // XXX collect() here, see the impl Analysed block comment
-        .flat_map(|corpus| corpus.into_analysed().files().collect::<Vec<_>>())
-        .collect();
+    let text_attrs = match (text_attrs, text_attrs_file) {
+        (None, None) => None,
+        (attrs, file) => {
+            let mut selected = TextAttrs::new(&attrs.unwrap_or_default());
+            if let Some(path) = file {
+                selected.extend(TextAttrs::from_file(path)?);
+            }
+            Some(selected)
+        }
+    };
+
+    let plausible_years = PlausibleYears::new(
+        min_year,
+        max_year.unwrap_or_else(PlausibleYears::current_year),
+    );
+
+    #[cfg(feature = "generate")]
+    let generator = match (generate_lemmas, &generator_cache) {
+        (None, _) => None,
+        (Some(transducer), None) => {
+            Some(Arc::new(CachedGenerator::new(Generator::new(transducer))))
+        }
+        (Some(transducer), Some(cache)) => Some(Arc::new(
+            CachedGenerator::load(Generator::new(transducer), cache)
+                .with_context(|| format!("failed to read generator cache '{}'", cache.display()))?,
+        )),
+    };
+
+    if let Some(Command::Count { dir }) = &command {
+        return count(dir, robust_parse);
+    }
+
+    if let Some(Command::ExtractText { dir, style, output }) = &command {
+        return extract_text(dir, *style, output.as_deref(), &columns);
+    }
+
+    if let Some(Command::MergeReports { reports, output }) = &command {
+        return merge_reports(reports, output.as_deref());
+    }
+
+    #[cfg(feature = "index")]
+    if let Some(Command::Search { dir, query, limit }) = &command {
+        return search(dir, query, *limit);
+    }
+
+    let conversion = Conversion {
+        header_mode,
+        meta_sidecars,
+        settings: (!no_corpus_settings).then(|| Arc::new(Settings::default())),
+        years: YearRange::new(year_from, year_to),
+        license_filter: license_filter.as_deref().map(LicenseFilter::new),
+        duplicates,
+        parallel_links,
+        robust_parse,
+        timeout,
+        min_tokens,
+        pos_coverage,
+        options,
+        #[cfg(feature = "generate")]
+        generator: generator.clone(),
+        domain_map,
+        plausible_years,
+        implausible_dates,
+        encoding_check,
+        header_extra: &header_extra,
+        text_id,
+        corpus_id,
+        source_url,
+        max_attr_length,
+        omit_empty_attrs,
+        text_attrs,
+        sentence_filter: &sentence_filter,
+        dedup,
+        empty_documents,
+        warnings,
+        demote_broken_trees,
+        info,
+        distribution,
+        compare_python: compare_python.clone(),
+        columns: &columns,
+    };
+
+    if let Some(Command::ConvertOne) = command {
+        tracing_subscriber::fmt()
+            .with_writer(std::io::stderr)
+            .with_max_level(tracing::Level::WARN)
+            .init();
+        // there is no corpus, so there are no corpus settings
+        let conversion = Conversion {
+            settings: None,
+            ..conversion
+        };
+        let pipeline = conversion_pipeline(Pipeline::new(), conversion, &Collected::default());
+        let code = convert_one(pipeline, max_text_sentences, format, &columns, newlines)?;
+        #[cfg(feature = "generate")]
+        if let (Some(generator), Some(cache)) = (&generator, &generator_cache) {
+            generator.save(cache).with_context(|| {
+                format!("failed to write generator cache '{}'", cache.display())
+            })?;
+        }
+        return Ok(code);
+    }
+    // with a list of files, the corpus directories are not walked
+    let listed = match (files_from, stdin_list_null) {
+        (Some(path), _) => Some(read_file_list(&path, Separator::Newline)?),
+        (None, true) => Some(read_file_list(Path::new("-"), Separator::Nul)?),
+        (None, false) => None,
+    };
+    let lang = language.unwrap_or_default();
+
+    let root_dir = root.clone();
+    // nor with an archive of the files
+    let root: Option<Root> = match (root, listed.is_some() || input_archive.is_some()) {
+        (_, true) => None,
+        (Some(dir), false) => Some(Root::new(dir)),
+        (None, false) => Some(Root::from_gut_config()
+            .with_context(|| format!("failed to get gut root directory:\nhint: you can specify where corpus root directory resides explicitly with the --corpus-root argument"))?),
+    };
+
+    let path_filter = PathFilter::new(include, exclude).with_shard(shard);
+    let skiplist = match skiplist {
+        Some(path) => Skiplist::from_file(path)?,
+        None => Skiplist::default(),
+    };
+    let skip = (skip_open, skip_closed);
+    let walk = Walk {
+        max_depth,
+        follow_symlinks,
+        one_file_system,
+    };
+    // with --input-archive, the files are read from the archive by a thread
+    // that is started with the pipeline, with the same filters
+    let archive_filters = input_archive
+        .is_some()
+        .then(|| (lang.clone(), path_filter.clone(), skiplist.clone()));
+    // with --stream-files, the files are found by the walker thread, and
+    // sent on to the pipeline as they are found
+    let (files, skipped, walker) = match stream_files {
+        false => {
+            let mut files = vec![];
+            match (listed, root) {
+                (Some(listed), _) => files.extend(
+                    listed
+                        .into_iter()
+                        .map(gtcorpusutil::AnalysedFilePath::new_unchecked),
+                ),
+                (None, Some(root)) => {
+                    find_files(root, root_dir.as_deref(), &lang, skip, walk, &mut |file| {
+                        files.push(file)
+                    })?
+                }
+                // the files are read from the archive, with the pipeline
+                (None, None) => assert!(
+                    input_archive.is_some(),
+                    "there is a root without a list of files or an archive"
+                ),
+            }
+            if prune {
+                let corpora: BTreeSet<PathBuf> = files
+                    .iter()
+                    .filter_map(|path| corpus_dir(&path.to_path_buf()).map(PathBuf::from))
+                    .collect();
+                for corpus in corpora {
+                    let pruned = manifest::prune(&corpus, output_dir.as_deref())
+                        .with_context(|| format!("failed to prune {}", corpus.display()))?;
+                    if !pruned.is_empty() && !quiet {
+                        say!(
+                            json_progress,
+                            "pruned {} korp_mono files whose analysed file is gone:\n",
+                            pruned.len()
+                        );
+                        for entry in &pruned {
+                            say!(json_progress, "  {}\n", entry.output.display());
+                        }
+                    }
+                }
+            }
+            let files: Vec<_> = files
+                .into_iter()
+                .filter(|path| path_filter.accepts(&path.to_path_buf()))
+                .collect();
+            let (skipped, files): (Vec<_>, Vec<_>) = files
+                .into_iter()
+                .partition(|path| skiplist.contains(&path.to_path_buf()));
+            let files: Vec<_> = match since {
+                Some(since) => files
+                    .into_iter()
+                    .filter(|path| modified_since(&path.to_path_buf(), since))
+                    .collect(),
+                None => files,
+            };
+            (files, skipped, None)
+        }
+        true => {
+            let (sender, receiver) = mpsc::sync_channel(FILE_QUEUE_LEN);
+            let found = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let root = root.expect("--stream-files conflicts with the lists of files");
+            let walker = {
+                let found = Arc::clone(&found);
+                std::thread::Builder::new()
+                    .name("walk".to_string())
+                    .spawn(move || -> anyhow::Result<Vec<_>> {
+                        let mut skipped = vec![];
+                        find_files(root, root_dir.as_deref(), &lang, skip, walk, &mut |file| {
+                            let path = file.to_path_buf();
+                            if !path_filter.accepts(&path) {
+                                return;
+                            }
+                            if skiplist.contains(&path) {
+                                skipped.push(file);
+                                return;
+                            }
+                            if since.is_some_and(|since| !modified_since(&path, since)) {
+                                return;
+                            }
+                            found.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                            // the pipeline is gone if it stopped early
+                            let _ = sender.send(file);
+                        })?;
+                        Ok(skipped)
+                    })
+                    .expect("can spawn thread")
+            };
+            (vec![], vec![], Some((receiver, walker, found)))
+        }
+    };
 
     let nfiles = files.len();
-    println!("korp_mono starting, {nfiles} files to process...");
+    // with --stream-files, and an input archive, only known at the end
+    let mut found_files = nfiles;
+    let mut skipped_files = skipped.len();
+    let say_skipped = |skipped: &[gtcorpusutil::AnalysedFilePath]| {
+        if !skipped.is_empty() && !quiet {
+            say!(
+                json_progress,
+                "skipping {} files in the skiplist:\n",
+                skipped.len()
+            );
+            for path in skipped {
+                say!(json_progress, "  {}\n", path.to_path_buf().display());
+            }
+        }
+    };
+    say_skipped(&skipped);
+    if !quiet {
+        match (&walker, &input_archive) {
+            (None, Some(path)) => say!(
+                json_progress,
+                "korp_mono starting, processing the files of {} as they are read...\n",
+                path.display()
+            ),
+            (None, None) => say!(
+                json_progress,
+                "korp_mono starting, {nfiles} files to process...\n"
+            ),
+            (Some(_), _) => say!(
+                json_progress,
+                "korp_mono starting, processing the files as they are found...\n"
+            ),
+        }
+    }
 
+    // the dashboard is drawn on stdout, so it has to be a terminal
+    #[cfg(feature = "tui")]
+    let tui = tui && {
+        let is_terminal = std::io::stdout().is_terminal();
+        if !is_terminal {
+            say!(
+                json_progress,
+                "stdout is not a terminal, showing the progress bar instead of the dashboard\n"
+            );
+        }
+        is_terminal
+    };
+    #[cfg(not(feature = "tui"))]
+    let tui = false;
 
-    let indicatif_layer = IndicatifLayer::new();
+    let failures = Arc::new(Mutex::new(Failures::default()));
+    let progress = json_progress.then(|| Arc::new(JsonProgress::default()));
+    // the dashboard shows the runs of each stage, from the metrics, and the
+    // profile is recorded where the stages are measured
+    let metrics = (metrics_file.is_some() || tui || profile_file.is_some())
+        .then(|| Arc::new(Metrics::default()));
+    let profile = profile_file.as_ref().map(|_| Arc::new(Profile::default()));
+    let dashboard = match (&metrics, tui) {
+        (Some(metrics), true) => Some(Arc::new(Dashboard::new(nfiles, Arc::clone(metrics)))),
+        _ => None,
+    };
+    // the progress bar is only shown to people
+    let indicatif_layer = (!quiet && !json_progress && !tui).then(IndicatifLayer::new);
+    // the messages about the files that went fine, which are many on large
+    // runs, are only formatted with --verbose
+    let level = match (quiet, tui, verbose) {
+        // the errors are shown on the dashboard, anything else written to
+        // the terminal would mess it up
+        (_, true, _) => tracing_subscriber::filter::LevelFilter::OFF,
+        (true, false, _) => tracing_subscriber::filter::LevelFilter::ERROR,
+        (false, false, true) => tracing_subscriber::filter::LevelFilter::TRACE,
+        (false, false, false) => tracing_subscriber::filter::LevelFilter::INFO,
+    };
+    let fmt_layer = match &indicatif_layer {
+        Some(layer) => tracing_subscriber::fmt::layer()
+            .with_writer(layer.get_stderr_writer())
+            .with_filter(level)
+            .boxed(),
+        None => tracing_subscriber::fmt::layer()
+            .with_writer(std::io::stderr)
+            .with_filter(level)
+            .boxed(),
+    };
     tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().with_writer(indicatif_layer.get_stderr_writer()))
+        .with(fmt_layer)
         .with(indicatif_layer)
-        .with(tracing_subscriber::filter::Targets::new()
-            .with_target("giellacgparser", tracing_subscriber::filter::LevelFilter::OFF)
-        )
+        .with(FailureLayer::new(Arc::clone(&failures)))
+        .with(progress.clone().map(ProgressLayer::new))
+        .with(dashboard.clone().map(DashboardLayer::new))
+        .with(tracing_subscriber::filter::Targets::new().with_target(
+            "giellacgparser",
+            tracing_subscriber::filter::LevelFilter::OFF,
+        ))
         .init();
 
     let read_span = tracing::info_span!("read");
@@ -428,97 +1884,250 @@ fn main() -> anyhow::Result<()> {
     //pb_write.set_style(sty.clone());
     //pb_write.set_message("write korp_mono file");
 
-    let mut file_statuses = HashMap::<PathBuf, Vec<StatusMessage>>::new();
-    //let (tx, rx) = mpsc::channel::<StatusMessage>();
+    if let Some(progress) = &progress {
+        progress.start(nfiles);
+    }
 
-    /*
-    let jh = std::thread::spawn(move || {
-        //let mut stdout = std::io::stdout().lock();
-        use std::io::Write;
+    let chaos = chaos.map(|probability| match seed {
+        Some(seed) => Arc::new(Chaos::with_seed(probability, seed)),
+        None => Arc::new(Chaos::new(probability)),
+    });
+    if let Some(chaos) = &chaos {
+        tracing::info!(seed = chaos.seed(), "failing stages at random (--chaos)");
+    }
+    let budget = memory_budget.map(|limit| Arc::new(MemoryBudget::new(limit, memory_factor)));
+    let archive = match &archive_output {
+        Some(path) => Some(Arc::new(Archive::create(path).with_context(|| {
+            format!("failed to create archive '{}'", path.display())
+        })?)),
+        None => None,
+    };
+    let written_files = Arc::new(Mutex::new(Manifest::default()));
+    let comparison = match &compare_rules {
+        Some(path) => Comparison::new(Rules::from_file(path)?),
+        None => Comparison::default(),
+    };
+    let collected = Collected {
+        comparison: Arc::new(Mutex::new(comparison)),
+        ..Default::default()
+    };
+    let Collected {
+        sidecar_overrides,
+        duplicate_files,
+        links,
+        short_documents,
+        unknown_pos,
+        dropped_sentences,
+        sentence_dedup,
+        empty_counts,
+        excluded,
+        sanity_warnings,
+        demoted,
+        corpus_infos,
+        token_distribution,
+        comparison,
+    } = &collected;
+    #[cfg(feature = "index")]
+    let sentence_index = match &index {
+        Some(dir) => {
+            Some(Arc::new(SentenceIndex::create(dir).with_context(|| {
+                format!("failed to create index '{}'", dir.display())
+            })?))
+        }
+        None => None,
+    };
+    if let Some(metrics) = &metrics {
+        metrics.set_files(nfiles);
+        metrics.set_skipped(skipped.len());
+    }
+    let settings = conversion.settings.clone();
+    // the files are read separately, on the IO threads if there are any
+    let pipeline = Pipeline::new()
+        .with_metrics(metrics.clone())
+        .with_profile(profile.clone())
+        .with_chaos(chaos.clone());
+    let pipeline = conversion_pipeline(pipeline, conversion, &collected);
+    #[cfg(feature = "index")]
+    let pipeline = pipeline.then_if_some(sentence_index.clone().map(stages::IndexSentences::new));
+    let pipeline = match &metrics {
+        Some(metrics) => pipeline.then(stages::RecordMetrics::new(Arc::clone(metrics))),
+        None => pipeline,
+    };
+    let cwb = match (cwb_encode, registry, data) {
+        (true, Some(registry), Some(data)) => {
+            let cwb = CwbEncode::new(registry, data, columns.clone()).with_sort_by(sort_by);
+            Some(Arc::new(cwb))
+        }
+        _ => None,
+    };
+    let pipeline = pipeline.then_if_some(
+        cwb.clone()
+            .map(|cwb| stages::EncodeCwb::new(cwb).with_settings(settings.clone())),
+    );
 
-        let mut stats = Stats::new(nfiles);
+    let write = stages::WriteKorpMono {
+        partition_by_domain,
+        output_dir,
+        allow_any_path,
+        fsync,
+        manifest: manifest.then(|| Arc::clone(&written_files)),
+        max_text_sentences,
+        format,
+        columns,
+        progress: progress.clone(),
+        settings,
+        archive: archive.clone(),
+        newlines,
+        template: output_template,
+        outputs: Default::default(),
+    };
 
-        if !quiet {
-            //write!(stdout, "...").expect("can write to stdout");
+    // the progress bar grows as the walker finds more files
+    let (walker, streamed) = match walker {
+        Some((receiver, walker, found)) => {
+            let span = read_span.clone();
+            let counter = Arc::clone(&found);
+            let streamed = receiver.into_iter().inspect(move |_| {
+                span.pb_set_length(counter.load(std::sync::atomic::Ordering::Relaxed) as u64);
+            });
+            (Some((walker, found)), Some(streamed))
         }
-        loop {
-            match rx.recv() {
-                Err(_) => break,
-                Ok(msg) => {
-                    file_statuses
-                        .entry(msg.path.clone())
-                        .and_modify(|vec| vec.push(msg.clone()))
-                        .or_insert_with(|| vec![msg.clone()]);
-
-                    stats.update(&msg.kind);
-
-                    match msg.kind {
-                        StatusMessageKind::Read { result } => {
-                            *ii.lock().unwrap() += 1;
-                            pb_a.inc(1);
-                            //let _ = clear_line!(stdout);
-                            //let _ = write!(stdout, "{}", stats.display("read"));
-                        }
-                        StatusMessageKind::ParseXml { result } => {
-                            //pb2.inc(1);
-                            //let _ = clear_line!(stdout);
-                            //let _ = write!(stdout, "{}", stats.display("parse_xml"));
-                        }
-                        StatusMessageKind::ParseAnalyses { result } => {
-                            pb3.inc(1);
-                            //let _ = clear_line!(stdout);
-                            //let _ = write!(stdout, "{}", stats.display("parse_analyses"));
-                            match result {
-                                Ok(x) => {},
-                                Err(e) => {
-                                    //let _ = clear_line!(stdout);
-                                    //println!("\n\n\nERR: Parse analyses (file: {})", msg.path.display());
-                                    for x in e {
-                                        //println!("\n- {x}");
-                                    }
-                                }
-                            }
-                        }
-                        _ => {}
-                    }
-                }
+        None => (None, None),
+    };
+    // the progress bar grows as the archive is read, too
+    let (archived, from_archive) = match (input_archive, archive_filters) {
+        (Some(path), Some((lang, path_filter, skiplist))) => {
+            let (sender, receiver) = mpsc::sync_channel(FILE_QUEUE_LEN);
+            let found = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+            let filters = (skip, path_filter, skiplist);
+            let counted = (Arc::clone(&found), budget.clone());
+            let reader = read_archive(path, lang, filters, sender, counted);
+            let span = read_span.clone();
+            let counter = Arc::clone(&found);
+            let entries = receiver.into_iter().inspect(move |_| {
+                span.pb_set_length(counter.load(std::sync::atomic::Ordering::Relaxed) as u64);
+                span.pb_inc(1);
+            });
+            (Some((reader, found)), Some(entries))
+        }
+        _ => (None, None),
+    };
+    let log_context = LogContext::new(log_context);
+    let read = || {
+        let read = Admitted::new(
+            Chaotic::new(stages::ReadFile, chaos.clone()),
+            budget.clone(),
+        );
+        InDocumentSpan::new(read, log_context.clone())
+    };
+    let read_files = match from_archive {
+        // the archive is read by its own thread, instead of by the read stage
+        Some(entries) => Either::Right(entries.par_bridge()),
+        None => Either::Left(match (threads_io, streamed) {
+            (0, None) => {
+                let read = read();
+                Either::Left(Either::Left(
+                    files.into_par_iter().filter_map(move |path| read.run(path)),
+                ))
+            }
+            (0, Some(streamed)) => {
+                let read = read();
+                Either::Left(Either::Right(
+                    streamed.par_bridge().filter_map(move |path| read.run(path)),
+                ))
             }
+            (n, streamed) => Either::Right(
+                match streamed {
+                    None => SourcePool::new(read(), files, n, 4 * n),
+                    Some(streamed) => SourcePool::new(read(), streamed, n, 4 * n),
+                }
+                .par_bridge(),
+            ),
+        }),
+    };
+
+    #[cfg(feature = "tui")]
+    let dashboard_handle = match &dashboard {
+        Some(dashboard) => Some(
+            Arc::clone(dashboard)
+                .show()
+                .context("can't show the dashboard")?,
+        ),
+        None => None,
+    };
+    // each file is timed for the slowest files of the dashboard, and the
+    // profile, and released from the memory budget when it is done
+    let wants_path = dashboard.is_some() || profile.is_some() || budget.is_some();
+    let file_done = |path: Option<PathBuf>, started: Instant, duration, converted| {
+        let Some(path) = path else {
+            return;
+        };
+        if let Some(budget) = &budget {
+            budget.release(&path);
         }
-        //write!(stdout, "\n").expect("can write to stdout");
-        file_statuses
-    });
-    */
+        if let Some(profile) = &profile {
+            profile.record_file(&path, started, duration);
+        }
+        if let Some(dashboard) = &dashboard {
+            dashboard.file_done(path, duration, converted);
+        }
+    };
 
-    if !quiet {
-        //println!("korp-mono-rs starting, {nfiles} files to process...");
+    if write_threads == 0 {
+        let pipeline = pipeline.then(write);
+        read_files
+            .filter_map(|file| {
+                let path = wants_path.then(|| file.0.to_path_buf());
+                let span = log_context.span(&file.0.to_path_buf());
+                let _entered = span.enter();
+                let started = Instant::now();
+                let (duration, written) = timed(|| pipeline.run(file));
+                file_done(path, started, duration, written.is_some());
+                written
+            })
+            //.filter_map(|path| gen_missing_baseforms(tx.clone(), path))
+            .for_each(|_| {});
+    } else {
+        // the pool runs the write stage outside of the pipeline, so it has to
+        // be measured on its own
+        let write = Chaotic::new(write, chaos.clone());
+        let write: Box<dyn Stage<Input = stages::Converted, Output = PathBuf>> = match &metrics {
+            Some(metrics) => {
+                Box::new(Measured::new(write, Arc::clone(metrics)).with_profile(profile.clone()))
+            }
+            None => Box::new(write),
+        };
+        let writers = StagePool::new(write, write_threads, 2 * write_threads);
+        let pipeline = pipeline.then(writers.sender());
+        read_files.for_each(|file| {
+            let path = wants_path.then(|| file.0.to_path_buf());
+            let span = log_context.span(&file.0.to_path_buf());
+            let _entered = span.enter();
+            let started = Instant::now();
+            // the file is written later, by the pool, in the same span
+            let (duration, sent) = timed(|| pipeline.run(file));
+            file_done(path, started, duration, sent.is_some());
+        });
+        drop(pipeline);
+        writers.join();
     }
 
-    files
-        .into_par_iter()
-        .filter_map(|path| read_to_string(path))
-        .filter_map(|(path, string)| parse_xml(path, &string))
-        .filter_map(|(path, doc)| parse_analyses(path, doc))
-        .filter_map(|(path, doc)| convert_document(path, doc))
-        .map(|(path, doc)| (gtcorpusutil::KorpMonoFilePath::from(path), doc))
-        .filter_map(|(path, korp_mono_file)| write_korpmono_file(path, korp_mono_file))
-        //.filter_map(|path| gen_missing_baseforms(tx.clone(), path))
-        .for_each(|_| {});
+    if let Some(archive) = &archive {
+        archive
+            .finish()
+            .with_context(|| format!("failed to finish archive '{}'", archive.path.display()))?;
+    }
 
     //pb1.abandon();
     //pb2.abandon();
     //pb3.abandon();
     //pb4.abandon();
 
-    // Drop the sender, to indicate that work is done. When the printer thread
-    // notices that the transmitter is gone, it will break its loop, and stop,
-    // allowing the jh.join() to unblock.
-    //drop(tx);
-    //let file_statuses = jh.join().expect("printer thread didn't panic");
     //m.clear().unwrap();
 
     /*
     // write out all status files
-    for (path, statuses) in file_statuses.iter() {
+    for (path, statuses) in report.statuses.iter() {
         // the path we store is an analysed path
         let path = AnalysedFilePath::new_unchecked(path.to_path_buf());
         let path = korp_mono::path::KorpMonoPath::from(path);
@@ -532,6 +2141,228 @@ fn main() -> anyhow::Result<()> {
     }
     */
 
-    println!("all done");
-    Ok(())
+    #[cfg(feature = "tui")]
+    if let Some(handle) = dashboard_handle {
+        handle.finish().context("can't restore the terminal")?;
+    }
+
+    // with --stream-files, all files are only known now
+    if let Some((reader, found)) = archived {
+        let skipped = match reader.join() {
+            Ok(skipped) => skipped.context("failed to read the input archive")?,
+            Err(e) => std::panic::resume_unwind(e),
+        };
+        say_skipped(&skipped);
+        found_files = found.load(std::sync::atomic::Ordering::Relaxed);
+        skipped_files = skipped.len();
+        if let Some(metrics) = &metrics {
+            metrics.set_files(found_files);
+            metrics.set_skipped(skipped_files);
+        }
+    }
+
+    if let Some((walker, found)) = walker {
+        let skipped = match walker.join() {
+            Ok(skipped) => skipped.context("failed to find the analysed files")?,
+            Err(e) => std::panic::resume_unwind(e),
+        };
+        say_skipped(&skipped);
+        found_files = found.load(std::sync::atomic::Ordering::Relaxed);
+        skipped_files = skipped.len();
+        if let Some(metrics) = &metrics {
+            metrics.set_files(found_files);
+            metrics.set_skipped(skipped_files);
+        }
+    }
+
+    if warnings {
+        sanity_warnings
+            .lock()
+            .expect("pipeline is done")
+            .write_reports()
+            .context("failed to write warnings report")?;
+    }
+
+    if info {
+        corpus_infos
+            .lock()
+            .expect("pipeline is done")
+            .write_info_files()
+            .context("failed to write .info files")?;
+    }
+
+    if duplicates.is_some() {
+        duplicate_files
+            .lock()
+            .expect("pipeline is done")
+            .write_reports()
+            .context("failed to write duplicates report")?;
+    }
+
+    if parallel_links {
+        links
+            .lock()
+            .expect("pipeline is done")
+            .write_files()
+            .context("failed to write parallel links")?;
+    }
+
+    if manifest {
+        written_files
+            .lock()
+            .expect("pipeline is done")
+            .write_manifests()
+            .context("failed to write manifest files")?;
+    }
+
+    let excluded = excluded.lock().expect("pipeline is done");
+    // the license filter can also be set in the settings of a corpus
+    if license_filter.is_some() || excluded.total() > 0 {
+        say!(json_progress, "{}", excluded.report());
+    }
+
+    let empty_counts = *empty_counts.lock().expect("pipeline is done");
+    if empty_counts != EmptyCounts::default() {
+        say!(json_progress, "{}", empty_counts.report(empty_documents));
+    }
+
+    if let Some(budget) = &budget {
+        say!(json_progress, "{}", budget.report());
+    }
+
+    if meta_sidecars {
+        say!(
+            json_progress,
+            "{}",
+            sidecar_overrides.lock().expect("pipeline is done").report()
+        );
+    }
+
+    if let Some(min_tokens) = min_tokens {
+        let short_documents = short_documents.lock().expect("pipeline is done");
+        say!(json_progress, "{}", short_documents.report(min_tokens));
+    }
+
+    if demote_broken_trees {
+        say!(
+            json_progress,
+            "{}",
+            demoted.lock().expect("pipeline is done").report()
+        );
+    }
+
+    if distribution {
+        say!(
+            json_progress,
+            "{}",
+            token_distribution
+                .lock()
+                .expect("pipeline is done")
+                .report()
+        );
+    }
+
+    if pos_coverage {
+        let unknown_pos = unknown_pos.lock().expect("pipeline is done");
+        say!(json_progress, "{}", unknown_pos.report());
+        if let Some(path) = &pos_coverage_file {
+            unknown_pos
+                .write_file(path)
+                .with_context(|| format!("failed to write '{}'", path.display()))?;
+        }
+    }
+
+    if !sentence_filter.is_empty() {
+        let dropped = dropped_sentences.lock().expect("pipeline is done");
+        say!(json_progress, "{}", dropped.report(&sentence_filter));
+    }
+
+    if dedup.is_some() {
+        say!(
+            json_progress,
+            "{}",
+            sentence_dedup.lock().expect("pipeline is done").report()
+        );
+    }
+
+    if let Some(cwb) = &cwb {
+        let encoded = cwb.finish();
+        say!(json_progress, "{}", cwb::report(&encoded));
+        for corpus in encoded.iter().filter(|corpus| !corpus.success()) {
+            tracing::error!(
+                corpus = ?corpus.corpus,
+                status = ?corpus.status,
+                category = ErrorCategory::Write.as_str(),
+                "cwb-encode failed"
+            );
+        }
+    }
+
+    if compare_python.is_some() {
+        say!(
+            json_progress,
+            "{}",
+            comparison.lock().expect("pipeline is done").report()
+        );
+    }
+
+    #[cfg(feature = "index")]
+    if let Some(sentence_index) = &sentence_index {
+        sentence_index
+            .commit()
+            .context("failed to write the index")?;
+        say!(json_progress, "{}", sentence_index.report());
+    }
+
+    let failures = failures.lock().expect("pipeline is done");
+    say!(json_progress, "{}", failures.report());
+
+    #[cfg(feature = "generate")]
+    if let (Some(generator), Some(cache)) = (&generator, &generator_cache) {
+        generator
+            .save(cache)
+            .with_context(|| format!("failed to write generator cache '{}'", cache.display()))?;
+    }
+
+    if let (Some(metrics), Some(path)) = (&metrics, &metrics_file) {
+        metrics.set_failures(&failures);
+        metrics
+            .write_textfile(path, start.elapsed())
+            .context("failed to write metrics")?;
+    }
+
+    if let Some(path) = &json_report {
+        let report = RunReport {
+            shards: shard.into_iter().collect(),
+            files: found_files as u64,
+            skipped: skipped_files as u64,
+            failed: failures.total(),
+            failures: failures
+                .iter()
+                .map(|(category, n)| (category.as_str().to_string(), n))
+                .collect(),
+            errors: failures
+                .iter_errors()
+                .map(|(category, n)| (category.as_str().to_string(), n))
+                .collect(),
+            seconds: start.elapsed().as_secs_f64(),
+        };
+        report
+            .write_file(path)
+            .with_context(|| format!("failed to write report '{}'", path.display()))?;
+    }
+
+    if let (Some(profile), Some(path)) = (&profile, &profile_file) {
+        profile
+            .write_file(path)
+            .with_context(|| format!("failed to write profile '{}'", path.display()))?;
+    }
+
+    if let Some(progress) = &progress {
+        progress.done(nfiles, start.elapsed());
+    }
+    if !quiet {
+        say!(json_progress, "all done\n");
+    }
+    Ok(ExitCode::from(failures.exit_code()))
 }
@@ -0,0 +1,370 @@
+//! The manifest of the written korp_mono files.
+//!
+//! For each corpus, a `korp_mono.manifest` file is written to the corpus
+//! directory, listing every korp_mono file that was written, with its
+//! checksum, its size, and the analysed file it was converted from. One
+//! line per file, with tab-separated fields, sorted by source path:
+//!
+//! ```not_rust
+//! crc32     bytes   source                      output
+//! 1c291ca3  123456  analysed/news/avvir/a.xml   korp_mono/news/avvir/a.xml
+//! ```
+//!
+//! Paths are relative to the corpus directory, when they are inside it.
+//! Backslashes, tabs and line breaks in them are escaped as `\\`, `\t`,
+//! `\n` and `\r`.
+//!
+//! The manifest is updated, not replaced, by each run: the files that were
+//! not written in this run keep their entries. With [`prune`], the outputs
+//! of analysed files that no longer exist are removed, together with their
+//! entries.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::{Component, Path, PathBuf};
+
+use crate::korp_mono::path::corpus_dir;
+
+/// Name of the manifest file, written to the corpus directory.
+pub const MANIFEST_FILE_NAME: &str = "korp_mono.manifest";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub checksum: u32,
+    pub bytes: u64,
+    /// The analysed file
+    pub source: PathBuf,
+    /// The korp_mono file
+    pub output: PathBuf,
+}
+
+/// Manifest entries of all corpora, by corpus directory.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    corpora: BTreeMap<PathBuf, Vec<Entry>>,
+}
+
+fn relative_to<'a>(path: &'a Path, dir: &Path) -> &'a Path {
+    path.strip_prefix(dir).unwrap_or(path)
+}
+
+/// `path` as it is written in a manifest line, with the characters that
+/// separate the fields and lines escaped, so that file names with tabs or
+/// newlines in them (see `--stdin-list-null`) don't break the manifest.
+fn escape(path: &Path) -> String {
+    let mut s = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '\\' => s.push_str("\\\\"),
+            '\t' => s.push_str("\\t"),
+            '\n' => s.push_str("\\n"),
+            '\r' => s.push_str("\\r"),
+            c => s.push(c),
+        }
+    }
+    s
+}
+
+/// The path of the manifest field `s`, written by [`escape`].
+fn unescape(s: &str) -> PathBuf {
+    let mut path = String::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('t') => path.push('\t'),
+                Some('n') => path.push('\n'),
+                Some('r') => path.push('\r'),
+                Some(c) => path.push(c),
+                None => path.push('\\'),
+            },
+            c => path.push(c),
+        }
+    }
+    PathBuf::from(path)
+}
+
+impl Manifest {
+    pub fn add(&mut self, entry: Entry) {
+        let corpus = corpus_dir(&entry.source)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        self.corpora.entry(corpus).or_default().push(entry);
+    }
+
+    /// The entries of the manifest file `contents` of `corpus`. Lines that
+    /// can't be parsed are skipped.
+    fn parse_manifest_file(corpus: &Path, contents: &str) -> Vec<Entry> {
+        contents
+            .lines()
+            .filter_map(|line| {
+                let [checksum, bytes, source, output] = line.split('\t').collect::<Vec<_>>()[..]
+                else {
+                    return None;
+                };
+                Some(Entry {
+                    checksum: u32::from_str_radix(checksum, 16).ok()?,
+                    bytes: bytes.parse().ok()?,
+                    source: corpus.join(unescape(source)),
+                    output: corpus.join(unescape(output)),
+                })
+            })
+            .collect()
+    }
+
+    /// The entries of the manifest of `corpus`, none if it has no manifest.
+    pub fn read_manifest(corpus: &Path) -> std::io::Result<Vec<Entry>> {
+        match std::fs::read_to_string(corpus.join(MANIFEST_FILE_NAME)) {
+            Ok(contents) => Ok(Self::parse_manifest_file(corpus, &contents)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn manifest_file(corpus: &Path, entries: &[Entry]) -> String {
+        let mut entries = entries.iter().collect::<Vec<_>>();
+        entries.sort_by(|a, b| a.source.cmp(&b.source));
+        let mut s = String::new();
+        for entry in entries {
+            writeln!(
+                s,
+                "{:08x}\t{}\t{}\t{}",
+                entry.checksum,
+                entry.bytes,
+                escape(relative_to(&entry.source, corpus)),
+                escape(relative_to(&entry.output, corpus)),
+            )
+            .expect("can always write to String");
+        }
+        s
+    }
+
+    /// Write `{corpus}/korp_mono.manifest` for each corpus, keeping the
+    /// entries of the existing manifest for the files that were not written
+    /// this time.
+    pub fn write_manifests(&self) -> std::io::Result<()> {
+        for (corpus, entries) in self.corpora.iter() {
+            let written = entries.iter().map(|e| &e.source).collect::<BTreeSet<_>>();
+            let mut all = Self::read_manifest(corpus)?;
+            all.retain(|entry| !written.contains(&entry.source));
+            all.extend(entries.iter().cloned());
+            write_manifest_file(corpus, &Self::manifest_file(corpus, &all))?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `contents` to the manifest of `corpus`, through a temporary file
+/// that is renamed over it, so that an interrupted run leaves the old
+/// manifest, not a truncated one.
+fn write_manifest_file(corpus: &Path, contents: &str) -> std::io::Result<()> {
+    let path = corpus.join(MANIFEST_FILE_NAME);
+    let tmp = corpus.join(format!(".{MANIFEST_FILE_NAME}.tmp"));
+    std::fs::write(&tmp, contents)?;
+    std::fs::rename(&tmp, &path)
+}
+
+/// Whether `path` is in `dir`, and can't get out of it with `..`.
+fn is_inside(path: &Path, dir: &Path) -> bool {
+    path.starts_with(dir)
+        && !path
+            .components()
+            .any(|component| component == Component::ParentDir)
+}
+
+/// Remove the korp_mono files of `corpus` whose analysed file no longer
+/// exists, according to its manifest, and their entries in the manifest.
+/// Returns the entries that were pruned. Nothing is removed if one of the
+/// files is neither in `corpus` nor in `output_dir`, since the manifest
+/// can't be trusted then.
+pub fn prune(corpus: &Path, output_dir: Option<&Path>) -> std::io::Result<Vec<Entry>> {
+    let (kept, pruned): (Vec<_>, Vec<_>) = Manifest::read_manifest(corpus)?
+        .into_iter()
+        .partition(|entry| entry.source.exists());
+    if pruned.is_empty() {
+        return Ok(pruned);
+    }
+    let outside = pruned.iter().find(|entry| {
+        !is_inside(&entry.output, corpus)
+            && !output_dir.is_some_and(|dir| is_inside(&entry.output, dir))
+    });
+    if let Some(entry) = outside {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "the manifest lists '{}', which is not in the corpus or the output directory",
+                entry.output.display()
+            ),
+        ));
+    }
+    for entry in pruned.iter() {
+        match std::fs::remove_file(&entry.output) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+    write_manifest_file(corpus, &Manifest::manifest_file(corpus, &kept))?;
+    Ok(pruned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, MANIFEST_FILE_NAME, Manifest, prune};
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn manifest_file() {
+        let corpus = Path::new("/giellalt/corpus-sme");
+        let entry = |name: &str, checksum| Entry {
+            checksum,
+            bytes: 10,
+            source: corpus.join("analysed").join(name),
+            output: corpus.join("korp_mono").join(name),
+        };
+        let entries = [entry("b.xml", 0xabc), entry("a.xml", 0x1c291ca3)];
+        assert_eq!(
+            Manifest::manifest_file(corpus, &entries),
+            concat!(
+                "1c291ca3\t10\tanalysed/a.xml\tkorp_mono/a.xml\n",
+                "00000abc\t10\tanalysed/b.xml\tkorp_mono/b.xml\n",
+            )
+        );
+
+        let outside = Entry {
+            checksum: 1,
+            bytes: 1,
+            source: PathBuf::from("/tmp/c.xml"),
+            output: PathBuf::from("/out/c.xml"),
+        };
+        assert_eq!(
+            Manifest::manifest_file(corpus, &[outside]),
+            "00000001\t1\t/tmp/c.xml\t/out/c.xml\n"
+        );
+    }
+
+    #[test]
+    fn parse_manifest_file() {
+        let corpus = Path::new("/giellalt/corpus-sme");
+        let contents = concat!(
+            "1c291ca3\t10\tanalysed/a.xml\tkorp_mono/a.xml\n",
+            "not a manifest line\n",
+            "00000001\t1\t/tmp/c.xml\t/out/c.xml\n",
+        );
+        let entries = Manifest::parse_manifest_file(corpus, contents);
+        assert_eq!(
+            entries,
+            [
+                Entry {
+                    checksum: 0x1c291ca3,
+                    bytes: 10,
+                    source: corpus.join("analysed/a.xml"),
+                    output: corpus.join("korp_mono/a.xml"),
+                },
+                Entry {
+                    checksum: 1,
+                    bytes: 1,
+                    source: PathBuf::from("/tmp/c.xml"),
+                    output: PathBuf::from("/out/c.xml"),
+                },
+            ]
+        );
+        assert_eq!(
+            Manifest::manifest_file(corpus, &entries[..1]),
+            contents.lines().next().unwrap().to_string() + "\n"
+        );
+    }
+
+    #[test]
+    fn escaped_names() {
+        let corpus = Path::new("/giellalt/corpus-sme");
+        let entry = Entry {
+            checksum: 1,
+            bytes: 1,
+            source: corpus.join("analysed/a\tb\nc\\d.xml"),
+            output: corpus.join("korp_mono/a\tb\nc\\d.xml"),
+        };
+        let contents = Manifest::manifest_file(corpus, std::slice::from_ref(&entry));
+        assert_eq!(
+            contents,
+            "00000001\t1\tanalysed/a\\tb\\nc\\\\d.xml\tkorp_mono/a\\tb\\nc\\\\d.xml\n"
+        );
+        assert_eq!(Manifest::parse_manifest_file(corpus, &contents), [entry]);
+    }
+
+    #[test]
+    fn prune_vanished_sources() {
+        let corpus = std::env::temp_dir().join(format!("korp-mono-prune-{}", std::process::id()));
+        for dir in ["analysed", "korp_mono"] {
+            std::fs::create_dir_all(corpus.join(dir)).unwrap();
+        }
+        for file in ["analysed/a.xml", "korp_mono/a.xml", "korp_mono/b.xml"] {
+            std::fs::write(corpus.join(file), "").unwrap();
+        }
+        let mut manifest = Manifest::default();
+        for name in ["a.xml", "b.xml"] {
+            manifest.add(Entry {
+                checksum: 0,
+                bytes: 0,
+                source: corpus.join("analysed").join(name),
+                output: corpus.join("korp_mono").join(name),
+            });
+        }
+        manifest.write_manifests().unwrap();
+
+        let pruned = prune(&corpus, None).unwrap();
+        assert_eq!(pruned.len(), 1);
+        assert_eq!(pruned[0].source, corpus.join("analysed/b.xml"));
+        assert!(corpus.join("korp_mono/a.xml").exists());
+        assert!(!corpus.join("korp_mono/b.xml").exists());
+        let left = Manifest::read_manifest(&corpus).unwrap();
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].source, corpus.join("analysed/a.xml"));
+        assert!(!corpus.join(".korp_mono.manifest.tmp").exists());
+        std::fs::remove_dir_all(&corpus).unwrap();
+    }
+
+    #[test]
+    fn prune_only_in_corpus_or_output_dir() {
+        let corpus = std::env::temp_dir().join(format!("korp-mono-unsafe-{}", std::process::id()));
+        let out = corpus.with_extension("out");
+        for dir in [corpus.join("analysed"), out.clone()] {
+            std::fs::create_dir_all(dir).unwrap();
+        }
+        let outside = corpus.with_extension("keep");
+        std::fs::write(&outside, "").unwrap();
+        std::fs::write(out.join("b.xml"), "").unwrap();
+        for output in [
+            outside.clone(),
+            corpus
+                .join("korp_mono/../../")
+                .join(outside.file_name().unwrap()),
+        ] {
+            let mut manifest = Manifest::default();
+            manifest.add(Entry {
+                checksum: 0,
+                bytes: 0,
+                source: corpus.join("analysed/a.xml"),
+                output,
+            });
+            manifest.write_manifests().unwrap();
+            assert!(prune(&corpus, Some(&out)).is_err());
+            assert!(outside.exists());
+        }
+
+        let mut manifest = Manifest::default();
+        manifest.add(Entry {
+            checksum: 0,
+            bytes: 0,
+            source: corpus.join("analysed/b.xml"),
+            output: out.join("b.xml"),
+        });
+        std::fs::remove_file(corpus.join(MANIFEST_FILE_NAME)).unwrap();
+        manifest.write_manifests().unwrap();
+        let pruned = prune(&corpus, Some(&out));
+        for path in [&corpus, &out] {
+            std::fs::remove_dir_all(path).unwrap();
+        }
+        std::fs::remove_file(&outside).unwrap();
+        assert_eq!(pruned.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,279 @@
+//! Summary metrics of a conversion run, in the Prometheus text format.
+//!
+//! The metrics are written to a file at the end of the run, for the textfile
+//! collector of the Prometheus node exporter. The file is written to a
+//! temporary file first, and renamed into place, so that the collector never
+//! reads a half-written file.
+
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::failures::Failures;
+use crate::korp_mono::KorpMonoFile;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct StageMetrics {
+    runs: u64,
+    failures: u64,
+    seconds: f64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    files: u64,
+    skipped: u64,
+    documents: u64,
+    sentences: u64,
+    tokens: u64,
+    failures: Failures,
+    /// By stage name, in the order the stages were first seen
+    stages: Vec<(&'static str, StageMetrics)>,
+}
+
+/// Metrics collected while the pipeline runs. Shared between the threads.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    inner: Mutex<Inner>,
+}
+
+impl Metrics {
+    fn inner(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner
+            .lock()
+            .expect("no thread panics while holding the lock")
+    }
+
+    /// Set the number of files that were found for conversion.
+    pub fn set_files(&self, files: usize) {
+        self.inner().files = files as u64;
+    }
+
+    /// Set the number of files that were skipped on purpose, because they
+    /// are in the skiplist.
+    pub fn set_skipped(&self, skipped: usize) {
+        self.inner().skipped = skipped as u64;
+    }
+
+    /// Set the failed files, by category.
+    pub fn set_failures(&self, failures: &Failures) {
+        self.inner().failures = failures.clone();
+    }
+
+    /// Record that the stage `stage` ran for `duration`, and if it failed.
+    pub fn record_stage(&self, stage: &'static str, duration: Duration, failed: bool) {
+        let mut inner = self.inner();
+        let i = match inner.stages.iter().position(|(name, _)| *name == stage) {
+            Some(i) => i,
+            None => {
+                inner.stages.push((stage, StageMetrics::default()));
+                inner.stages.len() - 1
+            }
+        };
+        let metrics = &mut inner.stages[i].1;
+        metrics.runs += 1;
+        metrics.failures += u64::from(failed);
+        metrics.seconds += duration.as_secs_f64();
+    }
+
+    /// Count the sentences and tokens of a converted document.
+    pub fn add_document(&self, document: &KorpMonoFile) {
+        let sentences = document.sentence.len() as u64;
+        let tokens = document
+            .sentence
+            .iter()
+            .map(|sentence| sentence.rows.len() as u64)
+            .sum::<u64>();
+        let mut inner = self.inner();
+        inner.documents += 1;
+        inner.sentences += sentences;
+        inner.tokens += tokens;
+    }
+
+    /// The runs, failures and seconds of each stage, in the order the stages
+    /// were first seen.
+    pub fn stages(&self) -> Vec<(&'static str, u64, u64, f64)> {
+        self.inner()
+            .stages
+            .iter()
+            .map(|(name, m)| (*name, m.runs, m.failures, m.seconds))
+            .collect()
+    }
+
+    /// The metrics in the Prometheus text format. `duration` is how long the
+    /// whole run took, `timestamp` when it ended, in seconds since the epoch.
+    pub fn render(&self, duration: Duration, timestamp: u64) -> String {
+        let inner = self.inner();
+        let mut s = String::new();
+        let w = "can always write to String";
+        let mut metric = |name: &str, kind: &str, help: &str, values: &[(String, String)]| {
+            writeln!(s, "# HELP korp_mono_{name} {help}").expect(w);
+            writeln!(s, "# TYPE korp_mono_{name} {kind}").expect(w);
+            for (labels, value) in values {
+                writeln!(s, "korp_mono_{name}{labels} {value}").expect(w);
+            }
+        };
+        let single = |value: String| [(String::new(), value)];
+        let per_stage = |value: &dyn Fn(&StageMetrics) -> String| {
+            inner
+                .stages
+                .iter()
+                .map(|(name, m)| (format!("{{stage=\"{name}\"}}"), value(m)))
+                .collect::<Vec<_>>()
+        };
+
+        metric(
+            "files",
+            "gauge",
+            "Files found for conversion.",
+            &single(inner.files.to_string()),
+        );
+        metric(
+            "files_skipped",
+            "gauge",
+            "Files skipped because they are in the skiplist.",
+            &single(inner.skipped.to_string()),
+        );
+        metric(
+            "documents_converted",
+            "gauge",
+            "Documents that were converted.",
+            &single(inner.documents.to_string()),
+        );
+        metric(
+            "sentences",
+            "gauge",
+            "Sentences in the converted documents.",
+            &single(inner.sentences.to_string()),
+        );
+        metric(
+            "tokens",
+            "gauge",
+            "Tokens in the converted documents.",
+            &single(inner.tokens.to_string()),
+        );
+        metric(
+            "files_failed",
+            "gauge",
+            "Files that failed to convert, by error category.",
+            &inner
+                .failures
+                .iter()
+                .map(|(category, n)| (format!("{{category=\"{category}\"}}"), n.to_string()))
+                .collect::<Vec<_>>(),
+        );
+        metric(
+            "errors",
+            "gauge",
+            "Errors that are not about a single file, by error category.",
+            &inner
+                .failures
+                .iter_errors()
+                .map(|(category, n)| (format!("{{category=\"{category}\"}}"), n.to_string()))
+                .collect::<Vec<_>>(),
+        );
+        metric(
+            "stage_runs",
+            "gauge",
+            "Files that reached the stage.",
+            &per_stage(&|m| m.runs.to_string()),
+        );
+        metric(
+            "stage_failures",
+            "gauge",
+            "Files that failed in the stage.",
+            &per_stage(&|m| m.failures.to_string()),
+        );
+        metric(
+            "stage_seconds",
+            "gauge",
+            "Time spent in the stage, summed over all threads.",
+            &per_stage(&|m| format!("{:.3}", m.seconds)),
+        );
+        metric(
+            "run_duration_seconds",
+            "gauge",
+            "Wall clock time of the run.",
+            &single(format!("{:.3}", duration.as_secs_f64())),
+        );
+        metric(
+            "last_run_timestamp_seconds",
+            "gauge",
+            "When the run ended.",
+            &single(timestamp.to_string()),
+        );
+        s
+    }
+
+    /// Write the metrics to `path`. See [`Metrics::render`].
+    pub fn write_textfile(&self, path: &Path, duration: Duration) -> std::io::Result<()> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, self.render(duration, timestamp))?;
+        std::fs::rename(&tmp, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Metrics;
+    use crate::failures::Failures;
+    use crate::korp_mono::KorpMonoFile;
+    use crate::korp_mono::file::Sentence;
+    use crate::process_sentence::rows_from_lines;
+    use crate::status_message::ErrorCategory;
+    use std::time::Duration;
+
+    #[test]
+    fn render() {
+        let metrics = Metrics::default();
+        metrics.set_files(2);
+        metrics.set_skipped(1);
+        let mut failures = Failures::default();
+        failures.add_file("a.xml", ErrorCategory::Timeout);
+        failures.add_error(ErrorCategory::Write);
+        metrics.set_failures(&failures);
+        metrics.record_stage("read", Duration::from_millis(10), false);
+        metrics.record_stage("read", Duration::from_millis(20), true);
+        metrics.record_stage("write", Duration::from_millis(5), false);
+        metrics.add_document(&KorpMonoFile {
+            sentence: vec![Sentence::new(
+                "1".to_string(),
+                rows_from_lines("a\ta\nb\tb\n"),
+            )],
+            ..Default::default()
+        });
+
+        let text = metrics.render(Duration::from_secs(3), 1700000000);
+        let lines = text
+            .lines()
+            .filter(|l| !l.starts_with('#'))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                "korp_mono_files 2",
+                "korp_mono_files_skipped 1",
+                "korp_mono_documents_converted 1",
+                "korp_mono_sentences 1",
+                "korp_mono_tokens 2",
+                "korp_mono_files_failed{category=\"timeout\"} 1",
+                "korp_mono_errors{category=\"write\"} 1",
+                "korp_mono_stage_runs{stage=\"read\"} 2",
+                "korp_mono_stage_runs{stage=\"write\"} 1",
+                "korp_mono_stage_failures{stage=\"read\"} 1",
+                "korp_mono_stage_failures{stage=\"write\"} 0",
+                "korp_mono_stage_seconds{stage=\"read\"} 0.030",
+                "korp_mono_stage_seconds{stage=\"write\"} 0.005",
+                "korp_mono_run_duration_seconds 3.000",
+                "korp_mono_last_run_timestamp_seconds 1700000000",
+            ]
+        );
+        assert!(text.contains("# TYPE korp_mono_tokens gauge\n"));
+    }
+}
@@ -0,0 +1,147 @@
+//! Detect and repair mojibake in word forms.
+//!
+//! Some of the legacy corpus files have been through a round of "decode the
+//! UTF-8 bytes as latin-1 (or windows-1252), and encode the result as UTF-8
+//! again". The word "čálli" then ends up as "ÄÅ¡Ã¡lli", or similar, and
+//! such tokens are useless in Korp.
+//!
+//! The repair is to take each character of the broken string back to the
+//! byte it was decoded from, and decode those bytes as UTF-8 again. If any
+//! character can't be mapped back to a single byte, or the bytes are not
+//! valid UTF-8, the string was not double-encoded, and it is left alone.
+
+use std::borrow::Cow;
+
+use crate::process_sentence::TokenRow;
+
+/// The characters that windows-1252 decodes the bytes 0x80..=0x9F to. The
+/// holes in the table (0x81, 0x8D, 0x8F, 0x90, 0x9D) are not defined in
+/// windows-1252, and are usually decoded to the C1 control character with
+/// the same value, which [`to_byte`] already handles.
+const CP1252_HIGH: [(char, u8); 27] = [
+    ('€', 0x80),
+    ('‚', 0x82),
+    ('ƒ', 0x83),
+    ('„', 0x84),
+    ('…', 0x85),
+    ('†', 0x86),
+    ('‡', 0x87),
+    ('ˆ', 0x88),
+    ('‰', 0x89),
+    ('Š', 0x8A),
+    ('‹', 0x8B),
+    ('Œ', 0x8C),
+    ('Ž', 0x8E),
+    ('\u{2018}', 0x91),
+    ('\u{2019}', 0x92),
+    ('\u{201C}', 0x93),
+    ('\u{201D}', 0x94),
+    ('•', 0x95),
+    ('–', 0x96),
+    ('—', 0x97),
+    ('˜', 0x98),
+    ('™', 0x99),
+    ('š', 0x9A),
+    ('›', 0x9B),
+    ('œ', 0x9C),
+    ('ž', 0x9E),
+    ('Ÿ', 0x9F),
+];
+
+/// The byte that character `c` was decoded from, if it came from a latin-1
+/// or windows-1252 decoding.
+fn to_byte(c: char) -> Option<u8> {
+    match u8::try_from(c) {
+        Ok(b) => Some(b),
+        Err(_) => CP1252_HIGH.iter().find(|(ch, _)| *ch == c).map(|(_, b)| *b),
+    }
+}
+
+/// Undo one round of double encoding of `s`. Returns `None` if `s` does not
+/// look double-encoded.
+fn repair_once(s: &str) -> Option<String> {
+    if s.is_ascii() {
+        return None;
+    }
+    // A double-encoded multi-byte sequence always starts with one of the
+    // characters that a UTF-8 lead byte decodes to in latin-1.
+    if !s.chars().any(|c| matches!(c, '\u{C2}'..='\u{F4}')) {
+        return None;
+    }
+    let bytes = s.chars().map(to_byte).collect::<Option<Vec<u8>>>()?;
+    let repaired = String::from_utf8(bytes).ok()?;
+    (repaired != s).then_some(repaired)
+}
+
+/// Repair the mojibake in `s`. Returns the repaired string, or `None` if `s`
+/// did not need repairing. Strings that have been double-encoded more than
+/// once are repaired fully.
+pub fn repair(s: &str) -> Option<String> {
+    let mut repaired = repair_once(s)?;
+    // a string can't realistically have been through this more than a
+    // couple of times, so don't loop forever on something weird
+    for _ in 0..2 {
+        match repair_once(&repaired) {
+            Some(again) => repaired = again,
+            None => break,
+        }
+    }
+    Some(repaired)
+}
+
+/// Repair the word forms of `rows`, returning the number of tokens that
+/// were repaired.
+pub fn repair_word_forms(rows: &mut [TokenRow]) -> usize {
+    let mut repaired_tokens = 0;
+    for row in rows.iter_mut() {
+        if let Some(fixed) = repair(&row.word_form) {
+            row.word_form = Cow::Owned(fixed);
+            repaired_tokens += 1;
+        }
+    }
+    repaired_tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{repair, repair_word_forms};
+    use crate::process_sentence::rows_from_lines;
+
+    #[test]
+    fn repairs_latin1() {
+        assert_eq!(repair("Ã¡hkku").as_deref(), Some("áhkku"));
+        assert_eq!(repair("Ã˜stre").as_deref(), Some("Østre"));
+    }
+
+    #[test]
+    fn repairs_cp1252() {
+        // š is C5 A1, č is C4 8D
+        assert_eq!(repair("Å¡addat").as_deref(), Some("šaddat"));
+        assert_eq!(
+            repair("\u{C4}\u{8D}\u{C3}\u{A1}lli").as_deref(),
+            Some("čálli")
+        );
+    }
+
+    #[test]
+    fn repairs_twice_encoded() {
+        assert_eq!(repair("ÃƒÂ¡hkku").as_deref(), Some("áhkku"));
+    }
+
+    #[test]
+    fn leaves_correct_text_alone() {
+        ["áhkku", "čálli", "Sääʹmǩiõll", "Å", "Ã", "24", ""]
+            .iter()
+            .for_each(|s| assert_eq!(repair(s), None, "{s}"));
+    }
+
+    #[test]
+    fn only_word_form_column() {
+        let mut rows =
+            rows_from_lines("Ã¡hkku\tÃ¡hkku\tN\tN.Sg.Nom\t1\tSUBJ\t0\nda\tda\tCC\tCC\t2\tCNP\t1\n");
+        assert_eq!(repair_word_forms(&mut rows), 1);
+        let words = rows.iter().map(|row| &*row.word_form).collect::<Vec<_>>();
+        assert_eq!(words, ["áhkku", "da"]);
+        assert_eq!(rows[0].lemma, "Ã¡hkku");
+    }
+}
@@ -0,0 +1,121 @@
+//! A canonical order of the tags of the msd, with `--canonical-msd`.
+//!
+//! The msd has the tags in the order the analyser wrote them, which can
+//! change between versions of the analyser, and then every token shows up
+//! in a diff of two builds of a corpus. With a canonical order, the tags
+//! are sorted by the feature they mark, in the order of [`FEATURE_ORDER`]:
+//! the part of speech first, then the subclass, valency, mood, tense,
+//! person, comparison, number, case, possessive suffix and clitics, and
+//! last the error, usage and semantic tags.
+//!
+//! Tags after a derivation tag (`Der/...`) describe the derived word, so
+//! they are sorted on their own, and the derivation tags stay where they
+//! are: `V TV Der/NomAg Nom N Sg` becomes `V TV Der/NomAg N Sg Nom`. Tags
+//! that are not in the table keep their order, after the known tags of
+//! their part.
+
+/// The features of the msd, in their canonical order, with the tags of
+/// each. Tags that end with `/` stand for all tags that start with them.
+pub const FEATURE_ORDER: [(&str, &[&str]); 14] = [
+    (
+        "pos",
+        &[
+            "N", "A", "V", "Adv", "Pron", "Num", "Po", "Pr", "Adp", "CC", "CS", "Det", "Interj",
+            "Pcle", "ABBR", "ACR", "CLB", "PUNCT",
+        ],
+    ),
+    (
+        "subclass",
+        &[
+            "Prop", "Pers", "Dem", "Interr", "Rel", "Refl", "Recipr", "Indef", "Coll", "Card",
+            "Ord", "Arab", "Rom",
+        ],
+    ),
+    ("valency", &["IV", "TV"]),
+    (
+        "mood",
+        &[
+            "Ind", "Imprt", "ImprtII", "Cond", "Pot", "Inf", "PrfPrc", "PrsPrc", "VGen", "VAbess",
+            "Ger", "Sup", "Actio",
+        ],
+    ),
+    ("tense", &["Prs", "Prt"]),
+    (
+        "person",
+        &[
+            "Sg1", "Sg2", "Sg3", "Du1", "Du2", "Du3", "Pl1", "Pl2", "Pl3", "Sg4", "Pl4", "ConNeg",
+        ],
+    ),
+    ("comparison", &["Comp", "Superl"]),
+    ("attributive", &["Attr"]),
+    ("number", &["Sg", "Du", "Pl"]),
+    (
+        "case",
+        &[
+            "Nom", "Acc", "Gen", "Ill", "Loc", "Com", "Ess", "Par", "Abe", "Ine", "Ela", "All",
+            "Ade", "Abl", "Tra", "Ter", "Dat", "Lat",
+        ],
+    ),
+    (
+        "possessive",
+        &[
+            "PxSg1", "PxSg2", "PxSg3", "PxDu1", "PxDu2", "PxDu3", "PxPl1", "PxPl2", "PxPl3",
+        ],
+    ),
+    ("clitic", &["Qst", "Foc/"]),
+    ("compound", &["Cmp/", "Cmp"]),
+    ("other", &["Err/", "Gram/", "Use/", "Sem/"]),
+];
+
+/// The prefix of the derivation tags, which the tags are not moved across.
+const DERIVATION_PREFIX: &str = "Der/";
+
+/// The place of `tag` in [`FEATURE_ORDER`], or `None` if it isn't in it.
+fn rank(tag: &str) -> Option<usize> {
+    FEATURE_ORDER.iter().position(|(_, tags)| {
+        tags.iter().any(|known| match known.ends_with('/') {
+            true => tag.starts_with(known),
+            false => tag == *known,
+        })
+    })
+}
+
+/// Sort `tags` in the canonical order, see the module documentation.
+pub fn sort(tags: &mut [&str]) {
+    for part in tags.split_mut(|tag| tag.starts_with(DERIVATION_PREFIX)) {
+        // a stable sort, so that unknown tags, and several tags of the same
+        // feature, keep their order
+        part.sort_by_key(|tag| rank(tag).unwrap_or(FEATURE_ORDER.len()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sort;
+
+    fn sorted(msd: &str) -> String {
+        let mut tags = msd.split('.').collect::<Vec<_>>();
+        sort(&mut tags);
+        tags.join(".")
+    }
+
+    #[test]
+    fn canonical() {
+        assert_eq!(sorted("N.Nom.Sg"), "N.Sg.Nom");
+        assert_eq!(sorted("V.Sg1.Prs.Ind.IV"), "V.IV.Ind.Prs.Sg1");
+        assert_eq!(sorted("N.Sem/Hum.Prop.Sg.Gen"), "N.Prop.Sg.Gen.Sem/Hum");
+        assert_eq!(sorted("N.PxSg1.Sg.Acc.Foc/ge"), "N.Sg.Acc.PxSg1.Foc/ge");
+    }
+
+    #[test]
+    fn derivations() {
+        assert_eq!(sorted("V.TV.Der/NomAg.Nom.N.Sg"), "V.TV.Der/NomAg.N.Sg.Nom");
+        assert_eq!(sorted("Der/PassL.V.Ind.Prt.Sg3"), "Der/PassL.V.Ind.Prt.Sg3");
+    }
+
+    #[test]
+    fn unknown_tags() {
+        assert_eq!(sorted("N.Xyz.Nom.Abc.Sg"), "N.Sg.Nom.Xyz.Abc");
+        assert_eq!(sorted(""), "");
+    }
+}
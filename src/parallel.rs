@@ -0,0 +1,140 @@
+//! The translations of each document, from the `<parallel_text>` elements
+//! of the header, with `--parallel-links`.
+//!
+//! The links are written per corpus, to a `parallel_links.tsv` file in the
+//! corpus directory, so that the documents can be aligned later, without
+//! reading all analysed headers again. Each line has the path of the
+//! analysed file, relative to the corpus directory, the language of the
+//! translation, and its location, as given in the header, separated by
+//! tabs:
+//!
+//! ```not_rust
+//! analysed/facta/sami_statistihkka_2018.pdf.xml  nob  sami_statistihkka_2018.pdf
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::analysed::file::ParallelText;
+use crate::korp_mono::path::corpus_dir;
+
+/// Name of the file, written to the corpus directory.
+pub const LINKS_FILE_NAME: &str = "parallel_links.tsv";
+
+/// A translation of a document: its language, and location.
+pub type Link = (String, String);
+
+#[derive(Debug, Default)]
+pub struct ParallelLinks {
+    /// The links of each analysed file, by corpus directory
+    corpora: BTreeMap<PathBuf, BTreeMap<PathBuf, Vec<Link>>>,
+}
+
+/// The links of the `<parallel_text>` elements `parallel_texts`. Elements
+/// without a language or a location are left out.
+pub fn links(parallel_texts: &[ParallelText]) -> Vec<Link> {
+    let clean = |value: &Option<String>| {
+        value
+            .as_deref()
+            .map(|v| v.replace(['\t', '\n', '\r'], " ").trim().to_string())
+            .filter(|v| !v.is_empty())
+    };
+    parallel_texts
+        .iter()
+        .filter_map(|text| Some((clean(&text.lang)?, clean(&text.location)?)))
+        .collect()
+}
+
+impl ParallelLinks {
+    /// Record the links of the analysed file `analysed_file`. Files without
+    /// links are not recorded.
+    pub fn add(&mut self, analysed_file: PathBuf, links: Vec<Link>) {
+        if links.is_empty() {
+            return;
+        }
+        let corpus = corpus_dir(&analysed_file)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        self.corpora
+            .entry(corpus)
+            .or_default()
+            .insert(analysed_file, links);
+    }
+
+    /// The links file of a single corpus.
+    fn links_file(corpus: &Path, files: &BTreeMap<PathBuf, Vec<Link>>) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        for (file, links) in files.iter() {
+            let file = file.strip_prefix(corpus).unwrap_or(file).display();
+            for (lang, location) in links {
+                writeln!(s, "{file}\t{lang}\t{location}").expect(w);
+            }
+        }
+        s
+    }
+
+    /// Write the links of each corpus to `{corpus}/parallel_links.tsv`.
+    pub fn write_files(&self) -> std::io::Result<()> {
+        for (corpus, files) in self.corpora.iter() {
+            std::fs::write(
+                corpus.join(LINKS_FILE_NAME),
+                Self::links_file(corpus, files),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ParallelLinks, links};
+    use crate::analysed::file::ParallelText;
+    use std::path::{Path, PathBuf};
+
+    fn parallel_text(lang: Option<&str>, location: Option<&str>) -> ParallelText {
+        ParallelText {
+            lang: lang.map(str::to_string),
+            location: location.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn links_of_header() {
+        let texts = [
+            parallel_text(Some("nob"), Some(" a.pdf ")),
+            parallel_text(Some("sma"), None),
+            parallel_text(None, Some("b.pdf")),
+            parallel_text(Some("fin"), Some("")),
+        ];
+        assert_eq!(links(&texts), [("nob".to_string(), "a.pdf".to_string())]);
+    }
+
+    #[test]
+    fn links_file() {
+        let corpus = Path::new("/c/corpus-sme");
+        let mut parallel = ParallelLinks::default();
+        parallel.add(
+            corpus.join("analysed/facta/b.xml"),
+            vec![
+                ("nob".to_string(), "b.pdf".to_string()),
+                ("sma".to_string(), "b_sma.pdf".to_string()),
+            ],
+        );
+        parallel.add(corpus.join("analysed/facta/c.xml"), vec![]);
+        parallel.add(
+            corpus.join("analysed/admin/a.xml"),
+            vec![("nob".to_string(), "a.html".to_string())],
+        );
+        let files = &parallel.corpora[&PathBuf::from(corpus)];
+        assert_eq!(
+            ParallelLinks::links_file(corpus, files),
+            concat!(
+                "analysed/admin/a.xml\tnob\ta.html\n",
+                "analysed/facta/b.xml\tnob\tb.pdf\n",
+                "analysed/facta/b.xml\tsma\tb_sma.pdf\n",
+            )
+        );
+    }
+}
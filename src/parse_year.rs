@@ -1,42 +1,149 @@
+use crate::date;
+
+const DOT: u8 = b'.';
+const DASH: u8 = b'-';
+const SLASH: u8 = b'/';
+
+macro_rules! d {
+    () => {
+        b'0'..=b'9'
+    };
+}
+
+fn output(year: &str, month: &str, day: &str) -> (String, String, String) {
+    (
+        format!("{year}-{month}-{day}"),
+        format!("{year}{month}{day}"),
+        format!("{year}{month}{day}"),
+    )
+}
+
+/// The (date, datefrom, dateto) of a text with unknown date.
+pub fn zero_output() -> (String, String, String) {
+    output("0000", "00", "00")
+}
+
+/// Is `yyyy`-`mm`-`dd` a day of the calendar, with the day in the month?
+/// The parts are known to consist only of digits.
+fn is_date(yyyy: &str, mm: &str, dd: &str) -> bool {
+    // SAFETY: Slices consists only of digits, so parsing them is ok
+    let year: i64 = unsafe { yyyy.parse().unwrap_unchecked() };
+    let month: u32 = unsafe { mm.parse().unwrap_unchecked() };
+    let day: u32 = unsafe { dd.parse().unwrap_unchecked() };
+    (1..=12).contains(&month) && (1..=date::days_in_month(year, month)).contains(&day)
+}
+
+/// Parse a date of the form `"MM.YYYY"` or `"AA.BB.YYYY"` into (year, month,
+/// day). See [`parse_year`] for how `AA` and `BB` are interpreted.
+fn dotted_date(date: &str) -> Option<(&str, &str, &str)> {
+    match date.as_bytes() {
+        // mm.yyyy
+        [d!(), d!(), DOT, d!(), d!(), d!(), d!()] => {
+            let mm = &date[0..2];
+            // SAFETY: Slice consists only of digits, so parsing it as u8 is ok
+            let mmu: u8 = unsafe { mm.parse().unwrap_unchecked() };
+            match mmu {
+                1..=12 => Some((&date[3..7], mm, "01")),
+                _ => None,
+            }
+        }
+        // (mm|dd).(mm|dd).yyyy
+        [d!(), d!(), DOT, d!(), d!(), DOT, d!(), d!(), d!(), d!()] => {
+            let dd = &date[0..2];
+            let mm = &date[3..5];
+            let year = &date[6..10];
+            // SAFETY: Slices consists only of digits, so parsing them as u8 is ok
+            let ddu: u8 = unsafe { dd.parse().unwrap_unchecked() };
+            let mmu: u8 = unsafe { mm.parse().unwrap_unchecked() };
+            let date = match (ddu, mmu) {
+                (0, _) | (_, 0) => None,                  // 0 as day or month is invalid
+                (1..=12, 1..=12) => Some((year, mm, dd)), // both in 01..=12, assume sane choice of dd.mm.yyyy
+                (1..=12, _) => Some((year, dd, mm)),
+                (_, 1..=12) => Some((year, mm, dd)),
+                (_, _) => None, // invalid: both over 12
+            };
+            // the day has to be in the month, so not 31.04.yyyy
+            date.filter(|(year, mm, dd)| is_date(year, mm, dd))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a single dotted date, or a range of two of them separated by a
+/// dash, such as `"MM.YYYY-MM.YYYY"` or `"DD.MM.YYYY-DD.MM.YYYY"`.
+fn dotted_range(year: &str) -> Option<(String, String, String)> {
+    let Some((from, to)) = year.split_once('-') else {
+        let (y, m, d) = dotted_date(year)?;
+        return Some(output(y, m, d));
+    };
+    let (fy, fm, fd) = dotted_date(from)?;
+    let (ty, tm, td) = dotted_date(to)?;
+    Some((
+        format!("{fy}-{fm}-{fd}"),
+        format!("{fy}{fm}{fd}"),
+        format!("{ty}{tm}{td}"),
+    ))
+}
+
+/// Qualifiers of uncertain years, like `"ca. 1998"` and `"2005?"`, that are
+/// left out before the year is parsed. Longer ones first, so that `"ca."` is
+/// not taken for `"ca"`.
+const PREFIXES: &[&str] = &[
+    "approx.", "approx", "circa", "cirka", "omkring", "ca.", "ca", "c.", "~",
+];
+const SUFFIXES: &[&str] = &["(?)", "?"];
+
+/// The `<year>` string without surrounding whitespace, brackets and the
+/// qualifiers of [`PREFIXES`] and [`SUFFIXES`], in any case.
+fn strip_qualifiers(year: &str) -> &str {
+    let mut year = year.trim();
+    loop {
+        let prefix = PREFIXES.iter().find(|p| {
+            year.get(..p.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(p))
+        });
+        let suffix = SUFFIXES.iter().find(|s| year.ends_with(**s));
+        let stripped = match (prefix, suffix) {
+            (Some(prefix), _) => &year[prefix.len()..],
+            (None, Some(suffix)) => &year[..year.len() - suffix.len()],
+            (None, None) => year
+                .strip_prefix('[')
+                .and_then(|y| y.strip_suffix(']'))
+                .or_else(|| year.strip_prefix('(').and_then(|y| y.strip_suffix(')')))
+                .unwrap_or(year),
+        };
+        if stripped.len() == year.len() {
+            return year;
+        }
+        year = stripped.trim();
+    }
+}
+
 /// Parse the `<year>` tag of the analysed xml into the (date, datefrom, dateto)
 /// fields expected in the korp_mono format.
 ///
-/// Three forms of `<year>` strings are recognized: `"YYYY"`, `"YYYY-YYYY"`, and
-/// `"AA.BB.YYYY"`. In the latter form, which of `AA` and `BB` is the month is not
-/// neccesarily known, but it will be parsed as expected when it is unambigous, or
-/// sanely when not (that is, if both `AA` and `BB` are between 1 and 12, it is
-/// recognized as `DD.MM.YYYY`.
+/// These forms of `<year>` strings are recognized: `"YYYY"`, `"YYYY-YYYY"`,
+/// `"YYYY-MM-DD"`, `"YYYY/MM/DD"`, `"MM.YYYY"` and `"AA.BB.YYYY"`. In the latter
+/// form, which of `AA` and `BB` is the month is not neccesarily known, but it will
+/// be parsed as expected when it is unambigous, or sanely when not (that is, if
+/// both `AA` and `BB` are between 1 and 12, it is recognized as `DD.MM.YYYY`.
+/// In the `"MM.YYYY"` form, the day is taken to be `01`. The two last forms can
+/// also be given as a range, e.g. `"MM.YYYY-MM.YYYY"`.
 ///
 /// The output form is (`YYYY-MM-DD`, `YYYYMMDD`, `YYYYMMDD`). The first is always
 /// the first date, with `MM-DD` being `01-01` if unknown. If a year range is given,
 /// the output is (`AAAA-01-01`, `AAAA0101`, `BBBB0101`), where `AAAA` is the first year,
-/// and `BBBB` is the last year.
+/// and `BBBB` is the last year. Ranges with month or day precision use the actual
+/// dates of the endpoints instead.
+///
+/// Whitespace around the date, brackets, and qualifiers of uncertain dates,
+/// like `"ca. 1998"`, `"circa 1998"`, `"[1998]"` and `"1998?"`, are left
+/// out first, so those are parsed as `"1998"`.
 pub fn parse_year(year: Option<&str>) -> (String, String, String) {
-    fn output(year: &str, month: &str, day: &str) -> (String, String, String) {
-        (
-            format!("{year}-{month}-{day}"),
-            format!("{year}{month}{day}"),
-            format!("{year}{month}{day}"),
-        )
-    }
-
-    fn zero_output() -> (String, String, String) {
-        output("0000", "00", "00")
-    }
-
-    let Some(year) = year else {
+    let Some(year) = year.map(strip_qualifiers) else {
         return zero_output();
     };
 
-    const DOT: u8 = b'.';
-    const DASH: u8 = b'-';
-
-    macro_rules! d {
-        () => {
-            b'0'..=b'9'
-        };
-    }
-
     match year.as_bytes() {
         // yyyy
         [d!(), d!(), d!(), d!()] => output(year, "01", "01"),
@@ -49,30 +156,105 @@ pub fn parse_year(year: Option<&str>) -> (String, String, String) {
                 format!("{}0101", &year[5..9]),
             )
         }
-        // (mm|dd).(mm|dd).yyyy
-        [d!(), d!(), DOT, d!(), d!(), DOT, d!(), d!(), d!(), d!()] => {
-            let dd = &year[0..2];
-            let mm = &year[3..5];
-            let year = &year[6..10];
-            // SAFETY: Slices consists only of digits, so parsing them as u8 is ok
-            let ddu: u8 = unsafe { dd.parse().unwrap_unchecked() };
-            let mmu: u8 = unsafe { mm.parse().unwrap_unchecked() };
-            match (ddu, mmu) {
-                (0, _) | (_, 0) => zero_output(), // 0 as day or month is invalid
-                (1..=12, 1..=12) => output(year, mm, dd), // both in 01..=12, assume sane choice of dd.mm.yyyy
-                (1..=12, 1..=31) => output(year, dd, mm),
-                (1..=31, 1..=12) => output(year, mm, dd),
-                (_, _) => zero_output(), // invalid: both over 12
+        // yyyy-mm-dd and yyyy/mm/dd
+        [d!(), d!(), d!(), d!(), DASH, d!(), d!(), DASH, d!(), d!()]
+        | [d!(), d!(), d!(), d!(), SLASH, d!(), d!(), SLASH, d!(), d!()] => {
+            let mm = &year[5..7];
+            let dd = &year[8..10];
+            match is_date(&year[0..4], mm, dd) {
+                true => output(&year[0..4], mm, dd),
+                false => zero_output(),
             }
         }
-        // unrecognized format
-        _ => zero_output(),
+        // mm.yyyy, (mm|dd).(mm|dd).yyyy, and ranges of those,
+        // or an unrecognized format
+        _ => dotted_range(year).unwrap_or_else(zero_output),
+    }
+}
+
+/// What to do with dates outside of the plausible range.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ImplausibleDatePolicy {
+    /// Keep the date, but warn about it.
+    #[default]
+    Keep,
+    /// Warn about the date, and replace it with the unknown date.
+    Zero,
+}
+
+/// The range of years that are plausible for a corpus text to be from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PlausibleYears {
+    pub first: u16,
+    pub last: u16,
+}
+
+impl PlausibleYears {
+    pub fn new(first: u16, last: u16) -> Self {
+        Self { first, last }
+    }
+
+    /// The current year, according to the system clock.
+    pub fn current_year() -> u16 {
+        const SECS_PER_YEAR: u64 = 31_556_952; // 365.2425 days
+        let secs = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        1970 + (secs / SECS_PER_YEAR) as u16
+    }
+
+    /// Given the `datefrom` and `dateto` fields (`YYYYMMDD`), as returned by
+    /// [`parse_year`], return the first year that is outside of the
+    /// plausible range, if any. Unknown dates (year `0000`) are not
+    /// considered implausible.
+    pub fn implausible_year(&self, datefrom: &str, dateto: &str) -> Option<u16> {
+        [datefrom, dateto]
+            .iter()
+            .filter_map(|date| date.get(0..4)?.parse::<u16>().ok())
+            .filter(|&year| year != 0)
+            .find(|year| !(self.first..=self.last).contains(year))
+    }
+}
+
+impl Default for PlausibleYears {
+    fn default() -> Self {
+        Self::new(1600, Self::current_year())
+    }
+}
+
+/// The years to select documents by, with `--year-from` and `--year-to`.
+/// Both ends are inclusive.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct YearRange {
+    pub from: Option<u16>,
+    pub to: Option<u16>,
+}
+
+impl YearRange {
+    pub fn new(from: Option<u16>, to: Option<u16>) -> Self {
+        Self { from, to }
+    }
+
+    pub fn is_unbounded(&self) -> bool {
+        self.from.is_none() && self.to.is_none()
+    }
+
+    /// Does a document from `datefrom` to `dateto` (`YYYYMMDD`, as returned
+    /// by [`parse_year`]) overlap the range? Documents with an unknown date
+    /// are only in the unbounded range.
+    pub fn overlaps(&self, datefrom: &str, dateto: &str) -> bool {
+        let year = |date: &str| date.get(0..4)?.parse::<u16>().ok().filter(|&y| y != 0);
+        let (Some(first), Some(last)) = (year(datefrom), year(dateto)) else {
+            return self.is_unbounded();
+        };
+        self.from.is_none_or(|from| last >= from) && self.to.is_none_or(|to| first <= to)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parse_year;
+    use super::{PlausibleYears, YearRange, parse_year};
 
     #[test]
     fn test_none() {
@@ -90,6 +272,32 @@ mod tests {
             ("02.02.2025", "2025-02-02", "20250202", "20250202"),
             ("15.02.2025", "2025-02-15", "20250215", "20250215"),
             ("02.15.2025", "2025-02-15", "20250215", "20250215"),
+            ("2025-02-15", "2025-02-15", "20250215", "20250215"),
+            ("2025/02/15", "2025-02-15", "20250215", "20250215"),
+            ("2020-02-29", "2020-02-29", "20200229", "20200229"),
+            ("02.2025", "2025-02-01", "20250201", "20250201"),
+            ("12.1999", "1999-12-01", "19991201", "19991201"),
+            ("03.2001-11.2001", "2001-03-01", "20010301", "20011101"),
+            (
+                "15.03.2001-02.04.2002",
+                "2001-03-15",
+                "20010315",
+                "20020402",
+            ),
+            (" 2012 ", "2012-01-01", "20120101", "20120101"),
+            ("\t2012\n", "2012-01-01", "20120101", "20120101"),
+            ("ca. 1998", "1998-01-01", "19980101", "19980101"),
+            ("Ca 1998", "1998-01-01", "19980101", "19980101"),
+            ("c.1998", "1998-01-01", "19980101", "19980101"),
+            ("circa 1998-2000", "1998-01-01", "19980101", "20000101"),
+            ("omkring 1998", "1998-01-01", "19980101", "19980101"),
+            ("approx. 1998", "1998-01-01", "19980101", "19980101"),
+            ("~1998", "1998-01-01", "19980101", "19980101"),
+            ("2005?", "2005-01-01", "20050101", "20050101"),
+            ("2005 (?)", "2005-01-01", "20050101", "20050101"),
+            ("[2005]", "2005-01-01", "20050101", "20050101"),
+            ("[ca. 2005?]", "2005-01-01", "20050101", "20050101"),
+            ("ca. 02.2025", "2025-02-01", "20250201", "20250201"),
         ]
         .iter()
         .for_each(|(input, out1, out2, out3)| {
@@ -106,6 +314,12 @@ mod tests {
         [
             "",
             "?",
+            " ",
+            "ca.",
+            "[]",
+            "ca. unknown",
+            "1998 ca",
+            "2005??x",
             "unknown",
             "999",
             "10000",
@@ -116,6 +330,25 @@ mod tests {
             "32.05.2000",
             "32.32.2015",
             "06.06.999",
+            "2025-13-01",
+            "2025-02-32",
+            "2025-02-31",
+            "2019-02-30",
+            "2019-02-29",
+            "2025-00-10",
+            "2025/02-15",
+            "2025-2-15",
+            "13.2025",
+            "00.2025",
+            "2.2025",
+            "03.2001-",
+            "03.2001-13.2001",
+            "03.2001-2001",
+            "32.03.2001-01.04.2001",
+            "31.04.2020",
+            "04.31.2020",
+            "29.02.2023",
+            "01.01.2001-30.02.2001",
         ]
         .iter()
         .for_each(|input| {
@@ -126,4 +359,40 @@ mod tests {
             );
         });
     }
+
+    #[test]
+    fn plausible_years() {
+        let years = PlausibleYears::new(1600, 2025);
+        [
+            ("1998", None),
+            ("1600-2025", None),
+            ("0001", Some(1)),
+            ("9999", Some(9999)),
+            ("1599-2000", Some(1599)),
+            ("2000-2026", Some(2026)),
+            ("unknown", None),
+        ]
+        .iter()
+        .for_each(|(input, expected)| {
+            let (_, datefrom, dateto) = parse_year(Some(input));
+            assert_eq!(
+                years.implausible_year(&datefrom, &dateto),
+                *expected,
+                "{input}"
+            );
+        });
+    }
+
+    #[test]
+    fn year_ranges() {
+        let range = YearRange::new(Some(2000), Some(2010));
+        assert!(range.overlaps("20050101", "20050101"));
+        assert!(range.overlaps("19950101", "20000101"));
+        assert!(range.overlaps("20100101", "20200101"));
+        assert!(!range.overlaps("19990101", "19990101"));
+        assert!(!range.overlaps("20110101", "20110101"));
+        assert!(!range.overlaps("00000000", "00000000"));
+        assert!(YearRange::new(Some(2000), None).overlaps("30000101", "30000101"));
+        assert!(YearRange::default().overlaps("00000000", "00000000"));
+    }
 }
@@ -0,0 +1,167 @@
+//! Select which analysed files to convert, with `--include` and `--exclude`
+//! glob patterns.
+//!
+//! The patterns are matched against the path of the file inside the
+//! `analysed/` directory, so `admin/sd/*2023*.xml` selects the files of 2023
+//! in `analysed/admin/sd/`, in every corpus. Files that are not inside an
+//! `analysed/` directory are matched by their full path.
+//!
+//! The patterns support `*` (anything but `/`), `?` (one character that is
+//! not `/`), and `**` (anything, including `/`). `**/` also matches no
+//! directory at all, so `**/*.xml` matches `a.xml`, too.
+
+use std::path::{Component, Path};
+
+use crate::korp_mono::path::ANALYSED_DIR;
+use crate::shard::Shard;
+
+fn glob_match_chars(pattern: &[char], s: &[char]) -> bool {
+    match pattern {
+        [] => s.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            glob_match_chars(rest, s)
+                || (0..s.len()).any(|i| s[i] == '/' && glob_match_chars(rest, &s[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=s.len()).any(|i| glob_match_chars(rest, &s[i..])),
+        ['*', rest @ ..] => (0..=s.len())
+            .take_while(|&i| i == 0 || s[i - 1] != '/')
+            .any(|i| glob_match_chars(rest, &s[i..])),
+        ['?', rest @ ..] => {
+            matches!(s.first(), Some(c) if *c != '/') && glob_match_chars(rest, &s[1..])
+        }
+        [c, rest @ ..] => s.first() == Some(c) && glob_match_chars(rest, &s[1..]),
+    }
+}
+
+/// Does the glob `pattern` match all of `s`?
+pub fn glob_match(pattern: &str, s: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let s = s.chars().collect::<Vec<_>>();
+    glob_match_chars(&pattern, &s)
+}
+
+/// `path` with `/` as separator, also on Windows, so that the same patterns
+/// work everywhere. Components that are not valid UTF-8 are converted
+/// lossily, they can still be matched with `*` and `?`.
+fn slash_separated(path: &Path) -> String {
+    let mut s = String::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(prefix) => s.push_str(&prefix.as_os_str().to_string_lossy()),
+            Component::RootDir => s.push('/'),
+            component => {
+                if !s.is_empty() && !s.ends_with('/') {
+                    s.push('/');
+                }
+                s.push_str(&component.as_os_str().to_string_lossy());
+            }
+        }
+    }
+    s
+}
+
+/// The part of `path` that is inside the `analysed/` directory, with `/` as
+/// separator. The full path, if it is not inside an `analysed/` directory.
+pub(crate) fn analysed_relative(path: &Path) -> String {
+    let relative = path
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| name == ANALYSED_DIR))
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path);
+    slash_separated(relative)
+}
+
+/// Include and exclude patterns, and the shard of `--shard`. With no
+/// patterns, and no shard, every file is accepted.
+#[derive(Debug, Default, Clone)]
+pub struct PathFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub shard: Option<Shard>,
+}
+
+impl PathFilter {
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self {
+            include,
+            exclude,
+            shard: None,
+        }
+    }
+
+    pub fn with_shard(mut self, shard: Option<Shard>) -> Self {
+        self.shard = shard;
+        self
+    }
+
+    /// Should the analysed file `path` be converted? It must match one of the
+    /// include patterns, if there are any, and none of the exclude patterns,
+    /// and be in the shard, if there is one.
+    pub fn accepts(&self, path: &Path) -> bool {
+        if self.shard.is_some_and(|shard| !shard.contains(path)) {
+            return false;
+        }
+        if self.include.is_empty() && self.exclude.is_empty() {
+            return true;
+        }
+        let relative = analysed_relative(path);
+        let matches = |pattern: &String| glob_match(pattern, &relative);
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PathFilter, analysed_relative, glob_match};
+    use std::path::Path;
+
+    #[test]
+    fn globs() {
+        assert!(glob_match("admin/sd/*2023*.xml", "admin/sd/dok_2023_1.xml"));
+        assert!(!glob_match(
+            "admin/sd/*2023*.xml",
+            "admin/sd/x/dok_2023.xml"
+        ));
+        assert!(!glob_match("admin/sd/*2023*.xml", "admin/sd/dok_2022.xml"));
+        assert!(glob_match("admin/**/*.xml", "admin/sd/x/a.xml"));
+        assert!(glob_match("admin/**/*.xml", "admin/a.xml"));
+        assert!(glob_match("**", "a/b/c"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(!glob_match("a?c", "a/c"));
+        assert!(!glob_match("a", "ab"));
+    }
+
+    #[test]
+    fn relative_paths() {
+        assert_eq!(
+            analysed_relative(Path::new("/c/corpus-sme/analysed/admin/a.xml")),
+            "admin/a.xml"
+        );
+        assert_eq!(analysed_relative(Path::new("/tmp/a.xml")), "/tmp/a.xml");
+        assert_eq!(analysed_relative(Path::new("tmp/a.xml")), "tmp/a.xml");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_path() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let name = OsStr::from_bytes(b"\xe1hkku.xml");
+        let path = Path::new("/c/corpus-sme/analysed/news").join(name);
+        assert_eq!(analysed_relative(&path), "news/\u{FFFD}hkku.xml");
+        let filter = PathFilter::new(vec!["news/*hkku.xml".to_string()], vec![]);
+        assert!(filter.accepts(&path));
+    }
+
+    #[test]
+    fn include_and_exclude() {
+        let filter = PathFilter::new(vec!["admin/**".to_string()], vec!["**/*2023*".to_string()]);
+        let accepts = |p: &str| filter.accepts(Path::new(p));
+        assert!(accepts("/c/corpus-sme/analysed/admin/sd/a.xml"));
+        assert!(!accepts("/c/corpus-sme/analysed/admin/sd/a_2023.xml"));
+        assert!(!accepts("/c/corpus-sme/analysed/news/a.xml"));
+        assert!(PathFilter::default().accepts(Path::new("/anything")));
+    }
+}
@@ -0,0 +1,231 @@
+//! Keeping the memory of the files that are converted at the same time
+//! within a budget, with `--memory-budget 8G`.
+//!
+//! Every rayon worker converts a file of its own, and a few large files at
+//! the same time can use up all memory. Each file is therefore admitted to
+//! the [`MemoryBudget`] before it is read, with an estimate of the memory it
+//! takes: its size times `--memory-factor`. When the estimates of the files
+//! in flight, and of the next one, don't fit in the budget, the next file
+//! waits until enough files are done. A file is always admitted when no
+//! other file is in flight, so that a file that is larger than the whole
+//! budget is converted on its own, instead of never.
+//!
+//! The files are admitted by [`Admitted`], which wraps the read stage, and
+//! released with [`MemoryBudget::release`] when they are done. With
+//! `--write-threads`, a file is done when it is handed to the writers, so a
+//! few more documents can wait in their queue.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use gtcorpusutil::AnalysedFilePath;
+
+use super::Stage;
+
+#[derive(Debug, Default)]
+struct InFlight {
+    /// The estimates of the files in flight, by path
+    files: HashMap<PathBuf, u64>,
+    bytes: u64,
+    peak: u64,
+    /// The number of files that had to wait, and for how long in total
+    waited: u64,
+    waiting: Duration,
+}
+
+#[derive(Debug)]
+pub struct MemoryBudget {
+    limit: u64,
+    factor: u64,
+    in_flight: Mutex<InFlight>,
+    released: Condvar,
+}
+
+impl MemoryBudget {
+    /// A budget of `limit` bytes, for files that take `factor` times their
+    /// size.
+    pub fn new(limit: u64, factor: u64) -> Self {
+        Self {
+            limit,
+            factor,
+            in_flight: Mutex::new(InFlight::default()),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Wait until the file `path`, of `size` bytes, fits in the budget, and
+    /// count it as in flight until it is released.
+    pub fn admit(&self, path: &Path, size: u64) {
+        let estimate = size.saturating_mul(self.factor);
+        let started = Instant::now();
+        let too_much = |in_flight: &mut InFlight| {
+            in_flight.bytes > 0 && in_flight.bytes.saturating_add(estimate) > self.limit
+        };
+        let in_flight = self
+            .in_flight
+            .lock()
+            .expect("no thread panics while holding the lock");
+        let mut in_flight = self
+            .released
+            .wait_while(in_flight, too_much)
+            .expect("no thread panics while holding the lock");
+        let waited = started.elapsed();
+        if waited > Duration::from_millis(1) {
+            in_flight.waited += 1;
+            in_flight.waiting += waited;
+            tracing::debug!(file = ?path, ?waited, "waited for memory");
+        }
+        *in_flight.files.entry(path.to_path_buf()).or_default() += estimate;
+        in_flight.bytes += estimate;
+        in_flight.peak = in_flight.peak.max(in_flight.bytes);
+    }
+
+    /// The file `path` is done, and its memory can be used by the next.
+    pub fn release(&self, path: &Path) {
+        let mut in_flight = self
+            .in_flight
+            .lock()
+            .expect("no thread panics while holding the lock");
+        if let Some(estimate) = in_flight.files.remove(path) {
+            in_flight.bytes -= estimate;
+            self.released.notify_all();
+        }
+    }
+
+    /// A summary for the end of the run.
+    pub fn report(&self) -> String {
+        let in_flight = self
+            .in_flight
+            .lock()
+            .expect("no thread panics while holding the lock");
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(
+            s,
+            "memory budget {}, at most {} estimated in flight",
+            format_size(self.limit),
+            format_size(in_flight.peak)
+        )
+        .expect(w);
+        writeln!(
+            s,
+            "{} files waited for memory, for {:.1} s in total",
+            in_flight.waited,
+            in_flight.waiting.as_secs_f64()
+        )
+        .expect(w);
+        s
+    }
+}
+
+const UNITS: [(&str, u64); 4] = [
+    ("T", 1 << 40),
+    ("G", 1 << 30),
+    ("M", 1 << 20),
+    ("K", 1 << 10),
+];
+
+/// The size of `--memory-budget`, in bytes: a number, optionally followed by
+/// `K`, `M`, `G` or `T` (powers of 1024), like `8G` or `512M`.
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let trimmed = s.trim().trim_end_matches(['B', 'b']);
+    let (number, unit) = UNITS
+        .iter()
+        .find_map(|(suffix, unit)| {
+            let number = trimmed
+                .strip_suffix(suffix)
+                .or_else(|| trimmed.strip_suffix(&suffix.to_lowercase()))?;
+            Some((number, *unit))
+        })
+        .unwrap_or((trimmed, 1));
+    match number.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 => Ok((n * unit as f64) as u64),
+        _ => Err(format!("'{s}' is not a size, like 8G or 512M")),
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    match UNITS.iter().find(|(_, unit)| bytes >= *unit) {
+        Some((suffix, unit)) => format!("{:.1}{suffix}", bytes as f64 / *unit as f64),
+        None => format!("{bytes}B"),
+    }
+}
+
+/// Wraps the read stage, and admits each file to the budget, if there is
+/// one, before it is read. Files that can't be read are released right
+/// away.
+pub struct Admitted<S> {
+    stage: S,
+    budget: Option<Arc<MemoryBudget>>,
+}
+
+impl<S> Admitted<S> {
+    pub fn new(stage: S, budget: Option<Arc<MemoryBudget>>) -> Self {
+        Self { stage, budget }
+    }
+}
+
+impl<S: Stage<Input = AnalysedFilePath>> Stage for Admitted<S> {
+    type Input = S::Input;
+    type Output = S::Output;
+
+    fn name(&self) -> &'static str {
+        self.stage.name()
+    }
+
+    fn run(&self, input: Self::Input) -> Option<Self::Output> {
+        let Some(budget) = &self.budget else {
+            return self.stage.run(input);
+        };
+        let path = input.to_path_buf();
+        let size = std::fs::metadata(&path)
+            .map(|meta| meta.len())
+            .unwrap_or_default();
+        budget.admit(&path, size);
+        let output = self.stage.run(input);
+        if output.is_none() {
+            budget.release(&path);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryBudget, parse_size};
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn sizes() {
+        assert_eq!(parse_size("8G"), Ok(8 << 30));
+        assert_eq!(parse_size("512m"), Ok(512 << 20));
+        assert_eq!(parse_size("1.5KB"), Ok(1536));
+        assert_eq!(parse_size("100"), Ok(100));
+        assert!(parse_size("G").is_err());
+        assert!(parse_size("-1G").is_err());
+    }
+
+    #[test]
+    fn admission() {
+        let budget = Arc::new(MemoryBudget::new(100, 10));
+        // larger than the budget, but nothing else is in flight
+        budget.admit(Path::new("a"), 20);
+        let waiter = {
+            let budget = Arc::clone(&budget);
+            std::thread::spawn(move || budget.admit(Path::new("b"), 5))
+        };
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+        budget.release(Path::new("a"));
+        waiter.join().unwrap();
+        budget.release(Path::new("b"));
+        let report = budget.report();
+        assert!(report.contains("at most 200B"), "{report}");
+        assert!(report.contains("1 files waited"), "{report}");
+    }
+}
@@ -0,0 +1,96 @@
+//! Reusing the buffers that the analysed files are read into.
+//!
+//! Every file is read into a `String` by [`ReadFile`](super::stages::ReadFile),
+//! which lives until [`ParseXml`](super::stages::ParseXml) has deserialized
+//! it. With a fresh `String` for each file, each read grows a buffer from
+//! nothing to the size of the file, and frees it again, which with many
+//! small files is a large part of the allocations of a run. Instead, the
+//! buffers are given back to [`READ_BUFFERS`] once the xml is parsed, and
+//! taken from there by the next read, keeping their capacity.
+//!
+//! The files are read on other threads than they are parsed on (with
+//! `--threads-io`), so the buffers are kept in one pool shared by all
+//! threads, rather than per thread.
+
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Buffers larger than this are not kept, so that a few huge files don't
+/// keep their memory for the rest of the run.
+const MAX_KEPT_CAPACITY: usize = 16 * 1024 * 1024;
+
+/// The pool of the buffers of the analysed files.
+pub static READ_BUFFERS: BufferPool = BufferPool::new(64);
+
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<String>>,
+    /// How many buffers are kept at most
+    max: AtomicUsize,
+}
+
+impl BufferPool {
+    pub const fn new(max: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            max: AtomicUsize::new(max),
+        }
+    }
+
+    /// Keep at most `max` buffers. With 0, no buffers are reused.
+    pub fn set_max(&self, max: usize) {
+        self.max.store(max, Ordering::Relaxed);
+        let mut buffers = self
+            .buffers
+            .lock()
+            .expect("no thread panics while holding the lock");
+        buffers.truncate(max);
+    }
+
+    /// An empty buffer, with the capacity of an earlier one if there is one.
+    pub fn take(&self) -> String {
+        self.buffers
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .pop()
+            .unwrap_or_default()
+    }
+
+    /// Give `buffer` back, to be reused by [`BufferPool::take`].
+    pub fn give(&self, mut buffer: String) {
+        if buffer.capacity() > MAX_KEPT_CAPACITY {
+            return;
+        }
+        buffer.clear();
+        let mut buffers = self
+            .buffers
+            .lock()
+            .expect("no thread panics while holding the lock");
+        if buffers.len() < self.max.load(Ordering::Relaxed) {
+            buffers.push(buffer);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BufferPool;
+
+    #[test]
+    fn reuse() {
+        let pool = BufferPool::new(1);
+        let mut buffer = pool.take();
+        buffer.push_str("<document/>");
+        let capacity = buffer.capacity();
+        pool.give(buffer);
+        pool.give(String::with_capacity(100));
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.capacity(), capacity);
+        assert_eq!(pool.take().capacity(), 0);
+
+        pool.set_max(0);
+        pool.give(String::with_capacity(100));
+        assert_eq!(pool.take().capacity(), 0);
+    }
+}
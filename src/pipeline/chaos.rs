@@ -0,0 +1,147 @@
+//! Failing stages at random, with the hidden `--chaos P` flag, to see that
+//! the failures are counted and reported as they should be, without having
+//! to find files that fail in each stage.
+//!
+//! Each stage that is added to a [`Pipeline`](super::Pipeline) after
+//! [`with_chaos`](super::Pipeline::with_chaos) fails with probability `P`,
+//! before it runs, and logs the failure as an error of the category
+//! `other`, like a stage that failed on its own.
+//!
+//! With `--seed`, the same numbers are drawn in every run. Which stage of
+//! which file gets each of them still depends on the order the threads take
+//! the files in, so only a run on a single thread (`RAYON_NUM_THREADS=1`)
+//! fails exactly the same files again.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::Stage;
+use crate::status_message::ErrorCategory;
+
+/// A source of random failures, shared by all stages and threads.
+#[derive(Debug)]
+pub struct Chaos {
+    probability: f64,
+    seed: u64,
+    state: AtomicU64,
+}
+
+impl Chaos {
+    /// Fail with `probability`, seeded from the clock.
+    pub fn new(probability: f64) -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|since| since.as_nanos() as u64)
+            .unwrap_or_default();
+        Self::with_seed(probability, seed)
+    }
+
+    /// Fail with `probability`, drawing the same numbers as every other
+    /// `Chaos` with the same `seed`, as with `--seed`.
+    pub fn with_seed(probability: f64, seed: u64) -> Self {
+        Self {
+            probability,
+            seed,
+            state: AtomicU64::new(seed),
+        }
+    }
+
+    /// The seed, to repeat a run with `--seed`.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// The next random number in `[0, 1)`, by splitmix64.
+    fn next(&self) -> f64 {
+        let mut x = self
+            .state
+            .fetch_add(0x9e37_79b9_7f4a_7c15, Ordering::Relaxed)
+            .wrapping_add(0x9e37_79b9_7f4a_7c15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^= x >> 31;
+        (x >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Should the next stage fail?
+    pub fn strikes(&self) -> bool {
+        self.next() < self.probability
+    }
+}
+
+/// The probability of `--chaos`, a number from 0 to 1.
+pub fn parse_probability(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(p) if (0.0..=1.0).contains(&p) => Ok(p),
+        _ => Err(format!("'{s}' is not a probability from 0 to 1")),
+    }
+}
+
+/// Wraps a stage, and fails it at random if there is [`Chaos`].
+pub struct Chaotic<S> {
+    stage: S,
+    chaos: Option<Arc<Chaos>>,
+}
+
+impl<S: Stage> Chaotic<S> {
+    pub fn new(stage: S, chaos: Option<Arc<Chaos>>) -> Self {
+        Self { stage, chaos }
+    }
+}
+
+impl<S: Stage> Stage for Chaotic<S> {
+    type Input = S::Input;
+    type Output = S::Output;
+
+    fn name(&self) -> &'static str {
+        self.stage.name()
+    }
+
+    fn run(&self, input: Self::Input) -> Option<Self::Output> {
+        if self.chaos.as_ref().is_some_and(|chaos| chaos.strikes()) {
+            tracing::error!(
+                stage = self.stage.name(),
+                category = ErrorCategory::Other.as_str(),
+                "injected failure (--chaos)"
+            );
+            return None;
+        }
+        self.stage.run(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Chaos, parse_probability};
+
+    #[test]
+    fn probability() {
+        let never = Chaos::with_seed(0.0, 1);
+        assert!((0..1000).all(|_| !never.strikes()));
+        let always = Chaos::with_seed(1.0, 1);
+        assert!((0..1000).all(|_| always.strikes()));
+        let half = Chaos::with_seed(0.5, 1);
+        let struck = (0..10_000).filter(|_| half.strikes()).count();
+        assert!((4500..5500).contains(&struck), "{struck}");
+
+        // the same seed, the same failures
+        let draws = |chaos: Chaos| (0..100).map(|_| chaos.strikes()).collect::<Vec<_>>();
+        assert_eq!(
+            draws(Chaos::with_seed(0.5, 7)),
+            draws(Chaos::with_seed(0.5, 7))
+        );
+        assert_ne!(
+            draws(Chaos::with_seed(0.5, 7)),
+            draws(Chaos::with_seed(0.5, 8))
+        );
+        assert_eq!(Chaos::with_seed(0.5, 7).seed(), 7);
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(parse_probability("0.25"), Ok(0.25));
+        assert!(parse_probability("1.5").is_err());
+        assert!(parse_probability("-0.1").is_err());
+        assert!(parse_probability("some").is_err());
+    }
+}
@@ -0,0 +1,250 @@
+//! The conversion pipeline.
+//!
+//! Converting one analysed file into one korp_mono file happens in a number
+//! of steps: read the file, parse the xml, parse the analyses, convert to the
+//! korp_mono format, and write the result. Each step is a [`Stage`], and a
+//! [`Pipeline`] chains stages together, where the output type of one stage
+//! must be the input type of the next.
+//!
+//! The default stages are found in [`stages`]. Custom stages, like filtering
+//! tokens out of the converted document, can be added in between them by
+//! implementing [`Stage`], typically with [`stages::Converted`] as both input
+//! and output:
+//!
+//! ```ignore
+//! let pipeline = Pipeline::new()
+//!     .then(stages::ReadFile)
+//!     .then(stages::ParseXml::default())
+//!     .then(stages::ParseAnalyses::default())
+//!     .then(stages::Convert::default())
+//!     .then(MyTokenFilter)
+//!     .then(stages::WriteKorpMono::default());
+//! ```
+
+pub mod budget;
+pub mod buffers;
+pub mod chaos;
+pub mod pool;
+pub mod stages;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+use gtcorpusutil::AnalysedFilePath;
+
+use self::chaos::{Chaos, Chaotic};
+use crate::metrics::Metrics;
+use crate::process_sentence;
+use crate::profile::Profile;
+
+/// One step of the pipeline. Returns `None` when the input could not be
+/// processed, in which case the rest of the pipeline is skipped for that
+/// file. Stages are responsible for reporting their own errors.
+pub trait Stage: Send + Sync {
+    type Input;
+    type Output;
+
+    /// A short name of the stage, used in logging.
+    fn name(&self) -> &'static str;
+
+    fn run(&self, input: Self::Input) -> Option<Self::Output>;
+}
+
+impl<S: Stage + ?Sized> Stage for Box<S> {
+    type Input = S::Input;
+    type Output = S::Output;
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+
+    fn run(&self, input: Self::Input) -> Option<Self::Output> {
+        (**self).run(input)
+    }
+}
+
+/// Wraps a stage, and records how long it takes, and how often it fails, in
+/// [`Metrics`], and each of its runs in a [`Profile`], if there is one.
+pub struct Measured<S> {
+    stage: S,
+    metrics: Arc<Metrics>,
+    profile: Option<Arc<Profile>>,
+}
+
+impl<S: Stage> Measured<S> {
+    pub fn new(stage: S, metrics: Arc<Metrics>) -> Self {
+        Self {
+            stage,
+            metrics,
+            profile: None,
+        }
+    }
+
+    pub fn with_profile(self, profile: Option<Arc<Profile>>) -> Self {
+        Self { profile, ..self }
+    }
+}
+
+impl<S: Stage> Stage for Measured<S> {
+    type Input = S::Input;
+    type Output = S::Output;
+
+    fn name(&self) -> &'static str {
+        self.stage.name()
+    }
+
+    fn run(&self, input: Self::Input) -> Option<Self::Output> {
+        let started = Instant::now();
+        let (duration, output) = timed(|| self.stage.run(input));
+        self.metrics
+            .record_stage(self.stage.name(), duration, output.is_none());
+        if let Some(profile) = &self.profile {
+            profile.record_stage(self.stage.name(), started, duration);
+        }
+        output
+    }
+}
+
+/// A chain of stages, taking an `I` and producing an `O`.
+pub struct Pipeline<I, O> {
+    names: Vec<&'static str>,
+    run: Box<dyn Fn(I) -> Option<O> + Send + Sync>,
+    metrics: Option<Arc<Metrics>>,
+    profile: Option<Arc<Profile>>,
+    chaos: Option<Arc<Chaos>>,
+}
+
+impl<I: 'static> Pipeline<I, I> {
+    /// An empty pipeline, which returns its input as is.
+    pub fn new() -> Self {
+        Self {
+            names: vec![],
+            run: Box::new(Some),
+            metrics: None,
+            profile: None,
+            chaos: None,
+        }
+    }
+}
+
+impl<I: 'static> Default for Pipeline<I, I> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<I: 'static, O: 'static> Pipeline<I, O> {
+    /// Add `stage` to the end of the pipeline.
+    pub fn then<S>(self, stage: S) -> Pipeline<I, S::Output>
+    where
+        S: Stage<Input = O> + 'static,
+    {
+        let Self {
+            mut names,
+            run,
+            metrics,
+            profile,
+            chaos,
+        } = self;
+        names.push(stage.name());
+        let stage = Chaotic::new(stage, chaos.clone());
+        let run: Box<dyn Fn(I) -> Option<S::Output> + Send + Sync> = match &metrics {
+            Some(metrics) => {
+                let stage = Measured::new(stage, Arc::clone(metrics)).with_profile(profile.clone());
+                Box::new(move |input| run(input).and_then(|x| stage.run(x)))
+            }
+            None => Box::new(move |input| run(input).and_then(|x| stage.run(x))),
+        };
+        Pipeline {
+            names,
+            run,
+            metrics,
+            profile,
+            chaos,
+        }
+    }
+
+    /// Record the time and failures of the stages that are added after this,
+    /// in `metrics`.
+    pub fn with_metrics(self, metrics: Option<Arc<Metrics>>) -> Self {
+        Self { metrics, ..self }
+    }
+
+    /// Also record each run of the stages that are added after this in
+    /// `profile`. Only the stages that are measured, see
+    /// [`Pipeline::with_metrics`], are recorded.
+    pub fn with_profile(self, profile: Option<Arc<Profile>>) -> Self {
+        Self { profile, ..self }
+    }
+
+    /// Fail the stages that are added after this at random, see [`chaos`].
+    pub fn with_chaos(self, chaos: Option<Arc<Chaos>>) -> Self {
+        Self { chaos, ..self }
+    }
+
+    /// Add `stage` to the end of the pipeline, but only if `enabled` is true.
+    /// Useful for optional stages, that are turned on by command line flags.
+    pub fn then_if<S>(self, enabled: bool, stage: S) -> Self
+    where
+        S: Stage<Input = O, Output = O> + 'static,
+    {
+        match enabled {
+            true => self.then(stage),
+            false => self,
+        }
+    }
+
+    /// Add `stage` to the end of the pipeline, if there is one. For optional
+    /// stages that need a value from the command line to be created.
+    pub fn then_if_some<S>(self, stage: Option<S>) -> Self
+    where
+        S: Stage<Input = O, Output = O> + 'static,
+    {
+        match stage {
+            Some(stage) => self.then(stage),
+            None => self,
+        }
+    }
+
+    /// The names of the stages in the pipeline, in order.
+    pub fn stage_names(&self) -> &[&'static str] {
+        &self.names
+    }
+
+    /// Run `input` through all stages of the pipeline.
+    pub fn run(&self, input: I) -> Option<O> {
+        (self.run)(input)
+    }
+}
+
+/// Convert the analysed document `input`, parsed with `parse` and converted
+/// with `options`, without reading or writing any files, and return the
+/// korp_mono xml. `None` if it could not be converted, the errors are
+/// logged by the stages.
+pub fn convert_document(
+    input: String,
+    parse: stages::ParseAnalyses,
+    options: process_sentence::Options,
+) -> Option<String> {
+    let columns = options.columns().into_owned();
+    let pipeline = Pipeline::new()
+        .then(stages::ParseXml::default())
+        .then(parse)
+        .then(stages::Convert::new(options));
+    let path = AnalysedFilePath::new_unchecked(PathBuf::from("<string>"));
+    let (_, korp_mono_file) = pipeline.run((path, input))?;
+    let mut output = vec![];
+    stages::write_parts(&mut output, &[korp_mono_file], &columns).ok()?;
+    String::from_utf8(output).ok()
+}
+
+#[inline(always)]
+pub fn timed<F, R>(f: F) -> (std::time::Duration, R)
+where
+    F: FnOnce() -> R,
+{
+    let t0 = Instant::now();
+    let result = f();
+    (t0.elapsed(), result)
+}
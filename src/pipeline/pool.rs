@@ -0,0 +1,198 @@
+//! Running a stage on a pool of dedicated threads.
+//!
+//! The stages of a [`Pipeline`](super::Pipeline) run on the rayon workers,
+//! one file at a time. A stage that mostly waits on IO, like writing to a
+//! slow network file system, then holds up a worker that could have been
+//! parsing. A [`StagePool`] instead runs the stage on its own threads, fed
+//! through a bounded queue. The rest of the pipeline hands its output over
+//! with a [`PoolSender`], which only blocks when the queue is full, so that
+//! finished documents don't pile up in memory.
+//!
+//! A [`SourcePool`] is the same thing at the start of the pipeline: it runs
+//! a stage, like reading the files, on its own threads, and hands the outputs
+//! to the rest of the pipeline through a bounded queue. The readers can then
+//! run ahead of the parsing, but never more than the length of the queue.
+//!
+//! The inputs of a [`StagePool`] are run in the span they were sent in, so
+//! that the messages of the stage have the same context, see
+//! [`crate::log_context`].
+
+use std::sync::mpsc::{Receiver, SyncSender, sync_channel};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use tracing::Span;
+
+use super::Stage;
+
+pub struct StagePool<I> {
+    sender: SyncSender<(Span, I)>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl<I: Send + 'static> StagePool<I> {
+    /// Start `threads` threads that run `stage` on the inputs they are sent.
+    /// At most `queue_len` inputs wait in the queue at any time.
+    pub fn new<S>(stage: S, threads: usize, queue_len: usize) -> Self
+    where
+        S: Stage<Input = I> + 'static,
+    {
+        let (sender, receiver) = sync_channel::<(Span, I)>(queue_len);
+        let receiver = Arc::new(Mutex::new(receiver));
+        let stage = Arc::new(stage);
+        let handles = (0..threads.max(1))
+            .map(|i| {
+                let receiver = Arc::clone(&receiver);
+                let stage = Arc::clone(&stage);
+                std::thread::Builder::new()
+                    .name(format!("{}-{i}", stage.name()))
+                    .spawn(move || worker(&*stage, &receiver))
+                    .expect("can spawn thread")
+            })
+            .collect();
+        Self { sender, handles }
+    }
+
+    /// A stage that hands its input over to the pool.
+    pub fn sender(&self) -> PoolSender<I> {
+        PoolSender {
+            sender: self.sender.clone(),
+        }
+    }
+
+    /// Wait for the threads to finish all queued inputs. All [`PoolSender`]s
+    /// must have been dropped first, otherwise this never returns.
+    pub fn join(self) {
+        drop(self.sender);
+        for handle in self.handles {
+            if let Err(e) = handle.join() {
+                std::panic::resume_unwind(e);
+            }
+        }
+    }
+}
+
+fn worker<S: Stage + ?Sized>(stage: &S, receiver: &Mutex<Receiver<(Span, S::Input)>>) {
+    loop {
+        // only hold the lock while waiting for the next input, not while
+        // running the stage
+        let input = receiver
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .recv();
+        match input {
+            Ok((span, input)) => {
+                let _entered = span.enter();
+                let _ = stage.run(input);
+            }
+            // all senders are gone, and the queue is empty
+            Err(_) => break,
+        }
+    }
+}
+
+/// The end of a pipeline that sends its input to a [`StagePool`].
+pub struct PoolSender<I> {
+    sender: SyncSender<(Span, I)>,
+}
+
+impl<I: Send> Stage for PoolSender<I> {
+    type Input = I;
+    type Output = ();
+
+    fn name(&self) -> &'static str {
+        "send_to_pool"
+    }
+
+    fn run(&self, input: Self::Input) -> Option<Self::Output> {
+        match self.sender.send((Span::current(), input)) {
+            Ok(()) => Some(()),
+            Err(_) => {
+                tracing::error!("all threads of the pool are gone");
+                None
+            }
+        }
+    }
+}
+
+/// Runs a stage on a list of inputs, or on inputs that are still coming in,
+/// on a pool of dedicated threads. The outputs are read by iterating over
+/// the pool.
+pub struct SourcePool<O> {
+    receiver: Receiver<O>,
+    handles: Vec<JoinHandle<()>>,
+}
+
+impl<O: Send + 'static> SourcePool<O> {
+    /// Start `threads` threads that run `stage` on each of `inputs`. At most
+    /// `queue_len` outputs wait in the queue at any time.
+    pub fn new<S, I>(stage: S, inputs: I, threads: usize, queue_len: usize) -> Self
+    where
+        S: Stage<Output = O> + 'static,
+        S::Input: Send + 'static,
+        I: IntoIterator<Item = S::Input>,
+        I::IntoIter: Send + 'static,
+    {
+        let (sender, receiver) = sync_channel::<O>(queue_len);
+        let inputs: Inputs<S::Input> = Box::new(inputs.into_iter());
+        let inputs = Arc::new(Mutex::new(inputs));
+        let stage = Arc::new(stage);
+        let handles = (0..threads.max(1))
+            .map(|i| {
+                let inputs = Arc::clone(&inputs);
+                let stage = Arc::clone(&stage);
+                let sender = sender.clone();
+                std::thread::Builder::new()
+                    .name(format!("{}-{i}", stage.name()))
+                    .spawn(move || source_worker(&*stage, &inputs, &sender))
+                    .expect("can spawn thread")
+            })
+            .collect();
+        Self { receiver, handles }
+    }
+}
+
+/// The inputs of a [`SourcePool`], shared by its threads.
+type Inputs<I> = Box<dyn Iterator<Item = I> + Send>;
+
+fn source_worker<S: Stage + ?Sized>(
+    stage: &S,
+    inputs: &Mutex<Inputs<S::Input>>,
+    sender: &SyncSender<S::Output>,
+) {
+    loop {
+        let input = inputs
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .next();
+        let Some(input) = input else {
+            break;
+        };
+        let Some(output) = stage.run(input) else {
+            continue;
+        };
+        if sender.send(output).is_err() {
+            // the pool was dropped, nobody wants the rest
+            break;
+        }
+    }
+}
+
+impl<O> Iterator for SourcePool<O> {
+    type Item = O;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.receiver.recv() {
+            Ok(output) => Some(output),
+            // all threads are done, make sure none of them panicked
+            Err(_) => {
+                for handle in self.handles.drain(..) {
+                    if let Err(e) = handle.join() {
+                        std::panic::resume_unwind(e);
+                    }
+                }
+                None
+            }
+        }
+    }
+}
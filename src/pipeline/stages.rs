@@ -0,0 +1,1908 @@
+//! The default stages of the pipeline, in the order they are run.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use gtcorpusutil::AnalysedFilePath;
+use tracing::Span;
+use tracing_indicatif::span_ext::IndicatifSpanExt;
+
+use super::buffers::READ_BUFFERS;
+use super::{Stage, timed};
+use crate::analysed::file::{
+    BodyVariant, HeaderMode, ParsedAnalysedDocument, UnparsedAnalysedDocument, header_extras,
+    parse_document,
+};
+use crate::archive::{self, Archive};
+use crate::checksum::ChecksumWriter;
+use crate::compare::Comparison;
+use crate::corpus_info::CorpusInfos;
+use crate::corpus_settings::{CorpusSettings, Settings};
+use crate::cwb::CwbEncode;
+use crate::dedup::{self, DuplicatePolicy, Duplicates};
+use crate::distribution::Distribution;
+use crate::domain::DomainMap;
+use crate::empty::{self, EmptyCounts, EmptyPolicy, ShortDocuments};
+use crate::encoding;
+#[cfg(feature = "generate")]
+use crate::generate::{self, CachedGenerator};
+#[cfg(feature = "index")]
+use crate::index::SentenceIndex;
+use crate::korp_mono::jsonl::{OutputFormat, write_jsonl};
+use crate::korp_mono::newline::NewlinePolicy;
+use crate::korp_mono::path::{KorpMonoPath, NotAnalysedPath, corpus_dir};
+use crate::korp_mono::template::OutputTemplate;
+use crate::korp_mono::{self, KorpMonoFile};
+use crate::license::{Excluded, LicenseFilter};
+use crate::log_context;
+use crate::manifest::{Entry, Manifest};
+use crate::metrics::Metrics;
+use crate::mojibake;
+use crate::parallel::{self, ParallelLinks};
+use crate::parse_year::{self, ImplausibleDatePolicy, PlausibleYears, YearRange};
+use crate::pos_coverage::PosCoverage;
+use crate::process_sentence::{self, Column};
+use crate::progress::JsonProgress;
+use crate::sanity::{self, Warnings};
+use crate::sentence_dedup::{DedupScope, SentenceDedup};
+use crate::sentence_filter::{DroppedSentences, SentenceFilter};
+use crate::sidecar::{self, Overrides};
+use crate::source_url::source_url;
+use crate::status_message::ErrorCategory;
+use crate::text_attrs::TextAttrs;
+use crate::text_id::{TextIdScheme, text_id};
+
+/// A converted document, along with the path of the analysed file it was
+/// converted from. This is the input and output type of stages that operate
+/// on the finished korp_mono document.
+pub type Converted = (AnalysedFilePath, KorpMonoFile);
+
+/// Read the analysed file into a string, reusing the buffer of an earlier
+/// file, see [`super::buffers`]. Files that are not UTF-8, or start with a
+/// byte order mark, are transcoded, see [`crate::encoding`].
+pub struct ReadFile;
+
+impl Stage for ReadFile {
+    type Input = AnalysedFilePath;
+    type Output = (AnalysedFilePath, String);
+
+    fn name(&self) -> &'static str {
+        "read"
+    }
+
+    fn run(&self, analysed_file: Self::Input) -> Option<Self::Output> {
+        let file = analysed_file.to_path_buf();
+        let span = tracing::info_span!("reading file", file = ?file);
+        let _guard = span.enter();
+
+        let (_dur, res) = timed(|| {
+            let mut buffer = READ_BUFFERS.take().into_bytes();
+            std::fs::File::open(&file)
+                .and_then(|mut f| f.read_to_end(&mut buffer))
+                .map(|_| encoding::decode_file(buffer, &file))
+        });
+        match res {
+            Ok(string) => {
+                // the files that are read are counted by the progress bar,
+                // and only logged with --verbose
+                tracing::debug!("file read ok");
+                Span::current().pb_inc(1);
+                Some((analysed_file, string))
+            }
+            Err(e) => {
+                tracing::error!(
+                    error = ?e,
+                    file = ?file,
+                    category = ErrorCategory::Read.as_str(),
+                    "error reading file"
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Use `quick_xml` to parse the contents of the file into an XML document,
+/// with the header read as `header_mode` says. The buffer of the file is
+/// given back to be reused.
+#[derive(Default)]
+pub struct ParseXml {
+    pub header_mode: HeaderMode,
+}
+
+impl Stage for ParseXml {
+    type Input = (AnalysedFilePath, String);
+    type Output = (AnalysedFilePath, UnparsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "parse_xml"
+    }
+
+    fn run(&self, (analysed_file, s): Self::Input) -> Option<Self::Output> {
+        let (_dur, res) = timed(|| parse_document(&s, self.header_mode));
+        let res = res.map(|mut xml| {
+            xml.header.extra = header_extras(&s);
+            xml
+        });
+        READ_BUFFERS.give(s);
+        match res {
+            Ok(xml) => {
+                if let Some(id) = &xml.id {
+                    log_context::record_doc_id(id);
+                }
+                if xml.body.variant != BodyVariant::Dependency {
+                    tracing::info!(
+                        file = ?analysed_file.to_path_buf(),
+                        variant = ?xml.body.variant,
+                        "analyses are in the older <analysis> format"
+                    );
+                }
+                Some((analysed_file, xml))
+            }
+            Err(e) => {
+                tracing::error!(
+                    file = ?analysed_file.to_path_buf(),
+                    error = %e,
+                    category = ErrorCategory::Xml.as_str(),
+                    "can't parse xml"
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Override the header of the documents that have a metadata sidecar, see
+/// [`crate::sidecar`], and count the overridden fields in `applied`. Runs
+/// right after the xml is parsed, so that the other stages see the fixed
+/// header. Documents with a sidecar that can't be read fail.
+pub struct ApplySidecars {
+    pub applied: Arc<Mutex<sidecar::Applied>>,
+}
+
+impl ApplySidecars {
+    pub fn new(applied: Arc<Mutex<sidecar::Applied>>) -> Self {
+        Self { applied }
+    }
+}
+
+impl Stage for ApplySidecars {
+    type Input = (AnalysedFilePath, UnparsedAnalysedDocument);
+    type Output = (AnalysedFilePath, UnparsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "apply_sidecars"
+    }
+
+    fn run(&self, (analysed_file, mut doc): Self::Input) -> Option<Self::Output> {
+        let file = analysed_file.to_path_buf();
+        let overrides = match Overrides::of(&file) {
+            Ok(Some(overrides)) => overrides,
+            Ok(None) => return Some((analysed_file, doc)),
+            Err(e) => {
+                tracing::error!(
+                    file = ?file,
+                    error = %format_args!("{e:#}"),
+                    category = ErrorCategory::Settings.as_str(),
+                    "can't read the metadata sidecar"
+                );
+                return None;
+            }
+        };
+        let fields = overrides.apply(&mut doc);
+        tracing::info!(file = ?file, fields = ?fields, "overrode the header from the sidecar");
+        self.applied
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add(&fields);
+        Some((analysed_file, doc))
+    }
+}
+
+/// Skip the documents whose `<year>` in the header is outside of `years`.
+/// Runs before the analyses are parsed, so that skipped documents are cheap.
+pub struct FilterYears {
+    pub years: YearRange,
+}
+
+impl FilterYears {
+    pub fn new(years: YearRange) -> Self {
+        Self { years }
+    }
+}
+
+impl Stage for FilterYears {
+    type Input = (AnalysedFilePath, UnparsedAnalysedDocument);
+    type Output = (AnalysedFilePath, UnparsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "filter_years"
+    }
+
+    fn run(&self, (analysed_file, doc): Self::Input) -> Option<Self::Output> {
+        let (_, datefrom, dateto) = parse_year::parse_year(doc.header.year.as_deref());
+        match self.years.overlaps(&datefrom, &dateto) {
+            true => Some((analysed_file, doc)),
+            false => {
+                tracing::info!(file = ?analysed_file.to_path_buf(), datefrom, dateto, "skipping, outside of the years");
+                None
+            }
+        }
+    }
+}
+
+/// The settings of the corpus of `analysed_file`, see
+/// [`crate::corpus_settings`].
+fn corpus_settings(
+    settings: &Option<Arc<Settings>>,
+    analysed_file: &Path,
+) -> Option<Arc<CorpusSettings>> {
+    settings
+        .as_ref()
+        .and_then(|settings| settings.of(analysed_file))
+}
+
+/// Read the `korp-mono.toml` of the corpus of each document, and fail the
+/// documents of corpora whose settings can't be read, instead of
+/// converting them with the wrong settings.
+pub struct LoadCorpusSettings {
+    pub settings: Arc<Settings>,
+}
+
+impl LoadCorpusSettings {
+    pub fn new(settings: Arc<Settings>) -> Self {
+        Self { settings }
+    }
+}
+
+impl Stage for LoadCorpusSettings {
+    type Input = (AnalysedFilePath, UnparsedAnalysedDocument);
+    type Output = (AnalysedFilePath, UnparsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "load_corpus_settings"
+    }
+
+    fn run(&self, (analysed_file, doc): Self::Input) -> Option<Self::Output> {
+        if let Err(e) = self.settings.load(&analysed_file.to_path_buf()) {
+            tracing::error!(
+                file = ?analysed_file.to_path_buf(),
+                error = %format_args!("{e:#}"),
+                category = ErrorCategory::Settings.as_str(),
+                "can't read the settings of the corpus"
+            );
+            return None;
+        }
+        Some((analysed_file, doc))
+    }
+}
+
+/// Skip the documents whose license is not allowed by `filter`, or the
+/// license filter of their corpus, and count them in `excluded`. Runs
+/// before the analyses are parsed, like [`FilterYears`].
+pub struct FilterLicenses {
+    /// Allow all licenses if `None`
+    pub filter: Option<LicenseFilter>,
+    pub excluded: Arc<Mutex<Excluded>>,
+    pub settings: Option<Arc<Settings>>,
+}
+
+impl FilterLicenses {
+    pub fn new(filter: Option<LicenseFilter>, excluded: Arc<Mutex<Excluded>>) -> Self {
+        Self {
+            filter,
+            excluded,
+            settings: None,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Option<Arc<Settings>>) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Stage for FilterLicenses {
+    type Input = (AnalysedFilePath, UnparsedAnalysedDocument);
+    type Output = (AnalysedFilePath, UnparsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "filter_licenses"
+    }
+
+    fn run(&self, (analysed_file, doc): Self::Input) -> Option<Self::Output> {
+        let corpus = corpus_settings(&self.settings, &analysed_file.to_path_buf());
+        let filter = corpus
+            .as_ref()
+            .and_then(|corpus| corpus.license_filter.as_ref())
+            .or(self.filter.as_ref());
+        let Some(filter) = filter else {
+            return Some((analysed_file, doc));
+        };
+        let license = doc.header.availability.license.as_ref();
+        let license = license.and_then(|license| license.r#type.as_deref());
+        let license = match filter.allows(license) {
+            true => None,
+            false => Some(LicenseFilter::license_of(license).to_string()),
+        };
+        let Some(license) = license else {
+            return Some((analysed_file, doc));
+        };
+        tracing::info!(file = ?analysed_file.to_path_buf(), license, "skipping, license not allowed");
+        self.excluded
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add(&license);
+        None
+    }
+}
+
+/// Find documents with the same content as a document that was seen before,
+/// and collect them into `duplicates`. With `skip`, the duplicates are not
+/// converted. The report is written once all files are done, with
+/// [`Duplicates::write_reports`].
+pub struct FindDuplicates {
+    pub duplicates: Arc<Mutex<Duplicates>>,
+    pub skip: bool,
+}
+
+impl FindDuplicates {
+    pub fn new(duplicates: Arc<Mutex<Duplicates>>, policy: DuplicatePolicy) -> Self {
+        Self {
+            duplicates,
+            skip: policy == DuplicatePolicy::Skip,
+        }
+    }
+}
+
+impl Stage for FindDuplicates {
+    type Input = (AnalysedFilePath, UnparsedAnalysedDocument);
+    type Output = (AnalysedFilePath, UnparsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "find_duplicates"
+    }
+
+    fn run(&self, (analysed_file, doc): Self::Input) -> Option<Self::Output> {
+        let hash = dedup::content_hash(&doc.body.dependency);
+        let Some(hash) = hash else {
+            return Some((analysed_file, doc));
+        };
+        let original = self
+            .duplicates
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .check(analysed_file.to_path_buf(), hash);
+        match original {
+            Some(original) if self.skip => {
+                tracing::info!(file = ?analysed_file.to_path_buf(), ?original, "skipping duplicate");
+                None
+            }
+            _ => Some((analysed_file, doc)),
+        }
+    }
+}
+
+/// Collect the `<parallel_text>` links of the documents into `links`. The
+/// files are written once all files are done, with
+/// [`ParallelLinks::write_files`].
+pub struct CollectParallelLinks {
+    pub links: Arc<Mutex<ParallelLinks>>,
+}
+
+impl CollectParallelLinks {
+    pub fn new(links: Arc<Mutex<ParallelLinks>>) -> Self {
+        Self { links }
+    }
+}
+
+impl Stage for CollectParallelLinks {
+    type Input = (AnalysedFilePath, UnparsedAnalysedDocument);
+    type Output = (AnalysedFilePath, UnparsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "collect_parallel_links"
+    }
+
+    fn run(&self, (analysed_file, doc): Self::Input) -> Option<Self::Output> {
+        let links = parallel::links(doc.header.parallel_text.as_deref().unwrap_or_default());
+        self.links
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add(analysed_file.to_path_buf(), links);
+        Some((analysed_file, doc))
+    }
+}
+
+/// How many parses that timed out can still be running in the background.
+/// Each of them holds a thread and its document, so when there are this
+/// many, the next documents are skipped instead of parsed, until some of
+/// them finish.
+pub const MAX_STALLED_PARSES: usize = 16;
+
+/// The state of a parse that runs on its own thread, see
+/// [`ParseAnalyses::timeout`].
+const PARSE_RUNNING: u8 = 0;
+const PARSE_TIMED_OUT: u8 = 1;
+const PARSE_DONE: u8 = 2;
+
+/// Parse the analyses in the `<dependency>` element using `giellacgparser`.
+#[derive(Default)]
+pub struct ParseAnalyses {
+    /// Skip cohorts that can't be parsed, instead of dropping the rest of
+    /// the document. See [`crate::analysed::file::parse_dependency`].
+    pub robust: bool,
+    /// Give up on documents that take longer than this to parse. The parse
+    /// then runs on its own thread, which is left to finish in the
+    /// background when it times out, since threads can't be stopped.
+    pub timeout: Option<Duration>,
+    /// The parses that timed out, and are still running
+    stalled: Arc<AtomicUsize>,
+}
+
+/// The result of parsing a document, or the panic that parsing ended in.
+type ParseResult = std::thread::Result<anyhow::Result<ParsedAnalysedDocument>>;
+
+impl ParseAnalyses {
+    pub fn new(robust: bool, timeout: Option<Duration>) -> Self {
+        Self {
+            robust,
+            timeout,
+            stalled: Arc::default(),
+        }
+    }
+
+    fn parse(&self, document: UnparsedAnalysedDocument) -> Option<ParseResult> {
+        let robust = self.robust;
+        let parse =
+            move || std::panic::catch_unwind(|| ParsedAnalysedDocument::parse(document, robust));
+        let Some(timeout) = self.timeout else {
+            return Some(parse());
+        };
+        let state = Arc::new(AtomicU8::new(PARSE_RUNNING));
+        let (sender, receiver) = std::sync::mpsc::sync_channel(1);
+        let (worker_state, stalled) = (Arc::clone(&state), Arc::clone(&self.stalled));
+        std::thread::Builder::new()
+            .name(self.name().to_string())
+            .spawn(move || {
+                // nobody is listening anymore if it timed out
+                let _ = sender.send(parse());
+                if worker_state.swap(PARSE_DONE, Ordering::SeqCst) == PARSE_TIMED_OUT {
+                    stalled.fetch_sub(1, Ordering::SeqCst);
+                }
+            })
+            .expect("can spawn thread");
+        let res = receiver.recv_timeout(timeout).ok();
+        if res.is_none() {
+            // counted before the state is changed, so that the worker can
+            // never take it from the count before it is added
+            self.stalled.fetch_add(1, Ordering::SeqCst);
+            let timed_out = state.compare_exchange(
+                PARSE_RUNNING,
+                PARSE_TIMED_OUT,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            );
+            if timed_out.is_err() {
+                // it finished after all
+                self.stalled.fetch_sub(1, Ordering::SeqCst);
+            }
+        }
+        res
+    }
+
+    /// The number of parses that timed out, and are still running.
+    pub fn stalled(&self) -> usize {
+        self.stalled.load(Ordering::SeqCst)
+    }
+}
+
+impl Stage for ParseAnalyses {
+    type Input = (AnalysedFilePath, UnparsedAnalysedDocument);
+    type Output = (AnalysedFilePath, ParsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "parse_analyses"
+    }
+
+    fn run(&self, (analysed_file_path, document): Self::Input) -> Option<Self::Output> {
+        if self.timeout.is_some() && self.stalled() >= MAX_STALLED_PARSES {
+            tracing::error!(
+                file = ?analysed_file_path.to_path_buf(),
+                category = ErrorCategory::Saturated.as_str(),
+                stalled = self.stalled(),
+                "skipped: parser saturated, too many parses that timed out are still running"
+            );
+            return None;
+        }
+        let (_dur, res) = timed(|| self.parse(document));
+        let Some(res) = res else {
+            tracing::error!(
+                file = ?analysed_file_path.to_path_buf(),
+                category = ErrorCategory::Timeout.as_str(),
+                timeout = ?self.timeout,
+                "parsing the analyses timed out, skipping file"
+            );
+            return None;
+        };
+        match res {
+            Ok(Ok(doc)) => {
+                if !doc.repairs.is_empty() {
+                    tracing::info!(
+                        file = ?analysed_file_path.to_path_buf(),
+                        repairs = ?doc.repairs,
+                        "repaired entities, CDATA markers or control characters in the analyses"
+                    );
+                }
+                if !doc.skipped.is_empty() {
+                    let file = analysed_file_path.to_path_buf();
+                    tracing::warn!(
+                        file = ?file,
+                        regions = ?doc.skipped,
+                        "skipped cohorts that could not be parsed"
+                    );
+                }
+                Some((analysed_file_path, doc))
+            }
+            Ok(Err(e)) => {
+                tracing::error!(
+                    file = ?analysed_file_path.to_path_buf(),
+                    error = %e,
+                    category = ErrorCategory::Analyses.as_str(),
+                    "can't parse analyses"
+                );
+                None
+            }
+            Err(e) => {
+                let m = if let Some(p) = e.downcast_ref::<&str>() {
+                    p.to_string()
+                } else if let Some(s) = e.downcast_ref::<String>() {
+                    s.clone()
+                } else {
+                    "(not &str nor String)".to_string()
+                };
+                tracing::error!(
+                    file = ?analysed_file_path.to_path_buf(),
+                    panic = %m,
+                    category = ErrorCategory::Panic.as_str(),
+                    "parsing analyses using giellacgparser panicked"
+                );
+                None
+            }
+        }
+    }
+}
+
+/// Skip the documents with fewer than `min_tokens` tokens, and count them
+/// in `skipped`. Runs right after the analyses are parsed, so that the
+/// skipped documents are not converted.
+pub struct FilterMinTokens {
+    pub min_tokens: usize,
+    pub skipped: Arc<Mutex<ShortDocuments>>,
+}
+
+impl FilterMinTokens {
+    pub fn new(min_tokens: usize, skipped: Arc<Mutex<ShortDocuments>>) -> Self {
+        Self {
+            min_tokens,
+            skipped,
+        }
+    }
+}
+
+impl Stage for FilterMinTokens {
+    type Input = (AnalysedFilePath, ParsedAnalysedDocument);
+    type Output = (AnalysedFilePath, ParsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "filter_min_tokens"
+    }
+
+    fn run(&self, (analysed_file_path, doc): Self::Input) -> Option<Self::Output> {
+        let tokens = empty::count_tokens(doc.sentences.as_deref().unwrap_or_default());
+        if tokens >= self.min_tokens {
+            return Some((analysed_file_path, doc));
+        }
+        self.skipped
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add(tokens);
+        tracing::info!(
+            file = ?analysed_file_path.to_path_buf(),
+            tokens,
+            "skipping, fewer tokens than --min-tokens"
+        );
+        None
+    }
+}
+
+/// Count the tokens with an unknown part of speech into `coverage`, which
+/// is printed at the end of the run, with `--pos-coverage`.
+pub struct CollectPosCoverage {
+    pub coverage: Arc<Mutex<PosCoverage>>,
+}
+
+impl CollectPosCoverage {
+    pub fn new(coverage: Arc<Mutex<PosCoverage>>) -> Self {
+        Self { coverage }
+    }
+}
+
+impl Stage for CollectPosCoverage {
+    type Input = (AnalysedFilePath, ParsedAnalysedDocument);
+    type Output = (AnalysedFilePath, ParsedAnalysedDocument);
+
+    fn name(&self) -> &'static str {
+        "collect_pos_coverage"
+    }
+
+    fn run(&self, (analysed_file_path, doc): Self::Input) -> Option<Self::Output> {
+        self.coverage
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add_sentences(doc.sentences.as_deref().unwrap_or_default());
+        Some((analysed_file_path, doc))
+    }
+}
+
+/// Convert the parsed document to the korp_mono format, with the columns
+/// of its corpus, if it has its own.
+#[derive(Default)]
+pub struct Convert {
+    pub options: process_sentence::Options,
+    pub settings: Option<Arc<Settings>>,
+}
+
+impl Convert {
+    pub fn new(options: process_sentence::Options) -> Self {
+        Self {
+            options,
+            settings: None,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Option<Arc<Settings>>) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Stage for Convert {
+    type Input = (AnalysedFilePath, ParsedAnalysedDocument);
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "convert"
+    }
+
+    fn run(&self, (analysed_file_path, document): Self::Input) -> Option<Self::Output> {
+        let corpus = corpus_settings(&self.settings, &analysed_file_path.to_path_buf());
+        let options = match corpus.as_ref().and_then(|corpus| corpus.columns.as_ref()) {
+            Some(columns) => Cow::Owned(process_sentence::Options {
+                columns: Some(columns.clone()),
+                ..self.options.clone()
+            }),
+            None => Cow::Borrowed(&self.options),
+        };
+        let (_dur, korp_mono_xml_file) = timed(|| KorpMonoFile::from_document(document, &options));
+        let escaped = korp_mono_xml_file.escaped_tokens;
+        if escaped > 0 {
+            let file = analysed_file_path.to_path_buf();
+            if self.options.strict_columns {
+                tracing::error!(
+                    file = ?file,
+                    escaped,
+                    category = ErrorCategory::Columns.as_str(),
+                    "tokens with a tab or a newline in a column"
+                );
+                return None;
+            }
+            tracing::warn!(file = ?file, escaped, "replaced tabs and newlines in token columns");
+        }
+        let renumbered = korp_mono_xml_file.renumbered_sentences;
+        if renumbered > 0 {
+            let file = analysed_file_path.to_path_buf();
+            tracing::info!(file = ?file, renumbered, "renumbered the token ids of sentences");
+        }
+        if self.options.multi_lemma {
+            let ambiguous: usize = korp_mono_xml_file
+                .sentence
+                .iter()
+                .map(|sentence| process_sentence::count_ambiguous_lemmas(&sentence.rows))
+                .sum();
+            let file = analysed_file_path.to_path_buf();
+            tracing::info!(file = ?file, ambiguous, "tokens with more than one lemma");
+        }
+        Some((analysed_file_path, korp_mono_xml_file))
+    }
+}
+
+/// Replace the `[[[GEN:#...]]]` lemmas of dynamic compounds with the lemma
+/// generated by `generator`. If the generator can't be run, the document is
+/// kept as it is, with the directives. The generator is shared between the
+/// threads, so that its cache is, too.
+#[cfg(feature = "generate")]
+pub struct GenerateLemmas {
+    pub generator: Arc<CachedGenerator>,
+}
+
+#[cfg(feature = "generate")]
+impl GenerateLemmas {
+    pub fn new(generator: Arc<CachedGenerator>) -> Self {
+        Self { generator }
+    }
+}
+
+#[cfg(feature = "generate")]
+impl Stage for GenerateLemmas {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "generate_lemmas"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let file = analysed_file_path.to_path_buf();
+        let lemmas = korp_mono_file
+            .sentence
+            .iter()
+            .flat_map(|sentence| sentence.rows.iter().map(|row| &*row.lemma));
+        let analyses = generate::analyses(lemmas).into_iter().collect::<Vec<_>>();
+        let generated = match self.generator.generate(&analyses) {
+            Ok(generated) => generated,
+            Err(e) => {
+                tracing::warn!(file = ?file, error = %e, "can't run the generator, keeping the lemmas as they are");
+                return Some((analysed_file_path, korp_mono_file));
+            }
+        };
+        let failed: usize = korp_mono_file
+            .sentence
+            .iter_mut()
+            .flat_map(|sentence| sentence.rows.iter_mut())
+            .map(|row| generate::replace_directives(row.lemma.to_mut(), &generated))
+            .sum();
+        if failed > 0 {
+            tracing::warn!(file = ?file, failed, "lemmas that could not be generated");
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Give the documents without a `gt_domain` the domain of the directory
+/// they are in, see [`crate::domain`]. The domain map of the corpus is used
+/// if it has one.
+pub struct InferDomain {
+    /// Don't infer domains if `None`
+    pub map: Option<DomainMap>,
+    pub settings: Option<Arc<Settings>>,
+}
+
+impl InferDomain {
+    pub fn new(map: Option<DomainMap>) -> Self {
+        Self {
+            map,
+            settings: None,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Option<Arc<Settings>>) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Stage for InferDomain {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "infer_domain"
+    }
+
+    fn run(&self, (analysed_file, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let file = analysed_file.to_path_buf();
+        let corpus = corpus_settings(&self.settings, &file);
+        let map = corpus
+            .as_ref()
+            .and_then(|corpus| corpus.domain_map.as_ref())
+            .or(self.map.as_ref());
+        let Some(map) = map else {
+            return Some((analysed_file, korp_mono_file));
+        };
+        if korp_mono_file
+            .gt_domain
+            .as_deref()
+            .unwrap_or_default()
+            .is_empty()
+        {
+            let domain = map.domain_of(&file);
+            if let Some(domain) = domain {
+                tracing::info!(file = ?file, domain, "inferred domain from directory");
+                korp_mono_file.gt_domain = Some(domain);
+            }
+        }
+        Some((analysed_file, korp_mono_file))
+    }
+}
+
+/// Repair mojibake in the word forms of all sentences in the document, and
+/// log how many tokens were repaired.
+pub struct RepairEncoding;
+
+impl Stage for RepairEncoding {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "repair_encoding"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let repaired: usize = korp_mono_file
+            .sentence
+            .iter_mut()
+            .map(|sentence| mojibake::repair_word_forms(&mut sentence.rows))
+            .sum();
+        if repaired > 0 {
+            let file = analysed_file_path.to_path_buf();
+            tracing::warn!(file = ?file, repaired, "repaired mojibake in word forms");
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Warn about dates outside of the plausible range of years, and, depending
+/// on the policy, replace them with the unknown date.
+#[derive(Default)]
+pub struct ValidateDates {
+    pub years: PlausibleYears,
+    pub policy: ImplausibleDatePolicy,
+}
+
+impl ValidateDates {
+    pub fn new(years: PlausibleYears, policy: ImplausibleDatePolicy) -> Self {
+        Self { years, policy }
+    }
+}
+
+impl Stage for ValidateDates {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "validate_dates"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let datefrom = korp_mono_file.datefrom.as_deref().unwrap_or_default();
+        let dateto = korp_mono_file.dateto.as_deref().unwrap_or_default();
+        if let Some(year) = self.years.implausible_year(datefrom, dateto) {
+            let file = analysed_file_path.to_path_buf();
+            tracing::warn!(
+                file = ?file,
+                year,
+                first = self.years.first,
+                last = self.years.last,
+                policy = ?self.policy,
+                "implausible date"
+            );
+            if self.policy == ImplausibleDatePolicy::Zero {
+                let (date, datefrom, dateto) = parse_year::zero_output();
+                korp_mono_file.date = Some(date);
+                korp_mono_file.datefrom = Some(datefrom);
+                korp_mono_file.dateto = Some(dateto);
+            }
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Give the `<text>` element an `id` attribute. See [`crate::text_id`].
+#[derive(Default)]
+pub struct AssignTextId {
+    pub scheme: TextIdScheme,
+    /// Used instead of the name of the corpus directory in the ids that are
+    /// made from paths
+    pub corpus_id: Option<String>,
+}
+
+impl AssignTextId {
+    pub fn new(scheme: TextIdScheme, corpus_id: Option<String>) -> Self {
+        Self { scheme, corpus_id }
+    }
+}
+
+impl Stage for AssignTextId {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "assign_text_id"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        korp_mono_file.id = Some(text_id(
+            self.scheme,
+            korp_mono_file.document_id.as_deref(),
+            &analysed_file_path.to_path_buf(),
+            self.corpus_id.as_deref(),
+        ));
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Give the `<text>` element a `source_url` attribute, from the
+/// `<origFileName>` of the document, if it looks like an URL or a path. See
+/// [`crate::source_url`].
+pub struct AddSourceUrl;
+
+impl Stage for AddSourceUrl {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "add_source_url"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let orig_file_name = korp_mono_file.orig_file_name.as_deref().unwrap_or_default();
+        let url = source_url(orig_file_name);
+        if url.is_none() && !orig_file_name.trim().is_empty() {
+            let file = analysed_file_path.to_path_buf();
+            tracing::info!(file = ?file, orig_file_name, "origFileName is not an URL or a path");
+        }
+        // always set, so that all texts have the attribute
+        korp_mono_file.source_url = Some(url.unwrap_or_default());
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Put the values of all attributes of the `<text>` element on one line,
+/// and optionally cut them to at most `max_chars` characters. See
+/// [`korp_mono::file::normalize_attr_value`].
+#[derive(Default)]
+pub struct NormalizeAttrs {
+    pub max_chars: Option<usize>,
+}
+
+impl NormalizeAttrs {
+    pub fn new(max_chars: Option<usize>) -> Self {
+        Self { max_chars }
+    }
+}
+
+impl Stage for NormalizeAttrs {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "normalize_attrs"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        korp_mono_file.normalize_attrs(self.max_chars);
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Leave attributes with an empty value out of the `<text>` element.
+pub struct OmitEmptyAttrs;
+
+impl Stage for OmitEmptyAttrs {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "omit_empty_attrs"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        korp_mono_file.omit_empty_attrs();
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Only write the selected attributes of the `<text>` element, or those
+/// selected for the corpus. See [`crate::text_attrs`].
+pub struct SelectTextAttrs {
+    /// Write all attributes if `None`
+    pub selected: Option<TextAttrs>,
+    pub settings: Option<Arc<Settings>>,
+}
+
+impl SelectTextAttrs {
+    pub fn new(selected: Option<TextAttrs>) -> Self {
+        Self {
+            selected,
+            settings: None,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Option<Arc<Settings>>) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Stage for SelectTextAttrs {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "select_text_attrs"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let corpus = corpus_settings(&self.settings, &analysed_file_path.to_path_buf());
+        let selected = corpus
+            .as_ref()
+            .and_then(|corpus| corpus.text_attrs.as_ref())
+            .or(self.selected.as_ref());
+        if let Some(selected) = selected {
+            korp_mono_file.select_attrs(selected);
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Copy selected elements of the header of the analysed document to
+/// attributes of `<text>`.
+pub struct CopyHeaderExtras {
+    /// Pairs of header element path, and attribute name
+    pub selected: Vec<(String, String)>,
+}
+
+impl CopyHeaderExtras {
+    /// Make the stage from specs of the form `PATH` or `PATH=ATTR`. Without
+    /// an explicit attribute name, the attribute is named after the path,
+    /// with `/` replaced by `_`.
+    pub fn from_specs(specs: &[String]) -> Self {
+        let selected = specs
+            .iter()
+            .map(|spec| match spec.split_once('=') {
+                Some((path, attr)) => (path.to_string(), attr.to_string()),
+                None => (spec.clone(), spec.replace('/', "_")),
+            })
+            .collect();
+        Self { selected }
+    }
+}
+
+impl Stage for CopyHeaderExtras {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "copy_header_extras"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        korp_mono_file.copy_header_extras(&self.selected);
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Remove the sentences that match a pattern of `filter`, and count them in
+/// `dropped`. See [`crate::sentence_filter`].
+pub struct DropSentences {
+    pub filter: SentenceFilter,
+    pub dropped: Arc<Mutex<DroppedSentences>>,
+}
+
+impl DropSentences {
+    pub fn new(filter: SentenceFilter, dropped: Arc<Mutex<DroppedSentences>>) -> Self {
+        Self { filter, dropped }
+    }
+}
+
+impl Stage for DropSentences {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "drop_sentences"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let file = analysed_file_path.to_path_buf();
+        let sentences = korp_mono_file.sentence.len();
+        let dropped = self.filter.drop_sentences(&mut korp_mono_file);
+        let removed = dropped.iter().sum::<u64>();
+        if removed > 0 {
+            tracing::info!(file = ?file, removed, "dropped sentences matching --drop-sentence-regex");
+        }
+        self.dropped
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add(sentences, &dropped);
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Remove the sentences that were seen before, in the same document or in
+/// the same corpus, and count them in `dedup`. See [`crate::sentence_dedup`].
+pub struct DedupSentences {
+    pub dedup: Arc<Mutex<SentenceDedup>>,
+    pub scope: DedupScope,
+}
+
+impl DedupSentences {
+    pub fn new(dedup: Arc<Mutex<SentenceDedup>>, scope: DedupScope) -> Self {
+        Self { dedup, scope }
+    }
+}
+
+impl Stage for DedupSentences {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "dedup_sentences"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let file = analysed_file_path.to_path_buf();
+        let corpus = corpus_dir(&file).map(Path::to_path_buf).unwrap_or_default();
+        let removed = self
+            .dedup
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .dedup(corpus, &mut korp_mono_file, self.scope);
+        if removed > 0 {
+            tracing::info!(file = ?file, removed, "removed duplicate sentences");
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Leave out the sentences without tokens, and don't write documents without
+/// any, or mark them, depending on the policy. See [`crate::empty`].
+pub struct HandleEmpty {
+    pub counts: Arc<Mutex<EmptyCounts>>,
+    pub policy: EmptyPolicy,
+}
+
+impl HandleEmpty {
+    pub fn new(counts: Arc<Mutex<EmptyCounts>>, policy: EmptyPolicy) -> Self {
+        Self { counts, policy }
+    }
+}
+
+impl Stage for HandleEmpty {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "handle_empty"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let keep = self
+            .counts
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .apply(&mut korp_mono_file, self.policy);
+        if !keep {
+            tracing::info!(file = ?analysed_file_path.to_path_buf(), "skipping empty document");
+            return None;
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Check the converted sentences for structural anomalies, and collect them
+/// into `warnings`. The report is written once all files are done, with
+/// [`Warnings::write_reports`].
+pub struct SanityCheck {
+    pub warnings: Arc<Mutex<Warnings>>,
+}
+
+impl SanityCheck {
+    pub fn new(warnings: Arc<Mutex<Warnings>>) -> Self {
+        Self { warnings }
+    }
+}
+
+impl Stage for SanityCheck {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "sanity_check"
+    }
+
+    fn run(&self, (analysed_file_path, korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let file_warnings = sanity::check_file(&korp_mono_file);
+        self.warnings
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add(analysed_file_path.to_path_buf(), file_warnings);
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Give the sentences whose dependency trees are broken `X` as deprel and
+/// `0` as parent, and count them in `demoted`. See
+/// [`sanity::demote_broken_trees`].
+pub struct DemoteBrokenTrees {
+    pub demoted: Arc<Mutex<sanity::Demoted>>,
+}
+
+impl DemoteBrokenTrees {
+    pub fn new(demoted: Arc<Mutex<sanity::Demoted>>) -> Self {
+        Self { demoted }
+    }
+}
+
+impl Stage for DemoteBrokenTrees {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "demote_broken_trees"
+    }
+
+    fn run(&self, (analysed_file_path, mut korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let demoted = sanity::demote_broken_trees(&mut korp_mono_file);
+        if demoted > 0 {
+            let file = analysed_file_path.to_path_buf();
+            tracing::info!(file = ?file, demoted, "demoted sentences with broken dependency trees");
+        }
+        self.demoted
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add(demoted);
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Collect the statistics of each corpus, for the `.info` files. The files
+/// are written once all files are done, with
+/// [`CorpusInfos::write_info_files`].
+pub struct CollectInfo {
+    pub infos: Arc<Mutex<CorpusInfos>>,
+}
+
+impl CollectInfo {
+    pub fn new(infos: Arc<Mutex<CorpusInfos>>) -> Self {
+        Self { infos }
+    }
+}
+
+impl Stage for CollectInfo {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "collect_info"
+    }
+
+    fn run(&self, (analysed_file_path, korp_mono_file): Self::Input) -> Option<Self::Output> {
+        self.infos
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add(&analysed_file_path.to_path_buf(), &korp_mono_file);
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Pipe the converted document into the `cwb-encode` of its corpus, see
+/// [`crate::cwb`]. The document is still written after this.
+pub struct EncodeCwb {
+    pub cwb: Arc<CwbEncode>,
+    /// For the columns of corpora with their own columns
+    pub settings: Option<Arc<Settings>>,
+}
+
+impl EncodeCwb {
+    pub fn new(cwb: Arc<CwbEncode>) -> Self {
+        Self {
+            cwb,
+            settings: None,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Option<Arc<Settings>>) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Stage for EncodeCwb {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "cwb_encode"
+    }
+
+    fn run(&self, (analysed_file_path, korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let file = analysed_file_path.to_path_buf();
+        let Some(corpus) = corpus_dir(&file) else {
+            tracing::error!(
+                file = ?file,
+                category = ErrorCategory::OutputPath.as_str(),
+                "can't encode a file that is not in a corpus"
+            );
+            return None;
+        };
+        let settings = corpus_settings(&self.settings, &file);
+        let columns = settings
+            .as_ref()
+            .and_then(|settings| settings.columns.as_deref());
+        let key = self
+            .cwb
+            .sort_by
+            .map(|sort_by| sort_by.key(&file, &korp_mono_file));
+        let mut vrt = Vec::new();
+        let written_columns = columns.unwrap_or(&self.cwb.columns);
+        let encoded = write_parts(
+            &mut vrt,
+            std::slice::from_ref(&korp_mono_file),
+            written_columns,
+        )
+        .and_then(|()| self.cwb.encode(corpus, columns, key, vrt));
+        if let Err(e) = encoded {
+            tracing::error!(
+                error = ?e,
+                file = ?file,
+                category = ErrorCategory::Write.as_str(),
+                "can't pipe document to cwb-encode"
+            );
+            return None;
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Count the tokens of the converted documents by domain and year, into
+/// `distribution`, which is printed at the end of the run.
+pub struct CollectDistribution {
+    pub distribution: Arc<Mutex<Distribution>>,
+}
+
+impl CollectDistribution {
+    pub fn new(distribution: Arc<Mutex<Distribution>>) -> Self {
+        Self { distribution }
+    }
+}
+
+impl Stage for CollectDistribution {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "collect_distribution"
+    }
+
+    fn run(&self, (analysed_file_path, korp_mono_file): Self::Input) -> Option<Self::Output> {
+        self.distribution
+            .lock()
+            .expect("no thread panics while holding the lock")
+            .add_document(&korp_mono_file);
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Compare the converted documents with the output of the old converter in
+/// `old_dir`, with `--compare-python`. The differences are counted in
+/// `comparison`, and printed at the end of the run.
+pub struct ComparePython {
+    pub old_dir: PathBuf,
+    pub comparison: Arc<Mutex<Comparison>>,
+    /// The columns of the token lines, as they are written for the
+    /// comparison, and named in the report
+    pub columns: Vec<Column>,
+    /// For the columns of corpora with their own columns
+    pub settings: Option<Arc<Settings>>,
+}
+
+impl ComparePython {
+    pub fn new(old_dir: PathBuf, comparison: Arc<Mutex<Comparison>>, columns: &[Column]) -> Self {
+        Self {
+            old_dir,
+            comparison,
+            columns: columns.to_vec(),
+            settings: None,
+        }
+    }
+
+    pub fn with_settings(mut self, settings: Option<Arc<Settings>>) -> Self {
+        self.settings = settings;
+        self
+    }
+}
+
+impl Stage for ComparePython {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "compare_python"
+    }
+
+    fn run(&self, (analysed_file_path, korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let file = analysed_file_path.to_path_buf();
+        let korp_mono_path = match KorpMonoPath::try_from(file.as_path()) {
+            Ok(path) => path,
+            Err(e) => {
+                tracing::warn!(file = ?file, error = %e, "can't compare with the old converter");
+                return Some((analysed_file_path, korp_mono_file));
+            }
+        };
+        let old_file = self.old_dir.join(korp_mono_path.relative());
+        let old = match std::fs::read_to_string(&old_file) {
+            Ok(old) => old,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                self.comparison
+                    .lock()
+                    .expect("no thread panics while holding the lock")
+                    .add_missing();
+                return Some((analysed_file_path, korp_mono_file));
+            }
+            Err(e) => {
+                tracing::warn!(file = ?old_file, error = %e, "can't read the old korp_mono file");
+                return Some((analysed_file_path, korp_mono_file));
+            }
+        };
+        let settings = corpus_settings(&self.settings, &file);
+        let columns = settings
+            .as_ref()
+            .and_then(|settings| settings.columns.as_deref())
+            .unwrap_or(&self.columns);
+        let mut new = Vec::new();
+        let compared = write_parts(&mut new, std::slice::from_ref(&korp_mono_file), columns)
+            .map_err(anyhow::Error::from)
+            .and_then(|()| {
+                self.comparison
+                    .lock()
+                    .expect("no thread panics while holding the lock")
+                    .compare(&old, &String::from_utf8_lossy(&new), columns)
+            });
+        if let Err(e) = compared {
+            tracing::warn!(file = ?old_file, error = %e, "can't compare with the old converter");
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Add the sentences of the converted documents to the search index, with
+/// `--index`. A document that can't be indexed is still written.
+#[cfg(feature = "index")]
+pub struct IndexSentences {
+    pub index: Arc<SentenceIndex>,
+}
+
+#[cfg(feature = "index")]
+impl IndexSentences {
+    pub fn new(index: Arc<SentenceIndex>) -> Self {
+        Self { index }
+    }
+}
+
+#[cfg(feature = "index")]
+impl Stage for IndexSentences {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "index_sentences"
+    }
+
+    fn run(&self, (analysed_file_path, korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let file = analysed_file_path.to_path_buf();
+        if let Err(e) = self.index.add(&file, &korp_mono_file) {
+            tracing::warn!(file = ?file, error = %e, "can't add the document to the index");
+        }
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Count the documents, sentences and tokens that are about to be written,
+/// for the run [`Metrics`].
+pub struct RecordMetrics {
+    pub metrics: Arc<Metrics>,
+}
+
+impl RecordMetrics {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl Stage for RecordMetrics {
+    type Input = Converted;
+    type Output = Converted;
+
+    fn name(&self) -> &'static str {
+        "record_metrics"
+    }
+
+    fn run(&self, (analysed_file_path, korp_mono_file): Self::Input) -> Option<Self::Output> {
+        self.metrics.add_document(&korp_mono_file);
+        Some((analysed_file_path, korp_mono_file))
+    }
+}
+
+/// Write the korp_mono file to its place in the `korp_mono/` directory, and
+/// return the path it was written to.
+///
+/// Files that are not inside an `analysed/` directory are refused, unless
+/// `allow_any_path` is set, in which case they are written directly to
+/// `output_dir`.
+///
+/// The file is first written to a temporary file next to it, and then
+/// renamed into place, so that a crash or a full disk never leaves a
+/// truncated korp_mono file behind. With an `archive`, the file is added to
+/// it instead, see [`crate::archive`].
+pub struct WriteKorpMono {
+    /// Write into `korp_mono/<gt_domain>/...` instead of `korp_mono/...`
+    pub partition_by_domain: bool,
+    /// Write into this directory, instead of the `korp_mono/` directory of
+    /// the corpus.
+    pub output_dir: Option<PathBuf>,
+    /// Accept input files that are not inside an `analysed/` directory.
+    /// Requires `output_dir`.
+    pub allow_any_path: bool,
+    /// `fsync` the file before renaming it into place.
+    pub fsync: bool,
+    /// Record the checksum of every written file here.
+    pub manifest: Option<Arc<Mutex<Manifest>>>,
+    /// Split documents with more sentences than this into several `<text>`
+    /// elements in the same file.
+    pub max_text_sentences: Option<usize>,
+    /// Write korp_mono XML, or JSON lines to a `.jsonl` file.
+    pub format: OutputFormat,
+    /// The columns of the tokens, the [`process_sentence::DEFAULT_COLUMNS`]
+    /// by default. See [`process_sentence::Options::columns`].
+    pub columns: Vec<Column>,
+    /// Write a `written` event for every written file.
+    pub progress: Option<Arc<JsonProgress>>,
+    /// For the columns of corpora with their own columns
+    pub settings: Option<Arc<Settings>>,
+    /// Add the files to this archive, instead of writing them.
+    pub archive: Option<Arc<Archive>>,
+    /// The line endings of the written files
+    pub newlines: NewlinePolicy,
+    /// Name the files after this template, instead of after the analysed
+    /// files, see [`korp_mono::template`].
+    pub template: Option<OutputTemplate>,
+    /// The files written with the `template` so far, and the analysed files
+    /// they were converted from, so that no document overwrites another.
+    pub outputs: Mutex<HashMap<PathBuf, PathBuf>>,
+}
+
+impl Default for WriteKorpMono {
+    fn default() -> Self {
+        Self {
+            partition_by_domain: false,
+            output_dir: None,
+            allow_any_path: false,
+            fsync: false,
+            manifest: None,
+            max_text_sentences: None,
+            format: OutputFormat::default(),
+            columns: process_sentence::DEFAULT_COLUMNS.to_vec(),
+            progress: None,
+            settings: None,
+            archive: None,
+            newlines: NewlinePolicy::default(),
+            template: None,
+            outputs: Mutex::default(),
+        }
+    }
+}
+
+/// The temporary file that `path` is written to before it is renamed.
+fn temp_path(path: &Path) -> PathBuf {
+    let mut name = std::ffi::OsString::from(".");
+    name.push(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    path.with_file_name(name)
+}
+
+/// Serialize the `<text>` elements of `parts` to `writer`, one after the
+/// other, with the token lines of `columns`.
+pub fn write_parts<W: Write>(
+    mut writer: W,
+    parts: &[KorpMonoFile],
+    columns: &[Column],
+) -> std::io::Result<()> {
+    for (i, part) in parts.iter().enumerate() {
+        if i > 0 {
+            writer.write_all(b"\n")?;
+        }
+        // the extra attributes are flattened into `<text>`, which makes it a
+        // map to serde, so the name of the root has to be given
+        let xml = quick_xml::se::to_string_with_root("text", &part.written(columns))
+            .map_err(std::io::Error::other)?;
+        writer.write_all(xml.as_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Write `parts` to `writer` in `format`, with the line endings of
+/// `newlines`. `columns` are the columns of the tokens.
+pub fn write_format<W: Write>(
+    mut writer: W,
+    parts: &[KorpMonoFile],
+    format: OutputFormat,
+    columns: &[Column],
+    newlines: NewlinePolicy,
+) -> std::io::Result<()> {
+    let mut buffer = vec![];
+    match format {
+        OutputFormat::Xml => write_parts(&mut buffer, parts, columns)?,
+        OutputFormat::Jsonl => write_jsonl(&mut buffer, parts, columns)?,
+    }
+    let text = String::from_utf8(buffer).map_err(std::io::Error::other)?;
+    writer.write_all(newlines.apply(&text).as_bytes())?;
+    writer.flush()
+}
+
+/// Write the `<text>` elements of `parts` to `tmp`, and rename it to `path`.
+/// Returns the checksum and size of the written file.
+fn write_atomically(
+    parts: &[KorpMonoFile],
+    tmp: &Path,
+    path: &Path,
+    fsync: bool,
+    write: impl FnOnce(&mut dyn Write, &[KorpMonoFile]) -> std::io::Result<()>,
+) -> std::io::Result<(u32, u64)> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(true)
+        .open(tmp)?;
+    let mut writer = ChecksumWriter::new(BufWriter::new(file));
+    write(&mut writer, parts)?;
+    let (checksum, bytes, writer) = writer.finish();
+    let file = writer.into_inner().map_err(|e| e.into_error())?;
+    if fsync {
+        file.sync_all()?;
+    }
+    std::fs::rename(tmp, path)?;
+    Ok((checksum, bytes))
+}
+
+/// Write the `<text>` elements of `parts` into `archive`, as `name`.
+/// Returns the checksum and size of the written file.
+fn write_archived(
+    parts: &[KorpMonoFile],
+    archive: &Archive,
+    name: &Path,
+    write: impl FnOnce(&mut dyn Write, &[KorpMonoFile]) -> std::io::Result<()>,
+) -> std::io::Result<(u32, u64)> {
+    let mut writer = ChecksumWriter::new(vec![]);
+    write(&mut writer, parts)?;
+    let (checksum, bytes, buffer) = writer.finish();
+    archive.append(name, &buffer)?;
+    Ok((checksum, bytes))
+}
+
+impl WriteKorpMono {
+    pub fn new(partition_by_domain: bool) -> Self {
+        Self {
+            partition_by_domain,
+            ..Default::default()
+        }
+    }
+
+    /// Where to write the korp_mono file `text`, converted from
+    /// `analysed_file`.
+    fn output_path(
+        &self,
+        analysed_file: &Path,
+        text: &KorpMonoFile,
+    ) -> Result<PathBuf, NotAnalysedPath> {
+        let (dir, relative) = match (KorpMonoPath::try_from(analysed_file), &self.output_dir) {
+            (Ok(path), None) => (path.dir().to_path_buf(), path.relative().to_path_buf()),
+            (Ok(path), Some(dir)) => (dir.clone(), path.relative().to_path_buf()),
+            (Err(_), Some(dir)) if self.allow_any_path => (
+                dir.clone(),
+                PathBuf::from(analysed_file.file_name().unwrap_or_default()),
+            ),
+            (Err(e), _) => return Err(e),
+        };
+        let path = match &self.template {
+            Some(template) => {
+                let corpus = corpus_dir(analysed_file)
+                    .and_then(Path::file_name)
+                    .and_then(|name| name.to_str());
+                dir.join(template.render(&relative, corpus, text))
+            }
+            None => dir.join(relative),
+        };
+        Ok(match self.format {
+            OutputFormat::Xml => path,
+            format => path.with_extension(format.extension()),
+        })
+    }
+}
+
+impl Stage for WriteKorpMono {
+    type Input = Converted;
+    type Output = PathBuf;
+
+    fn name(&self) -> &'static str {
+        "write"
+    }
+
+    fn run(&self, (analysed_file_path, korp_mono_file): Self::Input) -> Option<Self::Output> {
+        let p = match self.output_path(&analysed_file_path.to_path_buf(), &korp_mono_file) {
+            Ok(p) => p,
+            Err(e) => {
+                tracing::error!(
+                    error = %e,
+                    file = ?analysed_file_path.to_path_buf(),
+                    category = ErrorCategory::OutputPath.as_str(),
+                    "refusing to write korp_mono file"
+                );
+                return None;
+            }
+        };
+        let p = match self.partition_by_domain {
+            true => korp_mono::path::partition_by_domain(
+                &p,
+                korp_mono_file.gt_domain.as_deref().unwrap_or_default(),
+            ),
+            false => p,
+        };
+        if self.template.is_some() {
+            let mut outputs = self
+                .outputs
+                .lock()
+                .expect("no thread panics while holding the lock");
+            if let Some(other) = outputs.get(&p) {
+                tracing::error!(
+                    file = ?p,
+                    other = ?other,
+                    category = ErrorCategory::OutputPath.as_str(),
+                    "refusing to write korp_mono file, another document was written to the same path"
+                );
+                return None;
+            }
+            outputs.insert(p.clone(), analysed_file_path.to_path_buf());
+        }
+        /* rust: temporary value dropped while borrowed */
+        let parent = p.parent().expect("path to file has a parent directory");
+        // the files in an archive have no directories to create
+        let created = match &self.archive {
+            Some(_) => Ok(()),
+            None => std::fs::create_dir_all(parent),
+        };
+        if let Err(e) = created {
+            tracing::error!(
+                error = ?e,
+                file = ?p,
+                dir = ?parent,
+                category = ErrorCategory::CreateDir.as_str(),
+                "can't create directory"
+            );
+            return None;
+        }
+
+        let parts = match self.max_text_sentences {
+            Some(max) => korp_mono_file.split_parts(max),
+            None => vec![korp_mono_file],
+        };
+        let tmp = temp_path(&p);
+        let settings = corpus_settings(&self.settings, &analysed_file_path.to_path_buf());
+        let columns = settings
+            .as_ref()
+            .and_then(|settings| settings.columns.as_deref())
+            .unwrap_or(&self.columns);
+        let write = |writer: &mut dyn Write, parts: &[KorpMonoFile]| {
+            write_format(writer, parts, self.format, columns, self.newlines)
+        };
+        let written = match &self.archive {
+            Some(archive) => {
+                let analysed_file = analysed_file_path.to_path_buf();
+                let name = archive::entry_name(&p, &analysed_file, self.output_dir.as_deref());
+                write_archived(&parts, archive, &name, write)
+            }
+            None => write_atomically(&parts, &tmp, &p, self.fsync, write),
+        };
+        let (checksum, bytes) = match written {
+            Ok(written) => written,
+            Err(e) => {
+                tracing::error!(
+                    error = ?e,
+                    file = ?p,
+                    category = ErrorCategory::Write.as_str(),
+                    "can't write korp_mono file"
+                );
+                if self.archive.is_none() {
+                    let _ = std::fs::remove_file(&tmp);
+                }
+                return None;
+            }
+        };
+        if let Some(manifest) = &self.manifest {
+            manifest
+                .lock()
+                .expect("no thread panics while holding the lock")
+                .add(Entry {
+                    checksum,
+                    bytes,
+                    source: analysed_file_path.to_path_buf(),
+                    output: p.clone(),
+                });
+        }
+        if let Some(progress) = &self.progress {
+            progress.written(&analysed_file_path.to_path_buf(), &p);
+        }
+        Some(p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        MAX_STALLED_PARSES, ParseAnalyses, ParseXml, temp_path, write_atomically, write_parts,
+    };
+    use crate::korp_mono::KorpMonoFile;
+    use crate::pipeline::Stage;
+    use crate::process_sentence::Column;
+    use gtcorpusutil::AnalysedFilePath;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    #[test]
+    fn writes_extra_attributes() {
+        let dir = std::env::temp_dir().join(format!("korp-mono-write-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.xml");
+        let mut text = KorpMonoFile {
+            title: Some("Ođđasat".to_string()),
+            ..KorpMonoFile::with_sentences(&["Ođđasat\tođas\tN\tN.Pl.Nom\t1\tHNOUN\t0"])
+        };
+        text.header_extra = vec![("wordcount".to_string(), "803".to_string())];
+        text.copy_header_extras(&[("wordcount".to_string(), "wordcount".to_string())]);
+        let columns = [Column::Word, Column::Lemma, Column::Ref, Column::Dephead];
+        let written = write_atomically(&[text], &temp_path(&path), &path, false, |w, parts| {
+            write_parts(w, parts, &columns)
+        });
+        let xml = std::fs::read_to_string(&path);
+        std::fs::remove_dir_all(&dir).unwrap();
+        written.unwrap();
+        let xml = xml.unwrap();
+        assert!(xml.starts_with("<text "), "{xml}");
+        assert!(xml.contains(r#"title="Ođđasat""#), "{xml}");
+        assert!(xml.contains(r#"wordcount="803""#), "{xml}");
+        assert!(
+            xml.contains("<sentence id=\"1\">\nOđđasat\tođas\t1\t0\n</sentence>"),
+            "{xml}"
+        );
+    }
+
+    #[test]
+    fn skips_while_too_many_parses_are_stalled() {
+        let xml = concat!(
+            r#"<document xml:lang="sme" id="a"><header><title>A</title>"#,
+            r#"<conversion_status type="standard"/><availability/></header>"#,
+            r#"<body><dependency><![CDATA["<a>"]]></dependency></body></document>"#,
+        );
+        let file = || AnalysedFilePath::new_unchecked("/c/corpus-sme/analysed/a.xml".into());
+        let document = || {
+            ParseXml::default()
+                .run((file(), xml.to_string()))
+                .unwrap()
+                .1
+        };
+        let stage = ParseAnalyses::new(false, Some(Duration::from_secs(60)));
+        stage.stalled.store(MAX_STALLED_PARSES, Ordering::SeqCst);
+        assert!(stage.run((file(), document())).is_none());
+        stage
+            .stalled
+            .store(MAX_STALLED_PARSES - 1, Ordering::SeqCst);
+        assert!(stage.run((file(), document())).is_some());
+        assert_eq!(stage.stalled(), MAX_STALLED_PARSES - 1);
+    }
+}
@@ -0,0 +1,180 @@
+//! The tokens that end up with an unknown part of speech, with
+//! `--pos-coverage`, so that the tagsets of the parser can be completed.
+//!
+//! A token has an unknown part of speech when its analysis has none of the
+//! part of speech tags that `giellacgparser` knows, or when it has no
+//! analysis at all. The first are counted by their analysis line, the
+//! lemma and the tags as they are in the analyses, without the CG-internal
+//! tags (`"ja" CCC`), with a few of the word forms they were found for. The
+//! second are counted by their word form. The most frequent of both are
+//! printed at the end of the run, and all of them are written to a TSV file
+//! with `--pos-coverage-file`.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Write;
+use std::path::Path;
+
+use giellacgparser::tag::Pos;
+use itertools::Itertools;
+
+use crate::analysed::sentence::{Analysis, Sentence, SentencePart};
+
+/// The number of analysis lines and word forms in the report.
+const REPORTED: usize = 20;
+
+/// The number of example word forms kept for each analysis line.
+const EXAMPLES: usize = 3;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct Unknown {
+    tokens: u64,
+    forms: BTreeSet<String>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PosCoverage {
+    tokens: u64,
+    /// The tokens with an analysis without a known part of speech, by
+    /// analysis line
+    analyses: HashMap<String, Unknown>,
+    /// The tokens without an analysis, by word form
+    unanalysed: HashMap<String, u64>,
+}
+
+/// The analysis line of `analysis`: the lemma in quotes, and the tags,
+/// without the CG-internal ones, like the weights (`<W:0.0>`), so that the
+/// same analysis with other weights is counted together.
+fn analysis_line(analysis: &Analysis) -> String {
+    let tags = analysis.tags.iter().filter(|tag| !tag.angle_bracketed);
+    format!(
+        "\"{}\" {}",
+        analysis.lemma,
+        tags.map(|tag| &*tag.name).format(" ")
+    )
+}
+
+/// The entries of `counts`, the most frequent first, and in order of their
+/// keys when they are as frequent.
+fn by_frequency<'a, T>(
+    counts: impl Iterator<Item = (&'a String, T)>,
+    n: impl Fn(&T) -> u64,
+) -> Vec<(&'a String, T)> {
+    counts
+        .sorted_by(|(a, x), (b, y)| n(y).cmp(&n(x)).then_with(|| a.cmp(b)))
+        .collect()
+}
+
+impl PosCoverage {
+    /// Count the tokens of the parsed `sentences` of a document.
+    pub fn add_sentences(&mut self, sentences: &[Sentence]) {
+        let cohorts = sentences
+            .iter()
+            .flat_map(|sentence| sentence.parts.iter())
+            .filter_map(|part| match part {
+                SentencePart::Cohort(cohort) if cohort.word_form != "¶" => Some(cohort),
+                _ => None,
+            });
+        for cohort in cohorts {
+            self.tokens += 1;
+            match &cohort.analysis {
+                Some(analysis) if analysis.pos == Pos::Unknown => {
+                    let unknown = self.analyses.entry(analysis_line(analysis)).or_default();
+                    unknown.tokens += 1;
+                    if unknown.forms.len() < EXAMPLES {
+                        unknown.forms.insert(cohort.word_form.clone());
+                    }
+                }
+                Some(_) => {}
+                None => *self.unanalysed.entry(cohort.word_form.clone()).or_default() += 1,
+            }
+        }
+    }
+
+    fn unknown_tokens(&self) -> u64 {
+        self.analyses.values().map(|unknown| unknown.tokens).sum()
+    }
+
+    /// A summary for the end of the run, with the most frequent analysis
+    /// lines and word forms.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        let unanalysed = self.unanalysed.values().sum::<u64>();
+        writeln!(
+            s,
+            "{} of {} tokens with an unknown part of speech: {} with an analysis ({} distinct), {} without ({} distinct)",
+            self.unknown_tokens() + unanalysed,
+            self.tokens,
+            self.unknown_tokens(),
+            self.analyses.len(),
+            unanalysed,
+            self.unanalysed.len()
+        )
+        .expect(w);
+        let analyses = by_frequency(self.analyses.iter(), |unknown| unknown.tokens);
+        for (line, unknown) in analyses.into_iter().take(REPORTED) {
+            let forms = unknown.forms.iter().format(", ");
+            writeln!(s, "  {:>8}  {line}  ({forms})", unknown.tokens).expect(w);
+        }
+        if !self.unanalysed.is_empty() {
+            writeln!(s, "  without an analysis:").expect(w);
+        }
+        let unanalysed = by_frequency(self.unanalysed.iter(), |n| **n);
+        for (form, n) in unanalysed.into_iter().take(REPORTED) {
+            writeln!(s, "  {n:>8}  {form}").expect(w);
+        }
+        s
+    }
+
+    /// All analysis lines and word forms, as TSV: the kind (`analysis` or
+    /// `unanalysed`), the number of tokens, the analysis line or word form,
+    /// and the example word forms of the analysis lines.
+    pub fn render_tsv(&self) -> String {
+        let mut s = String::from("kind\ttokens\tvalue\tforms\n");
+        let w = "can always write to String";
+        for (line, unknown) in by_frequency(self.analyses.iter(), |unknown| unknown.tokens) {
+            let forms = unknown.forms.iter().format(" ");
+            writeln!(s, "analysis\t{}\t{line}\t{forms}", unknown.tokens).expect(w);
+        }
+        for (form, n) in by_frequency(self.unanalysed.iter(), |n| **n) {
+            writeln!(s, "unanalysed\t{n}\t{form}\t").expect(w);
+        }
+        s
+    }
+
+    pub fn write_file(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.render_tsv())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PosCoverage;
+    use crate::analysed::sentence::Sentence;
+
+    #[test]
+    fn unknown() {
+        let blob = concat!(
+            "\"<Ja>\"\n\t\"ja\" CCC <W:0.0> @CVP #1->0\n",
+            "\"<mun>\"\n\t\"mun\" Pron Pers Sg1 Nom <W:0.0> @SUBJ> #2->0\n",
+            "\"<jA>\"\n\t\"ja\" CCC <W:0.0> @CVP #3->0\n",
+            "\"<xyz>\"\n",
+        );
+        let (_, parsed) = giellacgparser::parse_sentences(blob).unwrap();
+        let mut coverage = PosCoverage::default();
+        coverage.add_sentences(&Sentence::all(&parsed.sentences, blob));
+        assert_eq!(
+            coverage.report(),
+            concat!(
+                "3 of 4 tokens with an unknown part of speech: 2 with an analysis (1 distinct), 1 without (1 distinct)\n",
+                "         2  \"ja\" CCC  (Ja, jA)\n",
+                "  without an analysis:\n",
+                "         1  xyz\n",
+            )
+        );
+        assert_eq!(
+            coverage.render_tsv(),
+            "kind\ttokens\tvalue\tforms\nanalysis\t2\t\"ja\" CCC\tJa jA\nunanalysed\t1\txyz\t\n"
+        );
+    }
+}
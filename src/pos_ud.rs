@@ -0,0 +1,81 @@
+//! Conversion of the giella parts of speech to the Universal Dependencies
+//! UPOS tags, for `--tagset ud` and the `upos` column.
+//!
+//! Most parts of speech map directly to a UPOS tag, see [`upos_of_pos`].
+//! Proper nouns are nouns with a `Prop` tag. Some words get a different tag
+//! than their part of speech says, depending on the language, e.g. the
+//! copula and the negation verb are `AUX` in the UD treebanks of the Sámi
+//! languages and of Finnish. Those are listed in [`LANGUAGE_OVERRIDES`].
+
+/// Which tags to write in the pos column.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Tagset {
+    /// The giella parts of speech, `N`, `V`, `Pcle`, ...
+    #[default]
+    Giella,
+    /// The Universal Dependencies UPOS tags, `NOUN`, `VERB`, `PART`, ...
+    Ud,
+}
+
+/// Words that get another UPOS tag than their part of speech gives, as
+/// `(language, lemma, giella pos, upos)`.
+pub const LANGUAGE_OVERRIDES: &[(&str, &str, &str, &str)] = &[
+    ("sme", "leat", "V", "AUX"),
+    ("sme", "ii", "V", "AUX"),
+    ("smj", "liehket", "V", "AUX"),
+    ("smj", "ij", "V", "AUX"),
+    ("fin", "olla", "V", "AUX"),
+    ("fin", "ei", "V", "AUX"),
+];
+
+/// The UPOS tag of the giella part of speech `pos`, `X` if it has none.
+pub fn upos_of_pos(pos: &str) -> &'static str {
+    match pos {
+        "N" => "NOUN",
+        "V" => "VERB",
+        "A" => "ADJ",
+        "Adv" => "ADV",
+        "Pron" => "PRON",
+        "Det" => "DET",
+        "Num" => "NUM",
+        "Pcle" => "PART",
+        "CC" => "CCONJ",
+        "CS" => "SCONJ",
+        "Po" | "Pr" | "Adp" => "ADP",
+        "Interj" => "INTJ",
+        "CLB" | "PUNCT" | "LEFT" | "RIGHT" => "PUNCT",
+        "Symbol" => "SYM",
+        _ => "X",
+    }
+}
+
+/// The UPOS tag of a token with the part of speech `pos`, and the lemma
+/// `lemma`, in the language `lang`. `proper` tells if the token has the
+/// `Prop` tag.
+pub fn upos(pos: &str, lemma: &str, lang: &str, proper: bool) -> &'static str {
+    let overridden = LANGUAGE_OVERRIDES
+        .iter()
+        .find(|(l, word, p, _)| *l == lang && *word == lemma && *p == pos);
+    match (overridden, pos) {
+        (Some((_, _, _, upos)), _) => upos,
+        (None, "N") if proper => "PROPN",
+        (None, pos) => upos_of_pos(pos),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::upos;
+
+    #[test]
+    fn tags() {
+        assert_eq!(upos("N", "girji", "sme", false), "NOUN");
+        assert_eq!(upos("N", "Kárášjohka", "sme", true), "PROPN");
+        assert_eq!(upos("V", "leat", "sme", false), "AUX");
+        assert_eq!(upos("V", "leat", "sma", false), "VERB");
+        assert_eq!(upos("V", "olla", "fin", false), "AUX");
+        assert_eq!(upos("Pcle", "go", "sme", false), "PART");
+        assert_eq!(upos("CLB", ".", "sme", false), "PUNCT");
+        assert_eq!(upos("Unknown", "x", "sme", false), "X");
+    }
+}
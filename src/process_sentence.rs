@@ -1,4 +1,4 @@
-//! Transform an fst_analysis_parser::Sentence to the string format
+//! Transform a parsed [`Sentence`] to the string format
 //! needed by the korp_mono file. This format contains each word in the
 //! sentence on its own line. Additionaly, each line contains tab-separated
 //! properties of that word. Such as this:
@@ -7,70 +7,628 @@
 //! da	da	CC	CC	2	CNP	1
 //! kulttuur	kulttuur	N	N.Pl.Nom	3	HNOUN	4
 //! jeälltummuš	jeälltummuš	N	N.Sg.Nom	4	HNOUN	0
+//!
+//! The sentence is first turned into a [`TokenRow`] per token, which is then
+//! written with the selected [`Column`]s.
 
-use std::fmt::Debug;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Debug, Write};
 
-use giellacgparser::{
-    Reading,
-    tag::{Pos, Tag},
-};
+use giellacgparser::tag::Pos;
 use itertools::Itertools;
 
-fn tags_of<'a>(analysis: &'a giellacgparser::Analysis<'a>) -> impl Iterator<Item = &'a Tag<'a>> {
+use crate::analysed::sentence::{Analysis, Sentence, SentencePart, Tag};
+use crate::anonymize::{self, Anonymizer};
+use crate::deprel::DeprelMap;
+use crate::msd_order;
+use crate::pos_ud::{self, Tagset};
+
+/// Which classes of tags to keep in the morpho syntactic description.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum MsdProfile {
+    /// All tags, including semantic, derivational, and error tags. Only the
+    /// angle bracketed CG-internal tags are left out.
+    Full,
+    /// The tags that korp_mono.py keeps: no semantic tags, and no
+    /// orthographic error tags.
+    #[default]
+    Korp,
+    /// Only the inflectional tags: like `korp`, but also without any
+    /// derivational, error, grammatical, or compound tags.
+    Minimal,
+}
+
+/// Tags starting with these are left out of the `minimal` profile.
+const NON_INFLECTIONAL_TAG_PREFIXES: [&str; 4] = ["Der/", "Err/", "Gram/", "Cmp"];
+
+impl MsdProfile {
+    /// Is `tag` included in the msd with this profile?
+    fn keeps(self, tag: &Tag) -> bool {
+        match self {
+            // don't include the tags that start with an "<",
+            // like <mv>, <ehead>, and <aux>, and also all of these
+            // from korp_mono.py:
+            // <cohort-with-dynamic-compound> <ext> <cs> <hab>
+            // <loc> <gen> <ctjHead>
+            MsdProfile::Full => !tag.angle_bracketed,
+            MsdProfile::Korp => !tag.sem && !tag.angle_bracketed && !tag.orth_error,
+            MsdProfile::Minimal => {
+                MsdProfile::Korp.keeps(tag)
+                    && !NON_INFLECTIONAL_TAG_PREFIXES
+                        .iter()
+                        .any(|prefix| tag.name.starts_with(prefix))
+            }
+        }
+    }
+}
+
+/// Where punctuation tokens are attached in the dependency tree, see
+/// [`attach_punct`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PunctAttachment {
+    /// To the root of the sentence
+    Root,
+    /// To the token before it, or to the root when it starts the sentence
+    Previous,
+}
+
+/// Options that change how sentences are processed.
+#[derive(Debug, Default, Clone)]
+pub struct Options {
+    /// If set, mask personal names in the word form and lemma fields.
+    pub anonymizer: Option<Anonymizer>,
+    /// Which tags to keep in the msd field.
+    pub msd_profile: MsdProfile,
+    /// Sort the tags of the msd field in a canonical order, instead of the
+    /// order of the analyser. See [`crate::msd_order`].
+    pub canonical_msd: bool,
+    /// Add two columns after parent_id, with the start and end character
+    /// offsets of the token in the text of the document.
+    pub with_offsets: bool,
+    /// Put the distinct lemmas of all readings in the lemma field, as a Korp
+    /// set-valued attribute (`|lemma1|lemma2|`), instead of only the lemma of
+    /// the first reading.
+    pub multi_lemma: bool,
+    /// Add a column with the language of the token, after the offset columns:
+    /// the language of its `Lang/xxx` tag, or else the language of the
+    /// document.
+    pub lang_column: bool,
+    /// Add a column with the lemmas of the parts of dynamic compounds, after
+    /// the language column. See [`compound_segments`].
+    pub compound_column: bool,
+    /// Write these columns, in this order, instead of the default ones.
+    /// Overrides `with_offsets`, `lang_column` and `tagset`.
+    pub columns: Option<Vec<Column>>,
+    /// With [`Tagset::Ud`], the pos column of the default columns is the
+    /// UPOS tag instead.
+    pub tagset: Tagset,
+    /// Add the sentence as plain text to each sentence, see
+    /// [`surface_text`].
+    pub sentence_text: bool,
+    /// Fail the document when a token has a tab or a newline in one of its
+    /// columns, instead of replacing them, see [`TokenRow::escape_columns`].
+    pub strict_columns: bool,
+    /// Renumber the self and parent ids of the tokens of each sentence to
+    /// start at 1, see [`renumber_ids`].
+    pub renumber_ids: bool,
+    /// Normalize the functional labels to the labels of [`crate::deprel`].
+    pub deprel_map: Option<DeprelMap>,
+    /// Add a column with the functional label before it was normalized,
+    /// after the compound column.
+    pub raw_deprel_column: bool,
+    /// Give the punctuation tokens a functional label, and attach them in
+    /// the dependency tree, see [`attach_punct`].
+    pub attach_punct: Option<PunctAttachment>,
+}
+
+/// A column of the token lines. See [`Options::columns`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Column {
+    /// The word form
+    Word,
+    /// The lemma
+    Lemma,
+    /// The part of speech
+    Pos,
+    /// The morpho syntactic description, the tags joined with `.`
+    Msd,
+    /// The self_id of the token in the dependency tree
+    Ref,
+    /// The functional label
+    Deprel,
+    /// The parent_id of the token in the dependency tree
+    Dephead,
+    /// The character offset of the start of the token in the document
+    Start,
+    /// The character offset of the end of the token in the document
+    End,
+    /// The language of the token
+    Lang,
+    /// The lemmas of the parts of a dynamic compound, joined with `+`
+    Compound,
+    /// The Universal Dependencies UPOS tag, see [`crate::pos_ud`]
+    Upos,
+    /// The functional label, before it was normalized, see
+    /// [`crate::deprel`]
+    #[value(name = "raw_deprel")]
+    RawDeprel,
+}
+
+/// The columns that are written when no columns are given explicitly.
+pub const DEFAULT_COLUMNS: [Column; 7] = [
+    Column::Word,
+    Column::Lemma,
+    Column::Pos,
+    Column::Msd,
+    Column::Ref,
+    Column::Deprel,
+    Column::Dephead,
+];
+
+impl Options {
+    /// The columns to write, in order: [`Options::columns`] if it is set.
+    /// Otherwise the [`DEFAULT_COLUMNS`], followed by the offset columns if
+    /// [`Options::with_offsets`] is set, the language column if
+    /// [`Options::lang_column`] is set, the compound column if
+    /// [`Options::compound_column`] is set, and the raw deprel column if
+    /// [`Options::raw_deprel_column`] is set. With [`Tagset::Ud`], the pos
+    /// column is replaced by the UPOS column.
+    pub fn columns(&self) -> Cow<'_, [Column]> {
+        if let Some(columns) = &self.columns {
+            return Cow::Borrowed(columns);
+        }
+        if !self.with_offsets
+            && !self.lang_column
+            && !self.compound_column
+            && !self.raw_deprel_column
+            && self.tagset == Tagset::Giella
+        {
+            return Cow::Borrowed(&DEFAULT_COLUMNS);
+        }
+        let mut columns = DEFAULT_COLUMNS.to_vec();
+        if self.tagset == Tagset::Ud {
+            for column in columns.iter_mut().filter(|c| **c == Column::Pos) {
+                *column = Column::Upos;
+            }
+        }
+        if self.with_offsets {
+            columns.extend([Column::Start, Column::End]);
+        }
+        if self.lang_column {
+            columns.push(Column::Lang);
+        }
+        if self.compound_column {
+            columns.push(Column::Compound);
+        }
+        if self.raw_deprel_column {
+            columns.push(Column::RawDeprel);
+        }
+        Cow::Owned(columns)
+    }
+}
+
+/// One token of a processed sentence, with all the fields that can be
+/// written as columns. Sentences are first turned into rows, with
+/// [`sentence_rows`], and then written with [`write_rows`], so that the rows
+/// can be filtered or changed in between. The text fields borrow from the
+/// [`Sentence`], and are only owned when they are made up for the row, or
+/// when the row has to outlive the sentence, see [`TokenRow::into_owned`].
+#[derive(Clone)]
+pub struct TokenRow<'a> {
+    pub word_form: Cow<'a, str>,
+    pub lemma: Cow<'a, str>,
+    pub pos: Pos,
+    pub msd: Cow<'a, str>,
+    pub self_id: usize,
+    pub func: Cow<'a, str>,
+    /// The functional label before it was normalized
+    pub raw_func: Cow<'a, str>,
+    pub parent_id: usize,
+    /// Character offset of the start of the token in the document
+    pub start: usize,
+    /// Character offset of the end of the token in the document
+    pub end: usize,
+    /// The language of the token, borrowed from its language tag, or from
+    /// the document
+    pub lang: Cow<'a, str>,
+    /// The lemmas of the parts, if the token is a dynamic compound
+    pub compound: Cow<'a, str>,
+    /// The UPOS tag, only set when the UPOS column is written
+    pub upos: &'static str,
+}
+
+/// The value of one column of a [`TokenRow`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnValue<'r> {
+    Number(usize),
+    Text(&'r str),
+}
+
+impl TokenRow<'_> {
+    /// The value of the column `column` of the token.
+    pub fn value(&self, column: Column) -> ColumnValue<'_> {
+        match column {
+            Column::Word => ColumnValue::Text(&self.word_form),
+            Column::Lemma => ColumnValue::Text(&self.lemma),
+            Column::Pos => ColumnValue::Text(self.pos.as_str()),
+            Column::Msd => ColumnValue::Text(&self.msd),
+            Column::Ref => ColumnValue::Number(self.self_id),
+            Column::Deprel => ColumnValue::Text(&self.func),
+            Column::Dephead => ColumnValue::Number(self.parent_id),
+            Column::Start => ColumnValue::Number(self.start),
+            Column::End => ColumnValue::Number(self.end),
+            Column::Lang => ColumnValue::Text(&self.lang),
+            Column::Compound => ColumnValue::Text(&self.compound),
+            Column::Upos => ColumnValue::Text(self.upos),
+            Column::RawDeprel => ColumnValue::Text(&self.raw_func),
+        }
+    }
+
+    /// Append the `columns` of the token to `s`, separated by tab. The line
+    /// is not ended, see [`write_rows`], so that no column ever ends with a
+    /// newline.
+    pub fn write_line(&self, s: &mut String, columns: &[Column]) {
+        for (i, column) in columns.iter().enumerate() {
+            if i > 0 {
+                s.push('\t');
+            }
+            match self.value(*column) {
+                ColumnValue::Number(n) => write!(s, "{n}").expect("can always write to String"),
+                ColumnValue::Text(text) => s.push_str(text),
+            }
+        }
+    }
+
+    /// The row, with its text fields owned, so that it can be kept after
+    /// the [`Sentence`] it was made from is gone.
+    pub fn into_owned(self) -> TokenRow<'static> {
+        TokenRow {
+            word_form: Cow::Owned(self.word_form.into_owned()),
+            lemma: Cow::Owned(self.lemma.into_owned()),
+            pos: self.pos,
+            msd: Cow::Owned(self.msd.into_owned()),
+            self_id: self.self_id,
+            func: Cow::Owned(self.func.into_owned()),
+            raw_func: Cow::Owned(self.raw_func.into_owned()),
+            parent_id: self.parent_id,
+            start: self.start,
+            end: self.end,
+            lang: Cow::Owned(self.lang.into_owned()),
+            compound: Cow::Owned(self.compound.into_owned()),
+            upos: self.upos,
+        }
+    }
+
+    /// Replace the tabs, newlines and other control characters in the text
+    /// columns with spaces, since they would break the token line. Returns
+    /// if anything was replaced.
+    pub fn escape_columns(&mut self) -> bool {
+        let mut escaped = false;
+        for column in [
+            &mut self.word_form,
+            &mut self.lemma,
+            &mut self.msd,
+            &mut self.func,
+            &mut self.raw_func,
+            &mut self.lang,
+            &mut self.compound,
+        ] {
+            if column.contains(char::is_control) {
+                *column = Cow::Owned(column.replace(char::is_control, " "));
+                escaped = true;
+            }
+        }
+        escaped
+    }
+}
+
+/// Escape the columns of all `rows`, see [`TokenRow::escape_columns`].
+/// Returns the number of rows that had something escaped.
+pub fn escape_rows(rows: &mut [TokenRow]) -> usize {
+    rows.iter_mut()
+        .map(TokenRow::escape_columns)
+        .filter(|&escaped| escaped)
+        .count()
+}
+
+/// Renumber the self ids of the tokens of a sentence to 1, 2, 3, ..., in
+/// order, and their parent ids to match. Some analyses number the tokens
+/// through the whole document, so that the ids of a sentence don't start
+/// at 1. Tokens without an analysis keep their self id of 0. A parent id of
+/// 0 (the root) is kept, and one that is not the self id of any token of
+/// the sentence becomes 0. Returns if any id changed.
+pub fn renumber_ids(rows: &mut [TokenRow]) -> bool {
+    let mut new_ids = HashMap::with_capacity(rows.len());
+    for (i, row) in rows.iter().enumerate() {
+        if row.self_id != 0 {
+            new_ids.entry(row.self_id).or_insert(i + 1);
+        }
+    }
+    let mut changed = false;
+    for (i, row) in rows.iter_mut().enumerate() {
+        let self_id = match row.self_id {
+            0 => 0,
+            _ => i + 1,
+        };
+        let parent_id = match row.parent_id {
+            0 => 0,
+            parent_id => new_ids.get(&parent_id).copied().unwrap_or(0),
+        };
+        changed |= row.self_id != self_id || row.parent_id != parent_id;
+        row.self_id = self_id;
+        row.parent_id = parent_id;
+    }
+    changed
+}
+
+/// Punctuation that is not ASCII, for the tokens without an analysis.
+const UNICODE_PUNCTUATION: &str = "«»“”„‘’‹›…–—•·¶§";
+
+/// Is `row` a punctuation token: a `CLB` or `PUNCT`, or a token without an
+/// analysis that is only punctuation?
+fn is_punct(row: &TokenRow) -> bool {
+    match row.pos.as_str() {
+        "CLB" | "PUNCT" => true,
+        _ => {
+            matches!(row.pos, Pos::Unknown)
+                && !row.word_form.is_empty()
+                && row
+                    .word_form
+                    .chars()
+                    .all(|c| c.is_ascii_punctuation() || UNICODE_PUNCTUATION.contains(c))
+        }
+    }
+}
+
+/// Give the punctuation tokens of a sentence the functional label `CLB` or
+/// `PUNCT` (normalized with `deprel_map`, if there is one), unless they
+/// already have one, and attach them to the root of the sentence, or to the
+/// token before them, as `attachment` says. Without a parse, punctuation is
+/// otherwise left with the label `X`, and often outside of the tree.
+/// Punctuation without a self id gets one after the highest id of the
+/// sentence. The root is the first token that is not punctuation and has
+/// no parent. Returns the number of tokens that were attached.
+pub fn attach_punct(
+    rows: &mut [TokenRow],
+    attachment: PunctAttachment,
+    deprel_map: Option<&DeprelMap>,
+) -> usize {
+    let root = rows
+        .iter()
+        .find(|row| row.self_id != 0 && row.parent_id == 0 && !is_punct(row))
+        .map_or(0, |row| row.self_id);
+    let mut next_id = rows.iter().map(|row| row.self_id).max().unwrap_or(0) + 1;
+    let mut previous = 0;
+    let mut attached = 0;
+    for row in rows.iter_mut() {
+        if !is_punct(row) {
+            if row.self_id != 0 {
+                previous = row.self_id;
+            }
+            continue;
+        }
+        if row.raw_func == "X" {
+            row.raw_func = Cow::Borrowed(match row.pos.as_str() {
+                "CLB" => "CLB",
+                _ => "PUNCT",
+            });
+            row.func = match deprel_map {
+                Some(map) => Cow::Borrowed(map.normalize(&row.raw_func)),
+                None => row.raw_func.clone(),
+            };
+        }
+        if row.self_id == 0 {
+            row.self_id = next_id;
+            next_id += 1;
+        }
+        row.parent_id = match (attachment, previous) {
+            (PunctAttachment::Previous, previous) if previous != 0 => previous,
+            _ => root,
+        };
+        if row.parent_id == row.self_id {
+            row.parent_id = 0;
+        }
+        attached += 1;
+    }
+    attached
+}
+
+/// The line terminator between the token lines of a sentence.
+pub const LINE_TERMINATOR: char = '\n';
+
+/// Append the `columns` of all `rows` to `s`, one line per row. The lines
+/// are separated by [`LINE_TERMINATOR`], and the last one is not ended: the
+/// sentence is put on lines of its own when it is serialized, see
+/// [`crate::korp_mono::file::Sentence`].
+pub fn write_rows(rows: &[TokenRow], columns: &[Column], s: &mut String) {
+    for (i, row) in rows.iter().enumerate() {
+        if i > 0 {
+            s.push(LINE_TERMINATOR);
+        }
+        row.write_line(s, columns);
+    }
+}
+
+/// The rows of the token lines `text`, with the [`DEFAULT_COLUMNS`], for
+/// the tests of the modules that look at converted sentences. The pos is
+/// left unknown, and the columns that are missing are empty, or 0.
+#[cfg(test)]
+pub(crate) fn rows_from_lines(text: &str) -> Vec<TokenRow<'static>> {
+    text.lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let columns = line.split('\t').collect::<Vec<_>>();
+            let text =
+                |i: usize| Cow::Owned(columns.get(i).copied().unwrap_or_default().to_string());
+            let id = |i: usize| columns.get(i).and_then(|id| id.parse().ok()).unwrap_or(0);
+            let func = text(5);
+            TokenRow {
+                word_form: text(0),
+                lemma: text(1),
+                pos: Pos::Unknown,
+                msd: text(3),
+                self_id: id(4),
+                raw_func: func.clone(),
+                func,
+                parent_id: id(6),
+                start: 0,
+                end: 0,
+                lang: Cow::Borrowed(""),
+                compound: Cow::Borrowed(""),
+                upos: "X",
+            }
+        })
+        .collect()
+}
+
+/// The lemmas of the parts of a dynamic compound, joined with `+`, from
+/// the generator directive that is its lemma. So
+/// `[[[GEN:#várri+N+Cmp/SgNom+Cmp#ovddasteaddji+N+NomAg+Sg+Nom]]]` gives
+/// `várri+ovddasteaddji`. `None` if `lemma` is not a directive.
+pub fn compound_segments(lemma: &str) -> Option<String> {
+    let analysis = lemma.strip_prefix("[[[GEN:")?.strip_suffix("]]]")?;
+    let segments = analysis
+        .split('#')
+        .filter(|part| !part.is_empty())
+        .map(|part| part.split('+').next().unwrap_or(part))
+        .collect::<Vec<_>>();
+    (segments.len() > 1).then(|| segments.join("+"))
+}
+
+/// The language of the tag `tag`, if it is a language tag (`Lang/xxx`).
+fn lang_of_tag(tag: &str) -> Option<&str> {
+    tag.strip_prefix("Lang/").filter(|lang| !lang.is_empty())
+}
+
+/// The number of tokens of `rows` that have more than one lemma in the
+/// lemma field (see [`Options::multi_lemma`]).
+pub fn count_ambiguous_lemmas(rows: &[TokenRow]) -> usize {
+    rows.iter()
+        .filter(|row| row.lemma.trim_matches('|').contains('|'))
+        .count()
+}
+
+fn tags_of(analysis: &Analysis, profile: MsdProfile) -> impl Iterator<Item = &str> {
     analysis
-        .all_tags()
-        // don't include the tags that start with an "<",
-        // like <mv>, <ehead>, and <aux>, and also all of these
-        // from korp_mono.py:
-        // <cohort-with-dynamic-compound> <ext> <cs> <hab>
-        // <loc> <gen> <ctjHead>
-        // We already did the pos
-        .filter(|&tag| !tag.is_sem())
-        .filter(|&tag| !tag.is_angle_bracketed())
-        .filter(|&tag| !tag.is_err_starts_with("Orth"))
+        .tags
+        .iter()
+        .filter(move |tag| profile.keeps(tag))
+        .map(|tag| &*tag.name)
 }
 
-/// Turn a [`fst_analysis_parser::Sentence`] into a [`String`].
+/// Turn a [`Sentence`] into a [`String`].
 ///
 /// Each Sentence will be turned into one line, with the fields separated by
 /// tab. The fields are, in this order:
 ///
 /// word form, lemma, pos, morpho syntactic description, self_id,
 /// functional label, parent_id
-pub fn process_sentence<'a, 'b>(sentence: &'a giellacgparser::Sentence<'b>) -> String {
+///
+/// If [`Options::with_offsets`] is set, two more fields follow: the start and
+/// end offsets of the token, in characters, counted from the start of the
+/// sentence. If [`Options::lang_column`] is set, the last field is the
+/// language of the token. [`Options::columns`] selects and orders the fields
+/// freely.
+pub fn process_sentence(sentence: &Sentence, options: &Options) -> String {
     let mut s = String::with_capacity(50);
+    process_sentence_into(sentence, options, "", &mut s, &mut 0);
+    s
+}
+
+/// The functional label `func`, with the `<` and `>` that indicate the
+/// direction to the head replaced by `←` and `→`. Only allocates if there
+/// is something to replace.
+fn func_label(func: &str) -> Cow<'_, str> {
+    if !func.contains(['<', '>']) {
+        return Cow::Borrowed(func);
+    }
+    Cow::Owned(
+        func.chars()
+            .map(|c| match c {
+                '<' => '←',
+                '>' => '→',
+                c => c,
+            })
+            .collect(),
+    )
+}
 
-    fn add_line(
-        s: &mut String,
-        word_form: &str,
-        lemma: &str,
-        pos: giellacgparser::tag::Pos,
-        tags: &str,
-        self_id: usize,
-        func: &str,
-        parent_id: usize,
-    ) {
-        use std::fmt::Write;
-        s.push_str(word_form);
-        s.push('\t');
-        s.push_str(lemma);
-        s.push('\t');
-        s.push_str(pos.as_str());
-        s.push('\t');
-        s.push_str(tags);
-        s.push('\t');
-        write!(s, "{self_id}").expect("can always write to String");
-        s.push('\t');
-        s.push_str(func);
-        s.push('\t');
-        write!(s, "{parent_id}").expect("can always write to String");
-        s.push('\n');
+/// Like [`process_sentence`], but appends to `s` instead of allocating a new
+/// [`String`]. Use this with a buffer that is reused between sentences, to
+/// avoid allocating for every sentence.
+///
+/// `offset` and `doc_lang` are as in [`sentence_rows`].
+pub fn process_sentence_into(
+    sentence: &Sentence,
+    options: &Options,
+    doc_lang: &str,
+    s: &mut String,
+    offset: &mut usize,
+) {
+    let mut rows = sentence_rows(sentence, options, doc_lang, offset);
+    escape_rows(&mut rows);
+    if options.renumber_ids {
+        renumber_ids(&mut rows);
     }
+    write_rows(&rows, &options.columns(), s);
+}
+
+/// Punctuation that is written without a space before it.
+const CLOSING_PUNCTUATION: &[&str] = &[
+    ".", ",", ";", ":", "!", "?", "%", ")", "]", "}", "»", "”", "…", "...",
+];
+/// Punctuation that is written without a space after it.
+const OPENING_PUNCTUATION: &[&str] = &["(", "[", "{", "«", "“"];
+
+/// The sentence as plain text: the word forms of `rows`, see
+/// [`detokenize`].
+pub fn surface_text(rows: &[TokenRow]) -> String {
+    detokenize(rows.iter().map(|row| &*row.word_form))
+}
+
+/// `words`, separated by spaces, except before closing punctuation, and
+/// after opening punctuation.
+pub fn detokenize<'a>(words: impl IntoIterator<Item = &'a str>) -> String {
+    let mut s = String::new();
+    let mut space_after_previous = false;
+    for word in words {
+        if space_after_previous && !CLOSING_PUNCTUATION.contains(&word) {
+            s.push(' ');
+        }
+        s.push_str(word);
+        space_after_previous = !OPENING_PUNCTUATION.contains(&word);
+    }
+    s
+}
+
+/// Turn the cohorts of `sentence` into token rows.
+///
+/// `offset` is the character offset of the start of the sentence in the
+/// document, and is advanced past the sentence. The text of the document is
+/// the word forms and the cohort separators (the whitespace between words)
+/// of all sentences, in order.
+///
+/// `doc_lang` is the language of the document, used for the language column
+/// of the tokens that have no language tag of their own.
+///
+/// With [`Options::attach_punct`], the punctuation is attached in the
+/// dependency tree, see [`attach_punct`].
+pub fn sentence_rows<'a>(
+    sentence: &'a Sentence,
+    options: &Options,
+    doc_lang: &'a str,
+    offset: &mut usize,
+) -> Vec<TokenRow<'a>> {
+    let mut rows = Vec::with_capacity(sentence.parts.len());
+    let columns = options.columns();
+    let want_lang = columns.contains(&Column::Lang);
+    let want_upos = columns.contains(&Column::Upos);
 
     for part in sentence.parts.iter() {
         match part {
-            giellacgparser::SentencePart::Cohort(cohort) => {
-                let wf: &str = cohort.word_form;
+            SentencePart::Cohort(cohort) => {
+                let wf = cohort.word_form.as_str();
 
                 if wf == "¶" {
                     // sentinel word to indicate end of paragraph,
@@ -78,41 +636,117 @@ pub fn process_sentence<'a, 'b>(sentence: &'a giellacgparser::Sentence<'b>) -> S
                     continue;
                 }
 
-                let mut pos = Pos::Unknown;
-                let mut self_id = 0;
-                let mut parent_id = 0;
-                let mut func = String::from("X");
-                let mut msd = String::from("___");
-
-                match cohort.first_reading_with_analysis() {
-                    Some(reading) => {
-                        let lemma = giellacgparser::reading_lemma(reading.clone());
-                        if let Some(ref analysis) = reading.borrow().analysis {
-                            if let Some(funcc) = analysis.func {
-                                func = funcc.replace(">", "→").as_str().replace("<", "←");
+                let start = *offset;
+                *offset += wf.chars().count();
+
+                // the row borrows from the sentence, so that only the msd,
+                // and the fields that are made up, are allocated per token
+                let mut row = TokenRow {
+                    word_form: Cow::Borrowed(wf),
+                    lemma: Cow::Borrowed(""),
+                    pos: Pos::Unknown,
+                    msd: Cow::Borrowed("___"),
+                    self_id: 0,
+                    func: Cow::Borrowed("X"),
+                    raw_func: Cow::Borrowed("X"),
+                    parent_id: 0,
+                    start,
+                    end: *offset,
+                    lang: Cow::Borrowed(doc_lang),
+                    compound: Cow::Borrowed(""),
+                    upos: "X",
+                };
+
+                match &cohort.analysis {
+                    Some(analysis) => {
+                        let lemma = &*analysis.lemma;
+                        if let Some(func) = &analysis.func {
+                            row.raw_func = func_label(func);
+                            row.func = match &options.deprel_map {
+                                Some(map) => Cow::Borrowed(map.normalize(&row.raw_func)),
+                                None => row.raw_func.clone(),
+                            };
+                        }
+                        if let Some((f, t)) = analysis.deprel {
+                            row.self_id = f;
+                            row.parent_id = t;
+                        }
+
+                        let mut msd = String::new();
+                        let tags = tags_of(analysis, options.msd_profile);
+                        let written = match options.canonical_msd {
+                            true => {
+                                let mut tags = tags.collect::<Vec<_>>();
+                                msd_order::sort(&mut tags);
+                                write!(msd, "{}", tags.iter().format("."))
                             }
-                            if let Some((f, t)) = analysis.deprel {
-                                self_id = f;
-                                parent_id = t;
+                            false => write!(msd, "{}", tags.format(".")),
+                        };
+                        written.expect("can always write to String");
+                        row.msd = Cow::Owned(msd);
+                        row.pos = analysis.pos;
+                        // the UPOS tag depends on the language of the token
+                        if want_lang || want_upos {
+                            let mut tags = analysis.tags.iter();
+                            if let Some(lang) = tags.find_map(|tag| lang_of_tag(&tag.name)) {
+                                row.lang = Cow::Borrowed(lang);
                             }
-
-                            msd = tags_of(analysis).join(".");
-                            pos = analysis.pos;
                         }
+                        if want_upos {
+                            let proper = analysis.tags.iter().any(|tag| &*tag.name == "Prop");
+                            let pos = analysis.pos.as_str();
+                            row.upos = pos_ud::upos(pos, lemma, &row.lang, proper);
+                        }
+
+                        let anonymizer = options.anonymizer.as_ref();
+                        let masked =
+                            anonymizer.is_some_and(|a| a.is_name(wf, lemma, &analysis.tags));
 
-                        add_line(&mut s, wf, &lemma, pos, &msd, self_id, &func, parent_id);
+                        let lemma: &str = match masked {
+                            true => {
+                                row.word_form = Cow::Borrowed(anonymize::PLACEHOLDER);
+                                anonymize::PLACEHOLDER
+                            }
+                            false => lemma,
+                        };
+                        if let Some(segments) = compound_segments(lemma) {
+                            row.compound = Cow::Owned(segments);
+                        }
+                        row.lemma = match options.multi_lemma {
+                            false => Cow::Borrowed(lemma),
+                            true => {
+                                let mut lemmas = format!("|{lemma}|");
+                                for other in cohort.lemmas.iter() {
+                                    if !masked && !lemmas.split('|').any(|l| l == &**other) {
+                                        lemmas.push_str(other);
+                                        lemmas.push('|');
+                                    }
+                                }
+                                Cow::Owned(lemmas)
+                            }
+                        };
                     }
                     None => {
                         // None of the readings had an analysis, so we're
                         // just going to have to put "empty" data for this word
                         // TODO what should the LEMMA field be? The word form,
                         // or some kind of blank value?
-                        let lemma = cohort.word_form;
-                        add_line(&mut s, wf, &lemma, pos, &msd, self_id, &func, parent_id);
+                        let lemma = wf;
+                        let anonymizer = options.anonymizer.as_ref();
+                        if anonymizer.is_some_and(|a| a.is_listed(wf, lemma)) {
+                            row.word_form = Cow::Borrowed(anonymize::PLACEHOLDER);
+                        }
+                        row.lemma = match options.multi_lemma {
+                            false => row.word_form.clone(),
+                            true => Cow::Owned(format!("|{}|", row.word_form)),
+                        };
                     }
                 }
+                rows.push(row);
             }
-            giellacgparser::SentencePart::CohortSeparator(_sep) => {
+            SentencePart::CohortSeparator(sep) => {
+                *offset += sep.chars().count();
+
                 // TODO check if sep is non-whitespace, and if so,
                 // add it (of course, there will be no analysis)
                 // code=something like the commented-out code
@@ -135,7 +769,10 @@ pub fn process_sentence<'a, 'b>(sentence: &'a giellacgparser::Sentence<'b>) -> S
             }
         }
     }
-    s
+    if let Some(attachment) = options.attach_punct {
+        attach_punct(&mut rows, attachment, options.deprel_map.as_ref());
+    }
+    rows
 }
 
 // THIS WILL BE IMPLEMENTED IN giellacgparser::Reading::get_full_lemma()
@@ -173,8 +810,18 @@ pub fn process_sentence<'a, 'b>(sentence: &'a giellacgparser::Sentence<'b>) -> S
 
 #[cfg(test)]
 mod tests {
-    use super::process_sentence;
+    use super::{
+        Column, DEFAULT_COLUMNS, Options, PunctAttachment, TokenRow, attach_punct,
+        compound_segments, count_ambiguous_lemmas, escape_rows, func_label, lang_of_tag,
+        process_sentence, renumber_ids, rows_from_lines, sentence_rows, surface_text, write_rows,
+    };
+    use crate::analysed::sentence::Sentence;
+    use crate::deprel::DeprelMap;
+    use crate::intern::Interner;
+    use crate::pos_ud::Tagset;
     use giellacgparser::parse_sentences;
+    use giellacgparser::tag::Pos;
+    use std::borrow::Cow;
 
     /// A processed line.
     #[derive(Debug, PartialEq, Eq)]
@@ -232,10 +879,275 @@ mod tests {
         };
         assert!(rest.is_empty());
         let first = sentences.sentences.first().expect("There is a sentence.");
-        let actual = process_sentence(first);
+        let actual = process_sentence(
+            &Sentence::new(first, input_text, &mut Interner::default()),
+            &Options::default(),
+        );
         expected.is_equal_to(&actual);
     }
 
+    #[test]
+    fn func_labels() {
+        assert!(matches!(func_label("HNOUN"), Cow::Borrowed("HNOUN")));
+        assert_eq!(func_label("-F<OBJ"), "-F←OBJ");
+        assert_eq!(func_label("SPRED>"), "SPRED→");
+        assert_eq!(func_label("<ADVL>"), "←ADVL→");
+    }
+
+    #[test]
+    fn columns() {
+        let token = TokenRow {
+            word_form: "da".into(),
+            lemma: "da".into(),
+            pos: Pos::Unknown,
+            msd: "CC".into(),
+            self_id: 2,
+            func: "conj".into(),
+            raw_func: "CNP".into(),
+            parent_id: 1,
+            start: 10,
+            end: 12,
+            lang: "sme".into(),
+            compound: "".into(),
+            upos: "CCONJ",
+        };
+        let mut s = String::new();
+        let mut line = |columns: &[Column]| {
+            token.write_line(&mut s, columns);
+            s.push('|');
+        };
+        line(&[Column::Word, Column::Ref, Column::Dephead, Column::Lang]);
+        line(&[Column::Start, Column::End]);
+        line(&[Column::Word, Column::Upos]);
+        line(&[Column::Deprel, Column::RawDeprel]);
+        assert_eq!(s, "da\t2\t1\tsme|10\t12|da\tCCONJ|conj\tCNP|");
+
+        let options = Options {
+            with_offsets: true,
+            ..Default::default()
+        };
+        assert_eq!(options.columns().len(), DEFAULT_COLUMNS.len() + 2);
+        let options = Options {
+            with_offsets: true,
+            columns: Some(vec![Column::Word]),
+            ..Default::default()
+        };
+        assert_eq!(*options.columns(), [Column::Word]);
+        let options = Options {
+            tagset: Tagset::Ud,
+            ..Default::default()
+        };
+        assert_eq!(options.columns()[2], Column::Upos);
+        let options = Options {
+            raw_deprel_column: true,
+            ..Default::default()
+        };
+        assert_eq!(options.columns().last(), Some(&Column::RawDeprel));
+    }
+
+    #[test]
+    fn surface() {
+        let rows = [
+            "«", "Ii", "mii", "(", "dan", ")", "diehtán", "»", ",", "son", "celkkii", ".",
+        ]
+        .iter()
+        .map(|word| TokenRow {
+            word_form: (*word).into(),
+            lemma: "".into(),
+            pos: Pos::Unknown,
+            msd: "".into(),
+            self_id: 0,
+            func: "".into(),
+            raw_func: "".into(),
+            parent_id: 0,
+            start: 0,
+            end: 0,
+            lang: "".into(),
+            compound: "".into(),
+            upos: "X",
+        })
+        .collect::<Vec<_>>();
+        assert_eq!(surface_text(&rows), "«Ii mii (dan) diehtán», son celkkii.");
+        assert_eq!(surface_text(&[]), "");
+    }
+
+    #[test]
+    fn escaped_columns() {
+        let row = |word_form: &'static str, lemma: &'static str| TokenRow {
+            word_form: word_form.into(),
+            lemma: lemma.into(),
+            pos: Pos::Unknown,
+            msd: "N".into(),
+            self_id: 1,
+            func: "X".into(),
+            raw_func: "X".into(),
+            parent_id: 0,
+            start: 0,
+            end: 0,
+            lang: "".into(),
+            compound: "".into(),
+            upos: "X",
+        };
+        let mut rows = [row("a\tb", "a\tb"), row("c", "c"), row("d", "d\r\n")];
+        assert_eq!(escape_rows(&mut rows), 2);
+        let mut s = String::new();
+        write_rows(&rows, &[Column::Word, Column::Lemma], &mut s);
+        assert_eq!(s, "a b\ta b\nc\tc\nd\td  ");
+    }
+
+    #[test]
+    fn renumbered_ids() {
+        let row = |self_id, parent_id| TokenRow {
+            word_form: "".into(),
+            lemma: "".into(),
+            pos: Pos::Unknown,
+            msd: "".into(),
+            self_id,
+            func: "X".into(),
+            raw_func: "X".into(),
+            parent_id,
+            start: 0,
+            end: 0,
+            lang: "".into(),
+            compound: "".into(),
+            upos: "X",
+        };
+        let ids = |rows: &[TokenRow]| {
+            rows.iter()
+                .map(|row| (row.self_id, row.parent_id))
+                .collect::<Vec<_>>()
+        };
+        let mut rows = [row(21, 22), row(22, 0), row(0, 0), row(23, 22), row(24, 7)];
+        assert!(renumber_ids(&mut rows));
+        assert_eq!(ids(&rows), [(1, 2), (2, 0), (0, 0), (4, 2), (5, 0)]);
+        assert!(!renumber_ids(&mut rows));
+        let mut rows = [row(0, 0), row(0, 0)];
+        assert!(!renumber_ids(&mut rows));
+    }
+
+    #[test]
+    fn punctuation() {
+        let row = |word_form: &'static str, self_id, parent_id| TokenRow {
+            word_form: word_form.into(),
+            lemma: word_form.into(),
+            pos: Pos::Unknown,
+            msd: "".into(),
+            self_id,
+            func: "X".into(),
+            raw_func: "X".into(),
+            parent_id,
+            start: 0,
+            end: 0,
+            lang: "".into(),
+            compound: "".into(),
+            upos: "X",
+        };
+        let sentence = || {
+            vec![
+                row("«", 0, 0),
+                row("Mun", 1, 2),
+                row("boađán", 2, 0),
+                row("».", 0, 0),
+            ]
+        };
+        let deps = |rows: &[TokenRow]| {
+            rows.iter()
+                .map(|row| (row.func.to_string(), row.self_id, row.parent_id))
+                .collect::<Vec<_>>()
+        };
+
+        let mut rows = sentence();
+        assert_eq!(attach_punct(&mut rows, PunctAttachment::Root, None), 2);
+        assert_eq!(
+            deps(&rows),
+            [
+                ("PUNCT".to_string(), 3, 2),
+                ("X".to_string(), 1, 2),
+                ("X".to_string(), 2, 0),
+                ("PUNCT".to_string(), 4, 2),
+            ]
+        );
+
+        let mut rows = sentence();
+        let map = DeprelMap::default();
+        attach_punct(&mut rows, PunctAttachment::Previous, Some(&map));
+        assert_eq!(deps(&rows)[0], ("punct".to_string(), 3, 2));
+        assert_eq!(deps(&rows)[3], ("punct".to_string(), 4, 2));
+        assert_eq!(rows[3].raw_func, "PUNCT");
+
+        let mut rows = vec![row("Mun", 1, 0), row("-", 0, 0), row("boađán", 2, 1)];
+        attach_punct(&mut rows, PunctAttachment::Previous, None);
+        assert_eq!(deps(&rows)[1], ("PUNCT".to_string(), 3, 1));
+    }
+
+    #[test]
+    fn compounds() {
+        assert_eq!(
+            compound_segments("[[[GEN:#várri+N+Cmp/SgNom+Cmp#ovddasteaddji+N+NomAg+Sg+Nom]]]")
+                .as_deref(),
+            Some("várri+ovddasteaddji")
+        );
+        assert_eq!(
+            compound_segments(
+                "[[[GEN:#vurkkodit+V+TV+Der/NomAct+N+Cmp/SgNom+Cmp#vástu+N+Sg+Nom]]]"
+            )
+            .as_deref(),
+            Some("vurkkodit+vástu")
+        );
+        assert_eq!(compound_segments("vástu"), None);
+        assert_eq!(compound_segments("[[[GEN:#vástu+N+Sg+Nom]]]"), None);
+    }
+
+    #[test]
+    fn lang_tags() {
+        assert_eq!(lang_of_tag("Lang/nob"), Some("nob"));
+        assert_eq!(lang_of_tag("Lang/"), None);
+        assert_eq!(lang_of_tag("N"), None);
+    }
+
+    #[test]
+    fn upos_in_token_language() {
+        // a sentence of a finnish section of a sámi document, and then a
+        // sámi sentence
+        let blob = concat!(
+            "\"<Se>\"\n\t\"se\" Pron Dem Sg Nom Lang/fin <W:0.0> @SUBJ> #1->2\n",
+            "\"<on>\"\n\t\"olla\" V IV Ind Prs Sg3 Lang/fin <W:0.0> @FMV #2->0\n",
+            "\n",
+            "\"<Dat>\"\n\t\"dat\" Pron Dem Sg Nom <W:0.0> @SUBJ> #1->2\n",
+            "\"<lea>\"\n\t\"leat\" V IV Ind Prs Sg3 <W:0.0> @FMV #2->0\n",
+        );
+        let (_, parsed) = parse_sentences(blob).unwrap();
+        let sentences = Sentence::all(&parsed.sentences, blob);
+        let options = Options {
+            columns: Some(vec![Column::Word, Column::Upos, Column::Lang]),
+            ..Default::default()
+        };
+        let upos = |sentence| {
+            let rows = sentence_rows(sentence, &options, "sme", &mut 0);
+            rows.iter()
+                .map(|row| (row.upos, row.lang.to_string()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            upos(&sentences[0]),
+            [("PRON", "fin".to_string()), ("AUX", "fin".to_string())]
+        );
+        assert_eq!(
+            upos(&sentences[1]),
+            [("PRON", "sme".to_string()), ("AUX", "sme".to_string())]
+        );
+    }
+
+    #[test]
+    fn ambiguous_lemmas() {
+        let text = concat!(
+            "a\t|a|\tN\tN\t1\tX\t0\n",
+            "b\t|b|bb|\tN\tN\t2\tX\t1\n",
+            "c\tc\tN\tN\t3\tX\t1\n",
+        );
+        assert_eq!(count_ambiguous_lemmas(&rows_from_lines(text)), 1);
+    }
+
     /// ------------------------
     /// Test casene under her:
     /// ----------------
@@ -255,7 +1167,7 @@ mod tests {
                 msd: "N.Pl.Acc",
                 self_id: "22",
                 func: "-F←OBJ",
-                parent_id: "19\n",
+                parent_id: "19",
             },
         );
     }
@@ -279,7 +1191,7 @@ mod tests {
                 msd: "N.Sg.Nom",
                 self_id: "7",
                 func: "←SUBJ",
-                parent_id: "3\n",
+                parent_id: "3",
             },
         );
     }
@@ -300,7 +1212,7 @@ mod tests {
                 msd: "N.Ess",
                 self_id: "1",
                 func: "SPRED→",
-                parent_id: "4\n",
+                parent_id: "4",
             },
         );
     }
@@ -321,7 +1233,7 @@ mod tests {
                 msd: "N.NomAg.Ess",
                 self_id: "18",
                 func: "←SPRED",
-                parent_id: "7\n",
+                parent_id: "7",
             },
         );
     }
@@ -342,7 +1254,7 @@ mod tests {
     //            msd: "N.Sg.Loc.South",
     //            self_id: "10",
     //            func: "←ADVL",
-    //            parent_id: "2\n",
+    //            parent_id: "2",
     //        },
     //    );
     //}
@@ -365,7 +1277,7 @@ mod tests {
                 msd: "N.Sg.Acc",
                 self_id: "8",
                 func: "-FSUBJ→",
-                parent_id: "9\n",
+                parent_id: "9",
             },
         );
     }
@@ -398,7 +1310,7 @@ mod tests {
                 msd: "N.Ess",
                 self_id: "1",
                 func: "SPRED→",
-                parent_id: "4\n",
+                parent_id: "4",
             },
         );
     }
@@ -422,7 +1334,7 @@ mod tests {
                 msd: "IV.PrfPrc",
                 self_id: "6",
                 func: "IMV",
-                parent_id: "2\n",
+                parent_id: "2",
             },
         );
     }
@@ -0,0 +1,192 @@
+//! The time each stage takes on each file, with `--profile`, in the Chrome
+//! trace event format.
+//!
+//! The trace can be opened in speedscope, Perfetto, or `chrome://tracing`.
+//! Each file that goes through the pipeline is one event, named by the path
+//! of the analysed file, with the stages that ran on it as events nested
+//! inside, on the same thread. So the files, and the corpora, that dominate
+//! the run stand out, and it can be seen which of their stages are slow.
+//! The stages that run on their own threads, like writing with
+//! `--write-threads`, are events without a file around them.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// The category of the events of files.
+const FILE: &str = "file";
+/// The category of the events of stages.
+const STAGE: &str = "stage";
+
+static NEXT_THREAD: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    /// A small number for the current thread, for the `tid` of its events
+    static THREAD: u64 = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Event {
+    name: String,
+    category: &'static str,
+    thread: u64,
+    start: Duration,
+    duration: Duration,
+}
+
+/// An event of the trace, one per line.
+#[derive(Serialize)]
+#[serde(untagged)]
+enum TraceEvent<'a> {
+    /// The name of the thread `tid`
+    ThreadName {
+        name: &'static str,
+        ph: &'static str,
+        pid: u32,
+        tid: u64,
+        args: ThreadName<'a>,
+    },
+    /// An event with a duration
+    Complete {
+        name: &'a str,
+        cat: &'static str,
+        ph: &'static str,
+        pid: u32,
+        tid: u64,
+        ts: u128,
+        dur: u128,
+    },
+}
+
+#[derive(Serialize)]
+struct ThreadName<'a> {
+    name: &'a str,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    events: Vec<Event>,
+    /// The names of the threads the events are from
+    threads: BTreeMap<u64, String>,
+}
+
+/// The events of the run. Shared between the threads.
+#[derive(Debug)]
+pub struct Profile {
+    start: Instant,
+    inner: Mutex<Inner>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+            inner: Mutex::default(),
+        }
+    }
+}
+
+impl Profile {
+    fn record(&self, name: String, category: &'static str, started: Instant, duration: Duration) {
+        let thread = THREAD.with(|thread| *thread);
+        let event = Event {
+            name,
+            category,
+            thread,
+            start: started.saturating_duration_since(self.start),
+            duration,
+        };
+        let mut inner = self
+            .inner
+            .lock()
+            .expect("no thread panics while holding the lock");
+        inner.events.push(event);
+        inner.threads.entry(thread).or_insert_with(|| {
+            let current = std::thread::current();
+            current.name().unwrap_or("thread").to_string()
+        });
+    }
+
+    /// The stage `stage` ran from `started`, for `duration`.
+    pub fn record_stage(&self, stage: &'static str, started: Instant, duration: Duration) {
+        self.record(stage.to_string(), STAGE, started, duration);
+    }
+
+    /// The analysed file `file` went through the pipeline from `started`,
+    /// for `duration`.
+    pub fn record_file(&self, file: &Path, started: Instant, duration: Duration) {
+        self.record(file.display().to_string(), FILE, started, duration);
+    }
+
+    /// The trace, as a JSON object with the events in `traceEvents`. The
+    /// times are in microseconds.
+    pub fn render(&self) -> String {
+        let inner = self
+            .inner
+            .lock()
+            .expect("no thread panics while holding the lock");
+        let threads = inner
+            .threads
+            .iter()
+            .map(|(thread, name)| TraceEvent::ThreadName {
+                name: "thread_name",
+                ph: "M",
+                pid: 1,
+                tid: *thread,
+                args: ThreadName { name },
+            });
+        let events = inner.events.iter().map(|event| TraceEvent::Complete {
+            name: &event.name,
+            cat: event.category,
+            ph: "X",
+            pid: 1,
+            tid: event.thread,
+            ts: event.start.as_micros(),
+            dur: event.duration.as_micros(),
+        });
+        let events = threads
+            .chain(events)
+            .map(|event| serde_json::to_string(&event).expect("an event can always be serialized"))
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"traceEvents\":[\n{}\n],\"displayTimeUnit\":\"ms\"}}\n",
+            events.join(",\n")
+        )
+    }
+
+    pub fn write_file(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.render())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profile;
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn trace() {
+        let profile = Profile::default();
+        let started = profile.start + Duration::from_micros(10);
+        profile.record_stage("parse_xml", started, Duration::from_micros(5));
+        profile.record_file(Path::new("a \"b\".xml"), started, Duration::from_micros(20));
+        let trace = profile.render();
+        let name = std::thread::current()
+            .name()
+            .unwrap_or("thread")
+            .to_string();
+        assert!(trace.starts_with("{\"traceEvents\":[\n{\"name\":\"thread_name\",\"ph\":\"M\""));
+        assert!(trace.contains(&format!(",\"args\":{{\"name\":\"{name}\"}}}}")));
+        assert!(
+            trace.contains("{\"name\":\"parse_xml\",\"cat\":\"stage\",\"ph\":\"X\",\"pid\":1,")
+        );
+        assert!(trace.contains("\"ts\":10,\"dur\":5}"));
+        assert!(trace.contains("{\"name\":\"a \\\"b\\\".xml\",\"cat\":\"file\""));
+        assert!(trace.ends_with("\n],\"displayTimeUnit\":\"ms\"}\n"));
+    }
+}
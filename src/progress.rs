@@ -0,0 +1,281 @@
+//! Progress events as JSON lines, with `--json-progress`, for tools that
+//! monitor the run, instead of the progress bar.
+//!
+//! ```not_rust
+//! {"event":"start","files":3}
+//! {"event":"written","file":"/c/corpus-sme/analysed/a.xml","output":"/c/corpus-sme/korp_mono/a.xml","written":1,"failed":0}
+//! {"event":"failed","file":"/c/corpus-sme/analysed/b.xml","category":"xml","corpus":"corpus-sme","written":1,"failed":1}
+//! {"event":"done","files":3,"written":1,"failed":1,"seconds":0.25}
+//! ```
+//!
+//! The `written` events come from the write stage, the `failed` events from
+//! the errors that are logged by the stages, through [`ProgressLayer`].
+//! Files that are skipped on purpose (by year, license, ...) have no event.
+//! The `failed` events have the fields of `--log-context` of the document,
+//! see [`crate::log_context`].
+
+use std::borrow::Cow;
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::log_context::{ContextFields, DOCUMENT_SPAN};
+use crate::status_message::ErrorCategory;
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+struct Counts {
+    written: u64,
+    failed: u64,
+}
+
+/// A progress event, one JSON object per line.
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+enum ProgressEvent<'a> {
+    Start {
+        files: usize,
+    },
+    Written {
+        file: Cow<'a, str>,
+        output: Cow<'a, str>,
+        #[serde(flatten)]
+        counts: Counts,
+    },
+    Failed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        file: Option<&'a str>,
+        category: &'static str,
+        #[serde(flatten)]
+        context: &'a ContextFields,
+        #[serde(flatten)]
+        counts: Counts,
+    },
+    Done {
+        files: usize,
+        #[serde(flatten)]
+        counts: Counts,
+        seconds: f64,
+    },
+}
+
+/// Writes the progress events, one JSON object per line. Shared between the
+/// threads.
+pub struct JsonProgress {
+    out: Mutex<Box<dyn Write + Send>>,
+    counts: Mutex<Counts>,
+}
+
+impl Default for JsonProgress {
+    fn default() -> Self {
+        Self::new(Box::new(std::io::stdout()))
+    }
+}
+
+impl JsonProgress {
+    pub fn new(out: Box<dyn Write + Send>) -> Self {
+        Self {
+            out: Mutex::new(out),
+            counts: Mutex::default(),
+        }
+    }
+
+    fn counts(&self) -> std::sync::MutexGuard<'_, Counts> {
+        self.counts
+            .lock()
+            .expect("no thread panics while holding the lock")
+    }
+
+    fn emit(&self, event: &ProgressEvent) {
+        let mut line = serde_json::to_string(event).expect("an event can always be serialized");
+        line.push('\n');
+        let mut out = self
+            .out
+            .lock()
+            .expect("no thread panics while holding the lock");
+        // progress is best effort, a closed pipe must not stop the run
+        let _ = out.write_all(line.as_bytes()).and_then(|_| out.flush());
+    }
+
+    pub fn start(&self, files: usize) {
+        self.emit(&ProgressEvent::Start { files });
+    }
+
+    /// The korp_mono file converted from `file` was written to `output`.
+    pub fn written(&self, file: &Path, output: &Path) {
+        let counts = {
+            let mut counts = self.counts();
+            counts.written += 1;
+            *counts
+        };
+        self.emit(&ProgressEvent::Written {
+            file: file.to_string_lossy(),
+            output: output.to_string_lossy(),
+            counts,
+        });
+    }
+
+    /// Converting `file`, if it is known, failed with `category`. `context`
+    /// are the fields of the document that are known.
+    pub fn failed(&self, file: Option<&str>, category: ErrorCategory, context: &ContextFields) {
+        let counts = {
+            let mut counts = self.counts();
+            counts.failed += 1;
+            *counts
+        };
+        self.emit(&ProgressEvent::Failed {
+            file,
+            category: category.as_str(),
+            context,
+            counts,
+        });
+    }
+
+    /// The run is over, after `duration`.
+    pub fn done(&self, files: usize, duration: Duration) {
+        let counts = *self.counts();
+        self.emit(&ProgressEvent::Done {
+            files,
+            counts,
+            seconds: duration.as_secs_f64(),
+        });
+    }
+}
+
+/// Writes a `failed` event for each error event, like
+/// [`crate::failures::FailureLayer`] counts them.
+pub struct ProgressLayer {
+    pub progress: Arc<JsonProgress>,
+}
+
+impl ProgressLayer {
+    pub fn new(progress: Arc<JsonProgress>) -> Self {
+        Self { progress }
+    }
+}
+
+#[derive(Default)]
+struct FailureVisitor {
+    file: Option<String>,
+    category: Option<ErrorCategory>,
+}
+
+impl Visit for FailureVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        match field.name() {
+            "category" => self.category = value.parse().ok(),
+            "file" => self.file = Some(value.to_string()),
+            _ => {}
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "file" {
+            // paths are logged with their Debug impl, which quotes them
+            self.file = Some(format!("{value:?}").trim_matches('"').to_string());
+        }
+    }
+}
+
+impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for ProgressLayer {
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if attrs.metadata().name() != DOCUMENT_SPAN {
+            return;
+        }
+        let mut fields = ContextFields::default();
+        attrs.record(&mut fields);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(fields);
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        if let Some(fields) = span.extensions_mut().get_mut::<ContextFields>() {
+            values.record(fields);
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+        let mut visitor = FailureVisitor::default();
+        event.record(&mut visitor);
+        let context = ctx
+            .event_scope(event)
+            .and_then(|mut scope| {
+                scope.find_map(|span| span.extensions().get::<ContextFields>().cloned())
+            })
+            .unwrap_or_default();
+        self.progress.failed(
+            visitor.file.as_deref(),
+            visitor.category.unwrap_or(ErrorCategory::Other),
+            &context,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{JsonProgress, ProgressLayer};
+    use crate::log_context::{LogContext, LogField};
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// A writer that can be read after it has been given away.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn events() {
+        let out = Shared::default();
+        let progress = Arc::new(JsonProgress::new(Box::new(out.clone())));
+        let subscriber =
+            tracing_subscriber::registry().with(ProgressLayer::new(Arc::clone(&progress)));
+        progress.start(2);
+        progress.written(Path::new("analysed/a.xml"), Path::new("korp_mono/a.xml"));
+        tracing::subscriber::with_default(subscriber, || {
+            let file = PathBuf::from("corpus-sme/analysed/b.xml");
+            let context = LogContext::new(vec![LogField::Corpus, LogField::DocId]);
+            let span = context.span(&file);
+            let _entered = span.enter();
+            tracing::error!(file = ?file, category = "xml", "can't parse xml");
+            tracing::warn!(file = ?file, "only a warning");
+        });
+        progress.done(2, Duration::from_millis(250));
+
+        let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(
+            out,
+            concat!(
+                "{\"event\":\"start\",\"files\":2}\n",
+                "{\"event\":\"written\",\"file\":\"analysed/a.xml\",\"output\":\"korp_mono/a.xml\",\"written\":1,\"failed\":0}\n",
+                "{\"event\":\"failed\",\"file\":\"corpus-sme/analysed/b.xml\",\"category\":\"xml\",\"corpus\":\"corpus-sme\",\"written\":1,\"failed\":1}\n",
+                "{\"event\":\"done\",\"files\":2,\"written\":1,\"failed\":1,\"seconds\":0.25}\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! The Python module `korp_mono_rs`, so that the CorpusTools pipeline can
+//! convert documents with this crate, one step at a time, instead of with
+//! `korp_mono.py`.
+//!
+//! Only with `--features python`, built with `maturin build`:
+//!
+//! ```python
+//! import korp_mono_rs
+//!
+//! xml = korp_mono_rs.convert_document(analysed_xml)
+//! converted, failed = korp_mono_rs.convert_tree(
+//!     "corpus-sme/analysed", {"partition_by_domain": True}
+//! )
+//! ```
+//!
+//! The options are given as a dict, with these keys, all optional:
+//!
+//! - `robust`: like `--robust-parse`
+//! - `multi_lemma`, `with_offsets`, `sentence_text`: like the options of the
+//!   same names
+//! - `columns`: a list of column names, like `--columns`
+//! - `partition_by_domain`, `output_dir`: like the options of the same
+//!   names, only for `convert_tree`
+//!
+//! The GIL is released while converting.
+
+use std::path::PathBuf;
+
+use clap::ValueEnum;
+use pyo3::exceptions::{PyFileNotFoundError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+use rayon::prelude::*;
+
+use crate::corpus_dirs::{Walk, analysed_files};
+use crate::pipeline::{self, Pipeline, stages};
+use crate::process_sentence::{self, Column};
+
+/// The options of the conversion, from the dict given in Python.
+#[derive(Debug, Default)]
+struct Options {
+    robust: bool,
+    partition_by_domain: bool,
+    output_dir: Option<PathBuf>,
+    sentence: process_sentence::Options,
+}
+
+impl Options {
+    fn from_dict(dict: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut options = Self::default();
+        let Some(dict) = dict else {
+            return Ok(options);
+        };
+        for (key, value) in dict.iter() {
+            let key = key.extract::<String>()?;
+            match key.as_str() {
+                "robust" => options.robust = value.extract()?,
+                "partition_by_domain" => options.partition_by_domain = value.extract()?,
+                "output_dir" => options.output_dir = Some(value.extract()?),
+                "multi_lemma" => options.sentence.multi_lemma = value.extract()?,
+                "with_offsets" => options.sentence.with_offsets = value.extract()?,
+                "sentence_text" => options.sentence.sentence_text = value.extract()?,
+                "columns" => {
+                    let columns = value
+                        .extract::<Vec<String>>()?
+                        .iter()
+                        .map(|name| Column::from_str(name, false).map_err(PyValueError::new_err))
+                        .collect::<PyResult<Vec<_>>>()?;
+                    options.sentence.columns = Some(columns);
+                }
+                key => return Err(PyValueError::new_err(format!("unknown option '{key}'"))),
+            }
+        }
+        Ok(options)
+    }
+
+    fn parse(&self) -> stages::ParseAnalyses {
+        stages::ParseAnalyses::new(self.robust, None)
+    }
+}
+
+/// Convert the analysed document `xml`, and return the korp_mono xml.
+/// Raises `ValueError` if it can't be converted.
+#[pyfunction]
+#[pyo3(signature = (xml, options = None))]
+fn convert_document(
+    py: Python<'_>,
+    xml: String,
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<String> {
+    let options = Options::from_dict(options)?;
+    py.allow_threads(|| pipeline::convert_document(xml, options.parse(), options.sentence))
+        .ok_or_else(|| PyValueError::new_err("can't convert the document"))
+}
+
+/// Convert all analysed files under the directory `path`, and write them
+/// to the `korp_mono/` directories of their corpora, or `output_dir`.
+/// Returns the number of converted and failed files.
+#[pyfunction]
+#[pyo3(signature = (path, options = None))]
+fn convert_tree(
+    py: Python<'_>,
+    path: PathBuf,
+    options: Option<&Bound<'_, PyDict>>,
+) -> PyResult<(usize, usize)> {
+    if !path.is_dir() {
+        return Err(PyFileNotFoundError::new_err(format!(
+            "no such directory '{}'",
+            path.display()
+        )));
+    }
+    let options = Options::from_dict(options)?;
+    let write = stages::WriteKorpMono {
+        partition_by_domain: options.partition_by_domain,
+        output_dir: options.output_dir.clone(),
+        columns: options.sentence.columns().into_owned(),
+        ..Default::default()
+    };
+    let pipeline = Pipeline::new()
+        .then(stages::ReadFile)
+        .then(stages::ParseXml::default())
+        .then(options.parse())
+        .then(stages::Convert::new(options.sentence))
+        .then(write);
+    let converted = py.allow_threads(|| {
+        let files = analysed_files(&path, &Walk::default());
+        let converted = files
+            .par_iter()
+            .cloned()
+            .map(gtcorpusutil::AnalysedFilePath::new_unchecked)
+            .filter_map(|file| pipeline.run(file))
+            .count();
+        (converted, files.len() - converted)
+    });
+    Ok(converted)
+}
+
+#[pymodule]
+fn korp_mono_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(convert_document, m)?)?;
+    m.add_function(wrap_pyfunction!(convert_tree, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,303 @@
+//! Keeping count of the [`StatusMessage`]s of the files, as they are sent
+//! by the stages, on a thread of its own: how many files each stage has
+//! done, how many failed and why, and the messages of the failed files.
+//!
+//! The messages are received from a channel that is given to [`collect`]
+//! or [`spawn`], so that the accounting can be tested by sending messages
+//! into it, without running the pipeline.
+//!
+//! On large runs, a message per file and stage is a lot of traffic for what
+//! is mostly a count of successes. The messages are therefore sent in
+//! [`Batch`]es, with a [`BatchSender`]: the successful ones are only
+//! counted, and the failed ones are kept in full, and sent right away.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::mpsc::{Receiver, SendError, Sender};
+use std::thread::JoinHandle;
+
+use crate::status_message::{ErrorCategory, StatusMessage, StatusMessageKind};
+
+/// The number of files that each stage has done.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Stats {
+    pub tot: usize,
+    pub read_ok: usize,
+    pub read_err: usize,
+    pub parsexml_ok: usize,
+    pub parsexml_err: usize,
+    pub parseanl_ok: usize,
+    pub parseanl_err: usize,
+}
+
+impl Stats {
+    pub fn new(tot: usize) -> Self {
+        Self {
+            tot,
+            ..Default::default()
+        }
+    }
+
+    /// Add the counts of `other`, but not its total.
+    pub fn add(&mut self, other: &Stats) {
+        self.read_ok += other.read_ok;
+        self.read_err += other.read_err;
+        self.parsexml_ok += other.parsexml_ok;
+        self.parsexml_err += other.parsexml_err;
+        self.parseanl_ok += other.parseanl_ok;
+        self.parseanl_err += other.parseanl_err;
+    }
+
+    pub fn update(&mut self, kind: &StatusMessageKind) {
+        let (is_ok, ok, err) = match kind {
+            StatusMessageKind::Read { result } => {
+                (result.is_ok(), &mut self.read_ok, &mut self.read_err)
+            }
+            StatusMessageKind::ParseXml { result } => (
+                result.is_ok(),
+                &mut self.parsexml_ok,
+                &mut self.parsexml_err,
+            ),
+            StatusMessageKind::ParseAnalyses { result } => (
+                result.is_ok(),
+                &mut self.parseanl_ok,
+                &mut self.parseanl_err,
+            ),
+            _ => return,
+        };
+        match is_ok {
+            true => *ok += 1,
+            false => *err += 1,
+        }
+    }
+
+    /// The line of the stage `stage` (`read`, `parse_xml` or
+    /// `parse_analyses`), if there is such a stage.
+    pub fn display(&self, stage: &str) -> Option<StatsDisplay> {
+        let (title, ok, err) = match stage {
+            "read" => ("Read", self.read_ok, self.read_err),
+            "parse_xml" => ("Parse XML", self.parsexml_ok, self.parsexml_err),
+            "parse_analyses" => ("Parse analyses", self.parseanl_ok, self.parseanl_err),
+            _ => return None,
+        };
+        Some(StatsDisplay {
+            title,
+            ok,
+            err,
+            tot: self.tot,
+        })
+    }
+}
+
+pub struct StatsDisplay {
+    title: &'static str,
+    ok: usize,
+    err: usize,
+    tot: usize,
+}
+
+impl std::fmt::Display for StatsDisplay {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ok = self.ok;
+        let err = self.err;
+        let tot = self.tot;
+        let pct = (ok + err) as f64 / tot.max(1) as f64 * 100.0;
+        write!(
+            formatter,
+            "{}: {ok} OK, {err} FAILED (of {tot}, {pct:.1}%)",
+            self.title
+        )
+    }
+}
+
+/// The messages of a number of files: the successful ones counted, the
+/// failed ones in full.
+#[derive(Default)]
+pub struct Batch {
+    pub ok: Stats,
+    pub failed: Vec<StatusMessage>,
+    len: usize,
+}
+
+impl Batch {
+    pub fn push(&mut self, message: StatusMessage) {
+        self.len += 1;
+        match message.is_err() {
+            true => self.failed.push(message),
+            false => self.ok.update(&message.kind),
+        }
+    }
+
+    /// The number of messages in the batch.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+/// The number of messages in a batch, unless one of them failed.
+pub const BATCH_SIZE: usize = 256;
+
+/// Sends the messages of a stage in [`Batch`]es of `size`. A batch with a
+/// failed message is sent right away, so that errors are not held back. The
+/// last batch is sent when the sender is dropped, or with
+/// [`BatchSender::flush`].
+pub struct BatchSender {
+    sender: Sender<Batch>,
+    batch: Batch,
+    size: usize,
+}
+
+impl BatchSender {
+    pub fn new(sender: Sender<Batch>, size: usize) -> Self {
+        Self {
+            sender,
+            batch: Batch::default(),
+            size,
+        }
+    }
+
+    pub fn send(&mut self, message: StatusMessage) -> Result<(), SendError<Batch>> {
+        let failed = message.is_err();
+        self.batch.push(message);
+        match failed || self.batch.len() >= self.size {
+            true => self.flush(),
+            false => Ok(()),
+        }
+    }
+
+    /// Send the messages that are not sent yet.
+    pub fn flush(&mut self) -> Result<(), SendError<Batch>> {
+        match self.batch.is_empty() {
+            true => Ok(()),
+            false => self.sender.send(std::mem::take(&mut self.batch)),
+        }
+    }
+}
+
+impl Drop for BatchSender {
+    fn drop(&mut self) {
+        // the receiver is gone when the run is stopped, and then nobody is
+        // counting anymore
+        let _ = self.flush();
+    }
+}
+
+/// Everything that was received, once the channel is closed.
+#[derive(Default)]
+pub struct Report {
+    pub stats: Stats,
+    /// The failed messages, by the category of their error
+    pub failed: BTreeMap<ErrorCategory, usize>,
+    /// The failed messages, by file
+    pub statuses: HashMap<PathBuf, Vec<StatusMessage>>,
+}
+
+/// Receive the batches of the messages of `total` files from `receiver`
+/// until all senders are gone, calling `on_batch` after each batch has been
+/// counted, with its failed messages.
+pub fn collect(
+    receiver: &Receiver<Batch>,
+    total: usize,
+    mut on_batch: impl FnMut(&Stats, &[StatusMessage]),
+) -> Report {
+    let mut report = Report {
+        stats: Stats::new(total),
+        ..Default::default()
+    };
+    for batch in receiver.iter() {
+        report.stats.add(&batch.ok);
+        for message in batch.failed.iter() {
+            report.stats.update(&message.kind);
+            if let Some(category) = message.category() {
+                *report.failed.entry(category).or_default() += 1;
+            }
+        }
+        on_batch(&report.stats, &batch.failed);
+        for message in batch.failed {
+            report
+                .statuses
+                .entry(message.path.clone())
+                .or_default()
+                .push(message);
+        }
+    }
+    report
+}
+
+/// [`collect`] on a thread of its own. The report is returned when the
+/// thread is joined, after all senders are dropped.
+pub fn spawn(
+    receiver: Receiver<Batch>,
+    total: usize,
+    on_batch: impl FnMut(&Stats, &[StatusMessage]) + Send + 'static,
+) -> JoinHandle<Report> {
+    std::thread::Builder::new()
+        .name("reporting".to_string())
+        .spawn(move || collect(&receiver, total, on_batch))
+        .expect("can spawn thread")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BatchSender, Stats, spawn};
+    use crate::status_message::{ErrorCategory, StatusMessage};
+    use std::sync::{Arc, Mutex, mpsc};
+    use std::time::Duration;
+
+    #[test]
+    fn counts() {
+        let (sender, receiver) = mpsc::channel();
+        let batches = Arc::new(Mutex::new(vec![]));
+        let seen = Arc::clone(&batches);
+        let handle = spawn(receiver, 4, move |stats, failed| {
+            seen.lock().unwrap().push((stats.read_ok, failed.len()));
+        });
+        let not_found = std::io::Error::from(std::io::ErrorKind::NotFound);
+        let ms = Duration::from_millis(1);
+        let mut batch = BatchSender::new(sender.clone(), 2);
+        batch
+            .send(StatusMessage::read("a.xml", ms, &Ok(String::new())))
+            .unwrap();
+        // sent right away, with the successful one before it
+        batch
+            .send(StatusMessage::read("b.xml", ms, &Err(not_found)))
+            .unwrap();
+        batch
+            .send(StatusMessage::read("c.xml", ms, &Ok(String::new())))
+            .unwrap();
+        let mut other = BatchSender::new(sender, 2);
+        std::thread::spawn(move || {
+            other
+                .send(StatusMessage::read("d.xml", ms, &Ok(String::new())))
+                .unwrap();
+        })
+        .join()
+        .unwrap();
+        // the last batch is sent on drop
+        drop(batch);
+
+        let report = handle.join().unwrap();
+        assert_eq!(
+            report.stats,
+            Stats {
+                tot: 4,
+                read_ok: 3,
+                read_err: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(*batches.lock().unwrap(), [(1, 1), (2, 0), (3, 0)]);
+        assert_eq!(report.failed.get(&ErrorCategory::Read), Some(&1));
+        // only the failed files are kept
+        assert_eq!(report.statuses.len(), 1);
+        assert_eq!(
+            report.stats.display("read").unwrap().to_string(),
+            "Read: 3 OK, 1 FAILED (of 4, 100.0%)"
+        );
+        assert!(report.stats.display("write").is_none());
+    }
+}
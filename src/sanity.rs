@@ -0,0 +1,393 @@
+//! Structural sanity checks of the converted sentences.
+//!
+//! After conversion, the sentences are scanned for things that indicate that
+//! the analysis of the document is broken in some way: empty sentences,
+//! tokens without a self_id, duplicated self_ids, and sentences where no
+//! token has a parent. The dependency trees are checked too: each sentence
+//! should have exactly one root, the parents should be tokens of the
+//! sentence, and there should be no cycles. The findings are aggregated per
+//! corpus, and written to a `korp_mono.warnings` file in the corpus
+//! directory, so that corpus maintainers can see which documents most need
+//! to be reanalysed.
+//!
+//! With `--demote-broken-trees`, the sentences whose trees are broken get
+//! `X` as deprel and `0` as parent for all their tokens, see
+//! [`demote_broken_trees`], so that Korp doesn't show broken trees.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::korp_mono::KorpMonoFile;
+use crate::korp_mono::path::corpus_dir;
+use crate::process_sentence::TokenRow;
+
+/// Name of the report file, written to the corpus directory.
+pub const REPORT_FILE_NAME: &str = "korp_mono.warnings";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Anomaly {
+    /// The sentence has no tokens.
+    EmptySentence,
+    /// A token has 0 as self_id (the analysis had no dependency info).
+    ZeroSelfId,
+    /// More than one token in the sentence has the same self_id.
+    DuplicateSelfId(usize),
+    /// The sentence has more than one token, but all have 0 as parent.
+    AllParentsZero,
+    /// No token has 0 as parent.
+    NoRoot,
+    /// More than one token, but not all, have 0 as parent.
+    MultipleRoots(usize),
+    /// A token has a parent that is not a token of the sentence.
+    HeadOutOfRange(usize),
+    /// The parents of some tokens form a cycle.
+    Cycle,
+}
+
+impl Anomaly {
+    /// Is the dependency tree of the sentence broken?
+    pub fn is_broken_tree(&self) -> bool {
+        matches!(
+            self,
+            Anomaly::NoRoot
+                | Anomaly::MultipleRoots(_)
+                | Anomaly::HeadOutOfRange(_)
+                | Anomaly::Cycle
+        )
+    }
+}
+
+impl std::fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Anomaly::EmptySentence => write!(f, "empty sentence"),
+            Anomaly::ZeroSelfId => write!(f, "token with self_id 0"),
+            Anomaly::DuplicateSelfId(id) => write!(f, "duplicate self_id {id}"),
+            Anomaly::AllParentsZero => write!(f, "all tokens have parent 0"),
+            Anomaly::NoRoot => write!(f, "no token has parent 0"),
+            Anomaly::MultipleRoots(n) => write!(f, "{n} tokens have parent 0"),
+            Anomaly::HeadOutOfRange(id) => write!(f, "parent {id} is not a token of the sentence"),
+            Anomaly::Cycle => write!(f, "the parents form a cycle"),
+        }
+    }
+}
+
+/// Check the token rows of a sentence, as produced by
+/// [`crate::process_sentence::sentence_rows`].
+pub fn check_sentence(rows: &[TokenRow]) -> Vec<Anomaly> {
+    let mut anomalies = vec![];
+    let mut seen = HashSet::new();
+    let ntokens = rows.len();
+    let mut all_parents_zero = true;
+    // (self_id, parent_id) of the tokens
+    let mut tree = vec![];
+
+    for row in rows {
+        match row.self_id {
+            0 => anomalies.push(Anomaly::ZeroSelfId),
+            id => {
+                if !seen.insert(id) {
+                    anomalies.push(Anomaly::DuplicateSelfId(id));
+                }
+            }
+        }
+        if row.parent_id != 0 {
+            all_parents_zero = false;
+        }
+        tree.push((row.self_id, row.parent_id));
+    }
+
+    if ntokens == 0 {
+        anomalies.push(Anomaly::EmptySentence);
+    } else if ntokens > 1 && all_parents_zero {
+        anomalies.push(Anomaly::AllParentsZero);
+    } else if anomalies.is_empty() {
+        // the tree can only be checked when the self_ids are sound
+        anomalies.extend(check_tree(&tree));
+    }
+    anomalies
+}
+
+/// Check that the tokens `tree`, as `(self_id, parent_id)`, form a tree:
+/// one root, parents within the sentence, and no cycles.
+fn check_tree(tree: &[(usize, usize)]) -> Vec<Anomaly> {
+    let mut anomalies = vec![];
+    let parents = tree.iter().copied().collect::<HashMap<_, _>>();
+    let roots = tree.iter().filter(|(_, parent)| *parent == 0).count();
+    match roots {
+        0 => anomalies.push(Anomaly::NoRoot),
+        1 => {}
+        n => anomalies.push(Anomaly::MultipleRoots(n)),
+    }
+    let mut out_of_range = tree
+        .iter()
+        .map(|(_, parent)| *parent)
+        .filter(|parent| *parent != 0 && !parents.contains_key(parent))
+        .collect::<Vec<_>>();
+    out_of_range.sort_unstable();
+    out_of_range.dedup();
+    anomalies.extend(out_of_range.into_iter().map(Anomaly::HeadOutOfRange));
+    // from any token, the root, or a parent out of range, is reached in at
+    // most as many steps as there are tokens, unless there is a cycle
+    let cycle = tree.iter().any(|(id, _)| {
+        let mut id = *id;
+        for _ in 0..=tree.len() {
+            match parents.get(&id) {
+                Some(&parent) if parent != 0 => id = parent,
+                _ => return false,
+            }
+        }
+        true
+    });
+    if cycle {
+        anomalies.push(Anomaly::Cycle);
+    }
+    anomalies
+}
+
+/// Give all tokens of the sentences of `korp_mono_file` whose dependency
+/// trees are broken `X` as deprel and `0` as parent. Returns the number of
+/// sentences that were demoted.
+pub fn demote_broken_trees(korp_mono_file: &mut KorpMonoFile) -> usize {
+    let mut demoted = 0;
+    for sentence in korp_mono_file.sentence.iter_mut() {
+        if !check_sentence(&sentence.rows)
+            .iter()
+            .any(Anomaly::is_broken_tree)
+        {
+            continue;
+        }
+        for row in sentence.rows.iter_mut() {
+            row.func = Cow::Borrowed("X");
+            row.parent_id = 0;
+        }
+        demoted += 1;
+    }
+    demoted
+}
+
+/// The anomalies found in one file, by sentence id.
+pub type FileWarnings = Vec<(String, Anomaly)>;
+
+/// Check all sentences in `korp_mono_file`.
+pub fn check_file(korp_mono_file: &KorpMonoFile) -> FileWarnings {
+    korp_mono_file
+        .sentence
+        .iter()
+        .flat_map(|sentence| {
+            check_sentence(&sentence.rows)
+                .into_iter()
+                .map(|anomaly| (sentence.id.clone(), anomaly))
+        })
+        .collect()
+}
+
+/// The sentences demoted with `--demote-broken-trees`, in all files.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Demoted {
+    pub files: usize,
+    pub sentences: usize,
+}
+
+impl Demoted {
+    pub fn add(&mut self, sentences: usize) {
+        if sentences > 0 {
+            self.files += 1;
+            self.sentences += sentences;
+        }
+    }
+
+    pub fn report(&self) -> String {
+        format!(
+            "demoted {} sentences with broken dependency trees, in {} files\n",
+            self.sentences, self.files
+        )
+    }
+}
+
+/// Warnings of all files, grouped by corpus.
+#[derive(Debug, Default)]
+pub struct Warnings {
+    corpora: BTreeMap<PathBuf, BTreeMap<PathBuf, FileWarnings>>,
+}
+
+impl Warnings {
+    /// Record the warnings of the file `analysed_file`. Files without
+    /// warnings are not recorded.
+    pub fn add(&mut self, analysed_file: PathBuf, warnings: FileWarnings) {
+        if warnings.is_empty() {
+            return;
+        }
+        let corpus = corpus_dir(&analysed_file)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        self.corpora
+            .entry(corpus)
+            .or_default()
+            .insert(analysed_file, warnings);
+    }
+
+    /// The report of a single corpus. Files are sorted by the number of
+    /// warnings, most first.
+    fn report(corpus: &Path, files: &BTreeMap<PathBuf, FileWarnings>) -> String {
+        let mut counts = BTreeMap::<&'static str, usize>::new();
+        for (_, anomaly) in files.values().flatten() {
+            let kind = match anomaly {
+                Anomaly::EmptySentence => "empty sentences",
+                Anomaly::ZeroSelfId => "tokens with self_id 0",
+                Anomaly::DuplicateSelfId(_) => "duplicate self_ids",
+                Anomaly::AllParentsZero => "sentences where all parents are 0",
+                Anomaly::NoRoot => "sentences without a root",
+                Anomaly::MultipleRoots(_) => "sentences with more than one root",
+                Anomaly::HeadOutOfRange(_) => "parents outside of the sentence",
+                Anomaly::Cycle => "sentences with cycles",
+            };
+            *counts.entry(kind).or_default() += 1;
+        }
+        let total: usize = counts.values().sum();
+        let broken_trees = files
+            .values()
+            .flat_map(|warnings| {
+                let broken = warnings
+                    .iter()
+                    .filter(|(_, anomaly)| anomaly.is_broken_tree());
+                broken
+                    .map(|(sentence_id, _)| sentence_id)
+                    .collect::<BTreeSet<_>>()
+            })
+            .count();
+
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(s, "# korp_mono warnings for {}", corpus.display()).expect(w);
+        writeln!(
+            s,
+            "# files with warnings: {}, total warnings: {total}",
+            files.len()
+        )
+        .expect(w);
+        writeln!(
+            s,
+            "# sentences with broken dependency trees: {broken_trees}"
+        )
+        .expect(w);
+        for (kind, count) in counts.iter() {
+            writeln!(s, "# {kind}: {count}").expect(w);
+        }
+
+        let mut files = files.iter().collect::<Vec<_>>();
+        files.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(b.0)));
+        for (path, warnings) in files {
+            writeln!(s, "\n{}\t{}", path.display(), warnings.len()).expect(w);
+            for (sentence_id, anomaly) in warnings {
+                writeln!(s, "\tsentence {sentence_id}: {anomaly}").expect(w);
+            }
+        }
+        s
+    }
+
+    /// Write the report of each corpus to `{corpus}/korp_mono.warnings`.
+    pub fn write_reports(&self) -> std::io::Result<()> {
+        for (corpus, files) in self.corpora.iter() {
+            std::fs::write(corpus.join(REPORT_FILE_NAME), Self::report(corpus, files))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Anomaly, check_sentence, demote_broken_trees};
+    use crate::korp_mono::KorpMonoFile;
+    use crate::korp_mono::file::Sentence;
+    use crate::process_sentence::rows_from_lines;
+
+    #[test]
+    fn ok_sentence() {
+        let text = "Sääʹmǩiõl\tsääʹmǩiõll\tN\tN.Pl.Nom\t1\tSUBJ\t3\nda\tda\tCC\tCC\t2\tCNP\t1\nkulttuur\tkulttuur\tN\tN.Pl.Nom\t3\tHNOUN\t0\n";
+        assert_eq!(check_sentence(&rows_from_lines(text)), vec![]);
+    }
+
+    #[test]
+    fn single_root_token() {
+        let text = "24\t24\tNum\tNum.Arab.Sg.Acc\t1\tHNOUN\t0\n";
+        assert_eq!(check_sentence(&rows_from_lines(text)), vec![]);
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(check_sentence(&[]), vec![Anomaly::EmptySentence]);
+    }
+
+    #[test]
+    fn zero_and_duplicate_self_ids() {
+        let text = "a\ta\tN\tN\t0\tX\t0\nb\tb\tN\tN\t2\tX\t1\nc\tc\tN\tN\t2\tX\t1\n";
+        assert_eq!(
+            check_sentence(&rows_from_lines(text)),
+            vec![Anomaly::ZeroSelfId, Anomaly::DuplicateSelfId(2)]
+        );
+    }
+
+    #[test]
+    fn all_parents_zero() {
+        let text = "a\ta\tN\tN\t1\tX\t0\nb\tb\tN\tN\t2\tX\t0\n";
+        assert_eq!(
+            check_sentence(&rows_from_lines(text)),
+            vec![Anomaly::AllParentsZero]
+        );
+    }
+
+    #[test]
+    fn broken_trees() {
+        let roots = "a\ta\tN\tN\t1\tX\t0\nb\tb\tN\tN\t2\tX\t0\nc\tc\tN\tN\t3\tX\t1\n";
+        assert_eq!(
+            check_sentence(&rows_from_lines(roots)),
+            vec![Anomaly::MultipleRoots(2)]
+        );
+        let out_of_range = "a\ta\tN\tN\t1\tX\t0\nb\tb\tN\tN\t2\tX\t5\n";
+        assert_eq!(
+            check_sentence(&rows_from_lines(out_of_range)),
+            vec![Anomaly::HeadOutOfRange(5)]
+        );
+        let cycle = "a\ta\tN\tN\t1\tX\t0\nb\tb\tN\tN\t2\tX\t3\nc\tc\tN\tN\t3\tX\t2\n";
+        assert_eq!(
+            check_sentence(&rows_from_lines(cycle)),
+            vec![Anomaly::Cycle]
+        );
+        let no_root = "a\ta\tN\tN\t1\tX\t2\nb\tb\tN\tN\t2\tX\t1\n";
+        assert_eq!(
+            check_sentence(&rows_from_lines(no_root)),
+            vec![Anomaly::NoRoot, Anomaly::Cycle]
+        );
+    }
+
+    #[test]
+    fn demote() {
+        let sentence = |id: &str, text: &str| Sentence::new(id.to_string(), rows_from_lines(text));
+        let mut file = KorpMonoFile {
+            sentence: vec![
+                sentence("1", "a\ta\tN\tN\t1\tSUBJ\t2\nb\tb\tV\tV\t2\tFMV\t0"),
+                sentence("2", "a\ta\tN\tN\t1\tSUBJ\t2\nb\tb\tV\tV\t2\tFMV\t1"),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(demote_broken_trees(&mut file), 1);
+        let tree = |sentence: &Sentence| {
+            sentence
+                .rows
+                .iter()
+                .map(|row| (row.self_id, row.func.to_string(), row.parent_id))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            tree(&file.sentence[0]),
+            [(1, "SUBJ".to_string(), 2), (2, "FMV".to_string(), 0)]
+        );
+        assert_eq!(
+            tree(&file.sentence[1]),
+            [(1, "X".to_string(), 0), (2, "X".to_string(), 0)]
+        );
+    }
+}
@@ -0,0 +1,188 @@
+//! Remove repeated sentences, with `--dedup`.
+//!
+//! Boilerplate, like headers, footers and navigation text, is repeated
+//! thousands of times in some corpora, and skews the statistics in Korp.
+//! Sentences are compared by their normalized text: the word forms,
+//! lowercased, joined with single spaces. Only the first occurrence of a
+//! sentence is kept, either within each document, or within the whole
+//! corpus. The kept sentences keep their ids.
+//!
+//! With `corpus`, which occurrence is the first can differ between runs,
+//! since files are processed in parallel.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt::Write;
+use std::hash::{DefaultHasher, Hasher};
+use std::path::PathBuf;
+
+use crate::korp_mono::KorpMonoFile;
+use crate::process_sentence::TokenRow;
+
+/// Where a sentence must not have been seen before to be kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum DedupScope {
+    /// Remove the sentences that occur earlier in the same document
+    WithinDoc,
+    /// Remove the sentences that occur earlier in any document of the same
+    /// corpus
+    Corpus,
+}
+
+/// The normalized text of the sentence of `rows`: the word forms,
+/// lowercased, joined with single spaces.
+pub fn normalize_sentence(rows: &[TokenRow]) -> String {
+    rows.iter()
+        .flat_map(|row| row.word_form.split_whitespace())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Hash of the normalized sentence of `rows`, see [`normalize_sentence`].
+/// Empty sentences have no hash, they are never removed.
+pub fn sentence_hash(rows: &[TokenRow]) -> Option<u64> {
+    let normalized = normalize_sentence(rows);
+    if normalized.is_empty() {
+        return None;
+    }
+    let mut hasher = DefaultHasher::new();
+    hasher.write(normalized.as_bytes());
+    Some(hasher.finish())
+}
+
+/// The number of sentences seen, and removed, in a corpus.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DedupCounts {
+    pub sentences: u64,
+    pub removed: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct SentenceDedup {
+    /// The hashes of the sentences seen so far, by corpus directory. Only
+    /// used with [`DedupScope::Corpus`].
+    seen: HashMap<PathBuf, HashSet<u64>>,
+    /// By corpus directory
+    counts: BTreeMap<PathBuf, DedupCounts>,
+}
+
+impl SentenceDedup {
+    /// Remove the sentences of `document` that were seen before in `scope`,
+    /// and count them for the corpus `corpus`. Returns the number of
+    /// removed sentences.
+    pub fn dedup(
+        &mut self,
+        corpus: PathBuf,
+        document: &mut KorpMonoFile,
+        scope: DedupScope,
+    ) -> usize {
+        let mut within_doc = HashSet::new();
+        let seen = match scope {
+            DedupScope::WithinDoc => &mut within_doc,
+            DedupScope::Corpus => self.seen.entry(corpus.clone()).or_default(),
+        };
+        let before = document.sentence.len();
+        document
+            .sentence
+            .retain(|sentence| match sentence_hash(&sentence.rows) {
+                Some(hash) => seen.insert(hash),
+                None => true,
+            });
+        let removed = before - document.sentence.len();
+        let counts = self.counts.entry(corpus).or_default();
+        counts.sentences += before as u64;
+        counts.removed += removed as u64;
+        removed
+    }
+
+    pub fn counts(&self) -> &BTreeMap<PathBuf, DedupCounts> {
+        &self.counts
+    }
+
+    /// A summary for the end of the run, one line per corpus.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        let removed: u64 = self.counts.values().map(|c| c.removed).sum();
+        writeln!(s, "{removed} duplicate sentences removed:").expect(w);
+        for (corpus, counts) in self.counts.iter() {
+            writeln!(
+                s,
+                "  {}: {} of {}",
+                corpus.display(),
+                counts.removed,
+                counts.sentences
+            )
+            .expect(w);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DedupScope, SentenceDedup, normalize_sentence, sentence_hash};
+    use crate::korp_mono::KorpMonoFile;
+    use crate::process_sentence::rows_from_lines;
+    use std::path::PathBuf;
+
+    fn ids(document: &KorpMonoFile) -> Vec<&str> {
+        document.sentence.iter().map(|s| s.id.as_str()).collect()
+    }
+
+    const A: &str = "Ođđasat\tođas\tN\tN.Pl.Nom\t1\tHNOUN\t0\n";
+    const A_UPPER: &str = "OĐĐASAT\tođas\tN\tN.Pl.Nom\t1\tHNOUN\t0\n";
+    const B: &str = "Mun\tmun\tPron\tPron.Pers.Sg1.Nom\t1\tSUBJ→\t2\n";
+
+    #[test]
+    fn normalize() {
+        let rows = rows_from_lines(&format!("{A}{B}"));
+        assert_eq!(normalize_sentence(&rows), "ođđasat mun");
+        assert_eq!(normalize_sentence(&[]), "");
+        let hash = |text: &str| sentence_hash(&rows_from_lines(text));
+        assert_eq!(hash(A), hash(A_UPPER));
+        // only the word forms count
+        let other_lemma = A.replace("\tođas\t", "\tođđasat\t");
+        assert_eq!(hash(A), hash(&other_lemma));
+        assert_eq!(hash("\n"), None);
+    }
+
+    #[test]
+    fn within_doc() {
+        let corpus = PathBuf::from("/c/corpus-sme");
+        let mut dedup = SentenceDedup::default();
+        let mut doc = KorpMonoFile::with_sentences(&[A, B, A_UPPER, "", ""]);
+        let removed = dedup.dedup(corpus.clone(), &mut doc, DedupScope::WithinDoc);
+        assert_eq!(removed, 1);
+        assert_eq!(ids(&doc), ["1", "2", "4", "5"]);
+
+        let mut doc = KorpMonoFile::with_sentences(&[B, A]);
+        dedup.dedup(corpus.clone(), &mut doc, DedupScope::WithinDoc);
+        assert_eq!(ids(&doc), ["1", "2"]);
+        assert_eq!(dedup.counts()[&corpus].sentences, 7);
+        assert_eq!(dedup.counts()[&corpus].removed, 1);
+    }
+
+    #[test]
+    fn corpus() {
+        let sme = PathBuf::from("/c/corpus-sme");
+        let sme_closed = PathBuf::from("/c/corpus-sme-x-closed");
+        let mut dedup = SentenceDedup::default();
+        let mut doc = KorpMonoFile::with_sentences(&[A]);
+        dedup.dedup(sme.clone(), &mut doc, DedupScope::Corpus);
+        let mut doc = KorpMonoFile::with_sentences(&[B, A]);
+        dedup.dedup(sme.clone(), &mut doc, DedupScope::Corpus);
+        assert_eq!(ids(&doc), ["1"]);
+        let mut doc = KorpMonoFile::with_sentences(&[A]);
+        dedup.dedup(sme_closed.clone(), &mut doc, DedupScope::Corpus);
+        assert_eq!(ids(&doc), ["1"]);
+        assert_eq!(
+            dedup.report(),
+            concat!(
+                "1 duplicate sentences removed:\n",
+                "  /c/corpus-sme: 1 of 3\n",
+                "  /c/corpus-sme-x-closed: 0 of 1\n",
+            )
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Leaving out boilerplate sentences, like page numbers and "Side 1 av 4",
+//! with `--drop-sentence-regex`.
+//!
+//! The patterns are matched against each sentence as plain text, the word
+//! forms put together as in running text (see
+//! [`crate::process_sentence::detokenize`]), so `^Side \d+ av \d+$`
+//! matches the sentence of the tokens `Side`, `1`, `av` and `4`. A sentence
+//! that matches any of them is left out, and counted for the first one it
+//! matches. The counts are printed at the end of the run.
+
+use std::fmt::Write;
+
+use regex::Regex;
+
+use crate::korp_mono::KorpMonoFile;
+use crate::process_sentence;
+
+#[derive(Debug, Default, Clone)]
+pub struct SentenceFilter {
+    patterns: Vec<Regex>,
+}
+
+impl SentenceFilter {
+    pub fn new(patterns: Vec<Regex>) -> Self {
+        Self { patterns }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// Remove the sentences of `document` that match a pattern. Returns the
+    /// number of removed sentences of each pattern.
+    pub fn drop_sentences(&self, document: &mut KorpMonoFile) -> Vec<u64> {
+        let mut dropped = vec![0; self.patterns.len()];
+        document.sentence.retain(|sentence| {
+            let line = process_sentence::surface_text(&sentence.rows);
+            match self
+                .patterns
+                .iter()
+                .position(|pattern| pattern.is_match(&line))
+            {
+                Some(i) => {
+                    dropped[i] += 1;
+                    false
+                }
+                None => true,
+            }
+        });
+        dropped
+    }
+}
+
+/// The number of sentences that were seen, and left out by each pattern.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DroppedSentences {
+    pub sentences: u64,
+    /// By pattern, in the order of the patterns
+    pub dropped: Vec<u64>,
+}
+
+impl DroppedSentences {
+    /// Count the `dropped` sentences of each pattern, of `sentences`.
+    pub fn add(&mut self, sentences: usize, dropped: &[u64]) {
+        self.sentences += sentences as u64;
+        if self.dropped.len() < dropped.len() {
+            self.dropped.resize(dropped.len(), 0);
+        }
+        for (total, n) in self.dropped.iter_mut().zip(dropped) {
+            *total += n;
+        }
+    }
+
+    /// A summary for the end of the run, one line per pattern of `filter`.
+    pub fn report(&self, filter: &SentenceFilter) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        let dropped: u64 = self.dropped.iter().sum();
+        writeln!(s, "{dropped} of {} sentences dropped:", self.sentences).expect(w);
+        for (i, pattern) in filter.patterns.iter().enumerate() {
+            let n = self.dropped.get(i).copied().unwrap_or_default();
+            writeln!(s, "  {n:>8}  {}", pattern.as_str()).expect(w);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DroppedSentences, SentenceFilter};
+    use crate::korp_mono::KorpMonoFile;
+    use crate::korp_mono::file::Sentence;
+    use crate::process_sentence::rows_from_lines;
+    use regex::Regex;
+
+    #[test]
+    fn drop() {
+        let sentence = |text: &str| Sentence::new(String::new(), rows_from_lines(text));
+        let mut document = KorpMonoFile {
+            sentence: vec![
+                sentence("Side\tside\n1\t1\nav\tav\n4\t4"),
+                sentence("Mun\tmun\nboađán\tboahtit\n.\t."),
+                sentence("12\t12"),
+            ],
+            ..Default::default()
+        };
+        let filter = SentenceFilter::new(vec![
+            Regex::new(r"^Side \d+ av \d+$").unwrap(),
+            Regex::new(r"^\d+$").unwrap(),
+            Regex::new(r"^Mun$").unwrap(),
+        ]);
+        let dropped = filter.drop_sentences(&mut document);
+        assert_eq!(dropped, [1, 1, 0]);
+        assert_eq!(document.sentence.len(), 1);
+
+        let mut counts = DroppedSentences::default();
+        counts.add(3, &dropped);
+        counts.add(2, &[0, 1, 0]);
+        assert_eq!(
+            counts.report(&filter),
+            concat!(
+                "3 of 5 sentences dropped:\n",
+                "         1  ^Side \\d+ av \\d+$\n",
+                "         2  ^\\d+$\n",
+                "         0  ^Mun$\n",
+            )
+        );
+    }
+}
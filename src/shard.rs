@@ -0,0 +1,238 @@
+//! Converting a corpus in several independent jobs, e.g. on a cluster, with
+//! `--shard i/n`, and putting their reports together with the
+//! `merge-reports` subcommand.
+//!
+//! Each job converts the files of its shard: the files whose stable hash
+//! (FNV-1a) of their path falls in it. The path is taken from the corpus
+//! directory on (`corpus-sme/analysed/news/a.xml`), so that the shards are
+//! the same on machines where the corpora are in other directories, and
+//! the jobs don't need to know about each other. The shards are numbered
+//! from 1 to `n`.
+//!
+//! With `--json-report report.json`, each job writes a [`RunReport`], and
+//! `korp-mono-rs merge-reports shard-*.json` adds them up, and says which
+//! shards are missing.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::korp_mono::path::corpus_dir;
+
+/// The shard `index` of `count`, as in `--shard 2/8`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Shard {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl std::fmt::Display for Shard {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.count)
+    }
+}
+
+impl std::str::FromStr for Shard {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("'{s}' is not a shard, like 2/8");
+        let (index, count) = s.split_once('/').ok_or_else(invalid)?;
+        let index = index.trim().parse::<u64>().map_err(|_| invalid())?;
+        let count = count.trim().parse::<u64>().map_err(|_| invalid())?;
+        match (1..=count).contains(&index) {
+            true => Ok(Self { index, count }),
+            false => Err(format!(
+                "shard {index} of {count} must be from 1 to {count}"
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for Shard {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Shard> for String {
+    fn from(shard: Shard) -> Self {
+        shard.to_string()
+    }
+}
+
+/// The 64 bit FNV-1a hash of `bytes`, which, unlike the hashers of the
+/// standard library, is the same in every build.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+impl Shard {
+    /// Is the analysed file `path` in this shard?
+    pub fn contains(&self, path: &Path) -> bool {
+        let key = corpus_dir(path)
+            .and_then(Path::parent)
+            .and_then(|dir| path.strip_prefix(dir).ok())
+            .unwrap_or(path);
+        let key = key
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        fnv1a(key.as_bytes()) % self.count == self.index - 1
+    }
+}
+
+/// The summary of a run, with `--json-report`, or of several, merged with
+/// [`RunReport::merge`].
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunReport {
+    /// The shards that were converted, none if the whole corpus was
+    #[serde(default)]
+    pub shards: Vec<Shard>,
+    /// The files that were found, and that were converted, or failed
+    pub files: u64,
+    /// The files in the skiplist
+    pub skipped: u64,
+    pub failed: u64,
+    /// The failed files by category, see
+    /// [`crate::status_message::ErrorCategory`]
+    #[serde(default)]
+    pub failures: BTreeMap<String, u64>,
+    /// The errors that were not about a single file, by category
+    #[serde(default)]
+    pub errors: BTreeMap<String, u64>,
+    /// How long the run took; of merged runs, the longest
+    pub seconds: f64,
+}
+
+impl RunReport {
+    pub fn from_file(path: &Path) -> anyhow::Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read report '{}'", path.display()))?;
+        serde_json::from_str(&contents)
+            .with_context(|| format!("invalid report '{}'", path.display()))
+    }
+
+    pub fn write_file(&self, path: &Path) -> anyhow::Result<()> {
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        std::fs::write(path, json)?;
+        Ok(())
+    }
+
+    /// The reports of the shards of one run, added up. They must all be of
+    /// the same number of shards, and each shard can only be in one.
+    pub fn merge(reports: &[RunReport]) -> anyhow::Result<Self> {
+        let mut merged = Self::default();
+        for report in reports {
+            for shard in report.shards.iter() {
+                if let Some(first) = merged
+                    .shards
+                    .first()
+                    .filter(|first| first.count != shard.count)
+                {
+                    anyhow::bail!("shard {shard} is not of the same run as shard {first}");
+                }
+                if merged.shards.contains(shard) {
+                    anyhow::bail!("shard {shard} is in more than one report");
+                }
+                merged.shards.push(*shard);
+            }
+            merged.files += report.files;
+            merged.skipped += report.skipped;
+            merged.failed += report.failed;
+            for (category, n) in report.failures.iter() {
+                *merged.failures.entry(category.clone()).or_default() += n;
+            }
+            for (category, n) in report.errors.iter() {
+                *merged.errors.entry(category.clone()).or_default() += n;
+            }
+            merged.seconds = merged.seconds.max(report.seconds);
+        }
+        merged.shards.sort();
+        Ok(merged)
+    }
+
+    /// The shards of the run that are not in the merged report.
+    pub fn missing_shards(&self) -> Vec<Shard> {
+        let Some(count) = self.shards.first().map(|shard| shard.count) else {
+            return vec![];
+        };
+        (1..=count)
+            .map(|index| Shard { index, count })
+            .filter(|shard| !self.shards.contains(shard))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunReport, Shard};
+    use std::path::Path;
+
+    #[test]
+    fn parse() {
+        assert_eq!("2/8".parse(), Ok(Shard { index: 2, count: 8 }));
+        assert!("0/8".parse::<Shard>().is_err());
+        assert!("9/8".parse::<Shard>().is_err());
+        assert!("2".parse::<Shard>().is_err());
+    }
+
+    #[test]
+    fn partition() {
+        let shards = (1..=3)
+            .map(|index| Shard { index, count: 3 })
+            .collect::<Vec<_>>();
+        for i in 0..100 {
+            let path = format!("/c/corpus-sme/analysed/news/{i}.xml");
+            let n = shards
+                .iter()
+                .filter(|shard| shard.contains(Path::new(&path)))
+                .count();
+            assert_eq!(n, 1, "{path}");
+        }
+        // the same shard wherever the corpus is
+        for shard in shards.iter() {
+            assert_eq!(
+                shard.contains(Path::new("/c/corpus-sme/analysed/news/a.xml")),
+                shard.contains(Path::new("/mnt/gut/corpus-sme/analysed/news/a.xml"))
+            );
+        }
+    }
+
+    #[test]
+    fn merge() {
+        let report = |index, failed| RunReport {
+            shards: vec![Shard { index, count: 3 }],
+            files: 10,
+            skipped: 1,
+            failed,
+            failures: [("xml".to_string(), failed)].into_iter().collect(),
+            errors: [("write".to_string(), 1)].into_iter().collect(),
+            seconds: index as f64,
+        };
+        let merged = RunReport::merge(&[report(3, 2), report(1, 1)]).unwrap();
+        assert_eq!(merged.files, 20);
+        assert_eq!(merged.failures["xml"], 3);
+        assert_eq!(merged.errors["write"], 2);
+        assert_eq!(merged.seconds, 3.0);
+        assert_eq!(merged.missing_shards(), [Shard { index: 2, count: 3 }]);
+
+        let json = serde_json::to_string(&merged).unwrap();
+        assert!(json.starts_with("{\"shards\":[\"1/3\",\"3/3\"],"), "{json}");
+        assert_eq!(serde_json::from_str::<RunReport>(&json).unwrap(), merged);
+
+        assert!(RunReport::merge(&[report(1, 0), report(1, 0)]).is_err());
+        let mut other = report(2, 0);
+        other.shards[0].count = 4;
+        assert!(RunReport::merge(&[report(1, 0), other]).is_err());
+    }
+}
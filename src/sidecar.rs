@@ -0,0 +1,229 @@
+//! Corrections of the header of single documents, from a sidecar file next
+//! to the analysed file, so that a wrong year or a missing author can be
+//! fixed without analysing the document again.
+//!
+//! The sidecar of `analysed/news/a.xml` is `analysed/news/a.xml.meta.toml`:
+//!
+//! ```toml
+//! year = 1998
+//! title = "Sámi statistihkka 2018"
+//! genre = "news"
+//! license = "free"
+//! authors = [{ firstname = "Anders", lastname = "Sønstebø", sex = "m" }]
+//! ```
+//!
+//! Each key replaces that value of the header: `title`, `year`, `genre` (the
+//! `code` of `<genre>`), `authors` (all `<person>`s), `license` (the `type`
+//! of `<license>`), `translated-from`, `orig-file-name`, and `lang` (the
+//! `xml:lang` of the document). The values that are not in the file are
+//! kept. The fields that were overridden are counted, see [`Applied`], and
+//! reported at the end of the run.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::analysed::file::UnparsedAnalysedDocument;
+use crate::giella_xml::header::{Genre, License, Person};
+
+/// What is added to the name of the analysed file to get the name of its
+/// sidecar.
+pub const SIDECAR_SUFFIX: &str = ".meta.toml";
+
+/// The sidecar file of `analysed_file`.
+pub fn sidecar_path(analysed_file: &Path) -> PathBuf {
+    let mut name = analysed_file.file_name().unwrap_or_default().to_os_string();
+    name.push(SIDECAR_SUFFIX);
+    analysed_file.with_file_name(name)
+}
+
+/// A year, which is more natural to write as a number, but is text in the
+/// header.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Year {
+    Number(i64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SidecarPerson {
+    firstname: Option<String>,
+    lastname: Option<String>,
+    sex: Option<String>,
+    born: Option<String>,
+    nationality: Option<String>,
+}
+
+/// The overrides of one document, as they are written in the sidecar.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Overrides {
+    title: Option<String>,
+    year: Option<Year>,
+    genre: Option<String>,
+    authors: Option<Vec<SidecarPerson>>,
+    license: Option<String>,
+    translated_from: Option<String>,
+    orig_file_name: Option<String>,
+    lang: Option<String>,
+}
+
+impl Overrides {
+    /// The overrides in the sidecar of `analysed_file`, or `None` if it has
+    /// none.
+    pub fn of(analysed_file: &Path) -> anyhow::Result<Option<Self>> {
+        let path = sidecar_path(analysed_file);
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("can't read metadata sidecar '{}'", path.display()));
+            }
+        };
+        let overrides = toml::from_str(&contents)
+            .with_context(|| format!("invalid metadata sidecar '{}'", path.display()))?;
+        Ok(Some(overrides))
+    }
+
+    /// Replace the values of `doc` that are in the sidecar, and return the
+    /// names of the fields that were replaced.
+    pub fn apply(self, doc: &mut UnparsedAnalysedDocument) -> Vec<&'static str> {
+        let mut applied = vec![];
+        let header = &mut doc.header;
+        if let Some(title) = self.title {
+            header.title = Some(title);
+            applied.push("title");
+        }
+        if let Some(year) = self.year {
+            header.year = Some(match year {
+                Year::Number(year) => year.to_string(),
+                Year::Text(year) => year,
+            });
+            applied.push("year");
+        }
+        if let Some(code) = self.genre {
+            header.genre = Some(Genre { code });
+            applied.push("genre");
+        }
+        if let Some(authors) = self.authors {
+            header.authors.0 = authors
+                .into_iter()
+                .map(|person| Person {
+                    firstname: person.firstname,
+                    lastname: person.lastname,
+                    sex: person.sex,
+                    born: person.born,
+                    nationality: person.nationality,
+                })
+                .collect();
+            applied.push("authors");
+        }
+        if let Some(license) = self.license {
+            header.availability.license = Some(License {
+                r#type: Some(license),
+            });
+            applied.push("license");
+        }
+        if let Some(translated_from) = self.translated_from {
+            header.translated_from = Some(translated_from);
+            applied.push("translated-from");
+        }
+        if let Some(orig_file_name) = self.orig_file_name {
+            header.orig_file_name = Some(orig_file_name);
+            applied.push("orig-file-name");
+        }
+        if let Some(lang) = self.lang {
+            doc.lang = Some(lang);
+            applied.push("lang");
+        }
+        applied
+    }
+}
+
+/// The number of documents that had a sidecar, and of each field that was
+/// overridden.
+#[derive(Debug, Default)]
+pub struct Applied {
+    documents: u64,
+    fields: BTreeMap<&'static str, u64>,
+}
+
+impl Applied {
+    pub fn add(&mut self, fields: &[&'static str]) {
+        self.documents += 1;
+        for field in fields {
+            *self.fields.entry(*field).or_default() += 1;
+        }
+    }
+
+    /// A summary for the end of the run, one line per field.
+    pub fn report(&self) -> String {
+        let mut s = String::new();
+        let w = "can always write to String";
+        writeln!(s, "{} documents with metadata overrides:", self.documents).expect(w);
+        for (field, n) in self.fields.iter() {
+            writeln!(s, "  {field}: {n}").expect(w);
+        }
+        s
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Applied, Overrides, sidecar_path};
+    use crate::analysed::file::{HeaderMode, parse_document};
+    use std::path::Path;
+
+    const DOC: &str = r#"<document xml:lang="sme" id="no_id">
+<header>
+  <title>Boares</title>
+  <genre code="facta"/>
+  <year>2081</year>
+  <conversion_status type="standard"/>
+  <availability><license type="standard"/></availability>
+</header>
+<body><dependency><![CDATA[
+"<Son>"
+	"son" Pron <W:0.0> @SUBJ> #1->0
+]]></dependency></body>
+</document>"#;
+
+    #[test]
+    fn path() {
+        assert_eq!(
+            sidecar_path(Path::new("analysed/news/a.xml")),
+            Path::new("analysed/news/a.xml.meta.toml")
+        );
+    }
+
+    #[test]
+    fn apply() {
+        let mut doc = parse_document(DOC, HeaderMode::Strict).unwrap();
+        let overrides: Overrides = toml::from_str(
+            "year = 1981\ntitle = \"Ođđa\"\nauthors = [{ lastname = \"Guttorm\" }]\n",
+        )
+        .unwrap();
+        let applied = overrides.apply(&mut doc);
+        assert_eq!(applied, ["title", "year", "authors"]);
+        assert_eq!(doc.header.year.as_deref(), Some("1981"));
+        assert_eq!(doc.header.title.as_deref(), Some("Ođđa"));
+        assert_eq!(doc.header.authors.0[0].lastname.as_deref(), Some("Guttorm"));
+        assert_eq!(doc.header.genre.as_ref().unwrap().code, "facta");
+
+        assert!(toml::from_str::<Overrides>("yaer = 1981").is_err());
+
+        let mut counts = Applied::default();
+        counts.add(&applied);
+        counts.add(&["year"]);
+        assert_eq!(
+            counts.report(),
+            "2 documents with metadata overrides:\n  authors: 1\n  title: 1\n  year: 2\n"
+        );
+    }
+}
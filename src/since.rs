@@ -0,0 +1,111 @@
+//! Select the analysed files that were modified since a given time, with
+//! `--since`, for incremental builds.
+//!
+//! Times are given as `YYYY-MM-DD`, optionally followed by `HH:MM` or
+//! `HH:MM:SS` (separated by a space or `T`), and are taken to be UTC.
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use crate::date::{days_from_civil, days_in_month};
+
+fn number<T: std::str::FromStr>(s: &str, what: &str, digits: usize) -> Result<T, String> {
+    match s.len() == digits && s.bytes().all(|b| b.is_ascii_digit()) {
+        true => s.parse().map_err(|_| format!("invalid {what} '{s}'")),
+        false => Err(format!(
+            "expected {digits} digits for the {what}, got '{s}'"
+        )),
+    }
+}
+
+/// Parse `s` as a UTC date, with an optional time of day. For `--since`.
+pub fn parse_since(s: &str) -> Result<SystemTime, String> {
+    let (date, time) = match s.split_once(['T', ' ']) {
+        Some((date, time)) => (date, Some(time)),
+        None => (s, None),
+    };
+    let [year, month, day] = date.split('-').collect::<Vec<_>>()[..] else {
+        return Err(format!("expected a date like 2025-01-31, got '{s}'"));
+    };
+    let year: i64 = number(year, "year", 4)?;
+    let month: u32 = number(month, "month", 2)?;
+    let day: u32 = number(day, "day", 2)?;
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) {
+        return Err(format!("no such date '{date}'"));
+    }
+
+    let seconds_of_day = match time.map(|time| time.split(':').collect::<Vec<_>>()) {
+        None => 0,
+        Some(parts) => {
+            let (hour, minute, second) = match parts[..] {
+                [hour, minute] => (hour, minute, "00"),
+                [hour, minute, second] => (hour, minute, second),
+                _ => return Err(format!("expected a time like 13:45 or 13:45:00, got '{s}'")),
+            };
+            let hour: u64 = number(hour, "hour", 2)?;
+            let minute: u64 = number(minute, "minute", 2)?;
+            let second: u64 = number(second, "second", 2)?;
+            if hour > 23 || minute > 59 || second > 59 {
+                return Err(format!("no such time of day in '{s}'"));
+            }
+            hour * 3600 + minute * 60 + second
+        }
+    };
+
+    let days = days_from_civil(year, month, day);
+    let day_start = match u64::try_from(days) {
+        Ok(days) => SystemTime::UNIX_EPOCH + Duration::from_secs(days * 86_400),
+        Err(_) => SystemTime::UNIX_EPOCH - Duration::from_secs(days.unsigned_abs() * 86_400),
+    };
+    Ok(day_start + Duration::from_secs(seconds_of_day))
+}
+
+/// Was the file at `path` modified at or after `since`? Files whose
+/// modification time can't be read are kept, so that the error is reported
+/// when they are read.
+pub fn modified_since(path: &Path, since: SystemTime) -> bool {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .map(|modified| modified >= since)
+        .unwrap_or(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_since;
+    use std::time::{Duration, SystemTime};
+
+    fn secs(s: &str) -> u64 {
+        parse_since(s)
+            .unwrap()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(secs("1970-01-01"), 0);
+        assert_eq!(secs("2000-03-01"), 951_868_800);
+        assert_eq!(secs("2024-02-29"), 1_709_164_800);
+        assert_eq!(secs("2025-01-31T13:45"), 1_738_331_100);
+        assert_eq!(secs("2025-01-31 13:45:30"), 1_738_331_130);
+        assert_eq!(
+            parse_since("1969-12-31").unwrap(),
+            SystemTime::UNIX_EPOCH - Duration::from_secs(86_400)
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        [
+            "2025-02-29",
+            "2025-13-01",
+            "2025-1-01",
+            "yesterday",
+            "2025-01-31T25:00",
+        ]
+        .iter()
+        .for_each(|s| assert!(parse_since(s).is_err(), "{s}"));
+    }
+}
@@ -0,0 +1,75 @@
+//! A list of documents that are known to be broken, and that are skipped
+//! on purpose, given with `--skiplist`.
+//!
+//! Each line of the list is either the path of a file inside the
+//! `analysed/` directory (`news/avvir/a.html.xml`), or a document id, which
+//! is the file name without the `.xml` extension (`a.html`). Everything
+//! after a `#` is a comment, e.g. the reason the document is broken.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::path_filter::analysed_relative;
+
+#[derive(Debug, Default, Clone)]
+pub struct Skiplist {
+    entries: HashSet<String>,
+}
+
+impl Skiplist {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read skiplist '{}'", path.display()))?;
+        Ok(Self::from_list(&contents))
+    }
+
+    fn from_list(contents: &str) -> Self {
+        let entries = contents
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(entry, _)| entry).trim())
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| entry.trim_start_matches("./").to_string())
+            .collect();
+        Self { entries }
+    }
+
+    /// Is the analysed file `path` in the list, by its path or its id?
+    pub fn contains(&self, path: &Path) -> bool {
+        if self.entries.is_empty() {
+            return false;
+        }
+        let relative = analysed_relative(path);
+        let file_name = relative.rsplit('/').next().unwrap_or(&relative);
+        let id = file_name.strip_suffix(".xml").unwrap_or(file_name);
+        [relative.as_str(), file_name, id]
+            .iter()
+            .any(|key| self.entries.contains(*key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Skiplist;
+    use std::path::Path;
+
+    #[test]
+    fn paths_and_ids() {
+        let skiplist = Skiplist::from_list(
+            "# known to be broken\n\
+             news/avvir/a.html.xml\n\
+             ./admin/b.pdf.xml  # truncated\n\
+             \n\
+             c.doc\n",
+        );
+        let contains = |p: &str| skiplist.contains(Path::new(p));
+        assert!(contains("/c/corpus-sme/analysed/news/avvir/a.html.xml"));
+        assert!(contains("/c/corpus-sme/analysed/admin/b.pdf.xml"));
+        assert!(contains("/c/corpus-sme/analysed/facta/x/c.doc.xml"));
+        assert!(!contains("/c/corpus-sme/analysed/news/a.html.xml"));
+        assert!(!contains("/c/corpus-sme/analysed/news/avvir/d.html.xml"));
+        assert!(!Skiplist::default().contains(Path::new("/c/a.xml")));
+    }
+}
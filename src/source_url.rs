@@ -0,0 +1,74 @@
+//! The original location of a document, from `<origFileName>` in the
+//! header, as a `source_url` attribute of `<text>`, with `--source-url`.
+//!
+//! The value is usually the URL the document was downloaded from, or the
+//! path of the original file. Only values that look like one of those are
+//! used: an URL with a known scheme, or a path with a `/` or an extension.
+//! Anything else (free text, placeholders) is left out.
+
+/// The URL schemes that are accepted.
+const SCHEMES: [&str; 5] = ["http://", "https://", "ftp://", "ftps://", "file://"];
+
+/// Characters that are never part of an URL or a path worth keeping.
+const FORBIDDEN: [char; 4] = ['<', '>', '"', '|'];
+
+/// The source URL made from the `<origFileName>` value `orig_file_name`,
+/// trimmed, or `None` if it doesn't look like an URL or a path.
+pub fn source_url(orig_file_name: &str) -> Option<String> {
+    let value = orig_file_name.trim();
+    if value.is_empty()
+        || value.contains(|c: char| c.is_whitespace() || c.is_control())
+        || value.contains(FORBIDDEN)
+    {
+        return None;
+    }
+    let lower = value.to_lowercase();
+    if let Some(scheme) = SCHEMES.iter().find(|scheme| lower.starts_with(*scheme)) {
+        return (value.len() > scheme.len()).then(|| value.to_string());
+    }
+    // not an URL with another scheme, like `mailto:`
+    if value.contains("://") {
+        return None;
+    }
+    let is_path = value.contains(['/', '\\'])
+        || value
+            .rsplit_once('.')
+            .is_some_and(|(name, ext)| !name.is_empty() && !ext.is_empty());
+    is_path.then(|| value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::source_url;
+
+    #[test]
+    fn urls_and_paths() {
+        let url = "https://www.ssb.no/befolkning/_attachment/339026?_ts=16151cb7dd0";
+        assert_eq!(source_url(&format!("  {url}\n")).as_deref(), Some(url));
+        assert_eq!(
+            source_url("HTTP://avvir.no/a").as_deref(),
+            Some("HTTP://avvir.no/a")
+        );
+        assert_eq!(
+            source_url("/home/user/orig/a.doc").as_deref(),
+            Some("/home/user/orig/a.doc")
+        );
+        assert_eq!(
+            source_url("sami_statistihkka_2018.pdf").as_deref(),
+            Some("sami_statistihkka_2018.pdf")
+        );
+        assert_eq!(source_url("C:\\orig\\a").as_deref(), Some("C:\\orig\\a"));
+    }
+
+    #[test]
+    fn rejected() {
+        assert_eq!(source_url(""), None);
+        assert_eq!(source_url("  "), None);
+        assert_eq!(source_url("https://"), None);
+        assert_eq!(source_url("unknown"), None);
+        assert_eq!(source_url("see the web page"), None);
+        assert_eq!(source_url("mailto://a@b.no"), None);
+        assert_eq!(source_url("<a.html>"), None);
+        assert_eq!(source_url(".pdf"), None);
+    }
+}
@@ -3,6 +3,91 @@ use std::time::Duration;
 
 use crate::analysed::file::{ParsedAnalysedDocument, UnparsedAnalysedDocument};
 
+/// Why a file failed to convert. The names, from [`ErrorCategory::as_str`],
+/// are stable, and meant to be matched on by scripts reading the logs, the
+/// report at the end of the run, and the metrics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ErrorCategory {
+    /// The analysed file could not be read
+    Read,
+    /// The analysed file is not valid xml, or not an analysed document
+    Xml,
+    /// The analyses in the `<dependency>` element could not be parsed
+    Analyses,
+    /// Parsing the analyses took longer than `--timeout`
+    Timeout,
+    /// The file was skipped without parsing it, while too many parses that
+    /// timed out were still running
+    Saturated,
+    /// A stage panicked
+    Panic,
+    /// The analysed file is not in a place the korp_mono path can be made from
+    OutputPath,
+    /// The directory of the korp_mono file could not be created
+    CreateDir,
+    /// The korp_mono file could not be serialized or written
+    Write,
+    /// A token has a tab or a newline in one of its columns, with
+    /// `--strict-columns`
+    Columns,
+    /// The `korp-mono.toml` of the corpus, or the metadata sidecar of the
+    /// document, could not be read
+    Settings,
+    /// Any error that was logged without a category
+    Other,
+}
+
+impl ErrorCategory {
+    pub const ALL: [ErrorCategory; 12] = [
+        ErrorCategory::Read,
+        ErrorCategory::Xml,
+        ErrorCategory::Analyses,
+        ErrorCategory::Timeout,
+        ErrorCategory::Saturated,
+        ErrorCategory::Panic,
+        ErrorCategory::OutputPath,
+        ErrorCategory::CreateDir,
+        ErrorCategory::Write,
+        ErrorCategory::Columns,
+        ErrorCategory::Settings,
+        ErrorCategory::Other,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCategory::Read => "read",
+            ErrorCategory::Xml => "xml",
+            ErrorCategory::Analyses => "analyses",
+            ErrorCategory::Timeout => "timeout",
+            ErrorCategory::Saturated => "saturated",
+            ErrorCategory::Panic => "panic",
+            ErrorCategory::OutputPath => "output_path",
+            ErrorCategory::CreateDir => "create_dir",
+            ErrorCategory::Write => "write",
+            ErrorCategory::Columns => "columns",
+            ErrorCategory::Settings => "settings",
+            ErrorCategory::Other => "other",
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ErrorCategory {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ErrorCategory::ALL
+            .into_iter()
+            .find(|category| category.as_str() == s)
+            .ok_or(())
+    }
+}
+
 pub struct StatusMessage {
     pub path: PathBuf,
     pub kind: StatusMessageKind,
@@ -155,6 +240,23 @@ impl StatusMessage {
     }
 }
 
+impl StatusMessage {
+    /// The category of the error, or `None` if the contained result is not
+    /// an error.
+    pub fn category(&self) -> Option<ErrorCategory> {
+        let category = match &self.kind {
+            StatusMessageKind::Read { .. } => ErrorCategory::Read,
+            StatusMessageKind::ParseXml { .. } => ErrorCategory::Xml,
+            StatusMessageKind::ParseAnalyses { .. } => ErrorCategory::Analyses,
+            StatusMessageKind::CannotCreateDirectory { .. } => ErrorCategory::CreateDir,
+            StatusMessageKind::CantOpenFile { .. } => ErrorCategory::Read,
+            StatusMessageKind::CantReadFile { .. } => ErrorCategory::Read,
+            StatusMessageKind::SerializationError { .. } => ErrorCategory::Write,
+        };
+        self.is_err().then_some(category)
+    }
+}
+
 impl std::fmt::Display for StatusMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.kind {
@@ -193,3 +295,26 @@ impl std::fmt::Display for StatusMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{ErrorCategory, StatusMessage};
+    use std::time::Duration;
+
+    #[test]
+    fn category_names_round_trip() {
+        for category in ErrorCategory::ALL {
+            assert_eq!(category.as_str().parse(), Ok(category));
+        }
+        assert_eq!("nonsense".parse::<ErrorCategory>(), Err(()));
+    }
+
+    #[test]
+    fn category_of_status() {
+        let ok = StatusMessage::read("a.xml", Duration::ZERO, &Ok(String::new()));
+        assert_eq!(ok.category(), None);
+        let err = std::io::Error::new(std::io::ErrorKind::NotFound, "gone");
+        let failed = StatusMessage::read("a.xml", Duration::ZERO, &Err(err));
+        assert_eq!(failed.category(), Some(ErrorCategory::Read));
+    }
+}
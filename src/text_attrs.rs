@@ -0,0 +1,75 @@
+//! Which attributes of `<text>` are written, with `--text-attrs` or
+//! `--text-attrs-file`.
+//!
+//! The structural attributes are declared per corpus in the Korp
+//! configuration, and cwb-encode warns about the ones that are not
+//! declared. The selected attributes are written, in their usual order, and
+//! all others are left out. Attributes copied with `--header-extra` are
+//! selected by the name they are copied to.
+//!
+//! The file has one attribute per line, or several separated by commas.
+//! Everything after a `#` is a comment.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use anyhow::Context;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TextAttrs {
+    names: BTreeSet<String>,
+}
+
+impl TextAttrs {
+    pub fn new<S: AsRef<str>>(names: &[S]) -> Self {
+        let names = names
+            .iter()
+            .map(|name| name.as_ref().trim())
+            .filter(|name| !name.is_empty())
+            .map(str::to_string)
+            .collect();
+        Self { names }
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("can't read text attributes '{}'", path.display()))?;
+        Ok(Self::from_list(&contents))
+    }
+
+    fn from_list(contents: &str) -> Self {
+        let names = contents
+            .lines()
+            .map(|line| line.split_once('#').map_or(line, |(names, _)| names))
+            .flat_map(|line| line.split(','))
+            .collect::<Vec<_>>();
+        Self::new(&names)
+    }
+
+    /// Add the attributes of `other`.
+    pub fn extend(&mut self, other: TextAttrs) {
+        self.names.extend(other.names);
+    }
+
+    pub fn keeps(&self, name: &str) -> bool {
+        self.names.contains(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TextAttrs;
+
+    #[test]
+    fn list() {
+        let attrs = TextAttrs::from_list("title, lang\n# the dates\ndatefrom,dateto # cwb\n\n");
+        assert_eq!(
+            attrs,
+            TextAttrs::new(&["lang", "title", "datefrom", "dateto"])
+        );
+        assert!(attrs.keeps("datefrom"));
+        assert!(!attrs.keeps("cwb"));
+        assert!(!attrs.keeps(""));
+    }
+}
@@ -0,0 +1,86 @@
+//! Stable ids for the `<text>` elements, with `--text-id`.
+//!
+//! The id is either the `id` of the analysed document, or made from the name
+//! of the corpus and the path of the file inside the `analysed/` directory,
+//! without the `.xml` extension: `corpus-sme/news/avvir/a.html`. The name
+//! of the corpus can be replaced with `--corpus-id`.
+
+use std::path::Path;
+
+use crate::korp_mono::path::corpus_dir;
+use crate::path_filter::analysed_relative;
+
+/// The id analysed documents have when they don't have one.
+const NO_ID: &str = "no_id";
+
+/// Where the id of a text comes from.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum TextIdScheme {
+    /// The `id` of the analysed document, or the path when it has none
+    #[default]
+    Document,
+    /// The corpus name and the path of the file in the corpus
+    Path,
+}
+
+/// The id of the analysed file at `path`, made from the name of its corpus
+/// (or `corpus_id`), and its path inside the `analysed/` directory.
+pub fn path_id(path: &Path, corpus_id: Option<&str>) -> String {
+    let relative = analysed_relative(path);
+    let relative = relative.strip_suffix(".xml").unwrap_or(&relative);
+    let corpus = corpus_id.map(str::to_string).or_else(|| {
+        corpus_dir(path)
+            .and_then(Path::file_name)
+            .map(|name| name.to_string_lossy().into_owned())
+    });
+    match corpus {
+        Some(corpus) => format!("{corpus}/{relative}"),
+        None => relative.to_string(),
+    }
+}
+
+/// The id of the text converted from the analysed file at `path`, whose
+/// document has the id `document_id`.
+pub fn text_id(
+    scheme: TextIdScheme,
+    document_id: Option<&str>,
+    path: &Path,
+    corpus_id: Option<&str>,
+) -> String {
+    let document_id = document_id
+        .map(str::trim)
+        .filter(|id| !id.is_empty() && *id != NO_ID);
+    match (scheme, document_id) {
+        (TextIdScheme::Document, Some(id)) => id.to_string(),
+        _ => path_id(path, corpus_id),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TextIdScheme, path_id, text_id};
+    use std::path::Path;
+
+    #[test]
+    fn ids() {
+        let path = Path::new("/c/corpus-sme/analysed/news/avvir/a.html.xml");
+        assert_eq!(path_id(path, None), "corpus-sme/news/avvir/a.html");
+        assert_eq!(path_id(path, Some("sme2025")), "sme2025/news/avvir/a.html");
+        assert_eq!(path_id(Path::new("a.xml"), None), "a");
+
+        let id = |scheme, document_id| text_id(scheme, document_id, path, None);
+        assert_eq!(id(TextIdScheme::Document, Some("d17")), "d17");
+        assert_eq!(
+            id(TextIdScheme::Document, Some("no_id")),
+            "corpus-sme/news/avvir/a.html"
+        );
+        assert_eq!(
+            id(TextIdScheme::Document, None),
+            "corpus-sme/news/avvir/a.html"
+        );
+        assert_eq!(
+            id(TextIdScheme::Path, Some("d17")),
+            "corpus-sme/news/avvir/a.html"
+        );
+    }
+}
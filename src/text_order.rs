@@ -0,0 +1,105 @@
+//! The order of the documents in a corpus that is encoded as a whole, with
+//! `--cwb-encode --sort-by`.
+//!
+//! The documents are converted in parallel, so they come out in no
+//! particular order. Sorted, two builds of the same corpus are the same,
+//! and the corpus can be browsed in order of time in Korp. Documents with
+//! the same key are sorted by the path of the analysed file.
+//!
+//! Titles are compared without regard to case, in the order of the
+//! alphabets of the Sámi languages, where the letters with diacritics come
+//! right after their base letter (`a á b c č d đ ...`), followed by the
+//! Nordic letters (`æ ø å ä ö`). Other characters come after all letters.
+
+use std::path::{Path, PathBuf};
+
+use crate::korp_mono::KorpMonoFile;
+
+/// The letters, in the order they are sorted.
+const ALPHABET: &str = "aàáâãbcčçdđeèéêëfgǧǥhiìíîïjkǩlmnñŋoòóôõpqrsštŧuùúûüvwxyýzʒǯžæøåäö";
+
+/// What to sort the documents by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum SortBy {
+    /// The `datefrom` of the documents, the ones without a date last
+    Date,
+    /// The path of the analysed files
+    Path,
+    /// The titles of the documents
+    Title,
+}
+
+/// The sort key of a document.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct TextKey {
+    key: Vec<u32>,
+    path: PathBuf,
+}
+
+/// The sort key of the character `c`, by its place in [`ALPHABET`].
+fn weight(c: char) -> u32 {
+    match ALPHABET.chars().position(|letter| letter == c) {
+        Some(i) => i as u32,
+        None => ALPHABET.chars().count() as u32 + c as u32,
+    }
+}
+
+/// The key to sort `s` by, as described in the [module docs](self).
+pub fn collation_key(s: &str) -> Vec<u32> {
+    s.trim()
+        .chars()
+        .flat_map(char::to_lowercase)
+        .map(weight)
+        .collect()
+}
+
+impl SortBy {
+    /// The sort key of `document`, converted from the analysed file `path`.
+    pub fn key(&self, path: &Path, document: &KorpMonoFile) -> TextKey {
+        let key = match self {
+            SortBy::Date => match document.datefrom.as_deref() {
+                None | Some("") | Some("00000000") => vec![u32::MAX],
+                Some(date) => date.chars().map(u32::from).collect(),
+            },
+            SortBy::Path => vec![],
+            SortBy::Title => collation_key(document.title.as_deref().unwrap_or_default()),
+        };
+        TextKey {
+            key,
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SortBy, collation_key};
+    use crate::korp_mono::KorpMonoFile;
+    use std::path::Path;
+
+    #[test]
+    fn titles() {
+        let mut titles = [
+            "čuoigan", "Dálki", "áhkku", "Cealkka", "øks", "ábbi", "ádjá", "zebra", "1990",
+        ];
+        titles.sort_by_key(|title| collation_key(title));
+        assert_eq!(
+            titles,
+            [
+                "ábbi", "ádjá", "áhkku", "Cealkka", "čuoigan", "Dálki", "zebra", "øks", "1990"
+            ]
+        );
+    }
+
+    #[test]
+    fn dates() {
+        let document = |datefrom: &str| KorpMonoFile {
+            datefrom: Some(datefrom.to_string()),
+            ..Default::default()
+        };
+        let key = |path, datefrom| SortBy::Date.key(Path::new(path), &document(datefrom));
+        assert!(key("b.xml", "20180101") < key("a.xml", "20190101"));
+        assert!(key("a.xml", "20190101") < key("b.xml", "20190101"));
+        assert!(key("a.xml", "20190101") < key("a.xml", "00000000"));
+    }
+}
@@ -0,0 +1,88 @@
+//! Reads the headers of real corpus documents, in `tests/headers/`, in both
+//! header modes, and checks that the lenient mode never fails, and gives
+//! the same header as the strict mode whenever the strict mode can read it,
+//! also when elements are missing, unknown elements are added, and the
+//! texts are escaped.
+
+use korp_mono_rs::giella_xml::header::{HeaderMode, parse_header};
+use proptest::prelude::*;
+
+const SAMPLES: [&str; 4] = [
+    include_str!("headers/sme-facta.xml"),
+    include_str!("headers/sme-news.xml"),
+    include_str!("headers/sma-admin.xml"),
+    include_str!("headers/smn-bible.xml"),
+];
+
+/// The child elements of the header `sample`, one per line.
+fn children(sample: &str) -> Vec<&str> {
+    sample
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.contains("header>"))
+        .collect()
+}
+
+fn header(children: &[&str]) -> String {
+    format!("<header>\n{}\n</header>\n", children.join("\n"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[test]
+fn samples() {
+    for sample in SAMPLES {
+        let strict = parse_header(sample, HeaderMode::Strict).unwrap();
+        let lenient = parse_header(sample, HeaderMode::Lenient).unwrap();
+        assert_eq!(strict, lenient, "{sample}");
+        assert!(strict.title.is_some(), "{sample}");
+    }
+}
+
+proptest! {
+    #[test]
+    fn missing_and_unknown_elements(
+        sample in 0..SAMPLES.len(),
+        keep in prop::collection::vec(any::<bool>(), 16),
+        at in any::<prop::sample::Index>(),
+        unknown in "[a-z]{1,10}",
+        text in "[^<&]{0,20}",
+    ) {
+        let mut children: Vec<&str> = children(SAMPLES[sample])
+            .into_iter()
+            .zip(keep.iter().cycle())
+            .filter(|(_, keep)| **keep)
+            .map(|(child, _)| child)
+            .collect();
+        let element = format!("<x{unknown}>{}</x{unknown}>", escape(&text));
+        children.insert(at.index(children.len() + 1), &element);
+        let xml = header(&children);
+
+        let lenient = parse_header(&xml, HeaderMode::Lenient);
+        prop_assert!(lenient.is_ok());
+        if let Ok(strict) = parse_header(&xml, HeaderMode::Strict) {
+            prop_assert_eq!(strict, lenient.unwrap());
+        }
+    }
+
+    #[test]
+    fn escaped_titles(title in "[\\p{L}\\p{N} .,:&<>'\"-]{1,40}") {
+        let title = title.trim_matches(' ');
+        prop_assume!(!title.is_empty());
+        let title_element = format!("<title>{}</title>", escape(title));
+        let xml = header(&[
+            &title_element,
+            "<year>2018</year>",
+            "<conversion_status type=\"standard\"/>",
+            "<availability/>",
+        ]);
+        let strict = parse_header(&xml, HeaderMode::Strict).unwrap();
+        let lenient = parse_header(&xml, HeaderMode::Lenient).unwrap();
+        prop_assert_eq!(strict.title.as_deref(), Some(title));
+        prop_assert_eq!(lenient.title.as_deref(), Some(title));
+    }
+}
@@ -0,0 +1,256 @@
+//! Runs the conversion pipeline, through the library, on a miniature corpus
+//! tree in a temporary directory, the same way `main` does: read, parse,
+//! convert and write every file of `corpus-sme/analysed/`, and check the
+//! korp_mono files, the manifest, and the failure report.
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use gtcorpusutil::AnalysedFilePath;
+use korp_mono_rs::failures::{EXIT_FILE_ERRORS, FailureLayer, Failures};
+use korp_mono_rs::manifest::{MANIFEST_FILE_NAME, Manifest};
+use korp_mono_rs::metrics::Metrics;
+use korp_mono_rs::pipeline::{Pipeline, Stage, stages};
+use korp_mono_rs::process_sentence;
+use korp_mono_rs::status_message::ErrorCategory;
+use tracing_subscriber::layer::SubscriberExt;
+
+const DOCUMENT: &str = r#"<?xml version='1.0' encoding='utf8'?>
+<document xml:lang="sme" id="no_id">
+  <header>
+    <title>Ođđasat</title>
+    <genre code="news"/>
+    <year>2018</year>
+    <conversion_status type="standard"/>
+    <availability><license type="standard"/></availability>
+  </header>
+  <body><dependency><![CDATA[
+"<Mun>"
+	"mun" Pron Pers Sg1 Nom <W:0.0> @SUBJ> #1->2
+"<boađán>"
+	"boahtit" V IV Ind Prs Sg1 <W:0.0> @FMV #2->0
+"<.>"
+	"." CLB <W:0.0> #3->2
+
+]]></dependency></body></document>"#;
+
+/// A `corpus-sme` tree in its own temporary directory, removed on drop.
+struct MiniCorpus {
+    dir: PathBuf,
+}
+
+impl MiniCorpus {
+    fn new(name: &str, files: &[(&str, &str)]) -> Self {
+        let dir = std::env::temp_dir().join(format!("korp-mono-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, contents) in files {
+            let path = dir.join("corpus-sme").join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+        Self { dir }
+    }
+
+    fn corpus(&self) -> PathBuf {
+        self.dir.join("corpus-sme")
+    }
+
+    fn analysed_files(&self) -> Vec<AnalysedFilePath> {
+        let mut files = vec![];
+        let mut dirs = vec![self.corpus().join("analysed")];
+        while let Some(dir) = dirs.pop() {
+            for entry in std::fs::read_dir(dir).unwrap() {
+                let path = entry.unwrap().path();
+                match path.is_dir() {
+                    true => dirs.push(path),
+                    false => files.push(path),
+                }
+            }
+        }
+        files.sort();
+        files
+            .into_iter()
+            .map(AnalysedFilePath::new_unchecked)
+            .collect()
+    }
+}
+
+impl Drop for MiniCorpus {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// What a run left behind, besides the files it wrote.
+struct Run {
+    written: Vec<PathBuf>,
+    failures: Failures,
+    metrics: Arc<Metrics>,
+}
+
+/// Convert all files of `corpus` with the default stages, on this thread.
+fn convert(corpus: &MiniCorpus) -> Run {
+    let failures = Arc::new(Mutex::new(Failures::default()));
+    let metrics = Arc::new(Metrics::default());
+    let manifest = Arc::new(Mutex::new(Manifest::default()));
+    let pipeline = Pipeline::new()
+        .with_metrics(Some(Arc::clone(&metrics)))
+        .then(stages::ReadFile)
+        .then(stages::ParseXml::default())
+        .then(stages::ParseAnalyses::new(false, None))
+        .then(stages::Convert::new(process_sentence::Options::default()))
+        .then(stages::NormalizeAttrs::new(None))
+        .then(stages::RecordMetrics::new(Arc::clone(&metrics)))
+        .then(stages::WriteKorpMono {
+            manifest: Some(Arc::clone(&manifest)),
+            ..Default::default()
+        });
+
+    let subscriber = tracing_subscriber::registry().with(FailureLayer::new(Arc::clone(&failures)));
+    let written = tracing::subscriber::with_default(subscriber, || {
+        corpus
+            .analysed_files()
+            .into_iter()
+            .filter_map(|path| pipeline.run(path))
+            .collect()
+    });
+    manifest.lock().unwrap().write_manifests().unwrap();
+    let failures = failures.lock().unwrap().clone();
+    Run {
+        written,
+        failures,
+        metrics,
+    }
+}
+
+fn relative(path: &Path, corpus: &MiniCorpus) -> String {
+    path.strip_prefix(corpus.corpus())
+        .unwrap()
+        .display()
+        .to_string()
+}
+
+#[test]
+fn converts_the_corpus() {
+    let corpus = MiniCorpus::new(
+        "converts",
+        &[
+            ("analysed/news/a.xml", DOCUMENT),
+            ("analysed/news/avvir/b.xml", DOCUMENT),
+        ],
+    );
+    let run = convert(&corpus);
+
+    let written = run
+        .written
+        .iter()
+        .map(|path| relative(path, &corpus))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        written,
+        ["korp_mono/news/a.xml", "korp_mono/news/avvir/b.xml"]
+    );
+    assert_eq!(run.failures.total(), 0);
+
+    let xml = std::fs::read_to_string(corpus.corpus().join("korp_mono/news/a.xml")).unwrap();
+    assert!(xml.starts_with("<text "), "{xml}");
+    assert!(xml.contains(r#"title="Ođđasat""#), "{xml}");
+    assert!(xml.contains(r#"datefrom="20180101""#), "{xml}");
+    assert!(xml.trim_end().ends_with("</text>"), "{xml}");
+    assert_eq!(
+        xml.matches("<sentence").count(),
+        xml.matches("</sentence>").count()
+    );
+    // the token lines are on lines of their own
+    assert!(xml.contains("<sentence id=\"1\">\nMun\t"), "{xml}");
+    assert!(xml.contains("\t2\n</sentence>"), "{xml}");
+    let tokens = xml
+        .lines()
+        .filter(|line| line.contains('\t'))
+        .map(|line| line.split('\t').take(2).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    assert_eq!(tokens, [["Mun", "mun"], ["boađán", "boahtit"], [".", "."]]);
+
+    let manifest = std::fs::read_to_string(corpus.corpus().join(MANIFEST_FILE_NAME)).unwrap();
+    let sources = manifest
+        .lines()
+        .map(|line| line.split('\t').nth(2).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sources,
+        ["analysed/news/a.xml", "analysed/news/avvir/b.xml"]
+    );
+
+    let metrics = run.metrics.render(std::time::Duration::ZERO, 0);
+    assert!(
+        metrics.contains("\nkorp_mono_documents_converted 2\n"),
+        "{metrics}"
+    );
+    assert!(metrics.contains("\nkorp_mono_tokens 6\n"), "{metrics}");
+}
+
+#[test]
+fn reports_broken_files() {
+    let corpus = MiniCorpus::new(
+        "broken",
+        &[
+            ("analysed/a.xml", DOCUMENT),
+            ("analysed/broken.xml", "<document><header>"),
+        ],
+    );
+    let run = convert(&corpus);
+
+    assert_eq!(run.written.len(), 1);
+    assert!(!corpus.corpus().join("korp_mono/broken.xml").exists());
+    assert_eq!(run.failures.count(ErrorCategory::Xml), 1);
+    assert_eq!(run.failures.exit_code(), EXIT_FILE_ERRORS);
+    assert_eq!(run.failures.report(), "1 files failed:\n  xml: 1\n");
+}
+
+#[test]
+fn refuses_files_outside_analysed() {
+    let corpus = MiniCorpus::new("outside", &[("analysed/a.xml", DOCUMENT)]);
+    let path = corpus.corpus().join("a.xml");
+    std::fs::write(&path, DOCUMENT).unwrap();
+
+    let write = stages::WriteKorpMono::default();
+    let converted = Pipeline::new()
+        .then(stages::ReadFile)
+        .then(stages::ParseXml::default())
+        .then(stages::ParseAnalyses::new(false, None))
+        .then(stages::Convert::new(process_sentence::Options::default()))
+        .run(AnalysedFilePath::new_unchecked(path))
+        .unwrap();
+    assert_eq!(write.run(converted), None);
+}
+
+#[test]
+fn names_files_after_the_template() {
+    let corpus = MiniCorpus::new(
+        "template",
+        &[
+            ("analysed/news/a.xml", DOCUMENT),
+            ("analysed/news/avvir/a.xml", DOCUMENT),
+        ],
+    );
+    let write = stages::WriteKorpMono {
+        template: Some("{lang}/{year}/{domain}-{stem}.xml".parse().unwrap()),
+        ..Default::default()
+    };
+    let pipeline = Pipeline::new()
+        .then(stages::ReadFile)
+        .then(stages::ParseXml::default())
+        .then(stages::ParseAnalyses::new(false, None))
+        .then(stages::Convert::new(process_sentence::Options::default()))
+        .then(write);
+    let written = corpus
+        .analysed_files()
+        .into_iter()
+        .map(|path| pipeline.run(path).map(|path| relative(&path, &corpus)))
+        .collect::<Vec<_>>();
+    // the second document would overwrite the first
+    assert_eq!(
+        written,
+        [Some("korp_mono/sme/2018/news-a.xml".to_string()), None]
+    );
+}